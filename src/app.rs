@@ -1,57 +1,471 @@
-use crate::config::Config;
-use crate::event::{self, watcher::FileWatcher};
-use crate::git;
-use crate::model::{CommitInfo, DiffState, FileTree};
-use crate::ui;
+use crate::cli::Args;
+use crate::clipboard;
+use crate::config::{Config, Cue};
+use crate::editor::{self, EditorTarget};
+use crate::event::{self, EventReader, Macros, watcher::FileWatcher};
+use crate::git::{self, blob::BlobStore, patch::PatchSource, stage::HunkAction};
+use crate::github;
+use crate::hooks::{self, Hook};
+use crate::i18n::{self, tr, tr_format};
+use crate::model::{
+    ActivityLog, BlameOverview, CommentThread, CommitDraft, CommitFilter, CommitInfo, Comparison,
+    ConfirmAction, Confirmation, DiffCache, DiffChunk, DiffKey, DiffState, FileStatus, FileTree,
+    HistoryList, NavEntry, NavStack, OutputPane, Prompt, PromptKind, RepeatAction, RepoSnapshot,
+    RevRange, ReviewQueue, SessionLog, StashEntry, StashPicker, StatusSection, TextArea, UiState,
+    UndoHistory,
+};
+use crate::notify;
+use crate::output::{OutputEvent, OutputProcess};
+use crate::pair::{PairLink, PairRole};
+use crate::plugin::{self, Plugins};
+use crate::recovery::{self, InstanceLock, ReviewState};
+use crate::ui::{self, patch_view::PatchLayout};
 use anyhow::Result;
-use crossterm::event::{self as ct_event, Event};
+use crossterm::{
+    event::{DisableMouseCapture, EnableMouseCapture, Event},
+    execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+};
 use ratatui::prelude::*;
-use std::path::PathBuf;
-use std::sync::mpsc;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
 
 pub struct App {
     pub file_tree: FileTree,
     pub diff_state: DiffState,
     pub show_tree: bool,
+    /// Group the working tree into status sections instead of one tree
+    pub group_by_status: bool,
+    /// Give each workspace package its own root in the tree
+    pub group_by_package: bool,
+    /// The repository's workspace members, read when grouping by them
+    packages: Vec<crate::workspace::Package>,
     pub show_help: bool,
+    /// Diff line whose raw escapes and parsed spans are being inspected
+    pub ansi_line: Option<usize>,
+    /// Review comments on the current hunk, shown in a popup (`i`)
+    pub comment_thread: Option<CommentThread>,
+    /// Show the per-author blame band next to the diff
+    pub show_blame: bool,
+    /// Blame overview of the selected file (when show_blame is on)
+    pub blame: Option<BlameOverview>,
+    /// Show the activity log panel below the diff
+    pub show_activity: bool,
+    /// What changed in the repo while kibitz was open
+    pub activity: ActivityLog,
+    /// Start files with both staged and unstaged changes on the staged side (`--staged`)
+    prefer_staged: bool,
+    /// Show the command output pane below the diff
+    pub show_output: bool,
+    /// Output of the command run in the output pane, once one was started
+    pub output: Option<OutputPane>,
+    /// The running output command (killed when replaced or on exit)
+    output_process: Option<OutputProcess>,
+    /// Repo state at the last refresh, to tell what changed since
+    snapshot: RepoSnapshot,
     pub repo_path: PathBuf,
     pub config: Config,
+    /// Whether the git CLI works (checked at startup; libgit2 is used otherwise)
+    git_available: bool,
+    /// Whether the diff formatter is installed; without it diffs use the
+    /// built-in renderer
+    formatter_available: bool,
     /// History position: 0 = working tree, 1 = HEAD, 2 = HEAD~1, etc.
     pub history_position: usize,
     /// Current commit info when viewing history (None when at working tree)
     pub current_commit: Option<CommitInfo>,
+    /// Branches and tags containing the current commit (None while computing)
+    pub commit_refs: Option<Vec<String>>,
+    /// Ad-hoc comparison shown instead of the status or commit diff
+    pub comparison: Option<Comparison>,
+    /// Range of commits (`kibitz A..B`) shown instead of the status diff
+    pub range: Option<RevRange>,
+    /// Stash shown in place of the history commit (N in stash@{N})
+    pub stash_index: Option<usize>,
+    /// Open stash list popup
+    pub stash_picker: Option<StashPicker>,
+    /// Open commit log pane
+    pub history_list: Option<HistoryList>,
+    /// One file's history being stepped through with [ ]; its selection is the
+    /// commit shown
+    pub file_log: Option<HistoryList>,
+    /// File shown on its own (`--file`), relative to the repo root
+    pub focus_file: Option<PathBuf>,
     #[allow(dead_code)]
     file_watcher: FileWatcher,
     watcher_rx: mpsc::Receiver<()>,
+    /// Transient notice shown in the hint line until the next key press
+    pub message: Option<String>,
+    /// Active text prompt, shown in place of the hint line
+    pub prompt: Option<Prompt>,
+    /// Commit message being written in the commit modal
+    pub commit_draft: Option<CommitDraft>,
+    /// Destructive action waiting for confirmation
+    pub confirmation: Option<Confirmation>,
+    /// Last working tree change, repeated with `.`
+    last_action: Option<RepeatAction>,
+    /// Order `f`/`F` step through files in
+    review_queue: ReviewQueue,
+    /// Show the diffs of files .gitattributes marks as generated (`V`)
+    pub show_generated: bool,
+    /// Render diffs with kibitz's own side-by-side view instead of delta (`|`)
+    pub side_by_side: bool,
+    /// Show a line number gutter beside delta's output (`#`)
+    line_numbers: bool,
+    /// Show git's word diff, or highlight changed words in the built-in view
+    /// (`Ctrl-w`)
+    word_diff: bool,
+    /// Leave out whitespace-only changes and blank lines (`_`)
+    ignore_whitespace: bool,
+    /// Tree node whose diff is loaded past `diff.max_lines` (`A`)
+    full_diff: Option<PathBuf>,
+    /// Tree node whose diff shows more context, and how many lines (`v`)
+    context: Option<(PathBuf, u32)>,
+    /// First key of a pending two-key sequence (e.g. `g` in `gd`)
+    pub pending_key: Option<char>,
+    /// Recorded key macros and the replay in progress
+    pub macros: Macros,
+    /// Lua plugins and the keys they bound
+    pub plugins: Plugins,
+    /// File to open in the editor once the terminal can be handed over
+    pending_editor: Option<EditorTarget>,
+    /// File and hunk last written to the editor follow file
+    followed: Option<(PathBuf, usize)>,
+    /// Temp files of old file versions opened in external programs
+    blobs: BlobStore,
     terminal_size: (u16, u16),
-    pending_diff: Option<mpsc::Receiver<DiffState>>,
+    /// Width the last diff was requested at, to re-run delta when the pane changes
+    diff_width: usize,
+    /// Hunk to return to once a refreshed diff arrives
+    restore_hunk: Option<usize>,
+    /// Scroll offset and hunk to go back to once the diff of a view returned to
+    /// arrives
+    restore_position: Option<(usize, usize)>,
+    /// New-file line whose hunk to select once a diff with more context arrives
+    restore_line: Option<usize>,
+    /// Views left while browsing history, returned to with Backspace
+    nav: NavStack,
+    /// The cursor shared with another instance in a pair review
+    pub pair: Option<PairLink>,
+    pending_diff: Option<git::diff::PendingDiff>,
+    /// What the pending diff was requested for, to cache it under when it arrives
+    pending_diff_key: Option<DiffKey>,
+    /// The diff shown is the part of the pending one loaded so far
+    streaming: bool,
+    diff_cache: DiffCache,
+    /// The index file, whose changes make the cached working tree diffs stale
+    index_path: PathBuf,
+    /// Diffs of the files next to the selection, loading into the cache
+    prefetching: Vec<(DiffKey, git::diff::PendingDiff)>,
+    pending_deepen: Option<mpsc::Receiver<Result<()>>>,
+    pending_blame: Option<mpsc::Receiver<(BlameKey, Option<BlameOverview>)>>,
+    /// Blame overviews loaded so far. The working tree's go when files change.
+    blame_cache: HashMap<BlameKey, BlameOverview>,
+    /// How often files changed before, when `review.churn_hints` is on
+    churn: Option<git::churn::ChurnHistory>,
+    pending_churn: Option<mpsc::Receiver<Option<git::churn::ChurnHistory>>>,
+    /// The branch's pull request, when `review.github_comments` is on and
+    /// there is one
+    pull_request: Option<github::PullRequest>,
+    /// When the pull request was last fetched, so refreshes don't refetch it
+    /// every time
+    pull_request_fetched: Option<Instant>,
+    pending_pull_request: Option<mpsc::Receiver<Option<github::PullRequest>>>,
+    /// Review comments written here, waiting to be submitted (`U`)
+    review_drafts: Vec<github::ReviewComment>,
+    pending_review: Option<mpsc::Receiver<Result<()>>>,
+    pending_refs: Option<mpsc::Receiver<Vec<String>>>,
+    pending_commit: Option<mpsc::Receiver<Result<String>>>,
+    /// When the diff being loaded was asked for; None when it's only a refresh
+    /// nobody is waiting on
+    diff_requested: Option<Instant>,
+    /// When kibitz started, until the first diff is shown
+    started: Option<Instant>,
+    /// Files and commits looked at, for `review.exit_summary`
+    session: SessionLog,
+    /// Review progress as last written to disk for crash recovery
+    saved_review: ReviewState,
+    review_saved_at: Instant,
+    diff_settings_checked_at: Instant,
+    /// Claim on saving the review progress; None with `--no-save`. Without
+    /// it the progress isn't saved, since another instance owns it.
+    lock: Option<InstanceLock>,
+    /// Tree and layout arrangements to step back through with `u`
+    undo: UndoHistory,
 }
 
+/// Commits fetched per deepen request in shallow clones
+const DEEPEN_BY: usize = 50;
+
+/// Commits loaded into the history pane at a time
+const HISTORY_PAGE: usize = 200;
+
+/// Longest a frame is held back for input waiting to be handled
+const MAX_FRAME_SKIP: Duration = Duration::from_millis(200);
+
+/// How long a diff has to keep us waiting for `notify.diff_cue` to announce it
+const SLOW_DIFF: Duration = Duration::from_secs(2);
+
+/// Least time between writes of the review progress to disk
+const REVIEW_SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Why hunks can't be staged or discarded while whitespace is ignored: the
+/// hunks shown aren't the ones git would apply
+const IGNORING_WHITESPACE: &str =
+    "Hunks can't be staged or discarded while ignoring whitespace; press _ to show it";
+
+/// Least time between checks of the files configuring diffs for changes
+const DIFF_SETTINGS_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Lines of context `v` adds around a diff's changes, like GitHub's expand arrows
+const EXPAND_CONTEXT_BY: u32 = 20;
+
+/// Blame overviews kept before the cache starts over
+const BLAME_CACHE_SIZE: usize = 256;
+
+/// The file and commit a blame overview is of, None for the working tree's
+type BlameKey = (PathBuf, Option<String>);
+
 impl App {
-    pub fn new() -> Result<Self> {
+    pub fn new(args: Args) -> Result<Self> {
+        let started = Instant::now();
         let repo_path = git::status::find_repo_root()?;
-        let config = Config::load(&repo_path);
-        let file_tree = FileTree::from_git_status(&repo_path)?;
 
+        // The status scan and the watcher's setup take a while in a huge
+        // repository, and nothing below needs them until the tree is built
+        let scan_status = args.rev.is_none() && !args.review;
+        let status = scan_status.then(|| {
+            let repo_path = repo_path.clone();
+            std::thread::spawn(move || timed(|| git::status::get_status(&repo_path)))
+        });
         let (tx, rx) = mpsc::channel();
-        let watcher = FileWatcher::new(&repo_path, tx)?;
+        let watcher = {
+            let repo_path = repo_path.clone();
+            std::thread::spawn(move || timed(|| FileWatcher::new(&repo_path, tx)))
+        };
+        let snapshot = {
+            let repo_path = repo_path.clone();
+            std::thread::spawn(move || git::status::snapshot(&repo_path).unwrap_or_default())
+        };
+
+        let index_path = git::status::index_path(&repo_path)?;
+        let (config, config_time) = timed(|| Config::load(&repo_path, args.config.as_deref()));
+        let mut config = config?;
+        if let Some(mode) = args.layout {
+            config.layout.mode = mode;
+        }
+        let git_available = git::diff::git_available(&config.git);
+        let focus_file = args
+            .file
+            .map(|path| git::status::repo_relative(&repo_path, &path))
+            .transpose()?;
+        let pair = args
+            .pair
+            .map(|role| PairLink::new(role, &repo_path))
+            .transpose()?;
+        let lock = (!args.no_save).then(|| InstanceLock::acquire(&repo_path));
+
+        let (watcher, watcher_time) = join(watcher);
+        let mut watcher = watcher?;
+        // The working tree is only watched at the top level
+        if let Some(dir) = focus_file.as_ref().and_then(|f| f.parent())
+            && !dir.as_os_str().is_empty()
+        {
+            watcher.watch_dir(&repo_path.join(dir))?;
+        }
 
-        let app = Self {
-            file_tree,
-            diff_state: DiffState::new(),
-            show_tree: true,
+        let snapshot = join(snapshot);
+        let mut activity = ActivityLog::default();
+        activity.push(match &snapshot.branch {
+            Some(branch) => format!("started on branch {branch}"),
+            None => "started".to_string(),
+        });
+
+        let (plugins, plugin_errors) = Plugins::load(&config.plugins.scripts);
+        for error in &plugin_errors {
+            activity.push(format!("plugin failed: {error}"));
+        }
+        if !config.untrusted.is_empty() {
+            activity.push(format!(
+                "ignored .kibitz.toml's {}: add {} to plugins.trusted_repos in your global config to use them",
+                config.untrusted.join(", "),
+                repo_path.display()
+            ));
+        }
+        if let Err(e) = i18n::init(config.layout.language.as_deref()) {
+            activity.push(format!("translations failed: {e:#}"));
+        }
+        let formatter = &config.formatter;
+        let formatter_available = git::diff::formatter_available(formatter);
+        let formatter_error = if formatter_available {
+            git::diff::check_formatter_args(&repo_path, formatter)
+        } else {
+            activity.push(format!(
+                "{} not found; using the built-in renderer",
+                formatter.name()
+            ));
+            None
+        };
+        let formatter_error =
+            formatter_error.map(|e| format!("{} rejected its args: {e}", formatter.name()));
+        if let Some(error) = &formatter_error {
+            activity.push(error.clone());
+        }
+
+        let mut app = Self {
+            file_tree: FileTree::from_commit_files(Vec::new()),
+            diff_state: DiffState {
+                wrap: config.diff.wrap,
+                ..DiffState::new()
+            },
+            show_tree: focus_file.is_none() && !args.no_tree,
+            group_by_status: config.layout.group_by_status,
+            group_by_package: config.layout.group_by_package,
+            packages: if config.layout.group_by_package {
+                crate::workspace::packages(&repo_path)
+            } else {
+                Vec::new()
+            },
+            side_by_side: config.diff.side_by_side,
+            line_numbers: config.diff.line_numbers,
+            word_diff: config.diff.word_diff,
+            ignore_whitespace: config.diff.ignore_whitespace,
+            full_diff: None,
+            context: None,
             show_help: false,
+            ansi_line: None,
+            comment_thread: None,
+            show_blame: false,
+            blame: None,
+            show_activity: false,
+            activity,
+            prefer_staged: args.staged,
+            show_output: false,
+            output: None,
+            output_process: None,
+            snapshot,
             repo_path,
             config,
+            git_available,
+            formatter_available,
             history_position: 0,
             current_commit: None,
+            commit_refs: None,
+            comparison: None,
+            range: None,
+            focus_file,
+            stash_index: None,
+            stash_picker: None,
+            history_list: None,
+            file_log: None,
             file_watcher: watcher,
             watcher_rx: rx,
+            message: match (&formatter_error, plugin_errors.first()) {
+                (Some(e), _) => Some(tr_format("{} (formatter.args)", &[e])),
+                (None, Some(e)) => Some(tr_format(
+                    "Plugin failed: {} (a shows the activity log)",
+                    &[&e.to_string()],
+                )),
+                (None, None) => None,
+            },
+            prompt: None,
+            commit_draft: None,
+            confirmation: None,
+            last_action: None,
+            review_queue: ReviewQueue::default(),
+            show_generated: false,
+            pending_key: None,
+            macros: Macros::default(),
+            plugins,
+            pending_editor: None,
+            followed: None,
+            blobs: BlobStore::new(),
             terminal_size: (0, 0),
+            diff_width: 0,
+            restore_hunk: None,
+            restore_position: None,
+            restore_line: None,
+            nav: NavStack::default(),
+            pair,
             pending_diff: None,
+            pending_diff_key: None,
+            streaming: false,
+            diff_cache: DiffCache::default(),
+            index_path,
+            prefetching: Vec::new(),
+            pending_deepen: None,
+            pending_blame: None,
+            blame_cache: HashMap::new(),
+            churn: None,
+            pending_churn: None,
+            pull_request: None,
+            pull_request_fetched: None,
+            pending_pull_request: None,
+            review_drafts: Vec::new(),
+            pending_review: None,
+            pending_refs: None,
+            pending_commit: None,
+            diff_requested: None,
+            started: Some(started),
+            session: SessionLog::default(),
+            saved_review: ReviewState::default(),
+            review_saved_at: Instant::now(),
+            diff_settings_checked_at: Instant::now(),
+            lock,
+            undo: UndoHistory::default(),
         };
+        let (status, status_time) = match status.map(join) {
+            Some((status, time)) => (Some(status?), Some(time)),
+            None => (None, None),
+        };
+        let (tree, tree_time) = timed(|| -> Result<()> {
+            let tree = match status {
+                Some(status) => app.worktree_tree(status),
+                None => app.load_tree()?,
+            };
+            app.set_tree(tree);
+            Ok(())
+        });
+        tree?;
+
+        match &args.rev {
+            Some(spec) if RevRange::split(spec).is_some() => {
+                let range = git::range::resolve(&app.repo_path, spec)?;
+                app.show_range(range)?;
+            }
+            Some(spec) => {
+                git::history::resolve_commit(&app.repo_path, spec)
+                    .map_err(|_| anyhow::anyhow!("Unknown revision: {spec}"))?;
+                app.jump_to_rev(spec)?;
+            }
+            None if args.review => {
+                let range = git::range::review(&app.repo_path)?;
+                app.show_range(range)?;
+            }
+            None => {}
+        }
 
+        if app.lock.as_ref().is_some_and(|lock| !lock.held()) {
+            let text =
+                tr("Another kibitz is running on this repository: review progress isn't saved");
+            app.activity.push(text.to_string());
+            app.message = Some(text.to_string());
+        } else if let Some(state) = recovery::load(&app.repo_path).filter(|s| !s.is_empty()) {
+            app.restore_review(state);
+        }
+
+        let status_time = status_time.map_or_else(String::new, |time| {
+            format!(", status {}ms", time.as_millis())
+        });
+        app.activity.push(format!(
+            "ready in {}ms (config {}ms, watcher {}ms{status_time}, tree {}ms)",
+            started.elapsed().as_millis(),
+            config_time.as_millis(),
+            watcher_time.as_millis(),
+            tree_time.as_millis(),
+        ));
         Ok(app)
     }
 
@@ -63,213 +477,3211 @@ impl App {
         let size = terminal.size()?;
         self.terminal_size = (size.width, size.height);
         self.request_diff();
+        self.request_churn();
+        self.request_pull_request();
+
+        let mut events = EventReader::new();
+        let frame_time = Duration::from_secs(1) / u32::from(self.config.layout.max_fps.max(1));
+        let mut last_draw: Option<Instant> = None;
 
         loop {
+            // Show a slow diff as it arrives
+            if let Some(ref rx) = self.pending_diff
+                && let Some(partial) = rx.try_recv_partial()
+            {
+                self.show_partial_diff(partial);
+            }
+
             // Check for completed async diff
             if let Some(ref rx) = self.pending_diff
-                && let Ok(diff) = rx.try_recv()
+                && let Ok(loaded) = rx.try_recv()
             {
-                self.diff_state = diff;
+                let key = self.pending_diff_key.take();
+                let diff = match loaded {
+                    Ok(diff) => {
+                        if let Some(key) = key {
+                            self.diff_cache.insert(key, Arc::new(diff.clone()));
+                        }
+                        diff
+                    }
+                    // Shown empty but not cached, so it's loaded again next time
+                    Err(e) => {
+                        self.activity.push(format!("diff failed: {e:#}"));
+                        DiffState::default()
+                    }
+                };
+                // Stay where the part shown so far was scrolled to
+                let streamed = std::mem::take(&mut self.streaming)
+                    .then_some((self.diff_state.scroll_offset, self.diff_state.current_hunk));
+                let restore_hunk = self.restore_hunk.take();
+                let kept_match = self.replace_diff(diff, restore_hunk.is_some());
                 self.pending_diff = None;
+                if let Some(hunk) = restore_hunk
+                    && !kept_match
+                {
+                    self.diff_state.select_hunk(hunk);
+                } else if let Some((scroll, hunk)) = streamed
+                    && self.restore_position.is_none()
+                {
+                    self.diff_state.scroll_offset = scroll;
+                    self.diff_state.current_hunk = hunk;
+                }
+                if let Some((scroll, hunk)) = self.restore_position.take() {
+                    self.diff_state.scroll_to_top();
+                    self.diff_state.scroll_down(scroll);
+                    // The offset alone can point at the hunk above, under the sticky rows
+                    let last = self.diff_state.hunk_positions.len().saturating_sub(1);
+                    self.diff_state.current_hunk = hunk.min(last);
+                }
+                if let Some(line) = self.restore_line.take() {
+                    self.select_hunk_around(line);
+                }
+                // Lines may have moved even if the hunk didn't
+                self.followed = None;
+                if let Some(started) = self.started.take() {
+                    self.activity.push(format!(
+                        "first diff shown after {}ms",
+                        started.elapsed().as_millis()
+                    ));
+                }
+                if self
+                    .diff_requested
+                    .take()
+                    .is_some_and(|at| at.elapsed() >= SLOW_DIFF)
+                {
+                    let path = self.file_tree.selected_tree_path().unwrap_or_default();
+                    let message = match path.strip_prefix(".") {
+                        Ok(name) if !name.as_os_str().is_empty() => {
+                            format!("kibitz: diff of {} loaded", name.display())
+                        }
+                        _ => "kibitz: diff loaded".to_string(),
+                    };
+                    notify::cue(self.config.notify.diff_cue, &message);
+                }
+            }
+            self.collect_prefetched();
+            self.follow_current_hunk();
+            self.sync_pair()?;
+
+            // Check for completed blame overview
+            if let Some(ref rx) = self.pending_blame
+                && let Ok((key, blame)) = rx.try_recv()
+            {
+                if let Some(blame) = &blame {
+                    if self.blame_cache.len() >= BLAME_CACHE_SIZE {
+                        self.blame_cache.clear();
+                    }
+                    self.blame_cache.insert(key, blame.clone());
+                }
+                self.blame = blame;
+                self.pending_blame = None;
+            }
+
+            // Check for counted churn
+            if let Some(ref rx) = self.pending_churn
+                && let Ok(churn) = rx.try_recv()
+            {
+                if let Some(churn) = &churn {
+                    self.file_tree.set_churn(&churn.files);
+                }
+                self.churn = churn;
+                self.pending_churn = None;
+            }
+
+            // Check for the pull request's review comments
+            if let Some(ref rx) = self.pending_pull_request
+                && let Ok(pull_request) = rx.try_recv()
+            {
+                self.pending_pull_request = None;
+                let comments = |pr: &Option<github::PullRequest>| {
+                    pr.as_ref()
+                        .map(|pr| pr.comments.clone())
+                        .unwrap_or_default()
+                };
+                let changed = comments(&pull_request) != comments(&self.pull_request);
+                self.pull_request = pull_request;
+                if changed {
+                    self.redraw_comments();
+                }
+            }
+
+            // Check for a submitted review
+            if let Some(ref rx) = self.pending_review
+                && let Ok(result) = rx.try_recv()
+            {
+                self.pending_review = None;
+                match result {
+                    Ok(()) => {
+                        let count = self.review_drafts.len();
+                        self.review_drafts.clear();
+                        self.message = Some(if count == 1 {
+                            tr("Submitted the review with 1 comment").to_string()
+                        } else {
+                            tr_format(
+                                "Submitted the review with {} comments",
+                                &[&count.to_string()],
+                            )
+                        });
+                        self.redraw_comments();
+                        // Bring the comments back as posted
+                        self.pull_request_fetched = None;
+                        self.request_pull_request();
+                    }
+                    // Keep the drafts to try again
+                    Err(e) => self.message = Some(e.to_string()),
+                }
+            }
+
+            // Check for completed branch/tag containment lookup
+            if let Some(ref rx) = self.pending_refs
+                && let Ok(refs) = rx.try_recv()
+            {
+                self.commit_refs = Some(refs);
+                self.pending_refs = None;
+            }
+
+            // Check for completed shallow clone deepening
+            if let Some(ref rx) = self.pending_deepen
+                && let Ok(result) = rx.try_recv()
+            {
+                self.pending_deepen = None;
+                self.message = Some(match result {
+                    Ok(()) => tr("Fetched more history; press [ to continue").to_string(),
+                    Err(e) => tr_format("Deepening failed: {}", &[&e.to_string()]),
+                });
+            }
+
+            // Check for a finished commit
+            if let Some(ref rx) = self.pending_commit
+                && let Ok(result) = rx.try_recv()
+            {
+                self.pending_commit = None;
+                match result {
+                    Ok(id) => {
+                        let amended = self.commit_draft.take().is_some_and(|d| d.amend);
+                        let template = if amended {
+                            "Amended {}"
+                        } else {
+                            "Committed {}"
+                        };
+                        self.message = Some(tr_format(template, &[&id]));
+                        hooks::run(
+                            &self.config.hooks,
+                            Hook::Commit,
+                            &self.repo_path,
+                            &[
+                                ("KIBITZ_COMMIT", id),
+                                ("KIBITZ_AMEND", (amended as u8).to_string()),
+                            ],
+                        );
+                        self.refresh()?;
+                    }
+                    // Keep the draft so the message isn't lost
+                    Err(e) => self.message = Some(e.to_string()),
+                }
+            }
+
+            self.drain_output();
+            self.save_review_state();
+
+            // Hand the terminal to the editor if requested
+            if let Some(target) = self.pending_editor.take()
+                && let Err(e) = with_terminal_suspended(terminal, || editor::open(&target))?
+            {
+                self.message = Some(e.to_string());
             }
 
             // Check for file system changes
             if self.watcher_rx.try_recv().is_ok() {
                 self.refresh()?;
             }
+            self.check_diff_settings()?;
+
+            // Review-until-clean: done once everything is committed
+            if self.config.review.exit_when_clean && self.is_clean() {
+                break;
+            }
 
             // Check for resize
             let size = terminal.size()?;
             if (size.width, size.height) != self.terminal_size {
                 self.terminal_size = (size.width, size.height);
+                if let Some(process) = &self.output_process {
+                    let (cols, rows) = self.output_pty_size();
+                    process.resize(cols, rows);
+                }
+            }
+
+            // The pane also narrows when the tree grows to fit a longer file name
+            if self.get_diff_width() != self.diff_width {
                 self.request_diff();
             }
 
-            terminal.draw(|frame| ui::render(frame, self))?;
+            // Replay macro keys one at a time, letting each key's diff load first
+            if self.pending_diff.is_none()
+                && let Some(key) = self.macros.next_key()
+                && event::handle_key(self, key)?
+            {
+                break;
+            }
+
+            // Draw at most `layout.max_fps` times a second (ratatui only sends
+            // the cells that changed). Input waiting to be handled comes first:
+            // over a slow link keys would otherwise queue up behind frames.
+            let since_draw = last_draw.map_or(Duration::MAX, |at| at.elapsed());
+            if since_draw >= frame_time
+                && (since_draw >= MAX_FRAME_SKIP || !events.has_pending()?)
+            {
+                terminal.draw(|frame| ui::render(frame, self))?;
+                // Counted from when the terminal took the frame, so a slow one
+                // gets fewer
+                last_draw = Some(Instant::now());
+            }
 
-            // Short poll timeout for responsive UI
-            if ct_event::poll(Duration::from_millis(16))? {
-                match ct_event::read()? {
-                    Event::Key(key) => {
-                        if event::handle_key(self, key)? {
-                            break;
-                        }
-                    }
-                    Event::Mouse(mouse) => {
-                        event::handle_mouse(self, mouse)?;
+            // Wait until the next frame is due; don't wait while replaying
+            let timeout = if self.macros.is_replaying() {
+                Duration::ZERO
+            } else {
+                let since_draw = last_draw.map_or(Duration::MAX, |at| at.elapsed());
+                frame_time.saturating_sub(since_draw)
+            };
+            match events.next(timeout)? {
+                // Any key interrupts a replay
+                Some(Event::Key(_)) if self.macros.is_replaying() => {
+                    self.macros.stop_replay();
+                    self.message = Some(tr("Macro stopped").to_string());
+                }
+                Some(Event::Key(key)) => {
+                    self.macros.record(key);
+                    if event::handle_key(self, key)? {
+                        break;
                     }
-                    _ => {}
                 }
+                Some(Event::Mouse(mouse)) => {
+                    event::handle_mouse(self, mouse)?;
+                }
+                None if self.pending_diff.is_none() => self.prefetch_adjacent(),
+                _ => {}
             }
         }
 
+        // Nothing to recover after a clean exit
+        if let Some(lock) = self.lock.as_mut().filter(|lock| lock.held()) {
+            recovery::clear(&self.repo_path);
+            lock.release();
+        }
+        Ok(())
+    }
+
+    /// The review progress to keep on disk
+    fn review_state(&self) -> ReviewState {
+        ReviewState {
+            viewed: self.session.files().to_vec(),
+            queue: self.review_queue.order().to_vec(),
+            drafts: self.review_drafts.clone(),
+            nav: self.nav.entries().to_vec(),
+        }
+    }
+
+    /// Write the review progress to disk when it changed, at most once every
+    /// `REVIEW_SAVE_INTERVAL`, taking the instance lock once another instance
+    /// that held it exits
+    fn save_review_state(&mut self) {
+        if self.review_saved_at.elapsed() < REVIEW_SAVE_INTERVAL {
+            return;
+        }
+        self.review_saved_at = Instant::now();
+        let Some(lock) = &mut self.lock else {
+            return;
+        };
+        if lock.retry() {
+            let text = tr("The other kibitz is gone: saving review progress again");
+            self.activity.push(text.to_string());
+            self.message = Some(text.to_string());
+        }
+        if !lock.held() {
+            return;
+        }
+        let state = self.review_state();
+        if state == self.saved_review {
+            return;
+        }
+        if let Err(e) = recovery::save(&self.repo_path, &state) {
+            self.activity
+                .push(format!("couldn't save review progress: {e}"));
+        }
+        self.saved_review = state;
+    }
+
+    /// Whether review progress isn't being saved, so this instance doesn't
+    /// overwrite another's
+    pub fn not_saving(&self) -> bool {
+        self.lock.as_ref().is_none_or(|lock| !lock.held())
+    }
+
+    /// Pick up the progress of a session that didn't exit cleanly
+    fn restore_review(&mut self, state: ReviewState) {
+        for path in &state.viewed {
+            self.session.view_file(path);
+        }
+        self.review_queue = ReviewQueue::from_order(state.queue.clone());
+        self.review_drafts.clone_from(&state.drafts);
+        for entry in &state.nav {
+            self.nav.push(entry.clone());
+        }
+        let viewed = match state.viewed.len() {
+            1 => tr("1 file viewed").to_string(),
+            n => tr_format("{} files viewed", &[&n.to_string()]),
+        };
+        let drafts = match state.drafts.len() {
+            0 => String::new(),
+            1 => tr(", 1 draft comment").to_string(),
+            n => tr_format(", {} draft comments", &[&n.to_string()]),
+        };
+        let text = tr_format(
+            "Recovered the review progress of a session that didn't exit cleanly ({}{})",
+            &[&viewed, &drafts],
+        );
+
+        self.activity.push(text.clone());
+        self.message = Some(text);
+        self.saved_review = state;
+    }
+
+    /// Reload every diff when `.gitattributes` or the git config defining
+    /// textconv and diff drivers changed, which the cache can't tell by itself.
+    /// Checked at most once every `DIFF_SETTINGS_CHECK_INTERVAL`, since the
+    /// global config files aren't watched.
+    fn check_diff_settings(&mut self) -> Result<()> {
+        if self.diff_settings_checked_at.elapsed() < DIFF_SETTINGS_CHECK_INTERVAL {
+            return Ok(());
+        }
+        self.diff_settings_checked_at = Instant::now();
+        let files =
+            git::attributes::diff_settings_files(&self.repo_path, &self.file_tree.file_paths());
+        if self.diff_cache.check_settings(&files) {
+            self.activity.push(
+                "diff settings changed (.gitattributes or git config): reloading diffs".to_string(),
+            );
+            self.refresh()?;
+        }
         Ok(())
     }
 
     pub fn refresh(&mut self) -> Result<()> {
-        self.file_tree = FileTree::from_git_status(&self.repo_path)?;
+        self.record_activity();
+        self.diff_cache.invalidate_live();
+        self.blame_cache.retain(|(_, oid), _| oid.is_some());
+        // Loaded from before the change
+        self.prefetching.clear();
+        let selected = self.file_tree.selected_tree_path();
+        let showing_staged = self.diff_state.showing_staged;
+        let collapsed = self.file_tree.collapsed_paths();
+        // A view just returned to may still be waiting for its diff
+        let restore_position = self.restore_position;
+
+        let tree = self.load_tree()?;
+        self.set_tree(tree);
+        // A new commit adds to it
+        self.request_churn();
+        self.request_pull_request();
+
+        // Keep the view stable across refreshes
+        for path in &collapsed {
+            self.file_tree.collapse_path(path);
+        }
+        if let Some(path) = selected {
+            // Fall back to the closest surviving ancestor if the file went away
+            path.ancestors().any(|p| self.file_tree.reveal(p));
+        }
+
         self.request_diff();
+        // Stay on the side being shown while the file still has changes there
+        let status = self
+            .file_tree
+            .selected_file_path()
+            .and_then(|path| self.file_tree.get_file_status(&path));
+        // A status section decides the side itself
+        let status_diff = self.in_working_tree() && self.file_tree.selected_section().is_none();
+        if status_diff && let Some(status) = status {
+            if showing_staged && status.has_staged() {
+                self.request_diff_staged(true);
+            } else if !showing_staged && self.prefer_staged && status.has_both() {
+                self.request_diff_staged(false);
+            }
+        }
+        match restore_position {
+            Some(_) => self.restore_position = restore_position,
+            None => self.restore_hunk = Some(self.diff_state.current_hunk),
+        }
+        // Files changing isn't something to be told about
+        self.diff_requested = None;
         Ok(())
     }
 
-    fn get_diff_width(&self) -> usize {
-        if self.show_tree {
-            // Estimate based on typical tree width
-            self.terminal_size.0.saturating_sub(35) as usize
-        } else {
-            self.terminal_size.0 as usize
+    /// Commits HEAD is ahead of and behind its upstream, as of the last refresh
+    pub fn ahead_behind(&self) -> Option<(usize, usize)> {
+        self.snapshot.ahead_behind
+    }
+
+    /// Log what changed in the repo since the last refresh
+    fn record_activity(&mut self) {
+        let Ok(snapshot) = git::status::snapshot(&self.repo_path) else {
+            return;
+        };
+        for change in self.snapshot.changes(&snapshot) {
+            self.activity.push(change);
+        }
+        if self.config.notify.enabled || self.config.notify.commits_cue != Cue::None {
+            self.notify_new_commits(&snapshot);
         }
+        self.snapshot = snapshot;
     }
 
-    pub fn request_diff(&mut self) {
-        let diff_width = self.get_diff_width();
+    /// Notify about commits that landed on HEAD or its upstream since the last refresh
+    fn notify_new_commits(&self, next: &RepoSnapshot) {
+        let prev = &self.snapshot;
+        let mut moved = Vec::new();
+        // A branch switch isn't new work
+        if prev.branch == next.branch
+            && let (Some(old), Some(new)) = (&prev.head_oid, &next.head_oid)
+        {
+            let name = next.branch.as_deref().unwrap_or("HEAD");
+            moved.push((name, old, new));
+        }
+        if let (Some((old_name, old)), Some((name, new))) = (&prev.upstream, &next.upstream)
+            && old_name == name
+        {
+            moved.push((name.as_str(), old, new));
+        }
 
-        // Check current_commit first - it's set before history_position is updated
-        if let Some(commit) = &self.current_commit {
-            self.request_commit_diff(commit.oid_full.clone(), diff_width);
-        } else {
-            self.request_working_tree_diff(diff_width);
+        for (name, old, new) in moved {
+            let commits = git::history::commits_between(&self.repo_path, old, new);
+            if let Ok(commits) = commits
+                && !commits.is_empty()
+            {
+                notify::new_commits(&self.config.notify, &self.repo_path, name, &commits);
+            }
         }
     }
 
-    fn clear_diff(&mut self) {
-        self.diff_state = DiffState::new();
-        self.pending_diff = None;
+    /// Showing the working tree and there's nothing in it to review
+    pub fn is_clean(&self) -> bool {
+        self.in_working_tree() && self.file_tree.root.is_empty()
     }
 
-    fn request_working_tree_diff(&mut self, diff_width: usize) {
-        let Some((path, is_dir)) = self.file_tree.selected_path() else {
-            return self.clear_diff();
-        };
+    /// Showing working tree changes (not a commit, stash, range or comparison)
+    fn in_working_tree(&self) -> bool {
+        self.current_commit.is_none() && self.comparison.is_none() && self.range.is_none()
+    }
 
-        let delta_args = self.config.delta.args.clone();
+    pub fn toggle_activity(&mut self) {
+        self.show_activity = !self.show_activity;
+    }
 
-        if is_dir {
-            let files = self.file_tree.files_under_path(&path);
-            if files.is_empty() {
-                return self.clear_diff();
+    /// Show or hide the output pane, starting the configured command the first
+    /// time (or again after it exited). Asks for a command if none is configured.
+    pub fn toggle_output(&mut self) -> Result<()> {
+        if self.show_output {
+            self.show_output = false;
+            return Ok(());
+        }
+        let running = self.output.as_ref().is_some_and(|o| o.status.is_none());
+        if !running {
+            let command = self.output.as_ref().map(|o| o.command.clone());
+            match command.or_else(|| self.config.output.command.clone()) {
+                Some(command) => self.start_output(&command),
+                None => {
+                    self.prompt = Some(Prompt::new(PromptKind::OutputCommand));
+                    return Ok(());
+                }
             }
-            self.pending_diff = Some(git::diff::get_diff_for_paths(
-                &self.repo_path,
-                &files,
-                diff_width,
-                delta_args,
-            ));
-        } else {
-            let status = self.file_tree.get_file_status(&path);
-            self.pending_diff = Some(git::diff::get_diff(
-                &self.repo_path,
-                &path,
-                status,
-                diff_width,
-                delta_args,
-            ));
         }
+        self.show_output = true;
+        Ok(())
     }
 
-    fn request_commit_diff(&mut self, oid: String, diff_width: usize) {
-        let Some((path, is_dir)) = self.file_tree.selected_path() else {
-            return self.clear_diff();
-        };
+    /// Run `command` in the output pane, replacing whatever ran there before
+    fn start_output(&mut self, command: &str) {
+        self.output_process = None;
+        let (cols, rows) = self.output_pty_size();
+        let mut pane = OutputPane::new(command.to_string());
+        match OutputProcess::spawn(command, &self.repo_path, cols, rows) {
+            Ok(process) => self.output_process = Some(process),
+            Err(e) => pane.status = Some(format!("failed to start: {e}")),
+        }
+        self.output = Some(pane);
+    }
 
-        let delta_args = self.config.delta.args.clone();
+    fn run_output_command(&mut self, command: &str) -> Result<()> {
+        if !command.is_empty() {
+            self.start_output(command);
+            self.show_output = true;
+        }
+        Ok(())
+    }
 
-        if is_dir {
-            let files = self.file_tree.files_under_path(&path);
-            if files.is_empty() {
-                return self.clear_diff();
+    /// Move new output from the command into the pane
+    fn drain_output(&mut self) {
+        let (Some(process), Some(pane)) = (&self.output_process, &mut self.output) else {
+            return;
+        };
+        let mut exited = false;
+        while let Ok(event) = process.events.try_recv() {
+            match event {
+                OutputEvent::Line(line) => pane.push(line),
+                OutputEvent::Clear => pane.clear(),
+                OutputEvent::Exited(status) => {
+                    pane.status = Some(status);
+                    exited = true;
+                }
             }
-            self.pending_diff = Some(git::diff::get_commit_files_diff(
-                &self.repo_path,
-                &oid,
-                &files,
-                diff_width,
-                delta_args,
-            ));
-        } else {
-            self.pending_diff = Some(git::diff::get_commit_file_diff(
-                &self.repo_path,
-                &oid,
-                &path,
-                diff_width,
-                delta_args,
-            ));
+        }
+        if exited {
+            self.output_process = None;
         }
     }
 
-    pub fn request_diff_staged(&mut self, staged: bool) {
-        if let Some(path) = self.file_tree.selected_file_path() {
-            let status = self.file_tree.get_file_status(&path);
-            let diff_width = self.get_diff_width();
-
-            let rx = git::diff::get_diff_staged(
-                &self.repo_path,
-                &path,
-                status,
-                diff_width,
-                staged,
-                self.config.delta.args.clone(),
-            );
-            self.pending_diff = Some(rx);
+    pub fn scroll_output(&mut self, delta: isize) {
+        if let Some(pane) = &mut self.output {
+            if delta > 0 {
+                pane.scroll_down(delta.unsigned_abs());
+            } else {
+                pane.scroll_up(delta.unsigned_abs());
+            }
         }
     }
 
-    pub fn toggle_tree(&mut self) {
-        self.show_tree = !self.show_tree;
-        self.request_diff();
+    /// Size of the output pane below its title border
+    fn output_pty_size(&self) -> (u16, u16) {
+        let cols = ui::diff_pane_width(self, self.terminal_size);
+        (cols, self.config.output.height.saturating_sub(1))
     }
 
-    pub fn toggle_staged(&mut self) {
-        if self.diff_state.has_both {
-            let new_staged = !self.diff_state.showing_staged;
-            self.diff_state.showing_staged = new_staged;
-            self.request_diff_staged(new_staged);
+    /// Note the file and commit about to be shown, for the exit summary
+    fn log_viewed(&mut self) {
+        if let Some(path) = self.file_tree.selected_file_path() {
+            self.session
+                .view_file(path.strip_prefix(".").unwrap_or(&path));
+        }
+        if let Some(commit) = &self.current_commit
+            && self.stash_index.is_none()
+        {
+            self.session.view_commit(commit);
         }
     }
 
-    pub fn navigate_tree(&mut self, navigate_fn: impl FnOnce(&mut FileTree)) {
-        let prev_path = self.file_tree.selected_path();
-        navigate_fn(&mut self.file_tree);
-        if self.file_tree.selected_path() != prev_path {
-            self.request_diff();
+    /// What `review.exit_summary` prints once the terminal is restored
+    pub fn exit_summary(&self) -> Option<String> {
+        self.config.review.exit_summary.then(|| {
+            self.session
+                .summary(git::status::diffstat(&self.repo_path).ok())
+        })
+    }
+
+    /// Columns delta gets to draw in: exactly the diff pane as laid out
+    fn get_diff_width(&self) -> usize {
+        let mut width = ui::diff_pane_width(self, self.terminal_size);
+        if self.show_blame {
+            width = width.saturating_sub(ui::BLAME_BAND_WIDTH);
         }
+        if self.shows_line_numbers() {
+            width = width.saturating_sub(ui::LINE_NUMBER_GUTTER_WIDTH);
+        }
+        width as usize
     }
 
-    /// Go back one commit in history (deeper into history)
-    pub fn go_back_in_history(&mut self) -> Result<()> {
-        let new_position = self.history_position + 1;
-        if self.load_history_position(new_position)? {
-            self.history_position = new_position;
+    /// Whether diffs get the line number gutter. Kibitz's own views number
+    /// their lines already.
+    fn shows_line_numbers(&self) -> bool {
+        self.line_numbers && !self.side_by_side && self.formatter_available && !self.word_diff
+    }
+
+    pub fn request_diff(&mut self) {
+        self.log_viewed();
+        self.diff_requested = Some(Instant::now());
+        self.streaming = false;
+        self.restore_hunk = None;
+        self.restore_position = None;
+        self.restore_line = None;
+        let diff_width = self.get_diff_width();
+        self.diff_width = diff_width;
+        self.request_blame();
+
+        let key = self.diff_key(
+            self.file_tree.selected_index,
+            self.prefer_staged,
+            diff_width,
+        );
+        if self.use_cached_diff(&key) {
+            return;
+        }
+        // Moved onto a file whose diff is already on its way
+        if let Some(i) = self.prefetching.iter().position(|(k, _)| *k == key) {
+            let (key, pending) = self.prefetching.swap_remove(i);
+            self.pending_diff = Some(pending);
+            self.pending_diff_key = Some(key);
+            return;
+        }
+        self.start_diff(diff_width);
+        if self.pending_diff.is_some() {
+            self.pending_diff_key = Some(key);
         }
-        Ok(())
     }
 
-    /// Go forward one commit (toward working tree)
-    pub fn go_forward_in_history(&mut self) -> Result<()> {
-        if self.history_position > 0 {
-            self.history_position -= 1;
-            self.load_history_position(self.history_position)?;
+    /// Identifies the diff of the tree's node at `at` about to be requested,
+    /// for the cache
+    fn diff_key(&self, at: usize, staged: bool, width: usize) -> DiffKey {
+        let (view, live) = self.view();
+        DiffKey {
+            view,
+            live,
+            path: self.file_tree.tree_path_at(at),
+            staged,
+            width,
+            side_by_side: self.side_by_side,
+            line_numbers: self.shows_line_numbers(),
+            show_generated: self.show_generated,
+            full: self.loads_full_diff(at),
+            context: self.context_lines_at(at),
+            word_diff: self.word_diff,
+            ignore_whitespace: self.ignore_whitespace,
         }
-        Ok(())
     }
 
-    /// Load file tree for a history position. Returns false if position doesn't exist.
-    fn load_history_position(&mut self, position: usize) -> Result<bool> {
-        if position == 0 {
-            self.current_commit = None;
-            self.file_tree = FileTree::from_git_status(&self.repo_path)?;
-            self.request_diff();
-            return Ok(true);
+    /// What's being shown, and whether it changes with the working tree
+    fn view(&self) -> (String, bool) {
+        match (&self.comparison, &self.range, &self.current_commit) {
+            (Some(c), _, _) => (format!("compare {} {}", c.from, c.to), true),
+            (None, Some(range), _) => (format!("range {}..{}", range.from, range.to), false),
+            (None, None, Some(commit)) => (format!("commit {}", commit.oid_full), false),
+            (None, None, None) => ("worktree".to_string(), true),
         }
+    }
 
-        let Some(commit) = git::history::get_commit_at(&self.repo_path, position - 1)? else {
-            return Ok(false);
+    /// Show the cached diff for `key`, if there is one. It's delivered like a
+    /// loaded one so restoring a position works the same.
+    fn use_cached_diff(&mut self, key: &DiffKey) -> bool {
+        self.pending_diff_key = None;
+        self.diff_cache.check_index(&self.index_path);
+        let Some(diff) = self.diff_cache.get(key) else {
+            return false;
         };
+        self.pending_diff = Some(git::diff::PendingDiff::ready(DiffState::clone(&diff)));
+        true
+    }
 
-        let files = git::history::get_commit_files(&self.repo_path, &commit.oid_full)?;
-        self.file_tree = FileTree::from_commit_files(files);
-        self.current_commit = Some(commit);
-        self.request_diff();
-        Ok(true)
+    /// While idle, load the diffs of the files around the selection into the
+    /// cache so moving to them with j/k shows them right away
+    fn prefetch_adjacent(&mut self) {
+        let mut wanted = Vec::new();
+        for index in self.file_tree.adjacent_files() {
+            let key = self.diff_key(index, self.prefer_staged, self.diff_width);
+            if !self.diff_cache.contains(&key)
+                && !self.prefetching.iter().any(|(k, _)| *k == key)
+                && let Some(pending) = self.load_diff(index, self.diff_width)
+            {
+                self.prefetching.push((key.clone(), pending));
+            }
+            wanted.push(key);
+        }
+        // Dropping the ones the selection moved away from cancels them
+        self.prefetching.retain(|(key, _)| wanted.contains(key));
+    }
+
+    /// Cache the prefetched diffs that finished loading
+    fn collect_prefetched(&mut self) {
+        let mut i = 0;
+        while i < self.prefetching.len() {
+            match self.prefetching[i].1.try_recv() {
+                Ok(loaded) => {
+                    let (key, _) = self.prefetching.swap_remove(i);
+                    if let Ok(diff) = loaded {
+                        self.diff_cache.insert(key, Arc::new(diff));
+                    }
+                }
+                Err(_) => i += 1,
+            }
+        }
+    }
+
+    /// Request the selection's diff for whatever is being browsed
+    fn start_diff(&mut self, diff_width: usize) {
+        match self.load_diff(self.file_tree.selected_index, diff_width) {
+            Some(pending) => self.pending_diff = Some(pending),
+            None => self.clear_diff(),
+        }
     }
+
+    /// Start loading the diff of the tree's node at `at` (usually the
+    /// selection), unless there's nothing to show
+    fn load_diff(&self, at: usize, diff_width: usize) -> Option<git::diff::PendingDiff> {
+        if let Some(comparison) = &self.comparison {
+            let comparisons = if comparison.dirs {
+                let (path, is_dir) = self.file_tree.path_at(at)?;
+                let files = if is_dir {
+                    self.file_tree.files_under_path(&path)
+                } else {
+                    vec![path]
+                };
+                files
+                    .iter()
+                    .map(|f| comparison.for_file(f.strip_prefix(".").unwrap_or(f)))
+                    .collect()
+            } else {
+                vec![comparison.clone()]
+            };
+            Some(git::diff::get_compare_diff(
+                &self.repo_path,
+                comparisons,
+                diff_width,
+                self.pipeline_options(at),
+            ))
+        } else if let Some(range) = &self.range {
+            let (path, is_dir) = self.file_tree.path_at(at)?;
+            let files = self
+                .file_tree
+                .diff_paths(&path, is_dir, !self.show_generated);
+            let mut opts = self.pipeline_options(at);
+            opts.summarize = self.hidden_generated(&path, is_dir);
+            if files.is_empty() && opts.summarize.is_empty() {
+                return None;
+            }
+            Some(git::diff::get_range_diff(
+                &self.repo_path,
+                range,
+                &files,
+                diff_width,
+                opts,
+            ))
+        // Check current_commit first - it's set before history_position is updated
+        } else if let Some(commit) = &self.current_commit {
+            self.load_commit_diff(&commit.oid_full, at, diff_width)
+        } else {
+            self.load_working_tree_diff(at, diff_width)
+        }
+    }
+
+    /// Files of the selection summarized instead of diffed as generated, unless
+    /// `V` shows them
+    fn hidden_generated(&self, path: &Path, is_dir: bool) -> Vec<PathBuf> {
+        if self.show_generated {
+            return Vec::new();
+        }
+        let mut files = if is_dir {
+            self.file_tree.files_under_path(path)
+        } else {
+            vec![path.to_path_buf()]
+        };
+        files.retain(|file| self.file_tree.is_generated(file));
+        files
+    }
+
+    fn clear_diff(&mut self) {
+        self.streaming = false;
+        self.replace_diff(DiffState::new(), false);
+        self.pending_diff = None;
+    }
+
+    /// Show more of the pending diff: its first lines in place of the last
+    /// diff, then each chunk below the ones shown, where they're scrolled to
+    fn show_partial_diff(&mut self, chunk: DiffChunk) {
+        if !self.streaming {
+            let diff = DiffState {
+                has_both: chunk.has_both,
+                showing_staged: chunk.showing_staged,
+                ..DiffState::new()
+            };
+            self.replace_diff(diff, false);
+            self.streaming = true;
+        }
+        self.diff_state.extend(chunk);
+        if let Some(search) = self.diff_state.search.take() {
+            self.diff_state.set_search(search.query);
+        }
+    }
+
+    /// Show a new diff, searching it for the query of the last one and wrapped
+    /// and scrolled sideways the same. On a refresh (`same_view`), stays on the
+    /// hit being looked at; returns whether it did.
+    fn replace_diff(&mut self, diff: DiffState, same_view: bool) -> bool {
+        let search = self.diff_state.search.take();
+        let (x_offset, wrap) = (self.diff_state.x_offset, self.diff_state.wrap);
+        self.diff_state = diff;
+        self.diff_state.x_offset = x_offset;
+        self.diff_state.wrap = wrap;
+        let Some(search) = search else {
+            return false;
+        };
+        let current = search.current.map(|i| search.matches[i]);
+        self.diff_state.set_search(search.query);
+        same_view && current.is_some_and(|m| self.diff_state.jump_to_match(m))
+    }
+
+    fn load_working_tree_diff(
+        &self,
+        at: usize,
+        diff_width: usize,
+    ) -> Option<git::diff::PendingDiff> {
+        let (path, is_dir) = self.file_tree.path_at(at)?;
+
+        let mut opts = self.pipeline_options(at);
+        let section = self.file_tree.section_at(at);
+
+        if is_dir {
+            let mut files = self.file_tree.files_under_path(&path);
+            if let Some(section) = section {
+                files.retain(|file| {
+                    self.file_tree
+                        .get_file_status(file)
+                        .is_some_and(|status| section.contains(status))
+                });
+            }
+            if files.is_empty() {
+                return None;
+            }
+            if !self.show_generated {
+                (opts.summarize, files) = files
+                    .into_iter()
+                    .partition(|file| self.file_tree.is_generated(file));
+            }
+            Some(git::diff::get_diff_for_paths(
+                &self.repo_path,
+                &files,
+                section == Some(StatusSection::Staged),
+                diff_width,
+                opts,
+            ))
+        } else {
+            let status = self.file_tree.get_file_status(&path);
+            let staged = match section {
+                Some(section) => section == StatusSection::Staged,
+                None => self.prefer_staged,
+            };
+            if !self.show_generated && self.file_tree.is_generated(&path) {
+                let staged = status.is_some_and(|s| s.has_staged() && (staged || !s.has_both()));
+                opts.summarize = vec![path];
+                return Some(git::diff::get_diff_for_paths(
+                    &self.repo_path,
+                    &[],
+                    staged,
+                    diff_width,
+                    opts,
+                ));
+            }
+            let renamed_from = self.file_tree.renamed_from(&path);
+            Some(if staged && status.is_some_and(|s| s.has_both()) {
+                git::diff::get_diff_staged(
+                    &self.repo_path,
+                    &path,
+                    renamed_from,
+                    status,
+                    diff_width,
+                    true,
+                    opts,
+                )
+            } else {
+                git::diff::get_diff(
+                    &self.repo_path,
+                    &path,
+                    renamed_from,
+                    status,
+                    diff_width,
+                    opts,
+                )
+            })
+        }
+    }
+
+    fn load_commit_diff(
+        &self,
+        oid: &str,
+        at: usize,
+        diff_width: usize,
+    ) -> Option<git::diff::PendingDiff> {
+        let (path, is_dir) = self.file_tree.path_at(at)?;
+
+        let files = self
+            .file_tree
+            .diff_paths(&path, is_dir, !self.show_generated);
+        let mut opts = self.pipeline_options(at);
+        opts.summarize = self.hidden_generated(&path, is_dir);
+        if files.is_empty() && opts.summarize.is_empty() {
+            return None;
+        }
+        Some(git::diff::get_commit_files_diff(
+            &self.repo_path,
+            oid,
+            &files,
+            diff_width,
+            opts,
+        ))
+    }
+
+    /// How the diff of the tree's node at `at` is produced
+    fn pipeline_options(&self, at: usize) -> git::diff::PipelineOptions {
+        git::diff::PipelineOptions {
+            git: self.config.git.clone(),
+            git_available: self.git_available,
+            formatter: self.config.formatter.clone(),
+            annotate: self
+                .config
+                .diff
+                .annotate_hunks
+                .then_some(self.config.colors.annotation),
+            summarize: Vec::new(),
+            comments: self.pull_request_in_view().and_then(|pr| {
+                let comments: Arc<[_]> = pr
+                    .comments
+                    .iter()
+                    .chain(self.review_drafts.iter().filter(|d| d.commit == pr.head))
+                    .cloned()
+                    .collect();
+                (!comments.is_empty()).then_some((comments, self.config.colors.info))
+            }),
+            line_numbers: self.shows_line_numbers(),
+            context: self.context_lines_at(at),
+            word_diff: self.word_diff,
+            ignore_whitespace: self.ignore_whitespace,
+            renames: self.config.diff.rename_detection(),
+            submodule_log: self.config.diff.submodule_log,
+            max_lines: (self.config.diff.max_lines > 0 && !self.loads_full_diff(at))
+                .then_some(self.config.diff.max_lines),
+            builtin: match (self.side_by_side, self.formatter_available) {
+                (true, _) => Some((PatchLayout::SideBySide, self.config.colors.clone())),
+                (false, false) => Some((PatchLayout::Unified, self.config.colors.clone())),
+                (false, true) => None,
+            },
+            cancel: git::diff::Cancel::default(),
+            progress: git::diff::Progress::default(),
+        }
+    }
+
+    pub fn request_diff_staged(&mut self, staged: bool) {
+        self.log_viewed();
+        self.diff_requested = Some(Instant::now());
+        self.streaming = false;
+        self.restore_hunk = None;
+        self.restore_position = None;
+        self.restore_line = None;
+        if let Some(path) = self.file_tree.selected_file_path() {
+            let status = self.file_tree.get_file_status(&path);
+            let diff_width = self.get_diff_width();
+            self.diff_width = diff_width;
+
+            let key = self.diff_key(self.file_tree.selected_index, staged, diff_width);
+            if self.use_cached_diff(&key) {
+                return;
+            }
+            self.pending_diff_key = Some(key);
+            let rx = git::diff::get_diff_staged(
+                &self.repo_path,
+                &path,
+                self.file_tree.renamed_from(&path),
+                status,
+                diff_width,
+                staged,
+                self.pipeline_options(self.file_tree.selected_index),
+            );
+            self.pending_diff = Some(rx);
+        }
+    }
+
+    /// Count how often files changed in the background, unless already counted
+    /// up to HEAD
+    fn request_churn(&mut self) {
+        if !self.config.review.churn_hints || self.pending_churn.is_some() {
+            return;
+        }
+        let head = git::churn::head(&self.repo_path);
+        if head.is_none() || self.churn.as_ref().map(|c| c.head) == head {
+            return;
+        }
+        let (tx, rx) = mpsc::channel();
+        let repo_path = self.repo_path.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send(git::churn::history(&repo_path).ok());
+        });
+        self.pending_churn = Some(rx);
+    }
+
+    /// Fetch the branch's pull request and its review comments in the
+    /// background, unless that was done in the last minute
+    fn request_pull_request(&mut self) {
+        const REFETCH_AFTER: Duration = Duration::from_secs(60);
+        if !self.config.review.github_comments
+            || self.pending_pull_request.is_some()
+            || self
+                .pull_request_fetched
+                .is_some_and(|at| at.elapsed() < REFETCH_AFTER)
+        {
+            return;
+        }
+        self.pull_request_fetched = Some(Instant::now());
+        let (tx, rx) = mpsc::channel();
+        let repo_path = self.repo_path.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send(github::pull_request(&repo_path).ok().flatten());
+        });
+        self.pending_pull_request = Some(rx);
+    }
+
+    /// Reload the diff with the comment counts on its hunks, staying where we
+    /// are. Cached diffs have the old counts.
+    fn redraw_comments(&mut self) {
+        self.diff_cache.clear();
+        self.prefetching.clear();
+        let position = (self.diff_state.scroll_offset, self.diff_state.current_hunk);
+        self.request_diff();
+        self.restore_position = Some(position);
+    }
+
+    /// The pull request, when the view is its diff at the commit last pushed
+    /// to it: the only diff its comments' line numbers are lines of
+    fn pull_request_in_view(&self) -> Option<&github::PullRequest> {
+        let pr = self.pull_request.as_ref()?;
+        let range = self.range.as_ref()?;
+        (range.to == pr.head && pr.merge_base.as_ref() == Some(&range.from)).then_some(pr)
+    }
+
+    /// Why review comments can't be drafted, if they can't: their line
+    /// numbers are taken from the pull request's diff, so it must be on screen
+    fn draft_blocker(&self) -> Option<String> {
+        let Some(pr) = &self.pull_request else {
+            return Some(
+                tr(if self.config.review.github_comments {
+                    "No pull request found for this branch (needs the gh CLI)"
+                } else {
+                    "Review comments are off (review.github_comments)"
+                })
+                .to_string(),
+            );
+        };
+        if self.pull_request_in_view().is_none() {
+            return Some(tr_format(
+                "Review comments go on PR #{}'s diff; R shows it",
+                &[&pr.number.to_string()],
+            ));
+        }
+        self.head_blocker(pr)
+    }
+
+    /// Why the drafts can't be submitted, if they can't
+    fn submit_blocker(&self) -> Option<String> {
+        match &self.pull_request {
+            Some(pr) => self.head_blocker(pr),
+            None => {
+                Some(tr("No pull request found for this branch (needs the gh CLI)").to_string())
+            }
+        }
+    }
+
+    /// Unless HEAD is what was last pushed to `pr`, what's reviewed here isn't
+    /// what's reviewed on GitHub
+    fn head_blocker(&self, pr: &github::PullRequest) -> Option<String> {
+        let head = git::churn::head(&self.repo_path).map(|oid| oid.to_string());
+        (head.as_ref() != Some(&pr.head)).then(|| {
+            tr_format(
+                "HEAD isn't the commit last pushed to PR #{} ({}); push or pull first",
+                &[&pr.number.to_string(), &pr.head[..pr.head.len().min(7)]],
+            )
+        })
+    }
+
+    /// Show the review comments on the hunk under the diff cursor
+    pub fn show_comments(&mut self) {
+        let Some(pull_request) = self.pull_request_in_view() else {
+            self.message = Some(match &self.pull_request {
+                Some(pr) => tr_format(
+                    "Review comments are on PR #{}'s diff; R shows it",
+                    &[&pr.number.to_string()],
+                ),
+                None if self.config.review.github_comments => {
+                    tr("No pull request found for this branch (needs the gh CLI)").to_string()
+                }
+                None => tr("Review comments are off (review.github_comments)").to_string(),
+            });
+            return;
+        };
+        let Some((path, _, file, idx)) = self.current_hunk() else {
+            self.message = Some(tr("No hunk under the cursor").to_string());
+            return;
+        };
+        let hunk = &file.hunks[idx];
+        let comments: Vec<_> = pull_request
+            .comments
+            .iter()
+            .chain(
+                self.review_drafts
+                    .iter()
+                    .filter(|d| d.commit == pull_request.head),
+            )
+            .filter(|c| c.is_on(&file, hunk))
+            .cloned()
+            .collect();
+        if comments.is_empty() {
+            self.message = Some(tr_format(
+                "No review comments on this hunk (PR #{})",
+                &[&pull_request.number.to_string()],
+            ));
+            return;
+        }
+        self.comment_thread = Some(CommentThread {
+            title: format!(
+                "{}:{}",
+                path.strip_prefix(".").unwrap_or(&path).display(),
+                hunk.new_start
+            ),
+            comments,
+            scroll: 0,
+        });
+    }
+
+    /// Drop the drafts in the comments popup
+    pub fn drop_thread_drafts(&mut self) {
+        let Some(thread) = &mut self.comment_thread else {
+            return;
+        };
+        let before = self.review_drafts.len();
+        self.review_drafts.retain(|d| !thread.comments.contains(d));
+        thread.comments.retain(|c| !c.draft);
+        let dropped = before - self.review_drafts.len();
+        if thread.comments.is_empty() {
+            self.comment_thread = None;
+        }
+        if dropped > 0 {
+            self.message = Some(if dropped == 1 {
+                tr("Dropped 1 draft").to_string()
+            } else {
+                tr_format("Dropped {} drafts", &[&dropped.to_string()])
+            });
+            self.redraw_comments();
+        }
+    }
+
+    /// Ask for a review comment on the hunk under the diff cursor
+    pub fn open_comment_prompt(&mut self) {
+        if let Some(blocker) = self.draft_blocker() {
+            self.message = Some(blocker);
+        } else if self.current_hunk().is_none() {
+            self.message = Some(tr("No hunk under the cursor").to_string());
+        } else {
+            self.open_prompt(PromptKind::ReviewComment);
+        }
+    }
+
+    /// Keep `body` as a draft comment on the hunk under the diff cursor
+    fn draft_comment(&mut self, body: &str) {
+        if body.is_empty() {
+            return;
+        }
+        if let Some(blocker) = self.draft_blocker() {
+            self.message = Some(blocker);
+            return;
+        }
+        let Some(head) = self.pull_request.as_ref().map(|pr| pr.head.clone()) else {
+            return;
+        };
+        let Some((_, _, file, idx)) = self.current_hunk() else {
+            self.message = Some(tr("No hunk under the cursor").to_string());
+            return;
+        };
+        let Some(draft) = github::ReviewComment::draft(&file, &file.hunks[idx], body.into(), head)
+        else {
+            self.message = Some(tr("This hunk has no changed line to comment on").to_string());
+            return;
+        };
+        self.message = Some(tr_format(
+            "Drafted a comment on {}:{} ({} waiting; U submits them)",
+            &[
+                &draft.path.display().to_string(),
+                &draft.line.to_string(),
+                &(self.review_drafts.len() + 1).to_string(),
+            ],
+        ));
+        self.review_drafts.push(draft);
+        self.redraw_comments();
+    }
+
+    /// Ask how to submit the drafts as a review
+    pub fn open_review_prompt(&mut self) {
+        if self.pending_review.is_some() {
+            self.message = Some(tr("Already submitting a review").to_string());
+        } else if let Some(blocker) = self.submit_blocker() {
+            self.message = Some(blocker);
+        } else {
+            self.open_prompt(PromptKind::SubmitReview);
+        }
+    }
+
+    /// Submit the drafts as a review: `text` is `approve`, `comment` or
+    /// `changes`, then the review's summary
+    fn submit_review(&mut self, text: &str) {
+        if let Some(blocker) = self.submit_blocker() {
+            self.message = Some(blocker);
+            return;
+        }
+        let Some(pull_request) = &self.pull_request else {
+            return;
+        };
+        let (word, body) = text.split_once(' ').unwrap_or((text, ""));
+        let Some(event) = github::ReviewEvent::parse(word) else {
+            self.message = Some(tr("Start with approve, comment or changes").to_string());
+            return;
+        };
+        self.pending_review = Some(github::submit_review_async(
+            &self.repo_path,
+            pull_request.number,
+            pull_request.head.clone(),
+            event,
+            body.trim().to_string(),
+            self.review_drafts.clone(),
+        ));
+        self.message = Some(tr_format(
+            "Submitting the review to PR #{}",
+            &[&pull_request.number.to_string()],
+        ));
+    }
+
+    /// Load the blame overview for the selected file in the background
+    fn request_blame(&mut self) {
+        self.pending_blame = None;
+        let selected = self.file_tree.selected_file_path();
+        let Some(path) = selected.filter(|_| self.show_blame) else {
+            self.blame = None;
+            return;
+        };
+        if self.blame.as_ref().is_some_and(|b| b.path != path) {
+            self.blame = None;
+        }
+
+        let oid = self.current_commit.as_ref().map(|c| c.oid_full.clone());
+        let key = (path, oid);
+        if let Some(blame) = self.blame_cache.get(&key) {
+            self.blame = Some(blame.clone());
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let repo_path = self.repo_path.clone();
+        std::thread::spawn(move || {
+            let (path, oid) = &key;
+            let blame = git::blame::blame_overview(&repo_path, path, oid.as_deref()).ok();
+            let _ = tx.send((key, blame));
+        });
+        self.pending_blame = Some(rx);
+    }
+
+    pub fn toggle_blame(&mut self) {
+        self.show_blame = !self.show_blame;
+        self.request_diff();
+    }
+
+    pub fn toggle_tree(&mut self) {
+        self.show_tree = !self.show_tree;
+        self.request_diff();
+    }
+
+    /// Switch the working tree between one tree and status sections, staying on
+    /// the selected file
+    pub fn toggle_group_by_status(&mut self) -> Result<()> {
+        self.group_by_status = !self.group_by_status;
+        if self.in_working_tree() && self.focus_file.is_none() {
+            self.refresh()?;
+        }
+        Ok(())
+    }
+
+    /// Switch between giving each workspace package its own root and one tree,
+    /// rereading the workspace's members when turned on
+    pub fn toggle_group_by_package(&mut self) -> Result<()> {
+        self.group_by_package = !self.group_by_package;
+        if self.group_by_package {
+            self.packages = crate::workspace::packages(&self.repo_path);
+        }
+        self.message = Some(match (self.group_by_package, self.packages.len()) {
+            (false, _) => tr("Not grouping by package").to_string(),
+            (true, 0) => tr("No Cargo or npm workspace members found").to_string(),
+            (true, n) => tr_format(
+                "Grouping by package ({} in the workspace)",
+                &[&n.to_string()],
+            ),
+        });
+        self.refresh()
+    }
+
+    /// Say which CODEOWNERS owners the changes shown involve
+    pub fn show_owners(&mut self) {
+        if git::owners::CodeOwners::load(&self.repo_path).is_none() {
+            self.message = Some(tr("No CODEOWNERS file").to_string());
+            return;
+        }
+        let (counts, unowned) = self.file_tree.owner_counts();
+        let mut parts: Vec<String> = counts
+            .iter()
+            .map(|(owner, n)| format!("{owner} ({n})"))
+            .collect();
+        if unowned > 0 {
+            parts.push(tr_format("unowned ({})", &[&unowned.to_string()]));
+        }
+        self.message = Some(if parts.is_empty() {
+            tr("No changed files").to_string()
+        } else {
+            tr_format("Owners: {}", &[&parts.join(", ")])
+        });
+    }
+
+    pub fn toggle_generated(&mut self) {
+        self.show_generated = !self.show_generated;
+        self.message = Some(
+            tr(if self.show_generated {
+                "Showing generated files' diffs"
+            } else {
+                "Hiding generated files' diffs"
+            })
+            .to_string(),
+        );
+
+        self.request_diff();
+    }
+
+    /// Load the lines of the selection's diff that the size limit left out,
+    /// staying where we are
+    pub fn load_full_diff(&mut self) {
+        if !self.diff_state.truncated {
+            self.message = Some(tr("The whole diff is already loaded").to_string());
+            return;
+        }
+        self.full_diff = self.file_tree.selected_tree_path();
+        let position = (self.diff_state.scroll_offset, self.diff_state.current_hunk);
+        self.message = Some(tr("Loading the full diff").to_string());
+        self.request_diff();
+        self.restore_position = Some(position);
+    }
+
+    /// Show `EXPAND_CONTEXT_BY` more lines around the changes in the
+    /// selection's diff, staying on the current hunk
+    pub fn expand_context(&mut self) {
+        let Some(path) = self.file_tree.selected_tree_path() else {
+            return;
+        };
+        let lines = self
+            .context_lines()
+            .unwrap_or_else(|| git::patch::default_context(&self.repo_path))
+            + EXPAND_CONTEXT_BY;
+        // The hunk's first line is in whichever hunk it grows into
+        let line = self
+            .current_hunk()
+            .map(|(_, _, file, idx)| file.hunks[idx].new_start);
+        self.context = Some((path, lines));
+        let position = (self.diff_state.scroll_offset, self.diff_state.current_hunk);
+        self.message = Some(tr_format(
+            "Showing {} lines of context",
+            &[&lines.to_string()],
+        ));
+        self.request_diff();
+        self.restore_position = Some(position);
+        self.restore_line = line;
+    }
+
+    /// Lines of context the selection's diff is shown with, when `v` changed it
+    fn context_lines(&self) -> Option<u32> {
+        self.context_lines_at(self.file_tree.selected_index)
+    }
+
+    /// Lines of context the diff of the tree's node at `at` is shown with
+    fn context_lines_at(&self, at: usize) -> Option<u32> {
+        let (path, lines) = self.context.as_ref()?;
+        (self.file_tree.tree_path_at(at).as_ref() == Some(path)).then_some(*lines)
+    }
+
+    /// Select the hunk of the file under the cursor showing new-file `line`
+    fn select_hunk_around(&mut self, line: usize) {
+        if let Some((_, _, file, idx)) = self.current_hunk()
+            && let Some(i) = file
+                .hunks
+                .iter()
+                .position(|hunk| hunk.new_range().contains(&line))
+        {
+            self.diff_state
+                .select_hunk(self.diff_state.current_hunk - idx + i);
+        }
+    }
+
+    /// Whether the diff of the tree's node at `at` is loaded past the size limit
+    fn loads_full_diff(&self, at: usize) -> bool {
+        self.full_diff.is_some() && self.full_diff == self.file_tree.tree_path_at(at)
+    }
+
+    pub fn toggle_wrap(&mut self) {
+        self.diff_state.wrap = !self.diff_state.wrap;
+        self.message = Some(if self.diff_state.wrap {
+            tr("Wrapping long lines").to_string()
+        } else {
+            tr("Cutting long lines off (Alt-h/l scroll sideways)").to_string()
+        });
+    }
+
+    pub fn toggle_line_numbers(&mut self) {
+        self.line_numbers = !self.line_numbers;
+        self.message = Some(match (self.line_numbers, self.shows_line_numbers()) {
+            (false, _) => tr("Line numbers hidden").to_string(),
+            (true, true) => tr("Line numbers shown").to_string(),
+            (true, false) => tr("Line numbers shown with delta's unified view").to_string(),
+        });
+        self.request_diff();
+    }
+
+    pub fn toggle_word_diff(&mut self) {
+        self.word_diff = !self.word_diff;
+        let builtin = self.side_by_side || !self.formatter_available;
+        self.message = Some(match (self.word_diff, builtin, self.git_available) {
+            (false, _, _) => tr("Word diff off").to_string(),
+            (true, true, _) => tr("Highlighting changed words").to_string(),
+            (true, false, true) => tr("Word diff (git --word-diff=color)").to_string(),
+            (true, false, false) => tr("Word diff needs the git CLI").to_string(),
+        });
+        self.request_diff();
+    }
+
+    pub fn toggle_ignore_whitespace(&mut self) {
+        self.ignore_whitespace = !self.ignore_whitespace;
+        self.message = Some(if self.ignore_whitespace {
+            tr("Ignoring whitespace changes and blank lines").to_string()
+        } else {
+            tr("Showing whitespace changes").to_string()
+        });
+        self.request_diff();
+    }
+
+    pub fn ignores_whitespace(&self) -> bool {
+        self.ignore_whitespace
+    }
+
+    pub fn toggle_side_by_side(&mut self) {
+        self.side_by_side = !self.side_by_side;
+        let formatter = self.config.formatter.name();
+        self.message = Some(match (self.side_by_side, self.formatter_available) {
+            (true, _) => tr("Side-by-side view").to_string(),
+            (false, true) => tr_format("{} view", &[formatter]),
+            (false, false) => tr_format("Unified view ({} isn't installed)", &[formatter]),
+        });
+        self.request_diff();
+    }
+
+    /// How the tree and diff are arranged right now
+    pub fn ui_state(&self) -> UiState {
+        UiState {
+            view: self.view().0,
+            collapsed: self.file_tree.collapsed_paths(),
+            show_tree: self.show_tree,
+            group_by_status: self.group_by_status,
+            group_by_package: self.group_by_package,
+            show_generated: self.show_generated,
+            side_by_side: self.side_by_side,
+            line_numbers: self.line_numbers,
+            wrap: self.diff_state.wrap,
+            show_blame: self.show_blame,
+            show_activity: self.show_activity,
+        }
+    }
+
+    /// Remember the arrangement a key press changed, so `u` can bring it back.
+    /// Moving to another view starts over, since its folders are different.
+    pub fn record_ui_change(&mut self, before: UiState) {
+        let after = self.ui_state();
+        if after.view != before.view {
+            self.undo.clear();
+        } else if after != before {
+            self.undo.record(before);
+        }
+    }
+
+    pub fn undo(&mut self) -> Result<()> {
+        match self.undo.undo(self.ui_state()) {
+            Some(state) => {
+                self.apply_ui_state(&state)?;
+                self.message = Some(tr("Undid the last layout change").to_string());
+            }
+            None => self.message = Some(tr("Nothing to undo").to_string()),
+        }
+        Ok(())
+    }
+
+    pub fn redo(&mut self) -> Result<()> {
+        match self.undo.redo(self.ui_state()) {
+            Some(state) => {
+                self.apply_ui_state(&state)?;
+                self.message = Some(tr("Redid the layout change").to_string());
+            }
+            None => self.message = Some(tr("Nothing to redo").to_string()),
+        }
+        Ok(())
+    }
+
+    /// Go back to `state` through the same toggles the keys use
+    fn apply_ui_state(&mut self, state: &UiState) -> Result<()> {
+        if self.group_by_status != state.group_by_status {
+            self.toggle_group_by_status()?;
+        }
+        if self.group_by_package != state.group_by_package {
+            self.toggle_group_by_package()?;
+        }
+        if self.show_tree != state.show_tree {
+            self.toggle_tree();
+        }
+        if self.show_generated != state.show_generated {
+            self.toggle_generated();
+        }
+        if self.side_by_side != state.side_by_side {
+            self.toggle_side_by_side();
+        }
+        if self.line_numbers != state.line_numbers {
+            self.toggle_line_numbers();
+        }
+        if self.diff_state.wrap != state.wrap {
+            self.toggle_wrap();
+        }
+        if self.show_blame != state.show_blame {
+            self.toggle_blame();
+        }
+        if self.show_activity != state.show_activity {
+            self.toggle_activity();
+        }
+
+        let prev_path = self.file_tree.selected_tree_path();
+        for path in self.file_tree.collapsed_paths() {
+            if !state.collapsed.contains(&path) {
+                self.file_tree.expand_path(&path);
+            }
+        }
+        for path in &state.collapsed {
+            self.file_tree.collapse_path(path);
+        }
+        if self.file_tree.selected_tree_path() != prev_path {
+            self.request_diff();
+        }
+        Ok(())
+    }
+
+    pub fn toggle_staged(&mut self) {
+        if self.diff_state.has_both {
+            let new_staged = !self.diff_state.showing_staged;
+            self.diff_state.showing_staged = new_staged;
+            self.request_diff_staged(new_staged);
+        }
+    }
+
+    pub fn navigate_tree(&mut self, navigate_fn: impl FnOnce(&mut FileTree)) {
+        let prev_path = self.file_tree.selected_tree_path();
+        navigate_fn(&mut self.file_tree);
+        if self.file_tree.selected_tree_path() != prev_path {
+            if let Some(path) = self.file_tree.selected_file_path() {
+                let path = path.strip_prefix(".").unwrap_or(&path);
+                hooks::run(
+                    &self.config.hooks,
+                    Hook::FileSelected,
+                    &self.repo_path,
+                    &[("KIBITZ_FILE", path.to_string_lossy().into_owned())],
+                );
+            }
+            // A file comparison ends when moving on; directory comparisons own the tree
+            if self.comparison.as_ref().is_some_and(|c| !c.dirs) {
+                self.comparison = None;
+            }
+            self.request_diff();
+        }
+    }
+
+    /// Select the file currently shown in the diff pane, expanding its ancestors.
+    /// Inverse of tree → diff navigation, useful when scrolling a folder diff.
+    pub fn reveal_diff_file(&mut self) {
+        let Some(name) = self.diff_state.current_file_name() else {
+            return;
+        };
+        let path = PathBuf::from(".").join(name);
+        self.navigate_tree(|tree| {
+            tree.reveal(&path);
+        });
+    }
+
+    /// `f`/`F`: select the next (or previous) file in the review queue
+    pub fn next_queued_file(&mut self, forward: bool) {
+        let files = self.file_tree.file_paths();
+        let selected = self.file_tree.selected_file_path();
+        match self.review_queue.next(&files, selected.as_deref(), forward) {
+            Some(path) => self.navigate_tree(|tree| {
+                tree.reveal(&path);
+            }),
+            None if forward => self.message = Some(tr("Last file in the review queue").to_string()),
+            None => self.message = Some(tr("First file in the review queue").to_string()),
+        }
+    }
+
+    /// `%`: jump between the selected file and its test (or source) file, when
+    /// both changed
+    pub fn jump_to_counterpart(&mut self) {
+        let Some(path) = self.file_tree.selected_file_path() else {
+            self.message = Some(tr("Select a file to jump to its test").to_string());
+            return;
+        };
+        let files: Vec<PathBuf> = self
+            .file_tree
+            .file_paths()
+            .iter()
+            .map(|file| file.strip_prefix(".").unwrap_or(file).to_path_buf())
+            .collect();
+        let relative = path.strip_prefix(".").unwrap_or(&path);
+        match crate::model::counterpart(&self.config.review.test_pairs, relative, &files) {
+            Some(other) => self.navigate_tree(|tree| {
+                tree.reveal(&Path::new(".").join(other));
+            }),
+            None => {
+                self.message = Some(tr_format(
+                    "No changed test or source file pairs with {}",
+                    &[&relative.display().to_string()],
+                ))
+            }
+        }
+    }
+
+    /// `<`/`>`/`m`: move the selected file `by` places later in the review queue
+    pub fn move_in_queue(&mut self, by: isize) {
+        let Some(path) = self.file_tree.selected_file_path() else {
+            self.message = Some(tr("Select a file to move it in the review queue").to_string());
+            return;
+        };
+        let files = self.file_tree.file_paths();
+        if let Some((pos, len)) = self.review_queue.shift(&files, &path, by) {
+            let name = path.strip_prefix(".").unwrap_or(&path).display();
+            self.message = Some(tr_format(
+                "{} is {} of {} in the review queue",
+                &[&name.to_string(), &(pos + 1).to_string(), &len.to_string()],
+            ));
+        }
+    }
+
+    /// How the diff for a file in the current view is produced
+    fn patch_source_for(&self, path: &Path) -> PatchSource {
+        if let Some(comparison) = &self.comparison {
+            let single = if comparison.dirs {
+                // Diff file names are the `to` side's repo-relative paths
+                let relative = path.strip_prefix(".").unwrap_or(path);
+                comparison.for_file(
+                    relative
+                        .strip_prefix(&comparison.to.path)
+                        .unwrap_or(relative),
+                )
+            } else {
+                comparison.clone()
+            };
+            return PatchSource::Compare(vec![single]);
+        }
+        let paths = vec![path.to_path_buf()];
+        if let Some(range) = &self.range {
+            return PatchSource::Range(range.from.clone(), range.to.clone(), paths);
+        }
+        if let Some(commit) = &self.current_commit {
+            return PatchSource::Commit(commit.oid_full.clone(), paths);
+        }
+        match self.file_tree.get_file_status(path) {
+            Some(FileStatus::Untracked) => PatchSource::Untracked(path.to_path_buf()),
+            Some(s) if s.has_staged() && self.diff_state.showing_staged => {
+                PatchSource::Staged(self.file_tree.diff_paths(path, false, false))
+            }
+            _ => PatchSource::Unstaged(paths),
+        }
+    }
+
+    /// Tree path of the file the current hunk belongs to
+    fn current_hunk_path(&self) -> Option<PathBuf> {
+        match self.diff_state.current_file_hunk()?.file {
+            Some(name) => Some(PathBuf::from(".").join(name)),
+            None => self.file_tree.selected_file_path(),
+        }
+    }
+
+    /// The file, its parsed patch and the index of the hunk under the diff cursor
+    fn current_hunk(&self) -> Option<(PathBuf, PatchSource, git::patch::FilePatch, usize)> {
+        let hunk_idx = self.diff_state.current_file_hunk()?.index;
+        let path = self.current_hunk_path()?;
+        let source = self.patch_source_for(&path);
+        let patch = git::patch::generate_with_context(
+            &self.repo_path,
+            &source,
+            self.context_lines(),
+            self.ignore_whitespace,
+            self.config.diff.rename_detection(),
+        )
+        .ok()?;
+        let file = git::patch::parse(&String::from_utf8_lossy(&patch))
+            .into_iter()
+            .next()?;
+        (hunk_idx < file.hunks.len()).then_some((path, source, file, hunk_idx))
+    }
+
+    /// Stage or unstage the hunk under the diff cursor
+    pub fn apply_current_hunk(&mut self, action: HunkAction) -> Result<()> {
+        if !self.in_working_tree() {
+            self.message = Some(tr("Hunks can only be staged in the working tree").to_string());
+            return Ok(());
+        }
+        let showing_staged = self.diff_state.showing_staged;
+        match action {
+            HunkAction::Stage if showing_staged => {
+                self.message = Some(tr("Already staged; press s for unstaged changes").to_string());
+                return Ok(());
+            }
+            HunkAction::Unstage if !showing_staged => {
+                self.message = Some(tr("Not staged; press s for staged changes").to_string());
+                return Ok(());
+            }
+            _ if self.ignore_whitespace => {
+                self.message = Some(tr(IGNORING_WHITESPACE).to_string());
+                return Ok(());
+            }
+            _ => {}
+        }
+        let (Some(location), Some(path)) = (
+            self.diff_state.current_file_hunk(),
+            self.current_hunk_path(),
+        ) else {
+            self.message = Some(tr("No hunk under the cursor").to_string());
+            return Ok(());
+        };
+
+        let untracked = self.file_tree.get_file_status(&path) == Some(FileStatus::Untracked);
+        self.last_action = Some(match action {
+            HunkAction::Stage => RepeatAction::StageHunk,
+            HunkAction::Unstage => RepeatAction::UnstageHunk,
+        });
+        let result = git::stage::apply_hunk(
+            &self.repo_path,
+            &path,
+            untracked,
+            action,
+            location.index,
+            location.count,
+            self.context_lines(),
+        );
+        match result {
+            Ok(()) => {
+                hooks::run(
+                    &self.config.hooks,
+                    Hook::Stage,
+                    &self.repo_path,
+                    &[
+                        (
+                            "KIBITZ_FILE",
+                            path.strip_prefix(".")
+                                .unwrap_or(&path)
+                                .to_string_lossy()
+                                .into_owned(),
+                        ),
+                        (
+                            "KIBITZ_ACTION",
+                            match action {
+                                HunkAction::Stage => "stage",
+                                HunkAction::Unstage => "unstage",
+                            }
+                            .to_string(),
+                        ),
+                        ("KIBITZ_HUNK", (location.index + 1).to_string()),
+                    ],
+                );
+                self.refresh()?;
+            }
+            Err(e) => self.message = Some(e.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Why unstaged changes can't be discarded in the current view, if they can't
+    fn discard_blocker(&self) -> Option<&'static str> {
+        if !self.in_working_tree() {
+            Some("Only working tree changes can be discarded")
+        } else if self.diff_state.showing_staged {
+            Some("Staged changes can't be discarded; unstage them first")
+        } else {
+            None
+        }
+    }
+
+    /// Ask before discarding the unstaged hunk under the cursor
+    pub fn request_discard_hunk(&mut self) {
+        let blocker = self
+            .discard_blocker()
+            .or(self.ignore_whitespace.then_some(IGNORING_WHITESPACE));
+        if let Some(reason) = blocker {
+            self.message = Some(tr(reason).to_string());
+            return;
+        }
+        let (Some(location), Some(path)) = (
+            self.diff_state.current_file_hunk(),
+            self.current_hunk_path(),
+        ) else {
+            self.message = Some(tr("No hunk under the cursor").to_string());
+            return;
+        };
+
+        let untracked = self.file_tree.get_file_status(&path) == Some(FileStatus::Untracked);
+        let name = path
+            .strip_prefix(".")
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        // An untracked file is all one hunk, so discarding it removes the file
+        let (message, action) = if untracked {
+            (
+                tr_format("Delete untracked file {}?", &[&name]),
+                ConfirmAction::DiscardFile { path, untracked },
+            )
+        } else {
+            (
+                tr_format(
+                    "Discard hunk {}/{} of {}?",
+                    &[
+                        &(location.index + 1).to_string(),
+                        &location.count.to_string(),
+                        &name,
+                    ],
+                ),
+                ConfirmAction::DiscardHunk {
+                    path,
+                    index: location.index,
+                    count: location.count,
+                },
+            )
+        };
+        self.confirmation = Some(Confirmation {
+            title: tr("Discard hunk").to_string(),
+            message,
+            action,
+        });
+    }
+
+    /// Ask before discarding all unstaged changes of the selected file
+    pub fn request_discard_file(&mut self) {
+        if let Some(reason) = self.discard_blocker() {
+            self.message = Some(tr(reason).to_string());
+            return;
+        }
+        let Some(path) = self.file_tree.selected_file_path() else {
+            self.message = Some(tr("Select a file to discard").to_string());
+            return;
+        };
+        let status = self.file_tree.get_file_status(&path);
+        if status == Some(FileStatus::Staged) {
+            self.message = Some(tr("No unstaged changes to discard").to_string());
+            return;
+        }
+
+        let untracked = status == Some(FileStatus::Untracked);
+        let name = path
+            .strip_prefix(".")
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        let message = if untracked {
+            tr_format("Delete untracked file {}?", &[&name])
+        } else {
+            tr_format("Discard all unstaged changes to {}?", &[&name])
+        };
+        self.confirmation = Some(Confirmation {
+            title: tr("Discard changes").to_string(),
+
+            message,
+            action: ConfirmAction::DiscardFile { path, untracked },
+        });
+    }
+
+    /// Carry out the confirmed action
+    pub fn confirm(&mut self) -> Result<()> {
+        let Some(confirmation) = self.confirmation.take() else {
+            return Ok(());
+        };
+        let result = match confirmation.action {
+            ConfirmAction::DiscardFile { path, untracked } => {
+                self.last_action = Some(RepeatAction::DiscardFile);
+                git::restore::discard_file(&self.repo_path, &path, untracked)
+            }
+            ConfirmAction::DiscardHunk { path, index, count } => {
+                self.last_action = Some(RepeatAction::DiscardHunk);
+                let context = self.context_lines();
+                git::restore::discard_hunk(&self.repo_path, &path, index, count, context)
+            }
+            ConfirmAction::DropStash { index, oid } => {
+                return match git::stash::drop(&self.repo_path, index, &oid) {
+                    Ok(()) => self.finish_stash_change(
+                        tr_format("Dropped {}", &[&format!("stash@{{{index}}}")]),
+                        true,
+                    ),
+                    Err(e) => {
+                        self.message = Some(e.to_string());
+                        Ok(())
+                    }
+                };
+            }
+        };
+        match result {
+            Ok(()) => self.refresh()?,
+            Err(e) => self.message = Some(e.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Do the last working tree change again on the current hunk or file. Discards
+    /// still ask first.
+    pub fn repeat_last_action(&mut self) -> Result<()> {
+        match self.last_action {
+            Some(RepeatAction::StageHunk) => self.apply_current_hunk(HunkAction::Stage)?,
+            Some(RepeatAction::UnstageHunk) => self.apply_current_hunk(HunkAction::Unstage)?,
+            Some(RepeatAction::DiscardHunk) => self.request_discard_hunk(),
+            Some(RepeatAction::DiscardFile) => self.request_discard_file(),
+            None => self.message = Some(tr("Nothing to repeat yet").to_string()),
+        }
+        Ok(())
+    }
+
+    /// Run the plugin callback bound to `key`, if there is one. Returns whether a
+    /// plugin handled it.
+    pub fn run_plugin(&mut self, key: crossterm::event::KeyEvent) -> bool {
+        if !self.plugins.is_bound(key) {
+            return false;
+        }
+        let context = self.plugin_context();
+        match self.plugins.handle(key, &context) {
+            Ok(effects) => {
+                if let Err(e) = self.macros.feed(&effects.keys) {
+                    self.message = Some(e);
+                } else if effects.message.is_some() {
+                    self.message = effects.message;
+                }
+            }
+            Err(e) => self.message = Some(tr_format("Plugin error: {}", &[&e.to_string()])),
+        }
+        true
+    }
+
+    /// What plugins get to see of the current view
+    fn plugin_context(&self) -> plugin::Context {
+        let file = self.file_tree.selected_file_path();
+        let status = file
+            .as_ref()
+            .and_then(|path| self.file_tree.get_file_status(path));
+        let diff = file
+            .as_ref()
+            .and_then(|path| {
+                let source = self.patch_source_for(path);
+                git::patch::generate_with_context(
+                    &self.repo_path,
+                    &source,
+                    self.context_lines(),
+                    self.ignore_whitespace,
+                    self.config.diff.rename_detection(),
+                )
+                .ok()
+            })
+            .map(|patch| String::from_utf8_lossy(&patch).into_owned())
+            .unwrap_or_default();
+        plugin::Context {
+            repo: self.repo_path.clone(),
+            file: file.map(|path| path.strip_prefix(".").unwrap_or(&path).to_path_buf()),
+            status: status.map(FileStatus::name),
+            commit: self.current_commit.as_ref().map(|c| c.oid_full.clone()),
+            range: self.range.as_ref().map(|r| (r.from.clone(), r.to.clone())),
+            hunk: self
+                .diff_state
+                .current_file_hunk()
+                .map(|h| (h.index, h.count)),
+            diff,
+        }
+    }
+
+    /// Tell an editor following along where the current hunk is, when it has moved
+    fn follow_current_hunk(&mut self) {
+        let Some(follow_file) = &self.config.editor.follow_file else {
+            return;
+        };
+        let key = self
+            .current_hunk_path()
+            .zip(self.diff_state.current_file_hunk().map(|h| h.index));
+        if key.is_none() || key == self.followed {
+            return;
+        }
+        self.followed = key;
+        let Some((path, source, file, idx)) = self.current_hunk() else {
+            return;
+        };
+        let result = self
+            .editor_target(&path, &source, &file, &file.hunks[idx], false)
+            .and_then(|target| editor::write_follow_file(follow_file, &self.repo_path, &target));
+        if let Err(e) = result {
+            self.message = Some(e.to_string());
+        }
+    }
+
+    /// Copy the hunk under the cursor to the clipboard as a patch that `git
+    /// apply` takes on its own
+    pub fn copy_hunk_patch(&mut self) {
+        if self.ignore_whitespace {
+            self.message = Some(
+                tr("Hunks can't be copied while ignoring whitespace; press _ to show it")
+                    .to_string(),
+            );
+            return;
+        }
+        let Some((path, _, file, idx)) = self.current_hunk() else {
+            self.message = Some(tr("No hunk under the cursor").to_string());
+            return;
+        };
+        let Some(patch) = file.standalone_hunk_patch(idx) else {
+            return;
+        };
+        let name = path.strip_prefix(".").unwrap_or(&path).display();
+        self.message = Some(match clipboard::copy(&patch) {
+            Ok(()) => tr_format(
+                "Copied hunk {} of {} as a patch",
+                &[&(idx + 1).to_string(), &name.to_string()],
+            ),
+            Err(e) => tr_format("Couldn't copy the hunk: {}", &[&e.to_string()]),
+        });
+    }
+
+    /// Open the current hunk in the editor, on the new side (current file) or the
+    /// old side. The old version is written to a temp file if it's gone from the worktree.
+    pub fn open_in_editor(&mut self, old_side: bool) {
+        let target = match self.current_hunk() {
+            Some((path, source, file, idx)) => {
+                self.editor_target(&path, &source, &file, &file.hunks[idx], old_side)
+            }
+            // No hunks (e.g. binary or empty diff): just open the selected file
+            None => match self.file_tree.selected_file_path() {
+                Some(path) if !old_side => Ok(EditorTarget {
+                    path: self.repo_path.join(path),
+                    line: 1,
+                }),
+                _ => Err(anyhow::anyhow!("No hunk to open")),
+            },
+        };
+        match target {
+            Ok(target) => self.pending_editor = Some(target),
+            Err(e) => self.message = Some(e.to_string()),
+        }
+    }
+
+    fn editor_target(
+        &self,
+        path: &Path,
+        source: &PatchSource,
+        file: &git::patch::FilePatch,
+        hunk: &git::patch::Hunk,
+        old_side: bool,
+    ) -> Result<EditorTarget> {
+        let offset = hunk.leading_context();
+        if let PatchSource::Compare(comparisons) = source
+            && let Some(comparison) = comparisons.first()
+        {
+            let (point, start) = if old_side {
+                (&comparison.from, hunk.old_start)
+            } else {
+                (&comparison.to, hunk.new_start)
+            };
+            let path = match &point.rev {
+                Some(rev) => self.blobs.materialize(&self.repo_path, rev, &point.path)?,
+                None => self.repo_path.join(&point.path),
+            };
+            return Ok(EditorTarget {
+                path,
+                line: (start + offset).max(1),
+            });
+        }
+        if !old_side {
+            return Ok(EditorTarget {
+                path: self.repo_path.join(path),
+                line: hunk.new_start + offset,
+            });
+        }
+
+        let line = (hunk.old_start + offset).max(1);
+        let Some(old_path) = &file.old_path else {
+            anyhow::bail!("Added files have no old version");
+        };
+        let worktree_path = self.repo_path.join(old_path);
+        if worktree_path.exists() {
+            return Ok(EditorTarget {
+                path: worktree_path,
+                line,
+            });
+        }
+
+        let rev = match source {
+            PatchSource::Unstaged(_) => String::new(),
+            PatchSource::Staged(_) => "HEAD".to_string(),
+            PatchSource::Commit(oid, _) => format!("{oid}^"),
+            PatchSource::Range(from, _, _) => from.clone(),
+            PatchSource::Untracked(_) => anyhow::bail!("Untracked files have no old version"),
+            PatchSource::Compare(_) => anyhow::bail!("Nothing to compare"),
+        };
+        let path = self.blobs.materialize(&self.repo_path, &rev, old_path)?;
+        Ok(EditorTarget { path, line })
+    }
+
+    /// Go back one commit in history (deeper into history)
+    pub fn go_back_in_history(&mut self) -> Result<()> {
+        if let Some(index) = self.stash_index {
+            return self.view_stash_at(index + 1);
+        }
+        if self.file_log.is_some() {
+            return self.step_file_log(1);
+        }
+        let new_position = self.history_position + 1;
+        if self.load_history_position(new_position)? {
+            self.history_position = new_position;
+        } else if git::history::is_shallow(&self.repo_path) {
+            self.message =
+                Some(tr("History truncated (shallow clone); press D to fetch more").to_string());
+        }
+        Ok(())
+    }
+
+    /// Open the commit modal, prefilled with HEAD's message when amending
+    pub fn open_commit(&mut self, amend: bool) {
+        if self.current_commit.is_some() {
+            self.message = Some(tr("Return to the working tree (]) to commit").to_string());
+            return;
+        }
+        let message = if amend {
+            match git::commit::head_message(&self.repo_path) {
+                Ok(message) => TextArea::from_text(&message),
+                Err(_) => {
+                    self.message = Some(tr("No commit to amend").to_string());
+                    return;
+                }
+            }
+        } else {
+            TextArea::default()
+        };
+        self.commit_draft = Some(CommitDraft { amend, message });
+    }
+
+    /// Whether a commit from the modal is being created
+    pub fn is_committing(&self) -> bool {
+        self.pending_commit.is_some()
+    }
+
+    /// Create the commit written in the commit modal
+    pub fn submit_commit(&mut self) {
+        let Some(draft) = &self.commit_draft else {
+            return;
+        };
+        let message = draft.message.text();
+        if message.trim().is_empty() {
+            self.message = Some(tr("Commit message is empty").to_string());
+            return;
+        }
+        self.pending_commit = Some(git::commit::commit_async(
+            &self.repo_path,
+            &self.config.git,
+            self.git_available,
+            message,
+            draft.amend,
+        ));
+    }
+
+    pub fn open_prompt(&mut self, kind: PromptKind) {
+        self.prompt = Some(Prompt::new(kind));
+    }
+
+    /// Open the compare prompt, prefilled to compare the selected file with HEAD
+    pub fn open_compare_prompt(&mut self) {
+        let text = match &self.comparison {
+            Some(c) => format!("{} {}", c.from, c.to),
+            None => match self.file_tree.selected_file_path() {
+                Some(path) => {
+                    let path = path
+                        .strip_prefix(".")
+                        .unwrap_or(&path)
+                        .display()
+                        .to_string();
+                    format!("{path}@HEAD {path}")
+                }
+                None => String::new(),
+            },
+        };
+        self.prompt = Some(Prompt::with_text(PromptKind::Compare, &text));
+    }
+
+    /// Act on a submitted prompt
+    pub fn submit_prompt(&mut self, prompt: Prompt) -> Result<()> {
+        let text = prompt.input.text().trim();
+        match prompt.kind {
+            PromptKind::DateJump => self.jump_to_date(text),
+            PromptKind::RevJump => self.jump_to_rev(text),
+            PromptKind::Compare => self.start_comparison(text),
+            PromptKind::Stash => self.stash_changes(text),
+            PromptKind::OutputCommand => self.run_output_command(text),
+            PromptKind::Pickaxe => self.search_history(text),
+            PromptKind::CommitFilter => self.filter_history(text),
+            PromptKind::ReviewComment => {
+                self.draft_comment(text);
+                Ok(())
+            }
+            PromptKind::SubmitReview => {
+                self.submit_review(text);
+                Ok(())
+            }
+            PromptKind::DiffSearch => {
+                self.search_diff(text);
+                Ok(())
+            }
+            PromptKind::LineJump => {
+                self.jump_to_line(text);
+                Ok(())
+            }
+        }
+    }
+
+    /// Scroll the diff to line `text` of the new version of the file under the
+    /// cursor, or to the hunk nearest it when the line isn't in the diff
+    fn jump_to_line(&mut self, text: &str) {
+        let Ok(line) = text.parse::<usize>() else {
+            self.message = Some(tr_format("Not a line number: {}", &[text]));
+            return;
+        };
+        let Some((_, _, file, idx)) = self.current_hunk() else {
+            self.message = Some(tr("No hunk under the cursor").to_string());
+            return;
+        };
+        // The file's hunks are the ones around the current hunk's marker
+        let first = self.diff_state.current_hunk - idx;
+        let Some(markers) = self
+            .diff_state
+            .hunk_marker_positions
+            .get(first..first + file.hunks.len())
+        else {
+            return;
+        };
+        let hunks: Vec<_> = file.hunks.iter().map(|hunk| (&file, hunk)).collect();
+        let numbers = git::diff::number_lines(&self.diff_state.content, &hunks, markers);
+        if let Some(pos) = numbers.iter().position(|&(_, new)| new == Some(line)) {
+            self.diff_state.scroll_to_line(pos);
+            return;
+        }
+        let distance = |range: std::ops::Range<usize>| {
+            if line < range.start {
+                range.start - line
+            } else {
+                line.saturating_sub(range.end.saturating_sub(1))
+            }
+        };
+        let nearest = (0..file.hunks.len()).min_by_key(|&i| distance(file.hunks[i].new_range()));
+        if let Some(nearest) = nearest {
+            self.diff_state.select_hunk(first + nearest);
+            self.message = Some(tr_format(
+                "Line {} isn't in the diff; showing the hunk nearest it",
+                &[&line.to_string()],
+            ));
+        }
+    }
+
+    /// Highlight `query` in the diff and jump to its first hit below the top
+    fn search_diff(&mut self, query: &str) {
+        if query.is_empty() {
+            self.diff_state.search = None;
+            return;
+        }
+        self.diff_state.set_search(query.to_string());
+        self.next_search_match(true);
+    }
+
+    /// `n`/`N`: jump between the hits of the last diff search
+    pub fn next_search_match(&mut self, forward: bool) {
+        let Some(query) = self.diff_state.search.as_ref().map(|s| s.query.clone()) else {
+            self.message = Some(tr("No search yet; press / to search the diff").to_string());
+            return;
+        };
+        match self.diff_state.next_match(forward) {
+            None => self.message = Some(tr_format("Pattern not found: {}", &[&query])),
+            Some(true) if forward => {
+                self.message = Some(tr("Search hit BOTTOM, continuing at TOP").to_string())
+            }
+            Some(true) => {
+                self.message = Some(tr("Search hit TOP, continuing at BOTTOM").to_string())
+            }
+            Some(false) => {}
+        }
+    }
+
+    /// Show the diff between the two points in `text` (see `Comparison::parse`).
+    /// Directories get a tree of the files that differ between them.
+    fn start_comparison(&mut self, text: &str) -> Result<()> {
+        let selected = self.file_tree.selected_file_path();
+        let Some(mut comparison) = Comparison::parse(text, selected.as_deref()) else {
+            self.message =
+                Some(tr("Expected two points like src/app.rs@HEAD~5 src/app.rs").to_string());
+            return Ok(());
+        };
+
+        comparison.dirs = [&comparison.from, &comparison.to]
+            .iter()
+            .any(|point| git::compare::is_dir(&self.repo_path, point));
+        if comparison.dirs {
+            return self.start_dir_comparison(comparison);
+        }
+
+        // Check both sides up front; a failed diff would just show up empty
+        for point in [&comparison.from, &comparison.to] {
+            if let Err(e) = git::blob::read_point(&self.repo_path, point) {
+                self.message = Some(e.to_string());
+                return Ok(());
+            }
+        }
+        self.comparison = Some(comparison);
+        self.request_diff();
+        Ok(())
+    }
+
+    /// Replace the tree with the files that differ between two directories
+    fn start_dir_comparison(&mut self, comparison: Comparison) -> Result<()> {
+        let files = match git::compare::changed_files(&self.repo_path, &comparison) {
+            Ok(files) if files.is_empty() => {
+                self.message = Some(tr("No differences").to_string());
+                return Ok(());
+            }
+            Ok(files) => files,
+            Err(e) => {
+                self.message = Some(e.to_string());
+                return Ok(());
+            }
+        };
+        self.set_tree(FileTree::from_commit_files(files));
+        self.comparison = Some(comparison);
+        self.request_diff();
+        Ok(())
+    }
+
+    /// Leave the comparison and go back to the regular diff (and tree)
+    pub fn end_comparison(&mut self) -> Result<()> {
+        match self.comparison.take() {
+            Some(c) if c.dirs => match self.range.clone() {
+                Some(range) => self.show_range(range)?,
+                None => {
+                    self.load_history_position(self.history_position)?;
+                }
+            },
+            Some(_) => self.request_diff(),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Files to show in the tree for the current mode
+    /// Show `tree` in the file tree, noting which of its files are generated
+    fn set_tree(&mut self, tree: FileTree) {
+        let tree = if self.group_by_package {
+            tree.grouped_by_package(&self.packages)
+        } else {
+            tree
+        };
+        let files = tree.file_paths();
+        let generated = git::attributes::generated_files(&self.repo_path, &files);
+        let owners = match git::owners::CodeOwners::load(&self.repo_path) {
+            Some(code_owners) => files
+                .into_iter()
+                .filter_map(|file| {
+                    let owners = code_owners.owners(file.strip_prefix(".").unwrap_or(&file));
+                    (!owners.is_empty()).then(|| (file.clone(), owners.to_vec()))
+                })
+                .collect(),
+            None => HashMap::new(),
+        };
+        self.file_tree = tree.with_generated(generated).with_owners(owners);
+        if let Some(churn) = &self.churn {
+            self.file_tree.set_churn(&churn.files);
+        }
+    }
+
+    fn load_tree(&self) -> Result<FileTree> {
+        match (&self.comparison, &self.current_commit) {
+            (Some(c), _) if c.dirs => Ok(FileTree::from_commit_files(git::compare::changed_files(
+                &self.repo_path,
+                c,
+            )?)),
+            _ if let Some(range) = &self.range => git::range::changed_files(
+                &self.repo_path,
+                range,
+                self.config.diff.rename_detection(),
+            ),
+            // A file history shows just that file, under its name at the time
+            (_, Some(commit)) if let Some(path) = self.file_log_path() => {
+                let files = git::history::get_commit_files(
+                    &self.repo_path,
+                    &commit.oid_full,
+                    self.config.diff.rename_detection(),
+                )?;
+                let tree_path = Path::new(".").join(path);
+                let status = files
+                    .get_file_status(&tree_path)
+                    .unwrap_or(FileStatus::Modified);
+                let renames = files.renamed_from(&tree_path).map(|old| {
+                    (
+                        path.to_path_buf(),
+                        old.strip_prefix(".").unwrap_or(old).into(),
+                    )
+                });
+                let mut tree = FileTree::from_commit_files(vec![(path.to_path_buf(), status)])
+                    .with_renames(renames.into_iter().collect());
+                tree.reveal(&tree_path);
+                Ok(tree)
+            }
+            // Commits and stashes don't move when the working tree changes
+            (_, Some(commit)) => git::history::get_commit_files(
+                &self.repo_path,
+                &commit.oid_full,
+                self.config.diff.rename_detection(),
+            ),
+            _ => Ok(self.worktree_tree(git::status::get_status(&self.repo_path)?)),
+        }
+    }
+
+    /// The tree of the working tree's changes in `status`
+    fn worktree_tree(&self, mut status: git::status::GitStatusResult) -> FileTree {
+        let renames = git::status::pair_staged_renames(
+            &self.repo_path,
+            &mut status,
+            self.config.diff.rename_detection(),
+        )
+        .unwrap_or_default();
+        match &self.focus_file {
+            Some(focus) => {
+                let (files, _) = status;
+                let files = files.into_iter().filter(|(path, _)| path == focus);
+                let mut tree = FileTree::from_commit_files(files.collect());
+                tree.reveal(&Path::new(".").join(focus));
+                tree
+            }
+            None => FileTree::from_status(status, self.group_by_status).with_renames(renames),
+        }
+    }
+
+    /// The file's path in the file history entry being shown
+    fn file_log_path(&self) -> Option<&Path> {
+        self.file_log.as_ref()?.selected_entry()?.path.as_deref()
+    }
+
+    /// Open the commit log pane, preselecting the commit being viewed
+    pub fn open_history_list(&mut self) -> Result<()> {
+        // Graph order differs from [ ] order around merges, but not by much
+        let pages = self.history_position / HISTORY_PAGE + 1;
+        let limit = pages * HISTORY_PAGE;
+        let entries = git::history::get_log(&self.repo_path, 0, limit)?;
+        if entries.is_empty() {
+            self.message = Some(tr("No commits yet").to_string());
+            return Ok(());
+        }
+        let mut list = HistoryList::new(entries, false);
+        list.complete = list.entries.len() < limit;
+        if let Some(commit) = &self.current_commit {
+            let viewing = list
+                .entries
+                .iter()
+                .position(|e| e.commit.oid_full == commit.oid_full);
+            list.selected = viewing.unwrap_or(0);
+        }
+        self.history_list = Some(list);
+        Ok(())
+    }
+
+    /// Move the selection in the commit log pane, loading more commits near the end
+    pub fn move_in_history_list(&mut self, delta: isize) -> Result<()> {
+        let Some(list) = self.history_list.as_mut() else {
+            return Ok(());
+        };
+        list.move_by(delta);
+        if list.wants_more(HISTORY_PAGE / 4) {
+            let more = git::history::get_log(&self.repo_path, list.entries.len(), HISTORY_PAGE)?;
+            list.complete = more.len() < HISTORY_PAGE;
+            list.extend(more);
+        }
+        Ok(())
+    }
+
+    /// Open the commit log pane for the selected file's history, following renames
+    pub fn open_file_history(&mut self) -> Result<()> {
+        let Some(path) = self.file_tree.selected_file_path() else {
+            self.message = Some(tr("Select a file to see its history").to_string());
+            return Ok(());
+        };
+        if !self.git_available {
+            self.message = Some(tr("File history needs the git CLI").to_string());
+            return Ok(());
+        }
+        let path = path.strip_prefix(".").unwrap_or(&path).to_path_buf();
+        let start = self.current_commit.as_ref().map(|c| c.oid_full.as_str());
+        let entries = match git::history::file_log(&self.repo_path, &self.config.git, start, &path)
+        {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.message = Some(e.to_string());
+                return Ok(());
+            }
+        };
+        if entries.is_empty() {
+            self.message = Some(tr_format(
+                "No commits touch {}",
+                &[&path.display().to_string()],
+            ));
+            return Ok(());
+        }
+        let mut list = HistoryList::for_file(path, entries);
+        if let Some(commit) = &self.current_commit {
+            let viewing = list
+                .entries
+                .iter()
+                .position(|e| e.commit.oid_full == commit.oid_full);
+            list.selected = viewing.unwrap_or(0);
+        }
+        self.history_list = Some(list);
+        Ok(())
+    }
+
+    /// List the commits from HEAD that add or remove `query` in the commit log pane
+    fn search_history(&mut self, query: &str) -> Result<()> {
+        if query.is_empty() {
+            return Ok(());
+        }
+        if !self.git_available {
+            self.message = Some(tr("Searching history needs the git CLI").to_string());
+            return Ok(());
+        }
+        let entries = match git::history::pickaxe(&self.repo_path, &self.config.git, query) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.message = Some(e.to_string());
+                return Ok(());
+            }
+        };
+        if entries.is_empty() {
+            self.message = Some(tr_format("No commits change {}", &[&format!("{query:?}")]));
+            return Ok(());
+        }
+        self.history_list = Some(HistoryList::for_search(query.to_string(), entries));
+        Ok(())
+    }
+
+    /// Narrow the commit log pane down to commits whose subject or author matches
+    /// `query`, searching all of history if it's only partly loaded
+    fn filter_history(&mut self, query: &str) -> Result<()> {
+        if query.is_empty() {
+            return Ok(());
+        }
+        let filter = match CommitFilter::parse(query) {
+            Ok(filter) => filter,
+            Err(e) => {
+                self.message = Some(e.to_string());
+                return Ok(());
+            }
+        };
+        let Some(list) = self.history_list.as_mut() else {
+            return Ok(());
+        };
+        let entries = if list.complete {
+            list.entries.clone()
+        } else {
+            git::history::search_log(&self.repo_path, &filter)?
+        };
+        if !entries.iter().any(|e| filter.matches(e)) {
+            self.message = Some(tr_format("No commits match {}", &[&format!("{query:?}")]));
+            return Ok(());
+        }
+        let selected = list.selected_entry().map(|e| e.commit.oid_full.clone());
+        list.entries = entries;
+        list.complete = true;
+        list.retain(query, &filter);
+        list.select(selected.as_deref());
+        Ok(())
+    }
+
+    /// Show the commit selected in the commit log pane
+    pub fn pick_history_entry(&mut self) -> Result<()> {
+        if self.history_list.as_ref().is_some_and(|l| l.file.is_some()) {
+            self.file_log = self.history_list.take();
+            return self.view_file_log_entry();
+        }
+        let Some(entry) = self
+            .history_list
+            .take()
+            .and_then(|list| list.entries.into_iter().nth(list.selected))
+        else {
+            return Ok(());
+        };
+        let Some(offset) = git::history::offset_of(&self.repo_path, &entry.commit.oid_full)? else {
+            return Ok(());
+        };
+        let position = offset + 1;
+        if self.load_history_position(position)? {
+            self.history_position = position;
+        }
+        Ok(())
+    }
+
+    /// Open the stash list, preselecting the stash being viewed
+    pub fn open_stash_picker(&mut self) -> Result<()> {
+        let entries = git::stash::list(&self.repo_path)?;
+        if entries.is_empty() {
+            self.message = Some(tr("No stashes").to_string());
+            return Ok(());
+        }
+        let selected = self.stash_index.unwrap_or(0);
+        self.stash_picker = Some(StashPicker::new(entries, selected));
+        Ok(())
+    }
+
+    /// View the stash selected in the stash list
+    pub fn pick_stash(&mut self) -> Result<()> {
+        let Some(entry) = self.stash_picker.take().and_then(|p| p.selected().cloned()) else {
+            return Ok(());
+        };
+        self.view_stash(&entry)
+    }
+
+    /// Stash the working tree and index, like `git stash push -m <message>`
+    fn stash_changes(&mut self, message: &str) -> Result<()> {
+        match git::stash::save(&self.repo_path, message) {
+            Ok(()) => {
+                self.finish_stash_change(tr("Stashed changes as stash@{0}").to_string(), true)
+            }
+            Err(e) => {
+                self.message = Some(e.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Apply the stash selected in the stash list, dropping it too if `pop`
+    pub fn apply_picked_stash(&mut self, pop: bool) -> Result<()> {
+        let Some(entry) = self.stash_picker.take().and_then(|p| p.selected().cloned()) else {
+            return Ok(());
+        };
+        if let Err(e) = git::stash::apply(&self.repo_path, entry.index, pop) {
+            self.message = Some(e.to_string());
+            return Ok(());
+        }
+        let template = if pop { "Popped {}" } else { "Applied {}" };
+        self.finish_stash_change(tr_format(template, &[&entry.name()]), pop)
+    }
+
+    /// Ask before dropping the stash selected in the stash list
+    pub fn request_drop_stash(&mut self) {
+        let Some(entry) = self.stash_picker.take().and_then(|p| p.selected().cloned()) else {
+            return;
+        };
+        self.confirmation = Some(Confirmation {
+            title: tr("Drop stash").to_string(),
+            message: tr_format("Drop {}: {}?", &[&entry.name(), &entry.message]),
+            action: ConfirmAction::DropStash {
+                index: entry.index,
+                oid: entry.oid,
+            },
+        });
+    }
+
+    /// Refresh after a stash operation. Indexes shift when the stash list changes,
+    /// so a stash being viewed is left.
+    fn finish_stash_change(&mut self, message: String, list_changed: bool) -> Result<()> {
+        if list_changed && self.stash_index.is_some() {
+            self.load_history_position(self.history_position)?;
+        }
+        self.refresh()?;
+        self.message = Some(message);
+        Ok(())
+    }
+
+    /// View stash@{index}, if it exists
+    fn view_stash_at(&mut self, index: usize) -> Result<()> {
+        let entries = git::stash::list(&self.repo_path)?;
+        match entries.into_iter().find(|e| e.index == index) {
+            Some(entry) => self.view_stash(&entry),
+            None => {
+                self.message = Some(tr("No older stash").to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Show a stash's changes like a commit, in place of the current view
+    fn view_stash(&mut self, entry: &StashEntry) -> Result<()> {
+        let commit = entry.commit_info();
+        let files = git::history::get_commit_files(
+            &self.repo_path,
+            &commit.oid_full,
+            self.config.diff.rename_detection(),
+        )?;
+        self.comparison = None;
+        self.range = None;
+        self.file_log = None;
+        self.set_tree(files);
+        self.commit_refs = None;
+        self.pending_refs = None;
+        self.current_commit = Some(commit);
+        self.stash_index = Some(entry.index);
+        self.request_diff();
+        Ok(())
+    }
+
+    /// Show the file history entry selected in `file_log`, with just that file
+    fn view_file_log_entry(&mut self) -> Result<()> {
+        let Some(entry) = self.file_log.as_ref().and_then(|l| l.selected_entry()) else {
+            return Ok(());
+        };
+        let commit = entry.commit.clone();
+        self.comparison = None;
+        self.stash_index = None;
+        self.range = None;
+        self.current_commit = Some(commit.clone());
+        let tree = self.load_tree()?;
+        self.set_tree(tree);
+        self.request_commit_refs(&commit.oid_full);
+        self.request_diff();
+        Ok(())
+    }
+
+    /// Step through the file history being browsed; older when `delta` is positive
+    fn step_file_log(&mut self, delta: isize) -> Result<()> {
+        let Some(list) = self.file_log.as_mut() else {
+            return Ok(());
+        };
+        let before = list.selected;
+        list.move_by(delta);
+        if list.selected == before {
+            let template = if delta > 0 {
+                "Oldest commit touching {}"
+            } else {
+                "Newest commit touching {}"
+            };
+            let file = list.file.as_deref().unwrap_or(Path::new("")).display();
+            self.message = Some(tr_format(template, &[&file.to_string()]));
+            return Ok(());
+        }
+        self.view_file_log_entry()
+    }
+
+    /// Go back to the history position the file history was opened from
+    pub fn leave_file_log(&mut self) -> Result<()> {
+        if self.file_log.is_some() {
+            self.load_history_position(self.history_position)?;
+        }
+        Ok(())
+    }
+
+    /// Go back to the history position the stash was opened from
+    pub fn leave_stash(&mut self) -> Result<()> {
+        if self.stash_index.is_some() {
+            self.load_history_position(self.history_position)?;
+        }
+        Ok(())
+    }
+
+    /// Show the commit a SHA, branch, tag or other revspec points at
+    fn jump_to_rev(&mut self, spec: &str) -> Result<()> {
+        if RevRange::split(spec).is_some() {
+            return match git::range::resolve(&self.repo_path, spec) {
+                Ok(range) => self.show_range(range),
+                Err(e) => {
+                    self.message = Some(e.to_string());
+                    Ok(())
+                }
+            };
+        }
+        let Ok(commit) = git::history::resolve_commit(&self.repo_path, spec) else {
+            self.message = Some(tr_format("Unknown revision: {}", &[spec]));
+            return Ok(());
+        };
+
+        // Within HEAD's history, [ and ] carry on from there
+        if let Some(offset) = git::history::offset_of(&self.repo_path, &commit.oid_full)? {
+            let position = offset + 1;
+            if self.load_history_position(position)? {
+                self.history_position = position;
+            }
+            return Ok(());
+        }
+
+        if let Some(entry) = self.nav_entry() {
+            self.nav.push(entry);
+        }
+        self.comparison = None;
+        self.stash_index = None;
+        self.range = None;
+        self.file_log = None;
+        self.message = Some(tr_format(
+            "{} isn't in HEAD's history; [ / ] return to it",
+            &[&commit.oid],
+        ));
+        self.show_commit(commit)
+    }
+
+    /// Show everything that changed between the two sides of a range, in place of
+    /// the current view
+    fn show_range(&mut self, range: RevRange) -> Result<()> {
+        let files = git::range::changed_files(
+            &self.repo_path,
+            &range,
+            self.config.diff.rename_detection(),
+        )?;
+        if files.is_empty() {
+            self.message = Some(tr_format("No differences in {}", &[&range.spec]));
+            return Ok(());
+        }
+        self.comparison = None;
+        self.stash_index = None;
+        self.file_log = None;
+        self.current_commit = None;
+        self.commit_refs = None;
+        self.pending_refs = None;
+        self.set_tree(files);
+        self.range = Some(range);
+        self.request_diff();
+        Ok(())
+    }
+
+    /// Show the branch's pull request as GitHub diffs it, or without one (or
+    /// its commits fetched) the branch's changes since it forked from the
+    /// default branch
+    pub fn start_review(&mut self) -> Result<()> {
+        if let Some(pr) = &self.pull_request
+            && let Some(merge_base) = &pr.merge_base
+            && let Ok(range) =
+                git::range::pull_request(&self.repo_path, pr.number, merge_base, &pr.head)
+        {
+            return self.show_range(range);
+        }
+        match git::range::review(&self.repo_path) {
+            Ok(range) => self.show_range(range),
+            Err(e) => {
+                self.message = Some(e.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Show what pushing would send: HEAD against where it forked from upstream
+    pub fn show_push(&mut self) -> Result<()> {
+        match git::range::push(&self.repo_path) {
+            Ok(range) if range.commits == 0 => {
+                self.message = Some(tr_format(
+                    "Nothing to push; {} is up to date",
+                    &[range.spec.trim_end_matches("...HEAD")],
+                ));
+
+                Ok(())
+            }
+            Ok(range) => self.show_range(range),
+            Err(e) => {
+                self.message = Some(e.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Leave whatever commit, range, stash, file history or comparison is being
+    /// browsed for the live working tree
+    pub fn return_to_working_tree(&mut self) -> Result<()> {
+        self.history_list = None;
+        self.stash_picker = None;
+        if self.in_working_tree() && self.stash_index.is_none() && self.file_log.is_none() {
+            self.message = Some(tr("Already at the working tree").to_string());
+            return Ok(());
+        }
+        self.show_blame = false;
+        self.visit_history_position(0, true)?;
+        self.history_position = 0;
+        self.message = Some(tr("Back to the working tree").to_string());
+        Ok(())
+    }
+
+    /// Go back to the history position the range was opened from
+    pub fn leave_range(&mut self) -> Result<()> {
+        if self.range.is_some() {
+            self.load_history_position(self.history_position)?;
+        }
+        Ok(())
+    }
+
+    /// Jump history to the first commit made before the given date
+    fn jump_to_date(&mut self, text: &str) -> Result<()> {
+        let Some(time) = git::history::parse_date(text, &chrono::Local::now()) else {
+            self.message = Some(tr_format("Couldn't parse date: {}", &[text]));
+            return Ok(());
+        };
+
+        match git::history::find_offset_before(&self.repo_path, time)? {
+            Some(offset) => {
+                let position = offset + 1;
+                if self.load_history_position(position)? {
+                    self.history_position = position;
+                }
+            }
+            None => self.message = Some(tr_format("No commits before {}", &[text])),
+        }
+        Ok(())
+    }
+
+    /// Find branches and tags containing a commit in the background
+    fn request_commit_refs(&mut self, oid: &str) {
+        self.commit_refs = None;
+        let (tx, rx) = mpsc::channel();
+        let repo_path = self.repo_path.clone();
+        let oid = oid.to_string();
+        std::thread::spawn(move || {
+            let refs = git::history::refs_containing(&repo_path, &oid).unwrap_or_default();
+            let _ = tx.send(refs);
+        });
+        self.pending_refs = Some(rx);
+    }
+
+    /// Fetch more history into a shallow clone in the background
+    pub fn deepen_history(&mut self) {
+        if self.pending_deepen.is_some() {
+            return;
+        }
+        if !git::history::is_shallow(&self.repo_path) {
+            self.message = Some(tr("Not a shallow clone").to_string());
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let repo_path = self.repo_path.clone();
+        let git = self.config.git.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send(git::history::deepen(&repo_path, &git, DEEPEN_BY));
+        });
+        self.pending_deepen = Some(rx);
+        self.message = Some(tr_format(
+            "Fetching {} more commits...",
+            &[&DEEPEN_BY.to_string()],
+        ));
+    }
+
+    /// Go forward one commit (toward working tree)
+    pub fn go_forward_in_history(&mut self) -> Result<()> {
+        if let Some(index) = self.stash_index {
+            if index == 0 {
+                self.message = Some(tr("Already at the newest stash").to_string());
+                return Ok(());
+            }
+            return self.view_stash_at(index - 1);
+        }
+        if self.file_log.is_some() {
+            return self.step_file_log(-1);
+        }
+        if self.history_position > 0 {
+            self.history_position -= 1;
+            self.load_history_position(self.history_position)?;
+        } else if self.current_commit.is_some() {
+            // A commit outside HEAD's history was shown over the working tree
+            self.load_history_position(0)?;
+        }
+        Ok(())
+    }
+
+    /// Load file tree for a history position. Returns false if position doesn't exist.
+    fn load_history_position(&mut self, position: usize) -> Result<bool> {
+        self.visit_history_position(position, true)
+    }
+
+    /// Load a history position. Moving to another commit puts the one left on
+    /// the navigation stack (when `record`) and returns to where the user last
+    /// was in the new one.
+    fn visit_history_position(&mut self, position: usize, record: bool) -> Result<bool> {
+        let commit = match position {
+            0 => None,
+            _ => match git::history::get_commit_at(&self.repo_path, position - 1)? {
+                Some(commit) => Some(commit),
+                None => return Ok(false),
+            },
+        };
+        let target = commit.as_ref().map(|c| c.oid_full.clone());
+        let leaving = self.nav_entry();
+        let moved = leaving.as_ref().is_none_or(|e| e.commit != target);
+        if record
+            && let Some(entry) = leaving
+            && moved
+        {
+            self.nav.push(entry);
+        }
+
+        self.comparison = None;
+        self.stash_index = None;
+        self.range = None;
+        self.file_log = None;
+        match commit {
+            Some(commit) => self.show_commit(commit)?,
+            None => {
+                self.current_commit = None;
+                self.commit_refs = None;
+                self.pending_refs = None;
+                let tree = self.load_tree()?;
+                self.set_tree(tree);
+                self.request_diff();
+            }
+        }
+        if moved && let Some(entry) = self.nav.spot(target.as_deref()).cloned() {
+            self.restore_spot(&entry);
+        }
+        Ok(true)
+    }
+
+    /// Where the user is, when that's the working tree or a plain commit
+    fn nav_entry(&self) -> Option<NavEntry> {
+        if self.comparison.is_some()
+            || self.stash_index.is_some()
+            || self.range.is_some()
+            || self.file_log.is_some()
+        {
+            return None;
+        }
+        Some(NavEntry {
+            commit: self.current_commit.as_ref().map(|c| c.oid_full.clone()),
+            file: self.file_tree.selected_tree_path(),
+            scroll: self.diff_state.scroll_offset,
+            hunk: self.diff_state.current_hunk,
+        })
+    }
+
+    /// Select the file `entry` had selected and scroll its diff back to where it was
+    fn restore_spot(&mut self, entry: &NavEntry) {
+        if let Some(file) = &entry.file
+            && self.file_tree.selected_tree_path().as_ref() != Some(file)
+            && self.file_tree.reveal(file)
+        {
+            self.request_diff();
+        }
+        self.restore_position = Some((entry.scroll, entry.hunk));
+    }
+
+    /// Return to the commit (or working tree) left most recently, as it was
+    pub fn go_back_in_nav(&mut self) -> Result<()> {
+        let Some(entry) = self.nav.pop() else {
+            self.message = Some(tr("Nowhere to go back to").to_string());
+            return Ok(());
+        };
+        self.go_to_spot(&entry)
+    }
+
+    /// Show the commit (or working tree) `entry` is in, as it was there
+    fn go_to_spot(&mut self, entry: &NavEntry) -> Result<()> {
+        let position = match &entry.commit {
+            None => Some(0),
+            Some(oid) => git::history::offset_of(&self.repo_path, oid)?.map(|offset| offset + 1),
+        };
+        if let Some(position) = position {
+            if self.visit_history_position(position, false)? {
+                self.history_position = position;
+            }
+        } else if let Some(oid) = &entry.commit {
+            // Shown over the working tree from outside HEAD's history
+            let commit = git::history::resolve_commit(&self.repo_path, oid)?;
+            self.comparison = None;
+            self.stash_index = None;
+            self.range = None;
+            self.file_log = None;
+            self.show_commit(commit)?;
+        }
+        self.restore_spot(entry);
+        Ok(())
+    }
+
+    /// Share the cursor with the follower, or move to wherever the leader is
+    fn sync_pair(&mut self) -> Result<()> {
+        match self.pair.as_ref().map(|pair| pair.role) {
+            Some(PairRole::Lead) => {
+                if let Some(entry) = self.nav_entry()
+                    && let Some(pair) = &mut self.pair
+                {
+                    pair.publish(entry);
+                }
+            }
+            Some(PairRole::Follow) => {
+                if let Some(entry) = self.pair.as_mut().and_then(PairLink::poll) {
+                    self.follow_spot(&entry)?;
+                }
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Move to the leader's spot. Scrolling within the diff already shown
+    /// applies right away rather than waiting for a reload.
+    fn follow_spot(&mut self, entry: &NavEntry) -> Result<()> {
+        let Some(current) = self.nav_entry() else {
+            return self.go_to_spot(entry);
+        };
+        if current.commit != entry.commit {
+            self.go_to_spot(entry)
+        } else if current.file != entry.file || self.pending_diff.is_some() {
+            self.restore_spot(entry);
+            Ok(())
+        } else {
+            self.diff_state.scroll_to_top();
+            self.diff_state.scroll_down(entry.scroll);
+            let last = self.diff_state.hunk_positions.len().saturating_sub(1);
+            self.diff_state.current_hunk = entry.hunk.min(last);
+            Ok(())
+        }
+    }
+
+    /// Load a commit's file tree and diff
+    fn show_commit(&mut self, commit: CommitInfo) -> Result<()> {
+        self.set_tree(git::history::get_commit_files(
+            &self.repo_path,
+            &commit.oid_full,
+            self.config.diff.rename_detection(),
+        )?);
+        self.request_commit_refs(&commit.oid_full);
+        self.current_commit = Some(commit);
+        self.request_diff();
+        Ok(())
+    }
+}
+
+/// Leave the TUI while `f` runs (e.g. an external editor), then restore it.
+/// The outer Result is for terminal errors, the inner one is `f`'s.
+fn with_terminal_suspended<T>(
+    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    f: impl FnOnce() -> Result<T>,
+) -> Result<Result<T>> {
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+
+    let result = f();
+
+    enable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        EnterAlternateScreen,
+        EnableMouseCapture
+    )?;
+    terminal.clear()?;
+    Ok(result)
+}
+
+/// Run `f` and say how long it took
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let value = f();
+    (value, start.elapsed())
+}
+
+/// Wait for a startup thread's result, passing on its panic
+fn join<T>(handle: std::thread::JoinHandle<T>) -> T {
+    handle
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
 }
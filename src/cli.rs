@@ -0,0 +1,131 @@
+use crate::config::LayoutMode;
+use crate::event::watcher::FileWatcher;
+use crate::git::{self, status::TreeState};
+use crate::pair::PairRole;
+use crate::serve;
+use anyhow::Result;
+use clap::Parser;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// What kibitz was asked to do on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// The interactive UI
+    Tui,
+    /// Exit with a code for the working tree state, without drawing anything
+    Check,
+    /// Block until the working tree is clean
+    WaitClean,
+    /// Serve a read-only web view of the changes
+    Serve,
+}
+
+/// Parsed command line
+#[derive(Debug, Clone, Parser)]
+#[command(
+    version,
+    about,
+    after_help = "Invalid arguments exit with code 3, as do errors in --check and --wait-clean."
+)]
+pub struct Args {
+    /// Start on this commit (SHA, branch, tag, HEAD~3, ...) instead of the working tree,
+    /// or on the changes between two: A..B (A to B) or A...B (B since it forked from A)
+    #[arg(value_name = "REVSPEC", conflicts_with = "file")]
+    pub rev: Option<String>,
+    /// Show and live-reload the diff of a single file, without the tree
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
+    /// File tree placement, overriding the config
+    #[arg(long, value_enum)]
+    pub layout: Option<LayoutMode>,
+    /// Start with the file tree hidden
+    #[arg(long)]
+    pub no_tree: bool,
+    /// Review the current branch: everything it changed since forking from the
+    /// default branch (origin/HEAD)
+    #[arg(long, conflicts_with_all = ["rev", "file"])]
+    pub review: bool,
+    /// Show the staged side first for files with both staged and unstaged changes
+    #[arg(long)]
+    pub staged: bool,
+    /// Share the selected file and scroll position with another kibitz on the
+    /// same repository: `lead` publishes them, `follow` keeps up
+    #[arg(long, value_enum, value_name = "ROLE")]
+    pub pair: Option<PairRole>,
+    /// Don't save review progress for crash recovery, like when another kibitz
+    /// is already running on the repository
+    #[arg(long)]
+    pub no_save: bool,
+    /// Read this config file instead of the global one (.kibitz.toml still applies)
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Exit with the working tree state: 0 clean, 1 dirty, 2 conflicted
+    #[arg(long, conflicts_with_all = ["wait_clean", "rev", "file", "review"])]
+    pub check: bool,
+    /// Block until the working tree is clean, then exit 0
+    #[arg(long, conflicts_with_all = ["rev", "file", "review"])]
+    pub wait_clean: bool,
+    /// Serve a read-only web view of the working tree's changes, on localhost
+    /// unless given another address. It reloads itself as files change.
+    #[arg(
+        long,
+        value_name = "ADDR",
+        num_args = 0..=1,
+        default_missing_value = serve::DEFAULT_ADDR,
+        conflicts_with_all = ["check", "wait_clean", "rev", "file", "review"]
+    )]
+    pub serve: Option<String>,
+}
+
+/// Exit code for errors, kept clear of the `--check` states
+pub const ERROR_EXIT_CODE: i32 = 3;
+
+impl Args {
+    /// Parse the process arguments. Help and version are printed here; invalid
+    /// arguments exit with `ERROR_EXIT_CODE`.
+    pub fn parse_or_exit() -> Self {
+        match Self::try_parse() {
+            Ok(args) => args,
+            Err(e) => {
+                let _ = e.print();
+                std::process::exit(if e.use_stderr() { ERROR_EXIT_CODE } else { 0 });
+            }
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        if self.check {
+            Mode::Check
+        } else if self.wait_clean {
+            Mode::WaitClean
+        } else if self.serve.is_some() {
+            Mode::Serve
+        } else {
+            Mode::Tui
+        }
+    }
+}
+
+/// Run a headless mode, returning the process exit code
+pub fn run_headless(mode: Mode, args: &Args) -> Result<i32> {
+    let repo_path = git::status::find_repo_root()?;
+    match mode {
+        Mode::Check => Ok(git::status::tree_state(&repo_path)?.exit_code()),
+        Mode::WaitClean => {
+            let (tx, rx) = mpsc::channel();
+            let _watcher = FileWatcher::new(&repo_path, tx)?;
+            // The watcher doesn't see changes deep in the working tree, so poll too
+            while git::status::tree_state(&repo_path)? != TreeState::Clean {
+                let _ = rx.recv_timeout(Duration::from_secs(2));
+            }
+            Ok(0)
+        }
+        Mode::Serve => serve::run(
+            &repo_path,
+            args.serve.as_deref().unwrap_or(serve::DEFAULT_ADDR),
+        ),
+        Mode::Tui => unreachable!("not a headless mode"),
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Put `text` on the system clipboard with the platform's copy command, or
+/// through the terminal (OSC 52) when there's none, like over SSH
+pub fn copy(text: &str) -> Result<()> {
+    if let Some(cmd) = copy_command()
+        && run(cmd, text).is_ok()
+    {
+        return Ok(());
+    }
+    osc52(text)
+}
+
+/// The copy command of the desktop kibitz runs on, if any
+fn copy_command() -> Option<Command> {
+    if cfg!(target_os = "macos") {
+        Some(Command::new("pbcopy"))
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        Some(Command::new("wl-copy"))
+    } else if std::env::var_os("DISPLAY").is_some() {
+        let mut cmd = Command::new("xclip");
+        cmd.args(["-selection", "clipboard"]);
+        Some(cmd)
+    } else {
+        None
+    }
+}
+
+fn run(mut cmd: Command, text: &str) -> Result<()> {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .context("No stdin")?
+        .write_all(text.as_bytes())?;
+    if !child.wait()?.success() {
+        bail!("Copy command failed");
+    }
+    Ok(())
+}
+
+/// Ask the terminal to set its clipboard. Terminals that don't support it
+/// ignore the sequence, so there's no telling whether it worked.
+fn osc52(text: &str) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", STANDARD.encode(text))?;
+    stdout.flush()?;
+    Ok(())
+}
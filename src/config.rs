@@ -1,9 +1,12 @@
+use crate::git::patch::RenameDetection;
+use anyhow::{Context, Result};
 use ratatui::style::Color;
 use serde::Deserialize;
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 /// Layout mode for the file tree
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
 #[serde(rename_all = "lowercase")]
 pub enum LayoutMode {
     #[default]
@@ -16,6 +19,14 @@ pub enum LayoutMode {
 pub struct LayoutConfig {
     pub mode: LayoutMode,
     pub max_rows: u16,
+    /// Group working tree files into Staged / Unstaged / Untracked / Conflicted
+    pub group_by_status: bool,
+    /// Give each Cargo or npm workspace package its own root in the tree
+    pub group_by_package: bool,
+    /// Most frames drawn per second; lower it over slow SSH links
+    pub max_fps: u16,
+    /// Language of the UI's text, like `de`; None follows the locale
+    pub language: Option<String>,
 }
 
 impl Default for LayoutConfig {
@@ -23,25 +34,261 @@ impl Default for LayoutConfig {
         Self {
             mode: LayoutMode::Vertical,
             max_rows: 5,
+            group_by_status: false,
+            group_by_package: false,
+            max_fps: 60,
+            language: None,
+        }
+    }
+}
+
+/// Git CLI configuration
+#[derive(Debug, Clone)]
+pub struct GitConfig {
+    /// Git executable used for diffs
+    pub binary: String,
+    /// Extra global flags placed before every git subcommand
+    /// Example: ["-c", "core.quotePath=false"]
+    pub extra_args: Vec<String>,
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            binary: "git".to_string(),
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+/// Signal the terminal gives for an event
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cue {
+    #[default]
+    None,
+    /// The terminal bell (BEL)
+    Bell,
+    /// An OSC 9 notification, which terminals like iTerm2, WezTerm and kitty
+    /// show on the desktop
+    Osc9,
+}
+
+/// Notifications about commits landing while kibitz runs
+#[derive(Debug, Clone, Default)]
+pub struct NotifyConfig {
+    /// Notify when HEAD or its upstream gains commits
+    pub enabled: bool,
+    /// Shell command run instead of the desktop notification.
+    /// Gets KIBITZ_REPO, KIBITZ_REF, KIBITZ_COUNT, KIBITZ_TITLE and KIBITZ_BODY.
+    pub command: Option<String>,
+    /// Terminal cue when HEAD or its upstream gains commits
+    pub commits_cue: Cue,
+    /// Terminal cue when a diff that kept us waiting finishes loading
+    pub diff_cue: Cue,
+}
+
+/// Diff pane options
+#[derive(Debug, Clone)]
+pub struct DiffConfig {
+    /// Show the commit that last touched each hunk's lines on its header
+    pub annotate_hunks: bool,
+    /// Start with kibitz's own side-by-side view instead of delta's (`|`)
+    pub side_by_side: bool,
+    /// Lines of a diff loaded before the rest waits for `A`; 0 for no limit
+    pub max_lines: usize,
+    /// Wrap long lines instead of cutting them off (`w`)
+    pub wrap: bool,
+    /// Show old and new line numbers beside delta's output (`#`)
+    pub line_numbers: bool,
+    /// Show git's word diff, or highlight changed words (`Ctrl-w`)
+    pub word_diff: bool,
+    /// Leave out whitespace-only changes and blank lines (`_`)
+    pub ignore_whitespace: bool,
+    /// Least similarity in percent for a deleted and an added file to show as
+    /// a rename (`-M`); 0 turns rename detection off
+    pub renames: u16,
+    /// Least similarity in percent for an added file to show as a copy of a
+    /// changed one (`-C`); 0 leaves copies undetected
+    pub copies: u16,
+    /// List the commits a submodule gained or lost below its change, like
+    /// `git diff --submodule=log`
+    pub submodule_log: bool,
+}
+
+impl DiffConfig {
+    pub fn rename_detection(&self) -> RenameDetection {
+        RenameDetection {
+            renames: self.renames,
+            copies: self.copies,
         }
     }
 }
 
+impl Default for DiffConfig {
+    fn default() -> Self {
+        Self {
+            annotate_hunks: false,
+            side_by_side: false,
+            max_lines: 20_000,
+            wrap: true,
+            line_numbers: false,
+            word_diff: false,
+            ignore_whitespace: false,
+            renames: RenameDetection::default().renames,
+            copies: RenameDetection::default().copies,
+            submodule_log: false,
+        }
+    }
+}
+
+/// Review workflow options
+#[derive(Debug, Clone)]
+pub struct ReviewConfig {
+    /// Quit (with status 0) once the working tree is clean
+    pub exit_when_clean: bool,
+    /// Print what was reviewed and the working tree's diffstat after quitting,
+    /// where it stays in the terminal's scrollback
+    pub exit_summary: bool,
+    /// Mark files by how often they changed before, and how often to fix bugs
+    pub churn_hints: bool,
+    /// Source and test file patterns `%` jumps between, `{}` standing for the
+    /// part they share
+    /// Example: ["src/{}.rs", "tests/{}.rs"]
+    pub test_pairs: Vec<(String, String)>,
+    /// Show the review comments of the branch's GitHub pull request on their
+    /// hunks (needs the `gh` CLI)
+    pub github_comments: bool,
+}
+
+impl Default for ReviewConfig {
+    fn default() -> Self {
+        let pair = |source: &str, test: &str| (source.to_string(), test.to_string());
+        Self {
+            exit_when_clean: false,
+            exit_summary: false,
+            churn_hints: false,
+            test_pairs: vec![
+                pair("src/{}.rs", "tests/{}.rs"),
+                pair("{}.ts", "{}.test.ts"),
+                pair("{}.ts", "{}.spec.ts"),
+                pair("{}.tsx", "{}.test.tsx"),
+                pair("{}.js", "{}.test.js"),
+                pair("{}.js", "{}.spec.js"),
+                pair("{}.jsx", "{}.test.jsx"),
+                pair("{}.go", "{}_test.go"),
+                pair("{}.py", "{}_test.py"),
+                pair("lib/{}.rb", "spec/{}_spec.rb"),
+                pair("app/{}.rb", "spec/{}_spec.rb"),
+                pair("src/main/{}.java", "src/test/{}Test.java"),
+            ],
+            github_comments: true,
+        }
+    }
+}
+
+/// Bottom pane tailing a command's output
+#[derive(Debug, Clone)]
+pub struct OutputConfig {
+    /// Shell command started when the pane is first shown
+    /// Example: "cargo watch -x test"
+    pub command: Option<String>,
+    /// Height of the pane in rows
+    pub height: u16,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            command: None,
+            height: 12,
+        }
+    }
+}
+
+/// Shell commands run on events, with KIBITZ_* variables describing them
+#[derive(Debug, Clone, Default)]
+pub struct HooksConfig {
+    /// After a commit or amend succeeds
+    pub on_commit: Option<String>,
+    /// After a hunk is staged or unstaged
+    pub on_stage: Option<String>,
+    /// When a different file is selected in the tree
+    pub on_file_selected: Option<String>,
+}
+
+/// Integration with an editor open next to kibitz
+#[derive(Debug, Clone, Default)]
+pub struct EditorConfig {
+    /// File rewritten with the current hunk's file and line whenever it changes,
+    /// for an editor to watch and follow along
+    pub follow_file: Option<PathBuf>,
+}
+
+/// User plugins
+#[derive(Debug, Clone, Default)]
+pub struct PluginConfig {
+    /// Lua scripts loaded at startup, global config's first
+    pub scripts: Vec<PathBuf>,
+    /// Repositories whose `.kibitz.toml` may add scripts and commands, from the
+    /// global config only: a repository can't vouch for itself
+    pub trusted_repos: Vec<PathBuf>,
+}
+
 /// Top-level configuration
 #[derive(Debug, Clone, Default)]
 pub struct Config {
-    pub delta: DeltaConfig,
+    pub formatter: FormatterConfig,
+    pub diff: DiffConfig,
     pub colors: ColorConfig,
     pub layout: LayoutConfig,
+    pub git: GitConfig,
+    pub notify: NotifyConfig,
+    pub review: ReviewConfig,
+    pub output: OutputConfig,
+    pub plugins: PluginConfig,
+    pub hooks: HooksConfig,
+    pub editor: EditorConfig,
+    /// Keys of the repository's `.kibitz.toml` left out because the repository
+    /// isn't trusted, like `plugins.scripts`
+    pub untrusted: Vec<&'static str>,
 }
 
-/// Delta pass-through configuration
-#[derive(Debug, Clone, Default, Deserialize)]
-#[serde(default)]
-pub struct DeltaConfig {
-    /// Additional args appended to delta command
-    /// Example: "--side-by-side --line-numbers"
-    pub args: Option<String>,
+/// External program diffs are piped through for display
+#[derive(Debug, Clone)]
+pub struct FormatterConfig {
+    /// `delta`, `diff-so-fancy`, `riff`, `bat`, or any command reading a patch
+    /// on stdin
+    pub command: String,
+    /// Additional args appended to each formatter's defaults, by formatter name
+    /// Example: delta = "--side-by-side --line-numbers"
+    pub args: HashMap<String, String>,
+}
+
+impl Default for FormatterConfig {
+    fn default() -> Self {
+        Self {
+            command: "delta".to_string(),
+            args: HashMap::new(),
+        }
+    }
+}
+
+impl FormatterConfig {
+    /// The formatter's program name without its directory or `.exe`, e.g. `delta`
+    pub fn name(&self) -> &str {
+        let program = self.command.split_whitespace().next().unwrap_or_default();
+        let file = program.rsplit(['/', '\\']).next().unwrap_or(program);
+        file.strip_suffix(".exe").unwrap_or(file)
+    }
+
+    /// The configured args for the formatter in use
+    pub fn user_args(&self) -> Option<&str> {
+        self.args
+            .get(self.name())
+            .map(String::as_str)
+            .filter(|args| !args.trim().is_empty())
+    }
 }
 
 /// Semantic color configuration
@@ -54,6 +301,7 @@ pub struct ColorConfig {
     pub warning: Color,
     pub error: Color,
     pub info: Color,
+    pub annotation: Color,
 }
 
 impl Default for ColorConfig {
@@ -66,6 +314,7 @@ impl Default for ColorConfig {
             warning: Color::Indexed(3),  // ANSI yellow (modified)
             error: Color::Indexed(1),    // ANSI red (deleted)
             info: Color::Indexed(6),     // ANSI cyan (renamed)
+            annotation: Color::DarkGray, // Last-touch annotations on hunk headers
         }
     }
 }
@@ -121,15 +370,121 @@ fn parse_hex_color(s: &str) -> Option<Color> {
 struct RawLayoutConfig {
     mode: Option<LayoutMode>,
     max_rows: Option<u16>,
+    group_by_status: Option<bool>,
+    group_by_package: Option<bool>,
+    max_fps: Option<u16>,
+    language: Option<String>,
+}
+
+/// Raw formatter config with optional fields for merging
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct RawFormatterConfig {
+    command: Option<String>,
+    args: Option<HashMap<String, String>>,
+}
+
+/// Raw `[delta]` section, kept working as the formatter args for delta
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct RawDeltaConfig {
+    args: Option<String>,
+}
+
+/// Raw git config with optional fields for merging
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct RawGitConfig {
+    binary: Option<String>,
+    extra_args: Option<Vec<String>>,
+}
+
+/// Raw notify config with optional fields for merging
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct RawNotifyConfig {
+    enabled: Option<bool>,
+    command: Option<String>,
+    commits_cue: Option<Cue>,
+    diff_cue: Option<Cue>,
+}
+
+/// Raw diff config with optional fields for merging
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct RawDiffConfig {
+    annotate_hunks: Option<bool>,
+    side_by_side: Option<bool>,
+    max_lines: Option<usize>,
+    wrap: Option<bool>,
+    line_numbers: Option<bool>,
+    word_diff: Option<bool>,
+    ignore_whitespace: Option<bool>,
+    renames: Option<u16>,
+    copies: Option<u16>,
+    submodule_log: Option<bool>,
+}
+
+/// Raw review config with optional fields for merging
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct RawReviewConfig {
+    exit_when_clean: Option<bool>,
+    exit_summary: Option<bool>,
+    churn_hints: Option<bool>,
+    test_pairs: Option<Vec<(String, String)>>,
+    github_comments: Option<bool>,
+}
+
+/// Raw output config with optional fields for merging
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct RawOutputConfig {
+    command: Option<String>,
+    height: Option<u16>,
+}
+
+/// Raw hooks config with optional fields for merging
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct RawHooksConfig {
+    on_commit: Option<String>,
+    on_stage: Option<String>,
+    on_file_selected: Option<String>,
+}
+
+/// Raw editor config with optional fields for merging
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct RawEditorConfig {
+    follow_file: Option<PathBuf>,
+}
+
+/// Raw plugin config with optional fields for merging
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct RawPluginConfig {
+    scripts: Option<Vec<PathBuf>>,
+    trusted_repos: Option<Vec<PathBuf>>,
 }
 
 /// Raw config as parsed from TOML (uses Option for merge semantics)
 #[derive(Debug, Clone, Default, Deserialize)]
 #[serde(default)]
 struct RawConfig {
-    delta: Option<DeltaConfig>,
+    formatter: Option<RawFormatterConfig>,
+    /// `[delta] args` from before other formatters were supported
+    delta: Option<RawDeltaConfig>,
+    diff: Option<RawDiffConfig>,
     colors: Option<RawColorConfig>,
     layout: Option<RawLayoutConfig>,
+    git: Option<RawGitConfig>,
+    notify: Option<RawNotifyConfig>,
+    review: Option<RawReviewConfig>,
+    output: Option<RawOutputConfig>,
+    plugins: Option<RawPluginConfig>,
+    hooks: Option<RawHooksConfig>,
+    editor: Option<RawEditorConfig>,
 }
 
 /// Raw color config with optional fields for merging
@@ -143,15 +498,64 @@ struct RawColorConfig {
     warning: Option<ColorValue>,
     error: Option<ColorValue>,
     info: Option<ColorValue>,
+    annotation: Option<ColorValue>,
+}
+
+impl RawConfig {
+    /// Take out the keys that run commands or write files, which only a trusted
+    /// repository's config may set. Returns the names of those that were set.
+    fn take_untrusted(&mut self) -> Vec<&'static str> {
+        fn take<T>(taken: &mut Vec<&'static str>, key: &'static str, value: &mut Option<T>) {
+            if value.take().is_some() {
+                taken.push(key);
+            }
+        }
+        let mut taken = Vec::new();
+        if let Some(plugins) = &mut self.plugins {
+            take(&mut taken, "plugins.scripts", &mut plugins.scripts);
+        }
+        if let Some(git) = &mut self.git {
+            take(&mut taken, "git.binary", &mut git.binary);
+            take(&mut taken, "git.extra_args", &mut git.extra_args);
+        }
+        if let Some(hooks) = &mut self.hooks {
+            take(&mut taken, "hooks.on_commit", &mut hooks.on_commit);
+            take(&mut taken, "hooks.on_stage", &mut hooks.on_stage);
+            take(
+                &mut taken,
+                "hooks.on_file_selected",
+                &mut hooks.on_file_selected,
+            );
+        }
+        if let Some(notify) = &mut self.notify {
+            take(&mut taken, "notify.command", &mut notify.command);
+        }
+        if let Some(output) = &mut self.output {
+            take(&mut taken, "output.command", &mut output.command);
+        }
+        if let Some(formatter) = &mut self.formatter {
+            take(&mut taken, "formatter.command", &mut formatter.command);
+        }
+        if let Some(editor) = &mut self.editor {
+            take(&mut taken, "editor.follow_file", &mut editor.follow_file);
+        }
+        taken
+    }
 }
 
 impl Config {
-    /// Load configuration, merging global and local configs
-    pub fn load(repo_path: &Path) -> Self {
+    /// Load configuration, merging global and local configs. `explicit` (from
+    /// `--config`) replaces the global config and must be readable.
+    pub fn load(repo_path: &Path, explicit: Option<&Path>) -> Result<Self> {
         let mut config = Config::default();
 
+        if let Some(path) = explicit {
+            let raw = Self::load_file(path)
+                .map_err(|e| anyhow::anyhow!("{e}"))
+                .with_context(|| format!("Couldn't load config {}", path.display()))?;
+            config.merge(raw);
         // Load global config
-        if let Some(global_path) = Self::global_config_path()
+        } else if let Some(global_path) = Self::global_config_path()
             && let Ok(raw) = Self::load_file(&global_path)
         {
             config.merge(raw);
@@ -159,11 +563,23 @@ impl Config {
 
         // Load local config (overrides global)
         let local_path = repo_path.join(".kibitz.toml");
-        if let Ok(raw) = Self::load_file(&local_path) {
+        if let Ok(mut raw) = Self::load_file(&local_path) {
+            if let Some(plugins) = &mut raw.plugins {
+                plugins.trusted_repos = None;
+            }
+            // Cloning a repository and running kibitz in it mustn't run its code
+            let canonical = |path: &Path| path.canonicalize().ok();
+            let trusted =
+                config.plugins.trusted_repos.iter().any(|repo| {
+                    canonical(repo).is_some() && canonical(repo) == canonical(repo_path)
+                });
+            if !trusted {
+                config.untrusted = raw.take_untrusted();
+            }
             config.merge(raw);
         }
 
-        config
+        Ok(config)
     }
 
     fn global_config_path() -> Option<std::path::PathBuf> {
@@ -172,14 +588,34 @@ impl Config {
 
     fn load_file(path: &Path) -> Result<RawConfig, Box<dyn std::error::Error>> {
         let contents = std::fs::read_to_string(path)?;
-        Ok(toml::from_str(&contents)?)
+        let mut raw: RawConfig = toml::from_str(&contents)?;
+        // Paths are relative to the config file naming them
+        let dir = path.parent().unwrap_or(Path::new(""));
+        if let Some(plugins) = raw.plugins.as_mut() {
+            let paths = plugins.scripts.iter_mut().chain(&mut plugins.trusted_repos);
+            for path in paths.flatten() {
+                *path = resolve_path(path, dir);
+            }
+        }
+        if let Some(file) = raw.editor.as_mut().and_then(|e| e.follow_file.as_mut()) {
+            *file = resolve_path(file, dir);
+        }
+        Ok(raw)
     }
 
     fn merge(&mut self, raw: RawConfig) {
         if let Some(delta) = raw.delta
-            && delta.args.is_some()
+            && let Some(args) = delta.args
         {
-            self.delta.args = delta.args;
+            self.formatter.args.insert("delta".to_string(), args);
+        }
+        if let Some(formatter) = raw.formatter {
+            if let Some(command) = formatter.command {
+                self.formatter.command = command;
+            }
+            if let Some(args) = formatter.args {
+                self.formatter.args.extend(args);
+            }
         }
 
         if let Some(colors) = raw.colors {
@@ -195,6 +631,40 @@ impl Config {
             apply(&mut self.colors.warning, colors.warning);
             apply(&mut self.colors.error, colors.error);
             apply(&mut self.colors.info, colors.info);
+            apply(&mut self.colors.annotation, colors.annotation);
+        }
+
+        if let Some(diff) = raw.diff {
+            if let Some(annotate_hunks) = diff.annotate_hunks {
+                self.diff.annotate_hunks = annotate_hunks;
+            }
+            if let Some(side_by_side) = diff.side_by_side {
+                self.diff.side_by_side = side_by_side;
+            }
+            if let Some(max_lines) = diff.max_lines {
+                self.diff.max_lines = max_lines;
+            }
+            if let Some(wrap) = diff.wrap {
+                self.diff.wrap = wrap;
+            }
+            if let Some(line_numbers) = diff.line_numbers {
+                self.diff.line_numbers = line_numbers;
+            }
+            if let Some(word_diff) = diff.word_diff {
+                self.diff.word_diff = word_diff;
+            }
+            if let Some(ignore_whitespace) = diff.ignore_whitespace {
+                self.diff.ignore_whitespace = ignore_whitespace;
+            }
+            if let Some(renames) = diff.renames {
+                self.diff.renames = renames.min(100);
+            }
+            if let Some(copies) = diff.copies {
+                self.diff.copies = copies.min(100);
+            }
+            if let Some(submodule_log) = diff.submodule_log {
+                self.diff.submodule_log = submodule_log;
+            }
         }
 
         if let Some(layout) = raw.layout {
@@ -204,6 +674,187 @@ impl Config {
             if let Some(max_rows) = layout.max_rows {
                 self.layout.max_rows = max_rows;
             }
+            if let Some(group_by_status) = layout.group_by_status {
+                self.layout.group_by_status = group_by_status;
+            }
+            if let Some(group_by_package) = layout.group_by_package {
+                self.layout.group_by_package = group_by_package;
+            }
+            if let Some(max_fps) = layout.max_fps {
+                self.layout.max_fps = max_fps.max(1);
+            }
+            if let Some(language) = layout.language {
+                self.layout.language = Some(language);
+            }
         }
+
+        if let Some(git) = raw.git {
+            if let Some(binary) = git.binary {
+                self.git.binary = binary;
+            }
+            if let Some(extra_args) = git.extra_args {
+                self.git.extra_args = extra_args;
+            }
+        }
+
+        if let Some(notify) = raw.notify {
+            if let Some(enabled) = notify.enabled {
+                self.notify.enabled = enabled;
+            }
+            if let Some(command) = notify.command {
+                self.notify.command = Some(command);
+            }
+            if let Some(commits_cue) = notify.commits_cue {
+                self.notify.commits_cue = commits_cue;
+            }
+            if let Some(diff_cue) = notify.diff_cue {
+                self.notify.diff_cue = diff_cue;
+            }
+        }
+
+        if let Some(review) = raw.review {
+            if let Some(exit_when_clean) = review.exit_when_clean {
+                self.review.exit_when_clean = exit_when_clean;
+            }
+            if let Some(exit_summary) = review.exit_summary {
+                self.review.exit_summary = exit_summary;
+            }
+            if let Some(churn_hints) = review.churn_hints {
+                self.review.churn_hints = churn_hints;
+            }
+            if let Some(test_pairs) = review.test_pairs {
+                self.review.test_pairs = test_pairs;
+            }
+            if let Some(github_comments) = review.github_comments {
+                self.review.github_comments = github_comments;
+            }
+        }
+
+        if let Some(output) = raw.output {
+            if let Some(command) = output.command {
+                self.output.command = Some(command);
+            }
+            if let Some(height) = output.height {
+                self.output.height = height;
+            }
+        }
+
+        if let Some(hooks) = raw.hooks {
+            if let Some(command) = hooks.on_commit {
+                self.hooks.on_commit = Some(command);
+            }
+            if let Some(command) = hooks.on_stage {
+                self.hooks.on_stage = Some(command);
+            }
+            if let Some(command) = hooks.on_file_selected {
+                self.hooks.on_file_selected = Some(command);
+            }
+        }
+
+        if let Some(file) = raw.editor.and_then(|e| e.follow_file) {
+            self.editor.follow_file = Some(file);
+        }
+
+        // Both global and trusted repo plugins load
+        if let Some(plugins) = raw.plugins {
+            if let Some(scripts) = plugins.scripts {
+                self.plugins.scripts.extend(scripts);
+            }
+            if let Some(repos) = plugins.trusted_repos {
+                self.plugins.trusted_repos.extend(repos);
+            }
+        }
+    }
+}
+
+/// A path from a config file: `~/` is the home directory, and relative paths
+/// start at `dir`
+fn resolve_path(path: &Path, dir: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        Err(_) => dir.join(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load `local` as a repository's `.kibitz.toml`, under a global config
+    /// that trusts the repository or not
+    fn load(name: &str, local: &str, trusted: bool) -> Config {
+        let dir = std::env::temp_dir().join(format!("kibitz-{name}-{}", std::process::id()));
+        let repo = dir.join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        std::fs::write(repo.join(".kibitz.toml"), local).unwrap();
+        let global = dir.join("config.toml");
+        let repos = if trusted { "[\"repo\"]" } else { "[]" };
+        std::fs::write(&global, format!("[plugins]\ntrusted_repos = {repos}\n")).unwrap();
+        let config = Config::load(&repo, Some(&global)).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        config
+    }
+
+    const RUNS_CODE: &str = r#"
+[plugins]
+scripts = ["evil.lua"]
+trusted_repos = ["/"]
+
+[git]
+binary = "./payload"
+extra_args = ["-c", "core.pager=./payload"]
+
+[hooks]
+on_commit = "./payload"
+on_stage = "./payload"
+on_file_selected = "./payload"
+
+[notify]
+command = "./payload"
+
+[output]
+command = "./payload"
+
+[formatter]
+command = "./payload"
+
+[editor]
+follow_file = "~/.bashrc"
+
+[layout]
+max_rows = 9
+"#;
+
+    #[test]
+    fn an_untrusted_repository_cant_run_code() {
+        let config = load("untrusted", RUNS_CODE, false);
+        assert_eq!(
+            config.untrusted,
+            [
+                "plugins.scripts",
+                "git.binary",
+                "git.extra_args",
+                "hooks.on_commit",
+                "hooks.on_stage",
+                "hooks.on_file_selected",
+                "notify.command",
+                "output.command",
+                "formatter.command",
+                "editor.follow_file"
+            ]
+        );
+        assert!(config.plugins.scripts.is_empty());
+        assert_eq!(config.git.binary, "git");
+        assert!(config.editor.follow_file.is_none());
+        assert_eq!(config.layout.max_rows, 9);
+    }
+
+    #[test]
+    fn a_trusted_repository_can() {
+        let config = load("trusted", RUNS_CODE, true);
+        assert!(config.untrusted.is_empty());
+        assert_eq!(config.plugins.scripts.len(), 1);
+        // Still can't trust others
+        assert_eq!(config.plugins.trusted_repos.len(), 1);
     }
 }
@@ -0,0 +1,75 @@
+use crate::persist;
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A file and line to open in the user's editor
+#[derive(Debug, Clone)]
+pub struct EditorTarget {
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+/// Editor used when neither `$VISUAL` nor `$EDITOR` is set
+const DEFAULT_EDITOR: &str = if cfg!(windows) { "notepad" } else { "vi" };
+
+/// Open the target in `$VISUAL` / `$EDITOR` (falling back to vi, or notepad on
+/// Windows) and wait for it to exit
+pub fn open(target: &EditorTarget) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().context("Editor command is empty")?;
+
+    let mut cmd = Command::new(program);
+    cmd.args(parts);
+
+    let path = target.path.to_string_lossy();
+    let name = std::path::Path::new(program)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // Editors disagree on how to pass a line number
+    match name.as_str() {
+        "code" | "code-insiders" | "codium" | "cursor" => {
+            cmd.args(["--goto", &format!("{}:{}", path, target.line)]);
+        }
+        "hx" | "helix" | "zed" | "subl" => {
+            cmd.arg(format!("{}:{}", path, target.line));
+        }
+        // Notepad can't jump to a line
+        "notepad" | "notepad.exe" => {
+            cmd.arg(path.as_ref());
+        }
+        _ => {
+            cmd.arg(format!("+{}", target.line)).arg(path.as_ref());
+        }
+    }
+
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run editor {program}"))?;
+    if !status.success() {
+        bail!("Editor exited with {status}");
+    }
+    Ok(())
+}
+
+/// Write the target to `follow_file` for an editor following along, as
+/// `key=value` lines: `file` (absolute), `path` (relative to the repo, when
+/// inside it) and `line`
+pub fn write_follow_file(
+    follow_file: &Path,
+    repo_path: &Path,
+    target: &EditorTarget,
+) -> Result<()> {
+    // Collecting the components drops the `./` tree paths carry
+    let file: PathBuf = target.path.components().collect();
+    let mut contents = format!("file={}\n", file.display());
+    if let Ok(relative) = file.strip_prefix(repo_path) {
+        contents.push_str(&format!("path={}\n", relative.display()));
+    }
+    contents.push_str(&format!("line={}\n", target.line));
+    persist::atomic_write(follow_file, contents)
+}
@@ -1,19 +1,27 @@
 //! Keybinding definitions and event handling.
 //!
-//! The KEYBINDINGS array and handle_key() are co-located in this file
-//! to help keep them in sync. When adding or changing keybindings,
-//! update both the KEYBINDINGS array (for help display) and the
-//! handle_key() match arms (for actual handling).
+//! KEYBINDINGS maps each key of the main screen to an `Action`. handle_key()
+//! looks keys up in it and the help popup lists it, so a binding can't be in
+//! one and missing from the other; `perform` carries out each action.
 
 use crate::app::App;
 use crate::config::LayoutMode;
+use crate::git::stage::HunkAction;
+use crate::i18n::tr_format;
+use crate::model::PromptKind;
 use anyhow::Result;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent, MouseEventKind};
 
+/// Lines moved per `{` / `}` press in the output pane
+const OUTPUT_SCROLL_LINES: usize = 5;
+/// Columns moved per Alt-h / Alt-l press in the diff
+const HORIZONTAL_SCROLL_COLUMNS: usize = 8;
+
 // ============================================================================
-// Keybinding definitions for help display
+// Keybinding definitions
 // ============================================================================
 
+use Action::*;
 use KeyCategory::*;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -23,6 +31,7 @@ pub enum KeyCategory {
     DiffScrolling,
     Toggles,
     History,
+    Macros,
 }
 
 impl KeyCategory {
@@ -33,96 +42,712 @@ impl KeyCategory {
             DiffScrolling => "Diff Scrolling",
             Toggles => "Toggles",
             History => "History",
+            Macros => "Macros",
+        }
+    }
+}
+
+/// What a key of the main screen does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Help,
+    Commit,
+    Amend,
+    NextFile,
+    PrevFile,
+    /// Expand the folder, or go to the next sibling in the horizontal layout
+    ExpandOrNextSibling,
+    Expand,
+    /// Collapse the folder, or go to the previous sibling in the horizontal layout
+    CollapseOrPrevSibling,
+    RevealFile,
+    Counterpart,
+    NextQueued,
+    PrevQueued,
+    QueueEarlier,
+    QueueLater,
+    QueueLast,
+    Owners,
+    ScrollDown,
+    ScrollUp,
+    ScrollLeft,
+    ScrollRight,
+    HalfPageDown,
+    HalfPageUp,
+    PageDown,
+    Top,
+    Bottom,
+    NextHunk,
+    PrevHunk,
+    Search,
+    NextMatch,
+    PrevMatch,
+    LineJump,
+    EditNew,
+    EditOld,
+    CopyHunk,
+    Stage,
+    Unstage,
+    DiscardHunk,
+    DiscardFile,
+    Repeat,
+    Compare,
+    Comments,
+    DraftComment,
+    SubmitReview,
+    /// Clear the search, or leave a comparison, range, file history or stash
+    Leave,
+    ToggleTree,
+    GroupByStatus,
+    GroupByPackage,
+    ToggleGenerated,
+    LoadFullDiff,
+    ExpandContext,
+    ToggleWrap,
+    ToggleLineNumbers,
+    ToggleSideBySide,
+    ToggleWordDiff,
+    ToggleWhitespace,
+    ToggleStaged,
+    ToggleBlame,
+    ToggleActivity,
+    ToggleOutput,
+    OutputUp,
+    OutputDown,
+    AnsiInspector,
+    Undo,
+    Redo,
+    PrevCommit,
+    NextCommit,
+    BackInNav,
+    WorkingTree,
+    CommitLog,
+    FileLog,
+    Pickaxe,
+    Deepen,
+    DateJump,
+    RevJump,
+    ShowPush,
+    StartReview,
+    Stashes,
+    Stash,
+    /// Record into the register named by the key
+    RecordMacro,
+    /// Replay the register named by the key, `@` for the last one
+    ReplayMacro,
+}
+
+/// Modifier key, rendered with platform-appropriate names in the help popup
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ModKey {
+    Alt,
+    Ctrl,
+    Shift,
+}
+
+impl ModKey {
+    pub fn label(self) -> &'static str {
+        if cfg!(target_os = "macos") {
+            match self {
+                ModKey::Alt => "\u{2325}",
+                ModKey::Ctrl => "\u{2303}",
+                ModKey::Shift => "\u{21e7}",
+            }
+        } else {
+            match self {
+                ModKey::Alt => "Alt",
+                ModKey::Ctrl => "Ctrl",
+                ModKey::Shift => "Shift",
+            }
+        }
+    }
+
+    fn modifiers(self) -> KeyModifiers {
+        match self {
+            ModKey::Alt => KeyModifiers::ALT,
+            ModKey::Ctrl => KeyModifiers::CONTROL,
+            ModKey::Shift => KeyModifiers::SHIFT,
+        }
+    }
+}
+
+/// What a key has to be to trigger its action
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum KeyPattern {
+    Code(KeyCode),
+    /// Any of a-z, naming a macro register
+    Register,
+}
+
+impl KeyPattern {
+    fn matches(self, code: KeyCode) -> bool {
+        match self {
+            KeyPattern::Code(own) => own == code,
+            KeyPattern::Register => matches!(code, KeyCode::Char('a'..='z')),
+        }
+    }
+
+    fn name(self) -> String {
+        match self {
+            KeyPattern::Code(KeyCode::Char(' ')) => "Space".to_string(),
+            KeyPattern::Code(KeyCode::Char(c)) => c.to_string(),
+            KeyPattern::Code(KeyCode::Up) => "\u{2191}".to_string(),
+            KeyPattern::Code(KeyCode::Down) => "\u{2193}".to_string(),
+            KeyPattern::Code(KeyCode::Left) => "\u{2190}".to_string(),
+            KeyPattern::Code(KeyCode::Right) => "\u{2192}".to_string(),
+            KeyPattern::Code(KeyCode::PageUp) => "PgUp".to_string(),
+            KeyPattern::Code(KeyCode::PageDown) => "PgDn".to_string(),
+            KeyPattern::Code(code) => code.to_string(),
+            KeyPattern::Register => "{a-z}".to_string(),
+        }
+    }
+}
+
+/// A key and what it does
+pub struct Key {
+    pub pattern: KeyPattern,
+    pub action: Action,
+}
+
+const fn ch(c: char, action: Action) -> Key {
+    code(KeyCode::Char(c), action)
+}
+
+const fn code(code: KeyCode, action: Action) -> Key {
+    Key {
+        pattern: KeyPattern::Code(code),
+        action,
+    }
+}
+
+const fn register(action: Action) -> Key {
+    Key {
+        pattern: KeyPattern::Register,
+        action,
+    }
+}
+
+/// Alternative keys sharing the same modifier, e.g. Alt + (j / k), or the same
+/// first key of a sequence, like the `g` of `gd`
+pub struct KeyGroup {
+    pub modifier: Option<ModKey>,
+    /// Key pressed before these ones
+    pub prefix: Option<char>,
+    pub keys: &'static [Key],
+}
+
+const fn plain(keys: &'static [Key]) -> KeyGroup {
+    KeyGroup {
+        modifier: None,
+        prefix: None,
+        keys,
+    }
+}
+
+const fn with(modifier: ModKey, keys: &'static [Key]) -> KeyGroup {
+    KeyGroup {
+        modifier: Some(modifier),
+        prefix: None,
+        keys,
+    }
+}
+
+const fn after(prefix: char, keys: &'static [Key]) -> KeyGroup {
+    KeyGroup {
+        modifier: None,
+        prefix: Some(prefix),
+        keys,
+    }
+}
+
+impl KeyGroup {
+    fn display(&self) -> String {
+        let keys = self
+            .keys
+            .iter()
+            .map(|key| {
+                let name = key.pattern.name();
+                match self.prefix {
+                    Some(prefix) => format!("{prefix}{name}"),
+                    None => name,
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" / ");
+        match self.modifier {
+            None => keys,
+            // macOS convention writes modifiers as glued-on symbols: ⌥j
+            Some(m) if cfg!(target_os = "macos") && self.keys.len() == 1 => {
+                format!("{}{keys}", m.label())
+            }
+            Some(m) if cfg!(target_os = "macos") => format!("{}({keys})", m.label()),
+            Some(m) if self.keys.len() == 1 => format!("{} + {keys}", m.label()),
+            Some(m) => format!("{} + ({keys})", m.label()),
+        }
+    }
+
+    /// The action of the key in this group that `key` is, pressed `after` the
+    /// first key of a sequence. Shift doesn't count for characters, since it
+    /// picks the character. `loose` lets keys other than characters match
+    /// with any modifiers, like PgDn with Shift.
+    fn action_for(&self, key: KeyEvent, after: Option<char>, loose: bool) -> Option<Action> {
+        if self.prefix != after {
+            return None;
+        }
+        let own = self.modifier.map_or(KeyModifiers::NONE, ModKey::modifiers);
+        let modifiers_match = match key.code {
+            KeyCode::Char(_) => own - KeyModifiers::SHIFT == key.modifiers - KeyModifiers::SHIFT,
+            _ => own == key.modifiers || (loose && self.modifier.is_none()),
+        };
+        if !modifiers_match {
+            return None;
         }
+        self.keys
+            .iter()
+            .find(|k| k.pattern.matches(key.code))
+            .map(|k| k.action)
     }
 }
 
 pub struct Keybinding {
-    pub keys: &'static str,
+    pub keys: &'static [KeyGroup],
     pub description: &'static str,
     pub category: KeyCategory,
 }
 
-/// All keybindings for help display. Keep in sync with handle_key() below!
+impl Keybinding {
+    /// Key names for display, generated from the keymap for the current platform
+    pub fn keys_display(&self) -> String {
+        self.keys
+            .iter()
+            .map(KeyGroup::display)
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+}
+
+/// The action `key` is bound to, pressed `after` the first key of a sequence
+pub fn action_for(key: KeyEvent, after: Option<char>) -> Option<Action> {
+    let find = |loose| {
+        KEYBINDINGS
+            .iter()
+            .flat_map(|binding| binding.keys)
+            .find_map(|group| group.action_for(key, after, loose))
+    };
+    find(false).or_else(|| find(true))
+}
+
+/// Whether `c` is the first key of a sequence, like the `g` of `gd`
+fn starts_sequence(c: char) -> bool {
+    KEYBINDINGS
+        .iter()
+        .flat_map(|binding| binding.keys)
+        .any(|group| group.prefix == Some(c))
+}
+
+/// Every key of the main screen and what it does. `handle_key` looks keys up
+/// here and the help popup lists them, so the two can't disagree.
 pub static KEYBINDINGS: &[Keybinding] = &[
     Keybinding {
-        keys: "q",
+        keys: &[
+            plain(&[ch('q', Quit)]),
+            with(ModKey::Ctrl, &[ch('c', Quit)]),
+        ],
         description: "Quit",
         category: General,
     },
     Keybinding {
-        keys: "?",
+        keys: &[plain(&[ch('?', Help)])],
         description: "Toggle help",
         category: General,
     },
     Keybinding {
-        keys: "j / k / \u{2191} / \u{2193}",
+        keys: &[plain(&[ch('c', Commit)])],
+        description: "Commit staged changes",
+        category: General,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('C', Amend)])],
+        description: "Amend last commit",
+        category: General,
+    },
+    Keybinding {
+        keys: &[plain(&[
+            ch('j', NextFile),
+            ch('k', PrevFile),
+            code(KeyCode::Up, PrevFile),
+            code(KeyCode::Down, NextFile),
+        ])],
         description: "Navigate files",
         category: FileTree,
     },
     Keybinding {
-        keys: "l / Enter / \u{2192}",
+        keys: &[plain(&[
+            ch('l', ExpandOrNextSibling),
+            code(KeyCode::Enter, Expand),
+            code(KeyCode::Right, ExpandOrNextSibling),
+        ])],
         description: "Expand folder",
         category: FileTree,
     },
     Keybinding {
-        keys: "h / \u{2190}",
+        keys: &[plain(&[
+            ch('h', CollapseOrPrevSibling),
+            code(KeyCode::Left, CollapseOrPrevSibling),
+        ])],
         description: "Collapse / go to parent",
         category: FileTree,
     },
     Keybinding {
-        keys: "Alt + (j / k / \u{2191} / \u{2193})",
+        keys: &[plain(&[ch('r', RevealFile)])],
+        description: "Reveal file under diff scroll",
+        category: FileTree,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('%', Counterpart)])],
+        description: "Jump between source and test file",
+        category: FileTree,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('f', NextQueued), ch('F', PrevQueued)])],
+        description: "Next / prev file in review queue",
+        category: FileTree,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('<', QueueEarlier), ch('>', QueueLater)])],
+        description: "Move file earlier / later in review queue",
+        category: FileTree,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('m', QueueLast)])],
+        description: "Move file to end of review queue",
+        category: FileTree,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('W', Owners)])],
+        description: "Show who owns the changes (CODEOWNERS)",
+        category: FileTree,
+    },
+    Keybinding {
+        keys: &[with(
+            ModKey::Alt,
+            &[
+                ch('j', ScrollDown),
+                ch('k', ScrollUp),
+                code(KeyCode::Up, ScrollUp),
+                code(KeyCode::Down, ScrollDown),
+            ],
+        )],
         description: "Scroll line by line",
         category: DiffScrolling,
     },
     Keybinding {
-        keys: "Ctrl + (j / k) / PgUp / PgDn",
+        keys: &[with(
+            ModKey::Alt,
+            &[
+                ch('h', ScrollLeft),
+                ch('l', ScrollRight),
+                code(KeyCode::Left, ScrollLeft),
+                code(KeyCode::Right, ScrollRight),
+            ],
+        )],
+        description: "Scroll sideways (lines unwrap while scrolled)",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[
+            with(ModKey::Ctrl, &[ch('j', HalfPageDown), ch('k', HalfPageUp)]),
+            plain(&[
+                code(KeyCode::PageUp, HalfPageUp),
+                code(KeyCode::PageDown, HalfPageDown),
+            ]),
+        ],
         description: "Scroll half page",
         category: DiffScrolling,
     },
     Keybinding {
-        keys: "Space",
+        keys: &[plain(&[ch(' ', PageDown)])],
         description: "Page down",
         category: DiffScrolling,
     },
     Keybinding {
-        keys: "g / Home",
+        keys: &[plain(&[ch('g', Top), code(KeyCode::Home, Top)])],
         description: "Top of diff",
         category: DiffScrolling,
     },
     Keybinding {
-        keys: "G / End",
+        keys: &[plain(&[ch('G', Bottom), code(KeyCode::End, Bottom)])],
         description: "Bottom of diff",
         category: DiffScrolling,
     },
     Keybinding {
-        keys: "Shift + (J / K / \u{2191} / \u{2193})",
+        keys: &[with(
+            ModKey::Shift,
+            &[
+                ch('J', NextHunk),
+                ch('K', PrevHunk),
+                code(KeyCode::Up, PrevHunk),
+                code(KeyCode::Down, NextHunk),
+            ],
+        )],
         description: "Next / prev hunk",
         category: DiffScrolling,
     },
     Keybinding {
-        keys: "t",
+        keys: &[plain(&[ch('/', Search)])],
+        description: "Search the diff",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('n', NextMatch), ch('N', PrevMatch)])],
+        description: "Next / prev search match",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch(':', LineJump)])],
+        description: "Jump to a line of the new file",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('e', EditNew)])],
+        description: "Open hunk in editor (new side)",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('E', EditOld)])],
+        description: "Open hunk in editor (old side)",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('y', CopyHunk)])],
+        description: "Copy hunk as a patch",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('+', Stage), ch('-', Unstage)])],
+        description: "Stage / unstage current hunk",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('x', DiscardHunk)])],
+        description: "Discard current hunk",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('X', DiscardFile)])],
+        description: "Discard file's unstaged changes",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('.', Repeat)])],
+        description: "Repeat last stage / unstage / discard",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('=', Compare)])],
+        description: "Compare two file versions",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('i', Comments)])],
+        description: "Pull request review comments on current hunk",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('I', DraftComment)])],
+        description: "Draft a review comment on current hunk",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('U', SubmitReview)])],
+        description: "Submit drafts as a review (approve / comment / changes)",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[code(KeyCode::Esc, Leave)])],
+        description: "Clear search / leave comparison / range / stash",
+        category: DiffScrolling,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('t', ToggleTree)])],
         description: "Toggle file tree",
         category: Toggles,
     },
     Keybinding {
-        keys: "s",
+        keys: &[plain(&[ch('T', GroupByStatus)])],
+        description: "Group changes by status (staged, unstaged, ...)",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('M', GroupByPackage)])],
+        description: "Group changes by workspace package",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('V', ToggleGenerated)])],
+        description: "Show / hide generated files' diffs",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('A', LoadFullDiff)])],
+        description: "Load the rest of a diff cut off by its size",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('v', ExpandContext)])],
+        description: "Show more context around the changes",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('w', ToggleWrap)])],
+        description: "Wrap / cut off long lines",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('#', ToggleLineNumbers)])],
+        description: "Show / hide line numbers",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('|', ToggleSideBySide)])],
+        description: "Side-by-side view / formatter",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[with(ModKey::Ctrl, &[ch('w', ToggleWordDiff)])],
+        description: "Word diff",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('_', ToggleWhitespace)])],
+        description: "Ignore whitespace changes",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('s', ToggleStaged)])],
         description: "Toggle staged / unstaged",
         category: Toggles,
     },
     Keybinding {
-        keys: "[ / ]",
+        keys: &[plain(&[ch('b', ToggleBlame)])],
+        description: "Toggle blame ownership band",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('a', ToggleActivity)])],
+        description: "Toggle activity log",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('O', ToggleOutput)])],
+        description: "Toggle command output pane",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('{', OutputUp), ch('}', OutputDown)])],
+        description: "Scroll output pane up / down",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('`', AnsiInspector)])],
+        description: "ANSI inspector, from the top diff line",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[
+            plain(&[ch('u', Undo)]),
+            with(ModKey::Ctrl, &[ch('r', Redo)]),
+        ],
+        description: "Undo / redo folding and layout changes",
+        category: Toggles,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('[', PrevCommit), ch(']', NextCommit)])],
         description: "Prev / next commit",
         category: History,
     },
+    Keybinding {
+        keys: &[plain(&[code(KeyCode::Backspace, BackInNav)])],
+        description: "Back to the last commit left, where you were in it",
+        category: History,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('~', WorkingTree)])],
+        description: "Back to the working tree from any commit, range or stash",
+        category: History,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('L', CommitLog)])],
+        description: "Commit log (Enter to view a commit, / to filter)",
+        category: History,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('H', FileLog)])],
+        description: "History of the selected file ([ ] step through it)",
+        category: History,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('S', Pickaxe)])],
+        description: "Find commits adding or removing text",
+        category: History,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('D', Deepen)])],
+        description: "Fetch more history (shallow clone)",
+        category: History,
+    },
+    Keybinding {
+        keys: &[after('g', &[ch('d', DateJump)])],
+        description: "Jump to commit before a date",
+        category: History,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('o', RevJump)])],
+        description: "Jump to a commit by hash or ref",
+        category: History,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('P', ShowPush)])],
+        description: "Show what a push would send (upstream...HEAD)",
+        category: History,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('R', StartReview)])],
+        description: "Review the branch against the default branch",
+        category: History,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('z', Stashes)])],
+        description: "Browse stashes ([ ] step through them)",
+        category: History,
+    },
+    Keybinding {
+        keys: &[plain(&[ch('Z', Stash)])],
+        description: "Stash changes",
+        category: History,
+    },
+    Keybinding {
+        keys: &[after('Q', &[register(RecordMacro)])],
+        description: "Record macro (Q again to stop)",
+        category: Macros,
+    },
+    Keybinding {
+        keys: &[after('@', &[register(ReplayMacro), ch('@', ReplayMacro)])],
+        description: "Replay macro / last macro",
+        category: Macros,
+    },
 ];
 
 // ============================================================================
-// Event handlers - keep in sync with KEYBINDINGS above!
+// Event handlers
 // ============================================================================
 
 /// Handle key events. Returns true if the app should quit.
 pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
+    // Notices only stay up until the next key press
+    app.message = None;
+
     // Handle help popup first - it captures most keys when open
     if app.show_help {
         match key.code {
@@ -134,107 +759,358 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
         return Ok(false);
     }
 
-    match (key.code, key.modifiers) {
-        // Quit
-        (KeyCode::Char('q'), KeyModifiers::NONE) => return Ok(true),
-        (KeyCode::Char('c'), KeyModifiers::CONTROL) => return Ok(true),
+    // ANSI inspector follows the diff line by line
+    if let Some(line) = app.ansi_line {
+        let last = app.diff_state.total_lines.saturating_sub(1);
+        app.ansi_line = match key.code {
+            KeyCode::Char('`') | KeyCode::Char('q') | KeyCode::Esc => None,
+            KeyCode::Char('j') | KeyCode::Down => Some((line + 1).min(last)),
+            KeyCode::Char('k') | KeyCode::Up => Some(line.saturating_sub(1)),
+            _ => Some(line),
+        };
+        return Ok(false);
+    }
 
-        // Help
-        (KeyCode::Char('?'), KeyModifiers::NONE) => {
-            app.show_help = true;
+    // Review comments popup scrolls until closed
+    if let Some(thread) = &mut app.comment_thread {
+        match key.code {
+            KeyCode::Char('i') | KeyCode::Char('q') | KeyCode::Esc => app.comment_thread = None,
+            KeyCode::Char('d') => app.drop_thread_drafts(),
+            KeyCode::Char('j') | KeyCode::Down => thread.scroll = thread.scroll.saturating_add(1),
+            KeyCode::Char('k') | KeyCode::Up => thread.scroll = thread.scroll.saturating_sub(1),
+            _ => {}
         }
+        return Ok(false);
+    }
 
-        // === j/k family - all navigation ===
+    // Text prompt captures all keys while open
+    if app.prompt.is_some() {
+        return handle_prompt_key(app, key).map(|()| false);
+    }
 
-        // j/k alone - navigate file tree (layout-dependent)
-        (KeyCode::Char('j'), KeyModifiers::NONE) | (KeyCode::Down, KeyModifiers::NONE) => {
-            match app.config.layout.mode {
-                LayoutMode::Vertical => app.navigate_tree(|tree| tree.move_down()),
-                LayoutMode::Horizontal => app.navigate_tree(|tree| tree.move_to_child()),
-            }
-        }
-        (KeyCode::Char('k'), KeyModifiers::NONE) | (KeyCode::Up, KeyModifiers::NONE) => {
-            match app.config.layout.mode {
-                LayoutMode::Vertical => app.navigate_tree(|tree| tree.move_up()),
-                LayoutMode::Horizontal => app.navigate_tree(|tree| tree.move_to_parent()),
-            }
+    // Confirmation popup waits for a yes or no
+    if app.confirmation.is_some() {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => app.confirm()?,
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => app.confirmation = None,
+            _ => {}
         }
+        return Ok(false);
+    }
 
-        // Alt+j/k or Alt+arrows - scroll diff line by line
-        (KeyCode::Char('j'), KeyModifiers::ALT) | (KeyCode::Down, KeyModifiers::ALT) => {
-            app.diff_state.scroll_down(1);
-        }
-        (KeyCode::Char('k'), KeyModifiers::ALT) | (KeyCode::Up, KeyModifiers::ALT) => {
-            app.diff_state.scroll_up(1);
-        }
+    // Commit log pane captures all keys while open
+    if app.history_list.is_some() {
+        return handle_history_list_key(app, key).map(|()| false);
+    }
+
+    // Stash list captures all keys while open
+    if app.stash_picker.is_some() {
+        return handle_stash_picker_key(app, key).map(|()| false);
+    }
 
-        // Ctrl+j/k - scroll diff half page
-        (KeyCode::Char('j'), KeyModifiers::CONTROL) => {
-            app.diff_state.scroll_down(15);
+    // Commit modal captures all keys while open
+    if app.commit_draft.is_some() {
+        handle_commit_key(app, key);
+        return Ok(false);
+    }
+
+    // Second key of a two-key sequence
+    if let Some(first) = app.pending_key.take() {
+        if let Some(action) = action_for(key, Some(first)) {
+            return perform(app, action, key);
         }
-        (KeyCode::Char('k'), KeyModifiers::CONTROL) => {
-            app.diff_state.scroll_up(15);
+        // Anything else cancels the sequence, and is only handled when the
+        // first key did something by itself (like `g`)
+        if action_for(KeyEvent::from(KeyCode::Char(first)), None).is_none() {
+            return Ok(false);
         }
+    }
 
-        // Shift+J/K or Shift+arrows - next/prev hunk
-        (KeyCode::Char('J'), KeyModifiers::SHIFT) | (KeyCode::Down, KeyModifiers::SHIFT) => {
-            app.diff_state.next_hunk();
+    // Q again stops a recording rather than starting another
+    if key.code == KeyCode::Char('Q')
+        && let Some((register, len)) = app.macros.stop_recording()
+    {
+        app.message = Some(tr_format(
+            "Recorded {} keys into @{}",
+            &[&len.to_string(), &register.to_string()],
+        ));
+        return Ok(false);
+    }
+
+    let first_of_sequence = match key.code {
+        KeyCode::Char(c) if key.modifiers - KeyModifiers::SHIFT == KeyModifiers::NONE => {
+            starts_sequence(c).then_some(c)
         }
-        (KeyCode::Char('K'), KeyModifiers::SHIFT) | (KeyCode::Up, KeyModifiers::SHIFT) => {
-            app.diff_state.prev_hunk();
+        _ => None,
+    };
+    let result = match action_for(key, None) {
+        Some(action) => perform(app, action, key),
+        None if first_of_sequence.is_some() => Ok(false),
+        // Keys kibitz doesn't use may belong to a plugin
+        None => {
+            let before = app.ui_state();
+            app.run_plugin(key);
+            app.record_ui_change(before);
+            Ok(false)
         }
+    };
+    app.pending_key = first_of_sequence;
+    result
+}
+
+/// Carry out what `key` is bound to. Returns true if the app should quit.
+fn perform(app: &mut App, action: Action, key: KeyEvent) -> Result<bool> {
+    // Stepping through layout changes isn't one itself
+    match action {
+        Undo => return app.undo().map(|()| false),
+        Redo => return app.redo().map(|()| false),
+        Quit => return Ok(true),
+        _ => {}
+    }
+    let before = app.ui_state();
+
+    match action {
+        Help => app.show_help = true,
+        Commit => app.open_commit(false),
+        Amend => app.open_commit(true),
 
-        // === File tree expansion / sibling navigation (layout-dependent) ===
-        (KeyCode::Char('l') | KeyCode::Right, KeyModifiers::NONE) => match app.config.layout.mode {
+        // === File tree (layout-dependent) ===
+        NextFile => match app.config.layout.mode {
+            LayoutMode::Vertical => app.navigate_tree(|tree| tree.move_down()),
+            LayoutMode::Horizontal => app.navigate_tree(|tree| tree.move_to_child()),
+        },
+        PrevFile => match app.config.layout.mode {
+            LayoutMode::Vertical => app.navigate_tree(|tree| tree.move_up()),
+            LayoutMode::Horizontal => app.navigate_tree(|tree| tree.move_to_parent()),
+        },
+        ExpandOrNextSibling => match app.config.layout.mode {
             LayoutMode::Vertical => app.file_tree.expand(),
             LayoutMode::Horizontal => app.navigate_tree(|tree| tree.move_to_next_sibling()),
         },
-        (KeyCode::Char('h') | KeyCode::Left, KeyModifiers::NONE) => match app.config.layout.mode {
+        CollapseOrPrevSibling => match app.config.layout.mode {
             LayoutMode::Vertical => app.file_tree.collapse(),
             LayoutMode::Horizontal => app.navigate_tree(|tree| tree.move_to_prev_sibling()),
         },
         // Enter always expands/enters in both modes
-        (KeyCode::Enter, KeyModifiers::NONE) => {
-            app.file_tree.expand();
-        }
+        Expand => app.file_tree.expand(),
+        RevealFile => app.reveal_diff_file(),
+        Counterpart => app.jump_to_counterpart(),
+
+        // === Review queue ===
+        NextQueued => app.next_queued_file(true),
+        PrevQueued => app.next_queued_file(false),
+        QueueEarlier => app.move_in_queue(-1),
+        QueueLater => app.move_in_queue(1),
+        QueueLast => app.move_in_queue(isize::MAX),
+        Owners => app.show_owners(),
+
+        // === Diff scrolling ===
+        ScrollDown => app.diff_state.scroll_down(1),
+        ScrollUp => app.diff_state.scroll_up(1),
+        ScrollLeft => app.diff_state.scroll_left(HORIZONTAL_SCROLL_COLUMNS),
+        ScrollRight => app.diff_state.scroll_right(HORIZONTAL_SCROLL_COLUMNS),
+        HalfPageDown => app.diff_state.scroll_down(15),
+        HalfPageUp => app.diff_state.scroll_up(15),
+        PageDown => app.diff_state.scroll_down(30),
+        Top => app.diff_state.scroll_to_top(),
+        Bottom => app.diff_state.scroll_to_bottom(),
+        NextHunk => app.diff_state.next_hunk(),
+        PrevHunk => app.diff_state.prev_hunk(),
+
+        // === Diff search ===
+        Search => app.open_prompt(PromptKind::DiffSearch),
+        NextMatch => app.next_search_match(true),
+        PrevMatch => app.next_search_match(false),
+        LineJump => app.open_prompt(PromptKind::LineJump),
+
+        // === Editor ===
+        EditNew => app.open_in_editor(false),
+        EditOld => app.open_in_editor(true),
+        CopyHunk => app.copy_hunk_patch(),
 
-        // === Additional scroll keys ===
-        (KeyCode::Char(' '), KeyModifiers::NONE) => {
-            app.diff_state.scroll_down(30);
+        // === Staging and discarding ===
+        Stage => app.apply_current_hunk(HunkAction::Stage)?,
+        Unstage => app.apply_current_hunk(HunkAction::Unstage)?,
+        DiscardHunk => app.request_discard_hunk(),
+        DiscardFile => app.request_discard_file(),
+        Repeat => app.repeat_last_action()?,
+
+        // === Comparison and review comments ===
+        Compare => app.open_compare_prompt(),
+        Comments => app.show_comments(),
+        DraftComment => app.open_comment_prompt(),
+        SubmitReview => app.open_review_prompt(),
+        Leave => {
+            if app.diff_state.search.is_some() {
+                app.diff_state.search = None;
+            } else if app.comparison.is_some() {
+                app.end_comparison()?;
+            } else if app.range.is_some() {
+                app.leave_range()?;
+            } else if app.file_log.is_some() {
+                app.leave_file_log()?;
+            } else {
+                app.leave_stash()?;
+            }
         }
-        (KeyCode::PageDown, _) => {
-            app.diff_state.scroll_down(15);
+
+        // === Toggles ===
+        ToggleTree => app.toggle_tree(),
+        GroupByStatus => app.toggle_group_by_status()?,
+        GroupByPackage => app.toggle_group_by_package()?,
+        ToggleGenerated => app.toggle_generated(),
+        LoadFullDiff => app.load_full_diff(),
+        ExpandContext => app.expand_context(),
+        ToggleWrap => app.toggle_wrap(),
+        ToggleLineNumbers => app.toggle_line_numbers(),
+        ToggleSideBySide => app.toggle_side_by_side(),
+        ToggleWordDiff => app.toggle_word_diff(),
+        ToggleWhitespace => app.toggle_ignore_whitespace(),
+        ToggleStaged => app.toggle_staged(),
+        ToggleBlame => app.toggle_blame(),
+        ToggleActivity => app.toggle_activity(),
+        ToggleOutput => app.toggle_output()?,
+        OutputUp => app.scroll_output(-(OUTPUT_SCROLL_LINES as isize)),
+        OutputDown => app.scroll_output(OUTPUT_SCROLL_LINES as isize),
+        AnsiInspector => app.ansi_line = Some(app.diff_state.scroll_offset),
+
+        // === History navigation ===
+        PrevCommit => app.go_back_in_history()?,
+        NextCommit => app.go_forward_in_history()?,
+        BackInNav => app.go_back_in_nav()?,
+        WorkingTree => app.return_to_working_tree()?,
+        CommitLog => app.open_history_list()?,
+        FileLog => app.open_file_history()?,
+        Pickaxe => app.open_prompt(PromptKind::Pickaxe),
+        Deepen => app.deepen_history(),
+        DateJump => app.open_prompt(PromptKind::DateJump),
+        RevJump => app.open_prompt(PromptKind::RevJump),
+        ShowPush => app.show_push()?,
+        StartReview => app.start_review()?,
+        Stashes => app.open_stash_picker()?,
+        Stash => app.open_prompt(PromptKind::Stash),
+
+        // === Macros ===
+        RecordMacro => {
+            if let KeyCode::Char(register) = key.code {
+                app.macros.start_recording(register);
+            }
         }
-        (KeyCode::PageUp, _) => {
-            app.diff_state.scroll_up(15);
+        ReplayMacro => {
+            if let KeyCode::Char(register) = key.code
+                && let Err(e) = app.macros.replay(register)
+            {
+                app.message = Some(e);
+            }
         }
-        (KeyCode::Char('g'), KeyModifiers::NONE) | (KeyCode::Home, _) => {
-            app.diff_state.scroll_to_top();
+
+        Quit | Undo | Redo => {}
+    }
+
+    app.record_ui_change(before);
+    Ok(false)
+}
+
+fn handle_prompt_key(app: &mut App, key: KeyEvent) -> Result<()> {
+    let Some(prompt) = app.prompt.as_mut() else {
+        return Ok(());
+    };
+
+    match (key.code, key.modifiers) {
+        (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+            app.prompt = None;
         }
-        (KeyCode::Char('G'), KeyModifiers::SHIFT) | (KeyCode::End, _) => {
-            app.diff_state.scroll_to_bottom();
+        (KeyCode::Enter, _) => {
+            if let Some(prompt) = app.prompt.take() {
+                app.submit_prompt(prompt)?;
+            }
         }
-
-        // === Toggles ===
-        (KeyCode::Char('t'), KeyModifiers::NONE) => {
-            app.toggle_tree();
+        (KeyCode::Backspace, _) => prompt.input.backspace(),
+        (KeyCode::Delete, _) => prompt.input.delete(),
+        (KeyCode::Left, _) => prompt.input.move_left(),
+        (KeyCode::Right, _) => prompt.input.move_right(),
+        (KeyCode::Home, _) | (KeyCode::Char('a'), KeyModifiers::CONTROL) => {
+            prompt.input.move_home();
         }
-        (KeyCode::Char('s'), KeyModifiers::NONE) => {
-            app.toggle_staged();
+        (KeyCode::End, _) | (KeyCode::Char('e'), KeyModifiers::CONTROL) => {
+            prompt.input.move_end();
         }
+        (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => prompt.input.insert(c),
+        _ => {}
+    }
+    Ok(())
+}
 
-        // === History navigation ===
-        (KeyCode::Char('['), KeyModifiers::NONE) => {
-            app.go_back_in_history()?;
+fn handle_history_list_key(app: &mut App, key: KeyEvent) -> Result<()> {
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('L') => app.history_list = None,
+        KeyCode::Enter | KeyCode::Char('l') => app.pick_history_entry()?,
+        KeyCode::Char('/') => app.open_prompt(PromptKind::CommitFilter),
+        KeyCode::Char('j') | KeyCode::Down => app.move_in_history_list(1)?,
+        KeyCode::Char('k') | KeyCode::Up => app.move_in_history_list(-1)?,
+        KeyCode::PageDown | KeyCode::Char(' ') => app.move_in_history_list(15)?,
+        KeyCode::PageUp => app.move_in_history_list(-15)?,
+        KeyCode::Char('g') | KeyCode::Home => {
+            if let Some(list) = app.history_list.as_mut() {
+                list.move_to_top();
+            }
         }
-        (KeyCode::Char(']'), KeyModifiers::NONE) => {
-            app.go_forward_in_history()?;
+        KeyCode::Char('G') | KeyCode::End => {
+            if let Some(list) = app.history_list.as_mut() {
+                list.move_to_bottom();
+            }
+            app.move_in_history_list(0)?;
         }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_stash_picker_key(app: &mut App, key: KeyEvent) -> Result<()> {
+    let Some(picker) = app.stash_picker.as_mut() else {
+        return Ok(());
+    };
 
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('z') => app.stash_picker = None,
+        KeyCode::Enter | KeyCode::Char('l') => app.pick_stash()?,
+        KeyCode::Char('a') => app.apply_picked_stash(false)?,
+        KeyCode::Char('p') => app.apply_picked_stash(true)?,
+        KeyCode::Char('d') => app.request_drop_stash(),
+        KeyCode::Char('j') | KeyCode::Down => picker.move_down(),
+        KeyCode::Char('k') | KeyCode::Up => picker.move_up(),
         _ => {}
     }
+    Ok(())
+}
 
-    Ok(false)
+fn handle_commit_key(app: &mut App, key: KeyEvent) {
+    // Wait for the running commit to finish
+    if app.is_committing() {
+        return;
+    }
+    let Some(draft) = app.commit_draft.as_mut() else {
+        return;
+    };
+    let message = &mut draft.message;
+
+    match (key.code, key.modifiers) {
+        (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+            app.commit_draft = None;
+        }
+        (KeyCode::Char('s'), KeyModifiers::CONTROL) => app.submit_commit(),
+        (KeyCode::Enter, _) => message.newline(),
+        (KeyCode::Backspace, _) => message.backspace(),
+        (KeyCode::Delete, _) => message.delete(),
+        (KeyCode::Left, _) => message.move_left(),
+        (KeyCode::Right, _) => message.move_right(),
+        (KeyCode::Up, _) => message.move_up(),
+        (KeyCode::Down, _) => message.move_down(),
+        (KeyCode::Home, _) | (KeyCode::Char('a'), KeyModifiers::CONTROL) => message.move_home(),
+        (KeyCode::End, _) | (KeyCode::Char('e'), KeyModifiers::CONTROL) => message.move_end(),
+        (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => message.insert(c),
+        _ => {}
+    }
 }
 
 pub fn handle_mouse(app: &mut App, mouse: MouseEvent) -> Result<()> {
@@ -249,3 +1125,91 @@ pub fn handle_mouse(app: &mut App, mouse: MouseEvent) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn every_key_in_the_help_does_what_it_says() {
+        for binding in KEYBINDINGS {
+            for group in binding.keys {
+                let modifiers = group.modifier.map_or(KeyModifiers::NONE, ModKey::modifiers);
+                for key in group.keys {
+                    let code = match key.pattern {
+                        KeyPattern::Code(code) => code,
+                        KeyPattern::Register => KeyCode::Char('a'),
+                    };
+                    assert_eq!(
+                        action_for(press(code, modifiers), group.prefix),
+                        Some(key.action),
+                        "{} ({})",
+                        binding.keys_display(),
+                        binding.description
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shift_picks_characters_and_other_keys_fall_back_to_plain() {
+        let shift = KeyModifiers::SHIFT;
+        assert_eq!(
+            action_for(press(KeyCode::Char('G'), shift), None),
+            Some(Bottom)
+        );
+        assert_eq!(
+            action_for(press(KeyCode::Char('G'), KeyModifiers::NONE), None),
+            Some(Bottom)
+        );
+        assert_eq!(
+            action_for(press(KeyCode::Down, shift), None),
+            Some(NextHunk)
+        );
+        assert_eq!(
+            action_for(press(KeyCode::PageDown, shift), None),
+            Some(HalfPageDown)
+        );
+        assert_eq!(
+            action_for(press(KeyCode::Char('x'), KeyModifiers::ALT), None),
+            None
+        );
+        assert_eq!(
+            action_for(press(KeyCode::Char('d'), KeyModifiers::NONE), None),
+            None
+        );
+        assert_eq!(
+            action_for(press(KeyCode::Char('d'), KeyModifiers::NONE), Some('g')),
+            Some(DateJump)
+        );
+    }
+
+    #[test]
+    fn sequences_start_with_keys_of_their_own_or_none() {
+        assert!(starts_sequence('g') && starts_sequence('Q') && starts_sequence('@'));
+        assert!(!starts_sequence('d'));
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn the_help_names_keys_from_the_keymap() {
+        let display = |description: &str| {
+            KEYBINDINGS
+                .iter()
+                .find(|binding| binding.description == description)
+                .unwrap()
+                .keys_display()
+        };
+        assert_eq!(display("Quit"), "q / Ctrl + c");
+        assert_eq!(display("Scroll half page"), "Ctrl + (j / k) / PgUp / PgDn");
+        assert_eq!(display("Navigate files"), "j / k / \u{2191} / \u{2193}");
+        assert_eq!(display("Page down"), "Space");
+        assert_eq!(display("Jump to commit before a date"), "gd");
+        assert_eq!(display("Replay macro / last macro"), "@{a-z} / @@");
+    }
+}
@@ -0,0 +1,99 @@
+use crate::i18n::{tr, tr_format};
+use crossterm::event::KeyEvent;
+use std::collections::{HashMap, VecDeque};
+
+/// Replaying more keys than this in one go means a macro keeps calling itself
+const MAX_REPLAYED_KEYS: usize = 10_000;
+
+/// Key sequences recorded with `Q{a-z}` and replayed with `@{a-z}`
+#[derive(Debug, Default)]
+pub struct Macros {
+    registers: HashMap<char, Vec<KeyEvent>>,
+    /// Register being recorded into, with the keys so far
+    recording: Option<(char, Vec<KeyEvent>)>,
+    /// Keys still to replay, handled one at a time by the run loop
+    queue: VecDeque<KeyEvent>,
+    /// Keys handled so far in the current replay
+    replayed: usize,
+    /// Register replayed last, for `@@`
+    last_replayed: Option<char>,
+}
+
+impl Macros {
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    pub fn start_recording(&mut self, register: char) {
+        self.recording = Some((register, Vec::new()));
+    }
+
+    /// Store the recording, dropping the key that stopped it. Returns the
+    /// register and how many keys it holds.
+    pub fn stop_recording(&mut self) -> Option<(char, usize)> {
+        let (register, mut keys) = self.recording.take()?;
+        keys.pop();
+        let len = keys.len();
+        self.registers.insert(register, keys);
+        Some((register, len))
+    }
+
+    /// Note a key typed by the user while recording
+    pub fn record(&mut self, key: KeyEvent) {
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(key);
+        }
+    }
+
+    /// Queue a register's keys ahead of anything still queued, so a macro can
+    /// call another. `@` replays the last register used.
+    pub fn replay(&mut self, register: char) -> Result<(), String> {
+        let register = match register {
+            '@' => self.last_replayed.ok_or(tr("No macro replayed yet"))?,
+            r => r,
+        };
+        let keys = match self.registers.get(&register) {
+            Some(keys) if !keys.is_empty() => keys,
+            _ => return Err(tr_format("Macro @{} is empty", &[&register.to_string()])),
+        };
+        let keys = keys.clone();
+        self.feed(&keys).map_err(|_| {
+            tr_format(
+                "Macro @{} keeps replaying itself; stopped",
+                &[&register.to_string()],
+            )
+        })?;
+        self.last_replayed = Some(register);
+        Ok(())
+    }
+
+    /// Queue keys to be handled next, as if typed (used by replays and plugins)
+    pub fn feed(&mut self, keys: &[KeyEvent]) -> Result<(), String> {
+        if self.replayed + self.queue.len() + keys.len() > MAX_REPLAYED_KEYS {
+            self.queue.clear();
+            return Err(tr("Keys keep replaying themselves; stopped").to_string());
+        }
+        for key in keys.iter().rev() {
+            self.queue.push_front(*key);
+        }
+        Ok(())
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    pub fn next_key(&mut self) -> Option<KeyEvent> {
+        let Some(key) = self.queue.pop_front() else {
+            self.replayed = 0;
+            return None;
+        };
+        self.replayed += 1;
+        Some(key)
+    }
+
+    pub fn stop_replay(&mut self) {
+        self.queue.clear();
+        self.replayed = 0;
+    }
+}
@@ -1,4 +1,8 @@
 mod handler;
+mod macros;
+mod reader;
 pub mod watcher;
 
-pub use handler::{KEYBINDINGS, KeyCategory, handle_key, handle_mouse};
+pub use handler::{KEYBINDINGS, ModKey, handle_key, handle_mouse};
+pub use macros::Macros;
+pub use reader::EventReader;
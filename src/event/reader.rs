@@ -0,0 +1,72 @@
+use anyhow::Result;
+use crossterm::event::{self as ct_event, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use std::time::Duration;
+
+/// How long to wait after a bare Esc for the key it may be prefixing.
+/// Terminals that send Alt as an ESC prefix deliver both bytes well within this.
+const ESC_PREFIX_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Reads terminal events, turning `Esc` followed quickly by a key into `Alt+key`.
+///
+/// Many terminals (PuTTY, older xterms, some tmux configs) send `ESC j` instead of
+/// a proper Alt+j, and crossterm only merges the two when they arrive in one read.
+#[derive(Default)]
+pub struct EventReader {
+    /// Event read while disambiguating an Esc that turned out not to be a prefix
+    pending: Option<Event>,
+}
+
+impl EventReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether an event is waiting to be read
+    pub fn has_pending(&self) -> Result<bool> {
+        Ok(self.pending.is_some() || ct_event::poll(Duration::ZERO)?)
+    }
+
+    /// Wait up to `timeout` for the next event
+    pub fn next(&mut self, timeout: Duration) -> Result<Option<Event>> {
+        if let Some(event) = self.pending.take() {
+            return Ok(Some(event));
+        }
+        if !ct_event::poll(timeout)? {
+            return Ok(None);
+        }
+
+        let event = ct_event::read()?;
+        if !is_bare_esc(&event) || !ct_event::poll(ESC_PREFIX_TIMEOUT)? {
+            return Ok(Some(event));
+        }
+
+        match ct_event::read()? {
+            Event::Key(key)
+                if key.kind == KeyEventKind::Press
+                    && key.code != KeyCode::Esc
+                    && !key.modifiers.contains(KeyModifiers::ALT) =>
+            {
+                Ok(Some(Event::Key(KeyEvent {
+                    modifiers: key.modifiers | KeyModifiers::ALT,
+                    ..key
+                })))
+            }
+            next => {
+                self.pending = Some(next);
+                Ok(Some(event))
+            }
+        }
+    }
+}
+
+fn is_bare_esc(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Key(KeyEvent {
+            code: KeyCode::Esc,
+            modifiers: KeyModifiers::NONE,
+            kind: KeyEventKind::Press,
+            ..
+        })
+    )
+}
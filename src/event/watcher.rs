@@ -1,46 +1,53 @@
 use anyhow::Result;
-use notify::{RecommendedWatcher, RecursiveMode};
-use notify_debouncer_mini::{DebouncedEvent, DebouncedEventKind, Debouncer, new_debouncer};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use std::path::Path;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
 use std::time::Duration;
 
+/// How long changes are collected before one refresh is signaled for all of them
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
 pub struct FileWatcher {
-    _debouncer: Debouncer<RecommendedWatcher>,
+    watcher: RecommendedWatcher,
 }
 
 impl FileWatcher {
     pub fn new(repo_path: &Path, tx: Sender<()>) -> Result<Self> {
-        let debouncer = new_debouncer(
-            Duration::from_millis(200),
-            move |res: Result<Vec<DebouncedEvent>, notify::Error>| {
-                if let Ok(events) = res {
-                    // Filter for relevant events
-                    let has_relevant = events
-                        .iter()
-                        .any(|e| matches!(e.kind, DebouncedEventKind::Any));
-                    if has_relevant {
-                        let _ = tx.send(());
-                    }
-                }
-            },
-        )?;
+        let (changes_tx, changes_rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+            // Files being opened and read (by git itself, say) change nothing, and
+            // refreshing on them would loop: every diff reads the repository
+            if let Ok(event) = res
+                && !matches!(event.kind, EventKind::Access(_))
+            {
+                let _ = changes_tx.send(());
+            }
+        })?;
 
-        let git_dir = repo_path.join(".git");
-        let mut watcher = debouncer;
+        // One signal per burst of changes
+        thread::spawn(move || {
+            while changes_rx.recv().is_ok() {
+                thread::sleep(DEBOUNCE);
+                while changes_rx.try_recv().is_ok() {}
+                if tx.send(()).is_err() {
+                    return;
+                }
+            }
+        });
 
         // Watch .git directory for index changes
-        watcher
-            .watcher()
-            .watch(&git_dir, RecursiveMode::Recursive)?;
+        watcher.watch(&repo_path.join(".git"), RecursiveMode::Recursive)?;
 
         // Watch working directory for file changes (non-recursive to avoid perf issues)
-        watcher
-            .watcher()
-            .watch(repo_path, RecursiveMode::NonRecursive)?;
+        watcher.watch(repo_path, RecursiveMode::NonRecursive)?;
+
+        Ok(Self { watcher })
+    }
 
-        Ok(Self {
-            _debouncer: watcher,
-        })
+    /// Also watch a directory deeper in the working tree (non-recursively)
+    pub fn watch_dir(&mut self, dir: &Path) -> Result<()> {
+        self.watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(())
     }
 }
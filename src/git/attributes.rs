@@ -0,0 +1,71 @@
+use git2::{AttrCheckFlags, AttrValue, Repository};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Files not for reading line by line: minified ones (`*.min.*`) and those
+/// .gitattributes marks `linguist-generated` or `-diff` (which includes
+/// `binary`). Paths are returned as given.
+pub fn generated_files(repo_path: &Path, files: &[PathBuf]) -> HashSet<PathBuf> {
+    let Ok(repo) = Repository::open(repo_path) else {
+        return HashSet::new();
+    };
+    let attr = |path: &Path, name: &str| {
+        AttrValue::from_string(
+            repo.get_attr(path, name, AttrCheckFlags::default())
+                .ok()
+                .flatten(),
+        )
+    };
+    files
+        .iter()
+        .filter(|file| {
+            let path = file.strip_prefix(".").unwrap_or(file);
+            let generated = match attr(path, "linguist-generated") {
+                AttrValue::True => true,
+                AttrValue::String(value) => value == "true",
+                _ => false,
+            };
+            let minified = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().contains(".min."));
+            generated || minified || attr(path, "diff") == AttrValue::False
+        })
+        .cloned()
+        .collect()
+}
+
+/// Files whose changes alter how diffs come out: the .gitattributes choosing
+/// textconv and diff drivers, and the git config defining them, in the
+/// repository and the user's home. Nested .gitattributes count when they're
+/// among `files`.
+pub fn diff_settings_files(repo_path: &Path, files: &[PathBuf]) -> Vec<PathBuf> {
+    let mut settings = vec![repo_path.join(".gitattributes")];
+    settings.extend(
+        files
+            .iter()
+            .filter(|file| {
+                file.file_name()
+                    .is_some_and(|name| name == ".gitattributes")
+            })
+            .map(|file| repo_path.join(file)),
+    );
+    if let Ok(repo) = Repository::open(repo_path) {
+        settings.push(repo.path().join("config"));
+        settings.push(repo.path().join("info").join("attributes"));
+        if let Ok(path) = repo
+            .config()
+            .and_then(|config| config.get_path("core.attributesFile"))
+        {
+            settings.push(path);
+        }
+    }
+    if let Some(home) = dirs::home_dir() {
+        let xdg = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".config"));
+        settings.push(home.join(".gitconfig"));
+        settings.push(xdg.join("git").join("config"));
+        settings.push(xdg.join("git").join("attributes"));
+    }
+    settings
+}
@@ -0,0 +1,97 @@
+use crate::model::{AuthorShare, BlameOverview};
+use anyhow::Result;
+use git2::{BlameOptions, Oid, Repository};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The newest commit among some blamed lines
+#[derive(Debug, Clone)]
+pub struct LastTouch {
+    /// Short id
+    pub oid: String,
+    pub author: String,
+    /// Author date, YYYY-MM-DD
+    pub date: String,
+    pub summary: String,
+}
+
+/// The commit that most recently touched any of `lines` (1-based) of `file_path`,
+/// as of `newest`. Only that line span is blamed.
+pub fn last_touch(
+    repo: &Repository,
+    file_path: &Path,
+    newest: Oid,
+    lines: &[usize],
+) -> Option<LastTouch> {
+    let (&first, &last) = (lines.iter().min()?, lines.iter().max()?);
+    let mut opts = BlameOptions::new();
+    opts.newest_commit(newest).min_line(first).max_line(last);
+    let blame = repo.blame_file(file_path, Some(&mut opts)).ok()?;
+
+    let commit = lines
+        .iter()
+        .filter_map(|&line| blame.get_line(line))
+        .filter_map(|hunk| repo.find_commit(hunk.final_commit_id()).ok())
+        .max_by_key(|commit| commit.time().seconds())?;
+    let author = commit.author();
+    let date = chrono::DateTime::from_timestamp(author.when().seconds(), 0)
+        .map(|d| {
+            d.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d")
+                .to_string()
+        })
+        .unwrap_or_default();
+    Some(LastTouch {
+        oid: commit.as_object().short_id().ok()?.as_str()?.to_string(),
+        author: author.name().unwrap_or("Unknown").to_string(),
+        date,
+        summary: commit.summary().unwrap_or("").to_string(),
+    })
+}
+
+/// Compute per-author line ownership for a file, as of `oid` (or HEAD when None)
+pub fn blame_overview(
+    repo_path: &Path,
+    file_path: &Path,
+    oid: Option<&str>,
+) -> Result<BlameOverview> {
+    let repo = Repository::open(repo_path)?;
+    let mut opts = BlameOptions::new();
+    if let Some(oid) = oid {
+        opts.newest_commit(Oid::from_str(oid)?);
+    }
+
+    let relative = file_path.strip_prefix(".").unwrap_or(file_path);
+    let blame = repo.blame_file(relative, Some(&mut opts))?;
+
+    let mut author_index: HashMap<String, usize> = HashMap::new();
+    let mut authors: Vec<AuthorShare> = Vec::new();
+    let mut line_authors = Vec::new();
+
+    for hunk in blame.iter() {
+        let signature = hunk.final_signature();
+        let name = signature.name().unwrap_or("Unknown").to_string();
+        let idx = *author_index.entry(name.clone()).or_insert_with(|| {
+            authors.push(AuthorShare { name, lines: 0 });
+            authors.len() - 1
+        });
+        authors[idx].lines += hunk.lines_in_hunk();
+        line_authors.extend(std::iter::repeat_n(idx, hunk.lines_in_hunk()));
+    }
+
+    // Sort authors by ownership and remap line indices accordingly
+    let mut order: Vec<usize> = (0..authors.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(authors[i].lines));
+    let mut remap = vec![0; authors.len()];
+    for (new, &old) in order.iter().enumerate() {
+        remap[old] = new;
+    }
+    let authors = order.iter().map(|&i| authors[i].clone()).collect();
+    let line_authors = line_authors.into_iter().map(|i| remap[i]).collect();
+
+    Ok(BlameOverview {
+        path: file_path.to_path_buf(),
+        authors,
+        line_authors,
+    })
+}
@@ -0,0 +1,89 @@
+use crate::model::ComparePoint;
+use anyhow::{Context, Result};
+use git2::Repository;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Temp directories left behind by runs that didn't exit cleanly are removed after this long
+const STALE_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+const DIR_PREFIX: &str = "kibitz-blobs-";
+
+/// Read a file's content at a revision. `rev` is anything revparse accepts
+/// ("HEAD", a commit hash, "abc123^"); an empty `rev` reads the index.
+pub fn read_blob(repo_path: &Path, rev: &str, path: &Path) -> Result<Vec<u8>> {
+    let repo = Repository::open(repo_path)?;
+    let relative = path.strip_prefix(".").unwrap_or(path);
+    let spec = format!("{}:{}", rev, relative.to_string_lossy());
+    let object = repo
+        .revparse_single(&spec)
+        .with_context(|| format!("{spec} not found"))?;
+    let blob = object.peel_to_blob()?;
+    Ok(blob.content().to_vec())
+}
+
+/// Read a comparison point: a blob at its revision, or the file in the working tree
+pub fn read_point(repo_path: &Path, point: &ComparePoint) -> Result<Vec<u8>> {
+    match &point.rev {
+        Some(rev) => read_blob(repo_path, rev, &point.path),
+        None => std::fs::read(repo_path.join(&point.path))
+            .with_context(|| format!("{} not found", point.path.display())),
+    }
+}
+
+/// Temp directory for blobs handed to external programs (editors, difftools, ...).
+/// Everything in it is removed when the store is dropped, so tools that detach
+/// (e.g. GUI editors) only see the files while kibitz is running.
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+impl BlobStore {
+    pub fn new() -> Self {
+        remove_stale_dirs();
+        Self {
+            root: std::env::temp_dir().join(format!("{DIR_PREFIX}{}", std::process::id())),
+        }
+    }
+
+    /// Write `path` as of `rev` (see `read_blob`) to a temp file and return its path.
+    /// The file keeps its name (so editors pick the right syntax) under a per-revision directory.
+    pub fn materialize(&self, repo_path: &Path, rev: &str, path: &Path) -> Result<PathBuf> {
+        let content = read_blob(repo_path, rev, path)?;
+        let relative = path.strip_prefix(".").unwrap_or(path);
+        let rev_dir = if rev.is_empty() { "index" } else { rev };
+        let target = self
+            .root
+            .join(rev_dir.replace(['/', ':', '^', '~'], "_"))
+            .join(relative);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&target, content)?;
+        Ok(target)
+    }
+}
+
+impl Drop for BlobStore {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Remove blob directories of earlier runs that crashed or were killed
+fn remove_stale_dirs() {
+    let Ok(entries) = std::fs::read_dir(std::env::temp_dir()) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let is_ours = entry.file_name().to_string_lossy().starts_with(DIR_PREFIX);
+        let age = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        if is_ours && age.is_some_and(|age| age > STALE_AFTER) {
+            let _ = std::fs::remove_dir_all(entry.path());
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::persist;
+use anyhow::{Context, Result};
+use git2::{DiffOptions, Oid, Repository, Sort};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Commits looked at when there's nothing cached yet, newest first
+const MAX_COMMITS: usize = 5000;
+
+/// How often a file has changed, and how many of those changes were fixes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Churn {
+    pub commits: usize,
+    pub fixes: usize,
+}
+
+/// Churn of every file in HEAD's history, and the HEAD it was counted up to
+pub struct ChurnHistory {
+    pub head: Oid,
+    pub files: HashMap<PathBuf, Churn>,
+}
+
+/// The id of HEAD, which churn is counted up to
+pub fn head(repo_path: &Path) -> Option<Oid> {
+    Repository::open(repo_path).ok()?.head().ok()?.target()
+}
+
+/// Count how often each file changed in HEAD's history (leaving out merges),
+/// starting from the counts cached for this repository when HEAD descends from
+/// where they stopped, and cache the result
+pub fn history(repo_path: &Path) -> Result<ChurnHistory> {
+    let repo = Repository::open(repo_path)?;
+    let head = repo.head()?.target().context("HEAD has no commit")?;
+    let cache = cache_path(repo_path);
+    let mut history = cache
+        .as_deref()
+        .and_then(read_cache)
+        .filter(|cached| {
+            cached.head == head || repo.graph_descendant_of(head, cached.head).unwrap_or(false)
+        })
+        .unwrap_or_else(|| ChurnHistory {
+            head,
+            files: HashMap::new(),
+        });
+    if history.head == head && !history.files.is_empty() {
+        return Ok(history);
+    }
+
+    let fix = Regex::new(r"(?i)\b(fix(e[sd])?|bug|hotfix|revert|regression)\b")?;
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TOPOLOGICAL)?;
+    walk.push(head)?;
+    if !history.files.is_empty() {
+        walk.hide(history.head)?;
+    }
+    for oid in walk.take(MAX_COMMITS) {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() > 1 {
+            continue;
+        }
+        let parent = commit.parents().next().map(|p| p.tree()).transpose()?;
+        let mut opts = DiffOptions::new();
+        opts.skip_binary_check(true);
+        let diff =
+            repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), Some(&mut opts))?;
+        let is_fix = commit.message().is_some_and(|m| fix.is_match(m));
+        for delta in diff.deltas() {
+            let Some(path) = delta.new_file().path().or(delta.old_file().path()) else {
+                continue;
+            };
+            let churn = history.files.entry(path.to_path_buf()).or_default();
+            churn.commits += 1;
+            churn.fixes += usize::from(is_fix);
+        }
+    }
+    history.head = head;
+
+    if let Some(cache) = cache {
+        // Counting again is the worst that happens without it
+        let _ = write_cache(&cache, &history);
+    }
+    Ok(history)
+}
+
+/// Where this repository's counts are kept between runs
+fn cache_path(repo_path: &Path) -> Option<PathBuf> {
+    Some(
+        dirs::cache_dir()?
+            .join("kibitz")
+            .join(format!("churn-{}", persist::repo_key(repo_path))),
+    )
+}
+
+/// The HEAD on the first line, then `commits fixes path` per file
+fn read_cache(path: &Path) -> Option<ChurnHistory> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let mut lines = contents.lines();
+    let head = Oid::from_str(lines.next()?).ok()?;
+    let files = lines
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ' ');
+            let commits = fields.next()?.parse().ok()?;
+            let fixes = fields.next()?.parse().ok()?;
+            Some((PathBuf::from(fields.next()?), Churn { commits, fixes }))
+        })
+        .collect();
+    Some(ChurnHistory { head, files })
+}
+
+fn write_cache(path: &Path, history: &ChurnHistory) -> Result<()> {
+    let mut contents = format!("{}\n", history.head);
+    for (file, churn) in &history.files {
+        let _ = writeln!(
+            contents,
+            "{} {} {}",
+            churn.commits,
+            churn.fixes,
+            file.display()
+        );
+    }
+    persist::atomic_write(path, contents)
+}
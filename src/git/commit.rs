@@ -0,0 +1,112 @@
+use crate::config::GitConfig;
+use anyhow::{Context, Result, bail};
+use git2::Repository;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+/// Message of the HEAD commit, used to prefill an amend
+pub fn head_message(repo_path: &Path) -> Result<String> {
+    let repo = Repository::open(repo_path)?;
+    let head = repo.head()?.peel_to_commit()?;
+    Ok(head.message().unwrap_or("").trim_end().to_string())
+}
+
+/// Create a commit from the index in the background. Yields the new commit's short id.
+pub fn commit_async(
+    repo_path: &Path,
+    git: &GitConfig,
+    git_available: bool,
+    message: String,
+    amend: bool,
+) -> mpsc::Receiver<Result<String>> {
+    let repo_path = repo_path.to_path_buf();
+    let git = git.clone();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let result = if git_available {
+            commit_with_cli(&repo_path, &git, &message, amend)
+        } else {
+            commit_with_libgit2(&repo_path, &message, amend)
+        };
+        let _ = tx.send(result.and_then(|()| head_short_id(&repo_path)));
+    });
+    rx
+}
+
+/// Commit with the git CLI so hooks and commit signing apply
+fn commit_with_cli(repo_path: &Path, git: &GitConfig, message: &str, amend: bool) -> Result<()> {
+    let mut cmd = Command::new(&git.binary);
+    cmd.args(&git.extra_args)
+        .args(["commit", "--quiet", "--cleanup=strip", "--file=-"])
+        .current_dir(repo_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    if amend {
+        cmd.arg("--amend");
+    }
+
+    let mut child = cmd.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(message.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // Hooks can be chatty; the last line is usually the verdict
+        let reason = stderr.lines().rfind(|l| !l.trim().is_empty()).unwrap_or("");
+        bail!("git commit failed: {}", reason.trim());
+    }
+    Ok(())
+}
+
+/// Commit with libgit2 when the git CLI isn't available (no hooks run)
+fn commit_with_libgit2(repo_path: &Path, message: &str, amend: bool) -> Result<()> {
+    let repo = Repository::open(repo_path)?;
+    let message = git2::message_prettify(message, Some(b'#'))?;
+    if message.is_empty() {
+        bail!("Aborting commit due to empty message");
+    }
+
+    let signature = repo
+        .signature()
+        .context("Set user.name and user.email to commit")?;
+    let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+    let head = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+
+    if amend {
+        let head = head.context("No commit to amend")?;
+        head.amend(
+            Some("HEAD"),
+            None,
+            Some(&signature),
+            None,
+            Some(&message),
+            Some(&tree),
+        )?;
+    } else {
+        if head.as_ref().is_some_and(|h| h.tree_id() == tree.id()) {
+            bail!("Nothing staged to commit");
+        }
+        let parents: Vec<_> = head.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            &message,
+            &tree,
+            &parents,
+        )?;
+    }
+    Ok(())
+}
+
+fn head_short_id(repo_path: &Path) -> Result<String> {
+    let repo = Repository::open(repo_path)?;
+    let head = repo.head()?.peel_to_commit()?;
+    let id = head.as_object().short_id()?;
+    Ok(id.as_str().unwrap_or_default().to_string())
+}
@@ -0,0 +1,68 @@
+use super::history;
+use crate::model::{ComparePoint, Comparison, FileStatus};
+use anyhow::{Context, Result, bail};
+use git2::{DiffOptions, ObjectType, Repository, Tree};
+use std::path::{Path, PathBuf};
+
+/// Whether a comparison point names a directory
+pub fn is_dir(repo_path: &Path, point: &ComparePoint) -> bool {
+    match &point.rev {
+        Some(rev) => Repository::open(repo_path)
+            .ok()
+            .and_then(|repo| {
+                let spec = format!("{rev}:{}", point.path.to_string_lossy());
+                repo.revparse_single(&spec).ok().map(|o| o.kind())
+            })
+            .is_some_and(|kind| kind == Some(ObjectType::Tree)),
+        None => repo_path.join(&point.path).is_dir(),
+    }
+}
+
+/// Files that differ between two compared directories, relative to those directories
+pub fn changed_files(
+    repo_path: &Path,
+    comparison: &Comparison,
+) -> Result<Vec<(PathBuf, FileStatus)>> {
+    let repo = Repository::open(repo_path)?;
+    let (from, to) = (&comparison.from, &comparison.to);
+    let mut opts = DiffOptions::new();
+
+    let diff = match (&from.rev, &to.rev) {
+        (Some(from_rev), Some(to_rev)) => {
+            let from_tree = subtree(&repo, from_rev, &from.path)?;
+            let to_tree = subtree(&repo, to_rev, &to.path)?;
+            repo.diff_tree_to_tree(Some(&from_tree), Some(&to_tree), Some(&mut opts))?
+        }
+        (Some(rev), None) | (None, Some(rev)) => {
+            // libgit2 diffs the whole working tree, so both sides must be the same directory
+            if from.path != to.path {
+                bail!("Comparing with the working tree needs the same directory on both sides");
+            }
+            if !from.path.as_os_str().is_empty() {
+                opts.pathspec(&from.path);
+            }
+            opts.include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .reverse(from.rev.is_none());
+            let tree = subtree(&repo, rev, Path::new(""))?;
+            let diff = repo.diff_tree_to_workdir(Some(&tree), Some(&mut opts))?;
+            return Ok(history::diff_files(&diff)
+                .into_iter()
+                .map(|(path, status)| {
+                    let relative = path.strip_prefix(&from.path).unwrap_or(&path);
+                    (relative.to_path_buf(), status)
+                })
+                .collect());
+        }
+        (None, None) => bail!("Both sides are the working tree"),
+    };
+    Ok(history::diff_files(&diff))
+}
+
+/// The tree of `dir` at `rev` (an empty `dir` is the root)
+fn subtree<'r>(repo: &'r Repository, rev: &str, dir: &Path) -> Result<Tree<'r>> {
+    let spec = format!("{rev}:{}", dir.to_string_lossy());
+    repo.revparse_single(&spec)
+        .and_then(|object| object.peel_to_tree())
+        .with_context(|| format!("{spec} is not a directory"))
+}
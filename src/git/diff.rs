@@ -1,51 +1,237 @@
-use crate::model::{DiffState, FileStatus};
+use super::blame;
+use super::patch::{self, FilePatch, Hunk, PatchSource, RenameDetection};
+use super::submodule;
+use crate::config::{ColorConfig, FormatterConfig, GitConfig};
+use crate::github::ReviewComment;
+use crate::model::{Comparison, DiffChunk, DiffState, FileStatus, RevRange};
+use crate::shell;
+use crate::ui::patch_view::{self, PatchLayout};
 use ansi_to_tui::IntoText;
-use anyhow::Result;
-use ratatui::text::Text;
-use std::path::Path;
-use std::process::{Command, Stdio};
+use anyhow::{Context, Result, bail};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
-use std::thread;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-pub fn delta_available() -> bool {
-    Command::new("delta")
+/// Whether the configured git CLI can be run. Without it, patches are
+/// generated with libgit2 instead.
+pub fn git_available(git: &GitConfig) -> bool {
+    Command::new(&git.binary)
         .arg("--version")
         .output()
         .map(|o| o.status.success())
         .unwrap_or(false)
 }
 
+/// Whether the configured formatter's program can be found. Without it diffs
+/// use the built-in renderer.
+pub fn formatter_available(formatter: &FormatterConfig) -> bool {
+    shell::split_args(&formatter.command)
+        .first()
+        .is_some_and(|program| shell::program_exists(program))
+}
+
+/// A patch small enough to render instantly, for checking the formatter's arguments
+const SAMPLE_PATCH: &str = "diff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b\n";
+
+/// Run the formatter with the user's args on a tiny patch so a bad flag shows
+/// up at startup rather than as a broken diff. Returns its complaint if it fails.
+pub fn check_formatter_args(repo_path: &Path, formatter: &FormatterConfig) -> Option<String> {
+    formatter.user_args()?;
+    let command = formatter_command(formatter, 80);
+    let output = run_formatter(
+        repo_path,
+        &command,
+        80,
+        Some(SAMPLE_PATCH.into()),
+        &Cancel::default(),
+        &mut |_| {},
+    )
+    .ok()?;
+    (!output.status.success()).then(|| failure_reason(&output))
+}
+
+/// Settings shared by every diff pipeline
+#[derive(Debug, Clone, Default)]
+pub struct PipelineOptions {
+    pub git: GitConfig,
+    /// Whether the git CLI is usable (otherwise libgit2 generates patches)
+    pub git_available: bool,
+    pub formatter: FormatterConfig,
+    /// Color of last-touch annotations on hunk headers; None when they're off
+    pub annotate: Option<Color>,
+    /// Files listed as one-line summaries after the diff instead of diffed
+    pub summarize: Vec<PathBuf>,
+    /// Render with kibitz's own renderer in these colors instead of delta
+    pub builtin: Option<(PatchLayout, ColorConfig)>,
+    /// Pull request review comments, counted on their hunks in this color
+    pub comments: Option<(Arc<[ReviewComment]>, Color)>,
+    /// Number the formatter's lines for the gutter
+    pub line_numbers: bool,
+    /// Lines of context around changes; None leaves git's default
+    pub context: Option<u32>,
+    /// How deleted and added files are paired into renames and copies
+    pub renames: RenameDetection,
+    /// List the commits between a submodule's old and new commit
+    pub submodule_log: bool,
+    /// Leave out whitespace-only changes and blank lines (`-w
+    /// --ignore-blank-lines`)
+    pub ignore_whitespace: bool,
+    /// Show git's own word diff instead of the formatter's rendering, or
+    /// highlight changed words in the built-in renderer
+    pub word_diff: bool,
+    /// Lines of diff kept before the rest is cut off; None keeps them all
+    pub max_lines: Option<usize>,
+    /// Set once the diff is no longer wanted
+    pub cancel: Cancel,
+    /// Where a slow formatter's output so far is shown
+    pub progress: Progress,
+}
+
+/// Shared flag telling a diff's worker to give up. Clones share the flag.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn is_canceled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Stop here if the diff was canceled
+    fn check(&self) -> Result<()> {
+        if self.is_canceled() {
+            bail!("Diff canceled");
+        }
+        Ok(())
+    }
+}
+
+/// What a slow formatter has put out of a load still running, since it was
+/// last shown. Clones share it.
+#[derive(Clone, Default)]
+pub struct Progress(Arc<Mutex<Option<DiffChunk>>>);
+
+impl Progress {
+    fn publish(&self, chunk: DiffChunk) {
+        if let Ok(mut slot) = self.0.lock() {
+            match slot.as_mut() {
+                Some(unseen) => unseen.append(chunk),
+                None => *slot = Some(chunk),
+            }
+        }
+    }
+
+    /// The lines published since the last call, if any
+    fn take(&self) -> Option<DiffChunk> {
+        self.0.lock().ok()?.take()
+    }
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Progress")
+    }
+}
+
+/// A diff loading in the background. Dropping it (say, for a newer request)
+/// cancels the load: the worker kills the processes it started and its result is
+/// never delivered.
+pub struct PendingDiff {
+    rx: mpsc::Receiver<Result<DiffState>>,
+    cancel: Cancel,
+    progress: Progress,
+}
+
+impl PendingDiff {
+    /// A diff that's already loaded, delivered like one from a worker
+    pub fn ready(diff: DiffState) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let _ = tx.send(Ok(diff));
+        Self {
+            rx,
+            cancel: Cancel::default(),
+            progress: Progress::default(),
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<Result<DiffState>, mpsc::TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    /// More of the diff, while its formatter is still writing it
+    pub fn try_recv_partial(&self) -> Option<DiffChunk> {
+        self.progress.take()
+    }
+}
+
+impl Drop for PendingDiff {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
 /// Request to load a diff asynchronously
 pub struct DiffRequest {
     pub repo_path: std::path::PathBuf,
     pub file_path: std::path::PathBuf,
+    /// Where the file was renamed or copied from in the index
+    pub renamed_from: Option<PathBuf>,
     pub status: Option<FileStatus>,
     pub width: usize,
     pub staged: bool,
-    pub delta_args: Option<String>,
+    pub opts: PipelineOptions,
 }
 
 /// Spawn a function on a thread and return a receiver for the result
-fn spawn_diff<F>(f: F) -> mpsc::Receiver<DiffState>
+fn spawn_diff<F>(cancel: Cancel, progress: Progress, f: F) -> PendingDiff
 where
     F: FnOnce() -> Result<DiffState> + Send + 'static,
 {
     let (tx, rx) = mpsc::channel();
+    let worker_cancel = cancel.clone();
     thread::spawn(move || {
-        let _ = tx.send(f().unwrap_or_default());
+        let diff = f();
+        if !worker_cancel.is_canceled() {
+            let _ = tx.send(diff);
+        }
     });
-    rx
+    PendingDiff {
+        rx,
+        cancel,
+        progress,
+    }
 }
 
 /// Spawn async diff loading, returns a receiver for the result
-pub fn load_diff_async(req: DiffRequest) -> mpsc::Receiver<DiffState> {
-    spawn_diff(move || get_diff_sync(&req))
+pub fn load_diff_async(req: DiffRequest) -> PendingDiff {
+    spawn_diff(
+        req.opts.cancel.clone(),
+        req.opts.progress.clone(),
+        move || get_diff_sync(&req),
+    )
 }
 
 fn get_diff_sync(req: &DiffRequest) -> Result<DiffState> {
-    let diff_cmd = build_diff_command(req);
     let has_both = req.status.is_some_and(|s| s.has_both());
-    run_diff_command(&req.repo_path, &diff_cmd, req.width, has_both, req.staged)
+    run_diff(
+        &req.repo_path,
+        &patch_source(req),
+        req.width,
+        &req.opts,
+        has_both,
+        req.staged,
+    )
 }
 
 /// Try to extract a file name from a delta file header line.
@@ -60,263 +246,1070 @@ fn extract_file_name(line: &str) -> Option<String> {
     } else if let Some(rest) = trimmed.strip_prefix("removed:") {
         Some(rest.trim().to_string())
     } else if let Some(rest) = trimmed.strip_prefix("renamed:") {
-        // Format is "old → new", extract the new name
+        // Format is "old → new" (diff-so-fancy: "old to new"), extract the new name
         if let Some(arrow_pos) = rest.find('→') {
             Some(rest[arrow_pos + '→'.len_utf8()..].trim().to_string())
+        } else if let Some((_, new)) = rest.split_once(" to ") {
+            Some(new.trim().to_string())
         } else {
             Some(rest.trim().to_string())
         }
+    } else if let Some(rest) = trimmed
+        .strip_prefix("modified:")
+        .or_else(|| trimmed.strip_prefix("deleted:"))
+    {
+        // diff-so-fancy
+        Some(rest.trim().to_string())
+    } else if let Some(rest) = trimmed.strip_prefix("diff --git ") {
+        // Formatters that keep the patch's own headers (riff, bat)
+        rest.split_once(" b/").map(|(_, new)| new.to_string())
     } else {
         None
     }
 }
 
-/// Result of parsing delta output for hunk navigation
+/// Whether a line starts a hunk: delta's `• 12:`, a plain `@@ -1 +1 @@` (riff,
+/// bat) or diff-so-fancy's `@ file:12 @`
+fn is_hunk_marker(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.starts_with('•')
+        || trimmed.starts_with("@@ ")
+        || (trimmed.starts_with("@ ") && trimmed.trim_end().ends_with(" @"))
+}
+
+/// Result of parsing formatter output for hunk navigation
+#[derive(Default)]
 struct HunkParseResult {
     positions: Vec<usize>,             // Navigation targets for J/K
     file_header_positions: Vec<usize>, // File header lines (Δ, added:, etc.)
+    file_names: Vec<String>,           // File name for each file header
     hunk_marker_positions: Vec<usize>, // Hunk marker lines (•)
 }
 
-/// Find hunk positions in delta output by looking for hunk markers (•)
+/// Finds hunk positions in delta output one line at a time, by looking for hunk
+/// markers (•).
 /// For the first hunk in each file, we use the file header position instead
 /// so the user sees the file context when navigating.
 /// For subsequent hunks, we back up to the box top line (───) to show the full header.
-fn find_hunk_positions(content: &Text) -> HunkParseResult {
-    let mut positions = Vec::new();
-    let mut file_header_positions = Vec::new();
-    let mut hunk_marker_positions = Vec::new();
-    let mut last_file_header_pos: Option<usize> = None;
-    let mut used_file_header = false;
-
-    for (i, line) in content.lines.iter().enumerate() {
-        let text: String = line.spans.iter().map(|s| s.content.as_ref()).collect();
+#[derive(Default)]
+struct HunkParser {
+    result: HunkParseResult,
+    last_file_header_pos: Option<usize>,
+    used_file_header: bool,
+    /// Text of the line before the next one
+    prev_text: String,
+    /// Lines taken in so far
+    lines: usize,
+}
 
-        if extract_file_name(&text).is_some() {
+impl HunkParser {
+    /// Take in line `i` of the output
+    fn push(&mut self, i: usize, text: String) {
+        if let Some(name) = extract_file_name(&text) {
             // Track file header position
-            last_file_header_pos = Some(i);
-            file_header_positions.push(i);
-            used_file_header = false;
-        } else if text.trim_start().starts_with('•') {
+            self.last_file_header_pos = Some(i);
+            self.result.file_header_positions.push(i);
+            self.result.file_names.push(name);
+            self.used_file_header = false;
+        } else if is_hunk_marker(&text) {
             // Track the actual hunk marker position for sticky headers
-            hunk_marker_positions.push(i);
+            self.result.hunk_marker_positions.push(i);
 
             // For first hunk after a file header, use the file header position
-            if !used_file_header {
-                if let Some(header_pos) = last_file_header_pos {
-                    positions.push(header_pos);
-                    used_file_header = true;
+            if !self.used_file_header {
+                if let Some(header_pos) = self.last_file_header_pos {
+                    self.result.positions.push(header_pos);
+                    self.used_file_header = true;
                 } else {
-                    positions.push(i);
+                    self.result.positions.push(i);
                 }
             } else {
                 // For subsequent hunks, back up to show the box top line (───)
                 // The line before • should be the box top
-                let hunk_pos = if i > 0 {
-                    let prev_text: String = content.lines[i - 1]
-                        .spans
-                        .iter()
-                        .map(|s| s.content.as_ref())
-                        .collect();
-                    if prev_text.trim_start().starts_with('─') {
-                        i - 1
-                    } else {
-                        i
-                    }
+                let hunk_pos = if i > 0 && self.prev_text.trim_start().starts_with('─') {
+                    i - 1
                 } else {
                     i
                 };
-                positions.push(hunk_pos);
+                self.result.positions.push(hunk_pos);
             }
         }
+        self.prev_text = text;
+        self.lines = i + 1;
+    }
+}
+
+fn line_text(line: &Line) -> String {
+    line.spans.iter().map(|s| s.content.as_ref()).collect()
+}
+
+/// A diff built from a formatter's output as it arrives, parsing only what's
+/// new each time. What's added while the formatter runs is published as it
+/// comes; the rest is taken in when it's done.
+struct StreamedDiff<'a> {
+    content: Text<'static>,
+    raw_lines: Vec<String>,
+    parser: HunkParser,
+    /// Output after the last complete line
+    rest: Vec<u8>,
+    /// Bytes of output taken in so far
+    seen: usize,
+    /// Lines of output so far, counting those past `max_lines`
+    total: usize,
+    max_lines: Option<usize>,
+    has_both: bool,
+    showing_staged: bool,
+    progress: &'a Progress,
+}
+
+impl<'a> StreamedDiff<'a> {
+    fn new(
+        has_both: bool,
+        showing_staged: bool,
+        max_lines: Option<usize>,
+        progress: &'a Progress,
+    ) -> Self {
+        Self {
+            content: Text::default(),
+            raw_lines: Vec::new(),
+            parser: HunkParser::default(),
+            rest: Vec::new(),
+            seen: 0,
+            total: 0,
+            max_lines,
+            has_both,
+            showing_staged,
+            progress,
+        }
+    }
+
+    /// Start over, for output that replaces what came so far
+    fn restart(&mut self) {
+        *self = Self::new(
+            self.has_both,
+            self.showing_staged,
+            self.max_lines,
+            self.progress,
+        );
+    }
+
+    /// Take in `output` (more of the formatter's output) and publish the
+    /// complete lines it adds
+    fn update(&mut self, output: &[u8]) {
+        let lines = self.content.lines.len();
+        let parsed = &self.parser.result;
+        let counts = (
+            parsed.positions.len(),
+            parsed.file_header_positions.len(),
+            parsed.hunk_marker_positions.len(),
+        );
+        self.take_in(output);
+        if self.content.lines.len() == lines {
+            return;
+        }
+        let parsed = &self.parser.result;
+        self.progress.publish(DiffChunk {
+            has_both: self.has_both,
+            showing_staged: self.showing_staged,
+            lines: self.content.lines[lines..].to_vec(),
+            raw_lines: self.raw_lines[lines..].to_vec(),
+            hunk_positions: parsed.positions[counts.0..].to_vec(),
+            file_header_positions: parsed.file_header_positions[counts.1..].to_vec(),
+            file_names: parsed.file_names[counts.1..].to_vec(),
+            hunk_marker_positions: parsed.hunk_marker_positions[counts.2..].to_vec(),
+        });
     }
 
-    HunkParseResult {
-        positions,
-        file_header_positions,
-        hunk_marker_positions,
+    /// The diff of `output`, the formatter's whole output, with the lines
+    /// past `max_lines` cut off: its lines, their text, the hunks and files
+    /// found, and the limit and total when some were cut
+    fn finish(
+        mut self,
+        output: &[u8],
+    ) -> (
+        Text<'static>,
+        Vec<String>,
+        HunkParser,
+        Option<(usize, usize)>,
+    ) {
+        self.take_in(output.get(self.seen..).unwrap_or_default());
+        let last = std::mem::take(&mut self.rest);
+        if !last.is_empty() {
+            self.push_lines(&last);
+        }
+        let cut = self
+            .max_lines
+            .filter(|&max| self.total > max)
+            .map(|max| (max, self.total));
+        (self.content, self.raw_lines, self.parser, cut)
+    }
+
+    /// Add the complete lines of `output`
+    fn take_in(&mut self, output: &[u8]) {
+        self.seen += output.len();
+        self.rest.extend_from_slice(output);
+        let Some(end) = self.rest.iter().rposition(|&b| b == b'\n') else {
+            return;
+        };
+        let chunk: Vec<u8> = self.rest.drain(..=end).collect();
+        self.push_lines(&chunk[..end]);
     }
+
+    /// Add the lines of `chunk`, which doesn't end in a newline. Past the
+    /// limit they're only counted, since converting them is where a huge
+    /// diff's memory would go.
+    fn push_lines(&mut self, chunk: &[u8]) {
+        let count = chunk.iter().filter(|&&b| b == b'\n').count() + 1;
+        self.total += count;
+        let room = self
+            .max_lines
+            .map_or(count, |max| max.saturating_sub(self.content.lines.len()));
+        if room == 0 {
+            return;
+        }
+        let chunk = if room < count {
+            let newlines = chunk.iter().enumerate().filter(|&(_, &b)| b == b'\n');
+            &chunk[..newlines
+                .map(|(i, _)| i)
+                .nth(room - 1)
+                .unwrap_or(chunk.len())]
+        } else {
+            chunk
+        };
+        let raw_lines: Vec<String> = String::from_utf8_lossy(chunk)
+            .split('\n')
+            .map(String::from)
+            .collect();
+        let mut lines = chunk.into_text().unwrap_or_default().lines;
+        lines.resize(raw_lines.len(), Line::default());
+        for mut line in lines {
+            patch_view::expand_tabs(&mut line);
+            self.parser.push(self.content.lines.len(), line_text(&line));
+            self.content.lines.push(line);
+        }
+        self.raw_lines.extend(raw_lines);
+    }
+}
+
+/// The formatter's command line: the configured command, its defaults for
+/// running in a pipe, then the user's args.
+/// Delta can't detect the terminal width through a pipe and `COLUMNS` isn't honored
+/// on every platform (notably macOS), so the width is passed explicitly unless the
+/// user already set one. bat gets it too; the others follow `COLUMNS`.
+fn formatter_command(formatter: &FormatterConfig, width: usize) -> Vec<String> {
+    let user_args = shell::split_args(formatter.user_args().unwrap_or(""));
+    let has_width = user_args.iter().any(|arg| {
+        arg.starts_with("-w") || arg.starts_with("--width") || arg.starts_with("--terminal-width")
+    });
+    let width_arg = |flag: &str| (!has_width).then(|| format!("{flag}={width}"));
+    let defaults: Vec<String> = match formatter.name() {
+        "delta" => ["--paging=never".to_string()]
+            .into_iter()
+            .chain(width_arg("--width"))
+            .collect(),
+        "bat" => [
+            "--language=diff",
+            "--paging=never",
+            "--color=always",
+            "--style=plain",
+        ]
+        .map(String::from)
+        .into_iter()
+        .chain(width_arg("--terminal-width"))
+        .collect(),
+        "riff" => vec!["--color=on".to_string()],
+        _ => Vec::new(),
+    };
+    shell::split_args(&formatter.command)
+        .into_iter()
+        .chain(defaults)
+        .chain(user_args)
+        .collect()
 }
 
-fn build_diff_command(req: &DiffRequest) -> String {
-    let file_path = req.file_path.to_string_lossy();
-    let user_args = req.delta_args.as_deref().unwrap_or("");
+/// Whether git should color the patch it pipes into the formatter.
+/// diff-so-fancy works from git's colors and delta ignores them; the others
+/// need a plain patch.
+fn wants_color(formatter: &FormatterConfig) -> bool {
+    matches!(formatter.name(), "delta" | "diff-so-fancy")
+}
 
+fn patch_source(req: &DiffRequest) -> PatchSource {
+    let paths = vec![req.file_path.clone()];
     match req.status {
-        Some(FileStatus::Untracked) => {
-            // For untracked files, show content as new file
-            format!(
-                "git diff --no-index --color=always -- /dev/null '{}' 2>/dev/null | delta --paging=never {} || cat '{}'",
-                file_path, user_args, file_path
-            )
-        }
+        Some(FileStatus::Untracked) => PatchSource::Untracked(req.file_path.clone()),
+        // Naming the old path too lets git pair them up
         Some(s) if s.has_staged() && req.staged => {
-            format!(
-                "git diff --cached --color=always -- '{}' | delta --paging=never {}",
-                file_path, user_args
-            )
+            PatchSource::Staged(paths.into_iter().chain(req.renamed_from.clone()).collect())
         }
-        _ => {
-            format!(
-                "git diff --color=always -- '{}' | delta --paging=never {}",
-                file_path, user_args
-            )
+        _ => PatchSource::Unstaged(paths),
+    }
+}
+
+/// Config overrides that keep the patch format predictable for delta and the header
+/// parser: `a/`/`b/` prefixes and repo-root-relative paths, whatever the user's
+/// diff.noprefix, diff.mnemonicPrefix, etc. say. User `extra_args` come after these.
+const NORMALIZE_DIFF_CONFIG: &[&str] = &[
+    "-c",
+    "diff.noprefix=false",
+    "-c",
+    "diff.mnemonicPrefix=false",
+    "-c",
+    "diff.srcPrefix=a/",
+    "-c",
+    "diff.dstPrefix=b/",
+    "-c",
+    "diff.relative=false",
+];
+
+/// The configured git binary's command with the normalizing config and the
+/// user's extra global flags
+fn git_command(git: &GitConfig) -> Command {
+    let mut cmd = Command::new(&git.binary);
+    cmd.args(NORMALIZE_DIFF_CONFIG).args(&git.extra_args);
+    cmd
+}
+
+/// git's arguments for producing `source`'s patch, after the global ones.
+/// None for sources git can't diff directly (they go through libgit2).
+fn git_diff_args(
+    source: &PatchSource,
+    color: &str,
+    opts: &PipelineOptions,
+) -> Option<Vec<OsString>> {
+    let color = format!("--color={color}");
+
+    let (args, paths): (Vec<&str>, &[PathBuf]) = match source {
+        PatchSource::Untracked(path) => {
+            // Compared to nothing, an untracked file shows as entirely added
+            let args = vec![
+                "diff",
+                "--no-index",
+                "--no-ext-diff",
+                &color,
+                "--",
+                "/dev/null",
+            ];
+            (args, std::slice::from_ref(path))
         }
+        PatchSource::Staged(paths) => (
+            vec!["diff", "--cached", "--no-ext-diff", &color, "--"],
+            paths,
+        ),
+        PatchSource::Unstaged(paths) => (vec!["diff", "--no-ext-diff", &color, "--"], paths),
+        PatchSource::Commit(oid, paths) => (
+            vec![
+                "show",
+                "--no-ext-diff",
+                "--format=",
+                "-m",
+                "--first-parent",
+                &color,
+                oid,
+                "--",
+            ],
+            paths,
+        ),
+        PatchSource::Range(from, to, paths) => {
+            (vec!["diff", "--no-ext-diff", &color, from, to, "--"], paths)
+        }
+        PatchSource::Compare(_) => return None,
+    };
+    let mut args: Vec<OsString> = args
+        .into_iter()
+        .map(OsString::from)
+        .chain(paths.iter().map(|p| p.as_os_str().to_owned()))
+        .collect();
+    // Right after the subcommand
+    if let Some(context) = opts.context {
+        args.insert(1, format!("--unified={context}").into());
+    }
+    if opts.ignore_whitespace {
+        args.splice(1..1, ["-w".into(), "--ignore-blank-lines".into()]);
+    }
+    if !matches!(source, PatchSource::Untracked(_)) {
+        args.splice(
+            1..1,
+            opts.renames.git_args().into_iter().map(OsString::from),
+        );
     }
+    Some(args)
 }
 
 pub fn get_diff(
     repo_path: &Path,
     file_path: &Path,
+    renamed_from: Option<&Path>,
     status: Option<FileStatus>,
     width: usize,
-    delta_args: Option<String>,
-) -> mpsc::Receiver<DiffState> {
+    opts: PipelineOptions,
+) -> PendingDiff {
     // Default: show unstaged if file has both, otherwise show staged if only staged
     let staged = status.is_some_and(|s| !s.has_both() && s.has_staged());
-    get_diff_staged(repo_path, file_path, status, width, staged, delta_args)
+    get_diff_staged(
+        repo_path,
+        file_path,
+        renamed_from,
+        status,
+        width,
+        staged,
+        opts,
+    )
 }
 
 pub fn get_diff_staged(
     repo_path: &Path,
     file_path: &Path,
+    renamed_from: Option<&Path>,
     status: Option<FileStatus>,
     width: usize,
     staged: bool,
-    delta_args: Option<String>,
-) -> mpsc::Receiver<DiffState> {
+    opts: PipelineOptions,
+) -> PendingDiff {
     load_diff_async(DiffRequest {
         repo_path: repo_path.to_path_buf(),
         file_path: file_path.to_path_buf(),
+        renamed_from: renamed_from.map(Path::to_path_buf),
         status,
         width,
         staged,
-        delta_args,
+        opts,
     })
 }
 
-/// Get combined diff for multiple files (used for folder diffs)
+/// Get combined diff for multiple files (used for folder diffs), staged or unstaged
 pub fn get_diff_for_paths(
     repo_path: &Path,
     file_paths: &[std::path::PathBuf],
+    staged: bool,
     width: usize,
-    delta_args: Option<String>,
-) -> mpsc::Receiver<DiffState> {
+    opts: PipelineOptions,
+) -> PendingDiff {
     let repo_path = repo_path.to_path_buf();
     let file_paths = file_paths.to_vec();
-    spawn_diff(move || get_multi_diff_sync(&repo_path, &file_paths, width, delta_args.as_deref()))
-}
-
-/// Build shell-quoted file path arguments
-fn quote_paths(paths: &[std::path::PathBuf]) -> String {
-    paths
-        .iter()
-        .map(|p| format!("'{}'", p.to_string_lossy()))
-        .collect::<Vec<_>>()
-        .join(" ")
+    spawn_diff(opts.cancel.clone(), opts.progress.clone(), move || {
+        get_multi_diff_sync(&repo_path, &file_paths, staged, width, &opts)
+    })
 }
 
 fn get_multi_diff_sync(
     repo_path: &Path,
     file_paths: &[std::path::PathBuf],
+    staged: bool,
     width: usize,
-    delta_args: Option<&str>,
+    opts: &PipelineOptions,
 ) -> Result<DiffState> {
-    if file_paths.is_empty() {
+    if file_paths.is_empty() && opts.summarize.is_empty() {
         return Ok(DiffState::new());
     }
 
-    let user_args = delta_args.unwrap_or("");
-    let diff_cmd = format!(
-        "git diff --color=always -- {} | delta --paging=never {}",
-        quote_paths(file_paths),
-        user_args
-    );
-
-    run_diff_command(repo_path, &diff_cmd, width, false, false)
+    let source = if staged {
+        PatchSource::Staged(file_paths.to_vec())
+    } else {
+        PatchSource::Unstaged(file_paths.to_vec())
+    };
+    run_diff(repo_path, &source, width, opts, false, staged)
 }
 
-/// Run a diff command and convert output to DiffState
-fn run_diff_command(
+/// Render a patch through delta and convert output to DiffState
+fn run_diff(
     repo_path: &Path,
-    diff_cmd: &str,
+    source: &PatchSource,
     width: usize,
+    opts: &PipelineOptions,
     has_both: bool,
     showing_staged: bool,
 ) -> Result<DiffState> {
-    let output = Command::new("sh")
-        .args(["-c", diff_cmd])
-        .current_dir(repo_path)
-        .env("TERM", "xterm-256color")
-        .env("COLUMNS", width.to_string())
-        .env("FORCE_COLOR", "1")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output()?;
-
-    let content = output.stdout.into_text().unwrap_or_default();
+    opts.cancel.check()?;
+    // With every file summarized there's nothing to diff, and no pathspecs
+    // would mean everything
+    let (mut content, mut raw_lines, mut parser, truncated) =
+        if source.paths().is_empty() && !opts.summarize.is_empty() {
+            (Text::default(), Vec::new(), HunkParser::default(), false)
+        } else if let Some((layout, colors)) = &opts.builtin {
+            let patch = patch::generate_with_context(
+                repo_path,
+                source,
+                opts.context,
+                opts.ignore_whitespace,
+                opts.renames,
+            )?;
+            let mut content = patch_view::render(
+                &String::from_utf8_lossy(&patch),
+                width,
+                *layout,
+                colors,
+                opts.word_diff,
+            );
+            let mut raw_lines = content.lines.iter().map(Line::to_string).collect();
+            let max = opts.max_lines.filter(|&max| content.lines.len() > max);
+            if let Some(max) = max {
+                let total = content.lines.len();
+                content.lines.truncate(max);
+                append_truncation_note(max, total, &mut content, &mut raw_lines);
+            }
+            (content, raw_lines, HunkParser::default(), max.is_some())
+        } else {
+            let mut streamed =
+                StreamedDiff::new(has_both, showing_staged, opts.max_lines, &opts.progress);
+            let output = render_patch(repo_path, source, width, opts, &mut streamed)?;
+            let (mut content, mut raw_lines, parser, cut) = streamed.finish(&output);
+            if let Some((max, total)) = cut {
+                append_truncation_note(max, total, &mut content, &mut raw_lines);
+            }
+            (content, raw_lines, parser, cut.is_some())
+        };
+    if let Some(summarized) = source.with_paths(opts.summarize.clone())
+        && !opts.summarize.is_empty()
+    {
+        append_summaries(repo_path, &summarized, &mut content, &mut raw_lines);
+    }
+    append_submodules(repo_path, source, opts, &mut content, &mut raw_lines);
     let total_lines = content.lines.len();
-    let parsed = find_hunk_positions(&content);
+    // Only the lines added since streaming (or all of the built-in
+    // renderer's) are left to parse
+    for (i, line) in content.lines.iter().enumerate().skip(parser.lines) {
+        parser.push(i, line_text(line));
+    }
+    let parsed = parser.result;
+    opts.cancel.check()?;
+    let mut line_numbers = Vec::new();
+    if (opts.annotate.is_some() || opts.comments.is_some() || opts.line_numbers)
+        && let Ok(patch) = patch::generate_with_context(
+            repo_path,
+            source,
+            opts.context,
+            opts.ignore_whitespace,
+            opts.renames,
+        )
+    {
+        let files = patch::parse(&String::from_utf8_lossy(&patch));
+        let hunks: Vec<_> = files
+            .iter()
+            .flat_map(|file| file.hunks.iter().map(move |hunk| (file, hunk)))
+            .collect();
+        // Skipped when the patch and the formatter's output disagree on hunks
+        if hunks.len() == parsed.hunk_marker_positions.len() {
+            let markers = &parsed.hunk_marker_positions;
+            if let Some(color) = opts.annotate {
+                annotate_hunks(repo_path, source, &mut content, &hunks, markers, color);
+            }
+            if let Some((comments, color)) = &opts.comments {
+                mark_comments(&mut content, &hunks, markers, comments, *color);
+            }
+            if opts.line_numbers {
+                line_numbers = number_lines(&content, &hunks, markers);
+            }
+        }
+    }
 
     Ok(DiffState {
-        content,
+        content: Arc::new(content),
+        raw_lines: Arc::new(raw_lines),
         scroll_offset: 0,
+        x_offset: 0,
+        wrap: true,
         hunk_positions: parsed.positions,
         file_header_positions: parsed.file_header_positions,
+        file_names: parsed.file_names,
         hunk_marker_positions: parsed.hunk_marker_positions,
         current_hunk: 0,
         total_lines,
         has_both,
         showing_staged,
+        search: None,
+        truncated,
+        line_numbers,
     })
 }
 
-/// Get diff for a specific file within a commit
-pub fn get_commit_file_diff(
+/// The formatter's rendering of `source`'s patch. `streamed` takes in the
+/// output of a slow formatter while it's still running.
+fn render_patch(
     repo_path: &Path,
-    oid: &str,
-    file_path: &Path,
+    source: &PatchSource,
     width: usize,
-    delta_args: Option<String>,
-) -> mpsc::Receiver<DiffState> {
-    let repo_path = repo_path.to_path_buf();
-    let oid = oid.to_string();
-    let file_path = file_path.to_path_buf();
-    spawn_diff(move || {
-        get_commit_file_diff_sync(&repo_path, &oid, &file_path, width, delta_args.as_deref())
+    opts: &PipelineOptions,
+    streamed: &mut StreamedDiff,
+) -> Result<Vec<u8>> {
+    let on_output = &mut |output: &[u8]| streamed.update(output);
+    let formatter = formatter_command(&opts.formatter, width);
+    let color = if opts.word_diff || wants_color(&opts.formatter) {
+        "always"
+    } else {
+        "never"
+    };
+    let output = match git_diff_args(source, color, opts).filter(|_| opts.git_available) {
+        // Formatters expect a patch, so git's word diff is shown as it is
+        Some(mut args) if opts.word_diff => {
+            args.insert(1, "--word-diff=color".into());
+            let mut git = git_command(&opts.git);
+            git.args(args)
+                .current_dir(repo_path)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            let output = wait_cancelable(git.spawn()?, &opts.cancel, on_output)?;
+            // `--no-index` exits with 1 when the files differ
+            if !matches!(output.status.code(), Some(0 | 1)) {
+                bail!("git's word diff failed: {}", failure_reason(&output));
+            }
+            return Ok(output.stdout);
+        }
+        Some(args) => {
+            let mut git = git_command(&opts.git);
+            git.args(args);
+            run_pipeline(repo_path, git, &formatter, width, &opts.cancel, on_output)?
+        }
+        None => {
+            // Let libgit2 produce the patch and feed it to the formatter
+            let patch = patch::generate_with_context(
+                repo_path,
+                source,
+                opts.context,
+                opts.ignore_whitespace,
+                opts.renames,
+            )?;
+            run_formatter(
+                repo_path,
+                &formatter,
+                width,
+                Some(patch),
+                &opts.cancel,
+                on_output,
+            )?
+        }
+    };
+    // The pipeline's status is the formatter's
+    Ok(if output.status.success() {
+        output.stdout
+    } else {
+        streamed.restart();
+        formatter_failure(repo_path, source, opts, &output)
     })
 }
 
-fn get_commit_file_diff_sync(
+/// Say below a cut-off diff how much of it is missing
+fn append_truncation_note(
+    max: usize,
+    total: usize,
+    content: &mut Text<'static>,
+    raw_lines: &mut Vec<String>,
+) {
+    if content.lines.last().is_some_and(|l| l.width() == 0) {
+        content.lines.pop();
+        raw_lines.truncate(content.lines.len());
+    }
+    let note = format!("Diff cut off after {max} of {total} lines; press A to load the rest");
+    for line in [String::new(), note] {
+        content.lines.push(Line::styled(
+            line.clone(),
+            Style::default().add_modifier(Modifier::DIM),
+        ));
+        raw_lines.push(line);
+    }
+}
+
+/// List files left out of the diff (generated, minified, binary) one line each
+/// below it
+fn append_summaries(
     repo_path: &Path,
-    oid: &str,
-    file_path: &Path,
+    source: &PatchSource,
+    content: &mut Text<'static>,
+    raw_lines: &mut Vec<String>,
+) {
+    let mut lines = match patch::summarize(repo_path, source) {
+        Ok(lines) => lines,
+        Err(e) => vec![format!("Couldn't summarize generated files: {e}")],
+    };
+    lines.push("Generated files are summarized; press V to show their diffs".to_string());
+    // Delta's output ends in a newline, leaving an empty last line
+    if content.lines.last().is_some_and(|l| l.width() == 0) {
+        content.lines.pop();
+        raw_lines.pop();
+    }
+    if !content.lines.is_empty() {
+        lines.insert(0, String::new());
+    }
+    let style = Style::default().add_modifier(Modifier::DIM);
+    for line in lines {
+        content.lines.push(Line::styled(line.clone(), style));
+        raw_lines.push(line);
+    }
+}
+
+/// Describe changed submodules below the diff by their old and new commits,
+/// which git's patch only names by id
+fn append_submodules(
+    repo_path: &Path,
+    source: &PatchSource,
+    opts: &PipelineOptions,
+    content: &mut Text<'static>,
+    raw_lines: &mut Vec<String>,
+) {
+    if source.paths().is_empty() || !repo_path.join(".gitmodules").exists() {
+        return;
+    }
+    let lines = match submodule::describe(repo_path, source, opts.submodule_log) {
+        Ok(lines) if lines.is_empty() => return,
+        Ok(lines) => lines,
+        Err(e) => vec![format!("Couldn't read submodule changes: {e}")],
+    };
+    if content.lines.last().is_some_and(|l| l.width() == 0) {
+        content.lines.pop();
+        raw_lines.pop();
+    }
+    if !content.lines.is_empty() {
+        content.lines.push(Line::default());
+        raw_lines.push(String::new());
+    }
+    for line in lines {
+        let style = if line.starts_with("Submodule") {
+            Style::default().add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        content.lines.push(Line::styled(line.clone(), style));
+        raw_lines.push(line);
+    }
+}
+
+/// Append the commit that last touched each hunk's old lines to the hunk's
+/// marker line
+/// The old and new line number of each line of `content`, following each
+/// hunk's lines from below its marker (and the box around it)
+pub fn number_lines(
+    content: &Text,
+    hunks: &[(&FilePatch, &Hunk)],
+    markers: &[usize],
+) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut numbers = vec![(None, None); content.lines.len()];
+    for ((_, hunk), &marker) in hunks.iter().zip(markers) {
+        let mut i = marker + 1;
+        if content
+            .lines
+            .get(i)
+            .is_some_and(|line| line_text(line).trim_start().starts_with('\u{2500}'))
+        {
+            i += 1;
+        }
+        let (mut old, mut new) = (hunk.old_start, hunk.new_start);
+        for line in &hunk.lines {
+            let Some(shown) = content.lines.get(i) else {
+                break;
+            };
+            let number = match line.as_bytes().first() {
+                Some(b'+') => (None, Some(new)),
+                Some(b'-') => (Some(old), None),
+                Some(b' ') => (Some(old), Some(new)),
+                // "\ No newline at end of file", which delta leaves out
+                _ if !line_text(shown).starts_with('\\') => continue,
+                _ => (None, None),
+            };
+            old += usize::from(number.0.is_some());
+            new += usize::from(number.1.is_some());
+            numbers[i] = number;
+            i += 1;
+        }
+    }
+    numbers
+}
+
+fn annotate_hunks(
+    repo_path: &Path,
+    source: &PatchSource,
+    content: &mut Text<'static>,
+    hunks: &[(&FilePatch, &Hunk)],
+    markers: &[usize],
+    color: Color,
+) {
+    let Ok(repo) = git2::Repository::open(repo_path) else {
+        return;
+    };
+    // The commit the old side of the diff comes from
+    let base = match source {
+        PatchSource::Unstaged(_) | PatchSource::Staged(_) => {
+            repo.head().ok().and_then(|head| head.target())
+        }
+        PatchSource::Commit(oid, _) => git2::Oid::from_str(oid)
+            .and_then(|oid| repo.find_commit(oid))
+            .and_then(|commit| commit.parent_id(0))
+            .ok(),
+        PatchSource::Range(from, _, _) => git2::Oid::from_str(from).ok(),
+        PatchSource::Untracked(_) | PatchSource::Compare(_) => None,
+    };
+    let Some(base) = base else {
+        return;
+    };
+
+    let style = Style::default().fg(color);
+    for ((file, hunk), &marker) in hunks.iter().zip(markers) {
+        let Some(old_path) = &file.old_path else {
+            continue;
+        };
+        let Some(touch) = blame::last_touch(&repo, old_path, base, &hunk.old_lines_touched())
+        else {
+            continue;
+        };
+        content.lines[marker].spans.push(Span::styled(
+            format!(
+                "  {} {}, {}: {}",
+                touch.oid, touch.author, touch.date, touch.summary
+            ),
+            style,
+        ));
+    }
+}
+
+/// Append how many review comments are on each hunk to its marker line
+fn mark_comments(
+    content: &mut Text<'static>,
+    hunks: &[(&FilePatch, &Hunk)],
+    markers: &[usize],
+    comments: &[ReviewComment],
+    color: Color,
+) {
+    let style = Style::default().fg(color);
+    for ((file, hunk), &marker) in hunks.iter().zip(markers) {
+        let count = comments.iter().filter(|c| c.is_on(file, hunk)).count();
+        if count > 0 {
+            content.lines[marker]
+                .spans
+                .push(Span::styled(format!("  💬 {count}"), style));
+        }
+    }
+}
+
+/// Why the formatter failed, followed by the plain patch so the diff can still
+/// be read
+fn formatter_failure(
+    repo_path: &Path,
+    source: &PatchSource,
+    opts: &PipelineOptions,
+    output: &Output,
+) -> Vec<u8> {
+    let name = opts.formatter.name();
+    let reason = failure_reason(output);
+    let mut text = format!(
+        "\x1b[31m{name} failed: {reason}\x1b[0m\n\
+         \x1b[2mCheck formatter.args in your kibitz config. Showing the plain diff.\x1b[0m\n\n"
+    )
+    .into_bytes();
+    match patch::generate_with_context(
+        repo_path,
+        source,
+        opts.context,
+        opts.ignore_whitespace,
+        opts.renames,
+    ) {
+        Ok(patch) => text.extend(patch),
+        Err(e) => text.extend(format!("Couldn't generate the diff either: {e}").bytes()),
+    }
+    text
+}
+
+/// First line of a failed command's stderr, or its exit status if it printed nothing
+fn failure_reason(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map_or_else(|| output.status.to_string(), String::from)
+}
+
+/// The formatter's command, set up to run in the repo. Plain pipes, not a PTY:
+/// formatters get the width as an argument or through `COLUMNS` and color
+/// through `FORCE_COLOR`/`--color` flags, so there's no terminal to emulate, and
+/// stderr stays apart for failure messages.
+fn formatter_process(repo_path: &Path, formatter: &[String], width: usize) -> Result<Command> {
+    let (program, args) = formatter
+        .split_first()
+        .context("Formatter command is empty")?;
+    let mut cmd = Command::new(program);
+    cmd.args(args)
+        .current_dir(repo_path)
+        .env("TERM", "xterm-256color")
+        .env("COLUMNS", width.to_string())
+        .env("FORCE_COLOR", "1")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    Ok(cmd)
+}
+
+/// Pipe git's output straight into the formatter and collect the formatter's
+/// output. git's complaints are dropped: the formatter's status is what counts.
+fn run_pipeline(
+    repo_path: &Path,
+    mut git: Command,
+    formatter: &[String],
     width: usize,
-    delta_args: Option<&str>,
-) -> Result<DiffState> {
-    let user_args = delta_args.unwrap_or("");
-    let diff_cmd = format!(
-        "git show --format='' --color=always {} -- '{}' | delta --paging=never {}",
-        oid,
-        file_path.to_string_lossy(),
-        user_args
-    );
-    run_diff_command(repo_path, &diff_cmd, width, false, false)
+    cancel: &Cancel,
+    on_output: &mut dyn FnMut(&[u8]),
+) -> Result<Output> {
+    let mut git = git
+        .current_dir(repo_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let patch = git.stdout.take().context("git's output wasn't captured")?;
+    let output = formatter_process(repo_path, formatter, width)?
+        .stdin(patch)
+        .spawn()
+        .map_err(anyhow::Error::from)
+        .and_then(|child| wait_cancelable(child, cancel, on_output));
+    if output.is_err() {
+        let _ = git.kill();
+    }
+    let _ = git.wait();
+    output
+}
+
+/// Run the formatter, optionally feeding it stdin, and collect its output
+fn run_formatter(
+    repo_path: &Path,
+    formatter: &[String],
+    width: usize,
+    input: Option<Vec<u8>>,
+    cancel: &Cancel,
+    on_output: &mut dyn FnMut(&[u8]),
+) -> Result<Output> {
+    let mut child = formatter_process(repo_path, formatter, width)?
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .spawn()?;
+
+    if let Some(input) = input
+        && let Some(mut stdin) = child.stdin.take()
+    {
+        // Write from another thread so a full stdout pipe can't deadlock us
+        thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+    }
+
+    wait_cancelable(child, cancel, on_output)
+}
+
+/// How often a running formatter checks whether its diff was canceled
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long a formatter runs before its output so far is shown
+const STREAM_AFTER: Duration = Duration::from_millis(150);
+
+/// How often the output of a formatter past `STREAM_AFTER` is shown again
+const STREAM_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `wait_with_output`, except the child is killed if the diff is canceled first.
+/// A child still running after `STREAM_AFTER` has its new output passed to
+/// `on_output` every `STREAM_INTERVAL`.
+fn wait_cancelable(
+    mut child: Child,
+    cancel: &Cancel,
+    on_output: &mut dyn FnMut(&[u8]),
+) -> Result<Output> {
+    let stdout = Arc::new(Mutex::new(Vec::new()));
+    let reader = stream_in_background(child.stdout.take(), stdout.clone());
+    let stderr = read_in_background(child.stderr.take());
+    let started = Instant::now();
+    let mut streamed = 0;
+    let mut last_streamed = started;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if cancel.is_canceled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("Diff canceled");
+        }
+        if started.elapsed() >= STREAM_AFTER && last_streamed.elapsed() >= STREAM_INTERVAL {
+            // Copied out so the reader isn't held up while it's parsed
+            let new = match stdout.lock() {
+                Ok(output) => output[streamed..].to_vec(),
+                Err(_) => Vec::new(),
+            };
+            if !new.is_empty() {
+                streamed += new.len();
+                on_output(&new);
+            }
+            last_streamed = Instant::now();
+        }
+        thread::sleep(CANCEL_POLL_INTERVAL);
+    };
+    let _ = reader.join();
+    let stdout = std::mem::take(&mut *stdout.lock().unwrap_or_else(|e| e.into_inner()));
+    Ok(Output {
+        status,
+        stdout,
+        stderr: stderr.join().unwrap_or_default(),
+    })
 }
 
-/// Get diff for multiple files within a commit (used for folder diffs in history)
+/// Drain a child's pipe on another thread into `output`, where it can be looked
+/// at before the child is done
+fn stream_in_background<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    output: Arc<Mutex<Vec<u8>>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let Some(mut pipe) = pipe else {
+            return;
+        };
+        let mut buf = [0; 64 * 1024];
+        while let Ok(n) = pipe.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            match output.lock() {
+                Ok(mut output) => output.extend_from_slice(&buf[..n]),
+                Err(_) => break,
+            }
+        }
+    })
+}
+
+/// Drain a child's pipe on another thread so it can't fill up and stall the child
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Get the diff between pairs of arbitrary file versions
+pub fn get_compare_diff(
+    repo_path: &Path,
+    comparisons: Vec<Comparison>,
+    width: usize,
+    opts: PipelineOptions,
+) -> PendingDiff {
+    let repo_path = repo_path.to_path_buf();
+    let source = PatchSource::Compare(comparisons);
+    spawn_diff(opts.cancel.clone(), opts.progress.clone(), move || {
+        run_diff(&repo_path, &source, width, &opts, false, false)
+    })
+}
+
+/// Get diff for files within a commit: one, or a folder's, plus where renamed ones came from
 pub fn get_commit_files_diff(
     repo_path: &Path,
     oid: &str,
     file_paths: &[std::path::PathBuf],
     width: usize,
-    delta_args: Option<String>,
-) -> mpsc::Receiver<DiffState> {
+    opts: PipelineOptions,
+) -> PendingDiff {
     let repo_path = repo_path.to_path_buf();
     let oid = oid.to_string();
     let file_paths = file_paths.to_vec();
-    spawn_diff(move || {
-        get_commit_files_diff_sync(&repo_path, &oid, &file_paths, width, delta_args.as_deref())
+    spawn_diff(opts.cancel.clone(), opts.progress.clone(), move || {
+        get_commit_files_diff_sync(&repo_path, &oid, &file_paths, width, &opts)
     })
 }
 
@@ -325,18 +1318,87 @@ fn get_commit_files_diff_sync(
     oid: &str,
     file_paths: &[std::path::PathBuf],
     width: usize,
-    delta_args: Option<&str>,
+    opts: &PipelineOptions,
 ) -> Result<DiffState> {
-    if file_paths.is_empty() {
+    if file_paths.is_empty() && opts.summarize.is_empty() {
         return Ok(DiffState::new());
     }
 
-    let user_args = delta_args.unwrap_or("");
-    let diff_cmd = format!(
-        "git show --format='' --color=always {} -- {} | delta --paging=never {}",
-        oid,
-        quote_paths(file_paths),
-        user_args
-    );
-    run_diff_command(repo_path, &diff_cmd, width, false, false)
+    let source = PatchSource::Commit(oid.to_string(), file_paths.to_vec());
+    run_diff(repo_path, &source, width, opts, false, false)
+}
+
+/// Get the diff of files (one, or a folder's) between the two sides of a range
+pub fn get_range_diff(
+    repo_path: &Path,
+    range: &RevRange,
+    file_paths: &[std::path::PathBuf],
+    width: usize,
+    opts: PipelineOptions,
+) -> PendingDiff {
+    let repo_path = repo_path.to_path_buf();
+    let source = PatchSource::Range(range.from.clone(), range.to.clone(), file_paths.to_vec());
+    spawn_diff(opts.cancel.clone(), opts.progress.clone(), move || {
+        run_diff(&repo_path, &source, width, &opts, false, false)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn formatter(command: &str, args: &str) -> FormatterConfig {
+        let name = command.rsplit('/').next().unwrap_or(command);
+        FormatterConfig {
+            command: command.to_string(),
+            args: HashMap::from([(name.to_string(), args.to_string())]),
+        }
+    }
+
+    #[test]
+    fn delta_and_bat_get_the_pane_width() {
+        assert!(formatter_command(&formatter("delta", ""), 93).contains(&"--width=93".into()));
+        let bat = formatter_command(&formatter("bat", ""), 93);
+        assert!(bat.contains(&"--terminal-width=93".into()));
+    }
+
+    #[test]
+    fn a_width_the_user_set_wins() {
+        for args in ["-w80", "-w 80", "--width=80", "--width 80"] {
+            let command = formatter_command(&formatter("delta", args), 93);
+            assert!(!command.contains(&"--width=93".into()), "{args}");
+            assert!(command.iter().any(|arg| arg.contains("80")), "{args}");
+        }
+    }
+
+    /// Through a pipe delta can't see the terminal, and on macOS it doesn't
+    /// honor `COLUMNS` either, so the width has to reach it as an argument
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    #[test]
+    fn the_width_reaches_a_running_delta() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("kibitz-width-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let delta = dir.join("delta");
+        std::fs::write(&delta, "#!/bin/sh\ncat >/dev/null\necho \"$@\"\n").unwrap();
+        std::fs::set_permissions(&delta, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let command = formatter_command(&formatter(&delta.to_string_lossy(), ""), 93);
+        let output = run_formatter(
+            &dir,
+            &command,
+            93,
+            Some(SAMPLE_PATCH.into()),
+            &Cancel::default(),
+            &mut |_| {},
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+        let args = String::from_utf8(output.unwrap().stdout).unwrap();
+        assert!(
+            args.split_whitespace().any(|arg| arg == "--width=93"),
+            "{args}"
+        );
+    }
 }
@@ -1,12 +1,21 @@
-use crate::model::{CommitInfo, FileStatus};
-use anyhow::Result;
+use super::patch::RenameDetection;
+use crate::config::GitConfig;
+use crate::model::{CommitFilter, CommitInfo, FileStatus, FileTree, LogEntry};
+use anyhow::{Result, bail};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone};
 use git2::Repository;
+use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
 /// Get commit at offset from HEAD (0 = HEAD, 1 = HEAD~1, etc.)
 /// Returns None if the offset is beyond available history.
 pub fn get_commit_at(repo_path: &Path, offset: usize) -> Result<Option<CommitInfo>> {
     let repo = Repository::open(repo_path)?;
+    if is_head_unborn(&repo) {
+        return Ok(None);
+    }
 
     let mut revwalk = repo.revwalk()?;
     revwalk.push_head()?;
@@ -16,7 +25,10 @@ pub fn get_commit_at(repo_path: &Path, offset: usize) -> Result<Option<CommitInf
         _ => return Ok(None),
     };
 
-    let commit = repo.find_commit(oid)?;
+    Ok(Some(commit_info(&repo.find_commit(oid)?)))
+}
+
+fn commit_info(commit: &git2::Commit) -> CommitInfo {
     let message = commit
         .message()
         .unwrap_or("")
@@ -25,16 +37,371 @@ pub fn get_commit_at(repo_path: &Path, offset: usize) -> Result<Option<CommitInf
         .unwrap_or("")
         .to_string();
 
-    Ok(Some(CommitInfo {
-        oid: format!("{:.7}", oid),
-        oid_full: oid.to_string(),
+    CommitInfo {
+        oid: format!("{:.7}", commit.id()),
+        oid_full: commit.id().to_string(),
         message,
-    }))
+    }
+}
+
+/// Up to `limit` commits from HEAD, skipping the first `skip`. Topologically sorted
+/// (children before parents) so they can be drawn as a graph.
+pub fn get_log(repo_path: &Path, skip: usize, limit: usize) -> Result<Vec<LogEntry>> {
+    let repo = Repository::open(repo_path)?;
+    if is_head_unborn(&repo) {
+        return Ok(Vec::new());
+    }
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+    revwalk.push_head()?;
+
+    revwalk
+        .skip(skip)
+        .take(limit)
+        .map(|oid| Ok(log_entry(&repo.find_commit(oid?)?)))
+        .collect()
+}
+
+/// Every commit from HEAD whose subject or author matches `filter`, in the same
+/// order as `get_log`
+pub fn search_log(repo_path: &Path, filter: &CommitFilter) -> Result<Vec<LogEntry>> {
+    let repo = Repository::open(repo_path)?;
+    if is_head_unborn(&repo) {
+        return Ok(Vec::new());
+    }
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+    revwalk.push_head()?;
+
+    let mut entries = Vec::new();
+    for oid in revwalk {
+        let entry = log_entry(&repo.find_commit(oid?)?);
+        if filter.matches(&entry) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+fn log_entry(commit: &git2::Commit) -> LogEntry {
+    let author = commit.author();
+    let date = DateTime::from_timestamp(author.when().seconds(), 0)
+        .map(|d| {
+            d.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_default();
+    LogEntry {
+        commit: commit_info(commit),
+        author: author.name().unwrap_or("").to_string(),
+        date,
+        parents: commit.parent_ids().map(|id| id.to_string()).collect(),
+        graph: String::new(),
+        path: None,
+    }
+}
+
+/// The commit a revspec (SHA, branch, tag, `HEAD~3`, ...) points at
+pub fn resolve_commit(repo_path: &Path, spec: &str) -> Result<CommitInfo> {
+    let repo = Repository::open(repo_path)?;
+    let commit = repo.revparse_single(spec)?.peel_to_commit()?;
+    Ok(commit_info(&commit))
+}
+
+/// Offset from HEAD (as used by `get_commit_at`) of the commit `oid`
+pub fn offset_of(repo_path: &Path, oid: &str) -> Result<Option<usize>> {
+    let repo = Repository::open(repo_path)?;
+    let oid = git2::Oid::from_str(oid)?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+
+    for (offset, id) in revwalk.enumerate() {
+        if id? == oid {
+            return Ok(Some(offset));
+        }
+    }
+    Ok(None)
+}
+
+/// Commits `new` gained on top of `old`, newest first. Empty unless `new` descends
+/// from `old`, so resets, rebases and amends don't count as new work.
+pub fn commits_between(repo_path: &Path, old: &str, new: &str) -> Result<Vec<CommitInfo>> {
+    let repo = Repository::open(repo_path)?;
+    let (old, new) = (git2::Oid::from_str(old)?, git2::Oid::from_str(new)?);
+    if old == new || !repo.graph_descendant_of(new, old)? {
+        return Ok(Vec::new());
+    }
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(new)?;
+    revwalk.hide(old)?;
+    revwalk
+        .map(|oid| Ok(commit_info(&repo.find_commit(oid?)?)))
+        .collect()
+}
+
+/// Offset from HEAD (as used by `get_commit_at`) of the first commit made before `time`
+/// (seconds since the epoch). Returns None if no commit is that old.
+pub fn find_offset_before(repo_path: &Path, time: i64) -> Result<Option<usize>> {
+    let repo = Repository::open(repo_path)?;
+    if is_head_unborn(&repo) {
+        return Ok(None);
+    }
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+
+    for (offset, oid) in revwalk.enumerate() {
+        if repo.find_commit(oid?)?.time().seconds() < time {
+            return Ok(Some(offset));
+        }
+    }
+    Ok(None)
+}
+
+/// Parse a date like "2024-03-01", "2024-03-01 14:30", "yesterday" or "2 weeks ago"
+/// into seconds since the epoch. Absolute dates are read in `now`'s time zone, the
+/// one the history list shows dates in.
+pub fn parse_date<Tz: TimeZone>(input: &str, now: &DateTime<Tz>) -> Option<i64> {
+    let input = input.trim().to_lowercase();
+    let timezone = now.timezone();
+
+    match input.as_str() {
+        "now" => return Some(now.timestamp()),
+        "today" => return local_timestamp(&timezone, now.date_naive().and_time(NaiveTime::MIN)),
+        "yesterday" => return Some(now.timestamp() - DAY),
+        _ => {}
+    }
+
+    if let Some(relative) = input.strip_suffix(" ago") {
+        let (amount, unit) = relative.trim().split_once(' ')?;
+        let amount: i64 = amount.parse().ok()?;
+        let unit_seconds = match unit.trim().trim_end_matches('s') {
+            "second" | "sec" => 1,
+            "minute" | "min" => 60,
+            "hour" => 60 * 60,
+            "day" => DAY,
+            "week" => 7 * DAY,
+            "month" => 30 * DAY,
+            "year" => 365 * DAY,
+            _ => return None,
+        };
+        return Some(now.timestamp() - amount.checked_mul(unit_seconds)?);
+    }
+
+    let input = input.replacen('t', " ", 1);
+    let datetime = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&input, format).ok())
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(&input, "%Y-%m-%d").ok()?;
+            Some(date.and_time(NaiveTime::MIN))
+        })?;
+    local_timestamp(&timezone, datetime)
+}
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// Seconds since the epoch of a wall-clock time in `timezone`. A time skipped by a
+/// daylight saving change is moved past the gap.
+fn local_timestamp<Tz: TimeZone>(timezone: &Tz, datetime: NaiveDateTime) -> Option<i64> {
+    (0..=2)
+        .find_map(|hours| {
+            let shifted = datetime + TimeDelta::hours(hours);
+            timezone.from_local_datetime(&shifted).earliest()
+        })
+        .map(|datetime| datetime.timestamp())
 }
 
-/// Get files changed in a commit (comparing to its parent).
-/// Returns a list of (path, status) pairs suitable for building a FileTree.
-pub fn get_commit_files(repo_path: &Path, oid: &str) -> Result<Vec<(PathBuf, FileStatus)>> {
+/// Branches, remote-tracking branches and tags whose history contains the
+/// commit. Local branches come first, then remote ones, then tags from oldest
+/// to newest (the first tag is the earliest release with the commit).
+pub fn refs_containing(repo_path: &Path, oid: &str) -> Result<Vec<String>> {
+    let repo = Repository::open(repo_path)?;
+    let oid = git2::Oid::from_str(oid)?;
+
+    let mut tips = Vec::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        if !reference.is_branch() && !reference.is_remote() && !reference.is_tag() {
+            continue;
+        }
+        let (Ok(target), Some(name)) = (reference.peel_to_commit(), reference.shorthand()) else {
+            continue;
+        };
+        // `origin/HEAD` only repeats the remote's default branch
+        if reference.is_remote() && reference.symbolic_target().is_some() {
+            continue;
+        }
+        let kind = if reference.is_branch() {
+            0
+        } else if reference.is_remote() {
+            1
+        } else {
+            2
+        };
+        tips.push((kind, target.id(), target.time().seconds(), name.to_string()));
+    }
+
+    let containing = descendants(&repo, oid, tips.iter().map(|(_, tip, _, _)| *tip))?;
+    let mut refs: Vec<_> = tips
+        .into_iter()
+        .filter(|(_, tip, _, _)| containing.contains(tip))
+        .map(|(kind, _, time, name)| (kind, if kind == 2 { time } else { 0 }, name))
+        .collect();
+    refs.sort();
+    Ok(refs.into_iter().map(|(_, _, name)| name).collect())
+}
+
+/// `oid` and the commits reachable from `tips` that have it in their history,
+/// found in one walk. The walk stops at `oid`'s parents, since nothing behind
+/// them can contain it.
+fn descendants(
+    repo: &Repository,
+    oid: git2::Oid,
+    tips: impl Iterator<Item = git2::Oid>,
+) -> Result<HashSet<git2::Oid>> {
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    for tip in tips {
+        walk.push(tip)?;
+    }
+    for parent in repo.find_commit(oid)?.parent_ids() {
+        walk.hide(parent)?;
+    }
+
+    let mut containing = HashSet::from([oid]);
+    for id in walk {
+        let id = id?;
+        if id == oid {
+            continue;
+        }
+        let commit = repo.find_commit(id)?;
+        if commit
+            .parent_ids()
+            .any(|parent| containing.contains(&parent))
+        {
+            containing.insert(id);
+        }
+    }
+    Ok(containing)
+}
+
+/// Commits that changed `path`, newest first from `start` (HEAD when None),
+/// following renames like `git log --follow`
+pub fn file_log(
+    repo_path: &Path,
+    git: &GitConfig,
+    start: Option<&str>,
+    path: &Path,
+) -> Result<Vec<LogEntry>> {
+    let args = [
+        OsStr::new("--follow"),
+        OsStr::new("--name-only"),
+        OsStr::new(start.unwrap_or("HEAD")),
+        OsStr::new("--"),
+        path.as_os_str(),
+    ];
+    let mut entries = run_log(repo_path, git, &args)?;
+    for entry in &mut entries {
+        entry.path.get_or_insert_with(|| path.to_path_buf());
+    }
+    Ok(entries)
+}
+
+/// Commits reachable from HEAD whose diffs add or remove `query`, newest first.
+/// A `/regex/` query matches changed lines against the regex instead (`git log -G`).
+pub fn pickaxe(repo_path: &Path, git: &GitConfig, query: &str) -> Result<Vec<LogEntry>> {
+    let flag = match query.strip_prefix('/').and_then(|q| q.strip_suffix('/')) {
+        Some(regex) if !regex.is_empty() => format!("-G{regex}"),
+        _ => format!("-S{query}"),
+    };
+    run_log(repo_path, git, &[OsStr::new(&flag), OsStr::new("HEAD")])
+}
+
+/// Run `git log` with `args`, reading each commit and, with `--name-only`, the
+/// first file it names
+fn run_log(repo_path: &Path, git: &GitConfig, args: &[&OsStr]) -> Result<Vec<LogEntry>> {
+    let output = Command::new(&git.binary)
+        .args(&git.extra_args)
+        .args(["log", "--format=%x01%H%x00%P%x00%an%x00%at%x00%s"])
+        .args(args)
+        .current_dir(repo_path)
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{}", stderr.trim());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let entries = stdout
+        .split('\x01')
+        .filter_map(|record| {
+            let mut lines = record.lines();
+            let mut fields = lines.next()?.split('\0');
+            let (oid, parents, author, time, message) = (
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+                fields.next().unwrap_or(""),
+            );
+            // --name-only lists the file under the name it had in this commit
+            let name = lines.find(|line| !line.is_empty());
+            let date = DateTime::from_timestamp(time.parse().ok()?, 0)
+                .map(|d| {
+                    d.with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string()
+                })
+                .unwrap_or_default();
+            Some(LogEntry {
+                commit: CommitInfo {
+                    oid: oid.chars().take(7).collect(),
+                    oid_full: oid.to_string(),
+                    message: message.to_string(),
+                },
+                author: author.to_string(),
+                date,
+                parents: parents.split_whitespace().map(String::from).collect(),
+                graph: String::new(),
+                path: name.map(PathBuf::from),
+            })
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Whether the repository is a shallow clone (history stops at a grafted boundary)
+pub fn is_shallow(repo_path: &Path) -> bool {
+    Repository::open(repo_path).is_ok_and(|repo| repo.is_shallow())
+}
+
+/// Fetch `depth` more commits of history into a shallow clone
+pub fn deepen(repo_path: &Path, git: &GitConfig, depth: usize) -> Result<()> {
+    let output = Command::new(&git.binary)
+        .args(&git.extra_args)
+        .args(["fetch", "--quiet", &format!("--deepen={depth}")])
+        .current_dir(repo_path)
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// Whether HEAD is a branch without commits yet (fresh `git init` or orphan branch)
+pub fn is_head_unborn(repo: &Repository) -> bool {
+    repo.head()
+        .is_err_and(|e| e.code() == git2::ErrorCode::UnbornBranch)
+}
+
+/// Tree of the files changed in a commit (comparing to its parent), renames included
+pub fn get_commit_files(repo_path: &Path, oid: &str, renames: RenameDetection) -> Result<FileTree> {
     let repo = Repository::open(repo_path)?;
     let oid = git2::Oid::from_str(oid)?;
     let commit = repo.find_commit(oid)?;
@@ -42,8 +409,28 @@ pub fn get_commit_files(repo_path: &Path, oid: &str) -> Result<Vec<(PathBuf, Fil
     let tree = commit.tree()?;
     let parent_tree = commit.parent(0).ok().and_then(|p| p.tree().ok());
 
-    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+    let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+    diff_file_tree(&mut diff, renames)
+}
+
+/// Tree of a diff's files, pairing deleted and added files into renames and
+/// copies as `renames` says
+pub fn diff_file_tree(diff: &mut git2::Diff, renames: RenameDetection) -> Result<FileTree> {
+    renames.apply(diff)?;
+    let renames = diff
+        .deltas()
+        .filter(|delta| matches!(delta.status(), git2::Delta::Renamed | git2::Delta::Copied))
+        .filter_map(|delta| {
+            let new = delta.new_file().path()?.to_path_buf();
+            let old = delta.old_file().path()?.to_path_buf();
+            Some((new, old))
+        })
+        .collect();
+    Ok(FileTree::from_commit_files(diff_files(diff)).with_renames(renames))
+}
 
+/// The (path, status) pairs of a diff's files, suitable for building a FileTree
+pub fn diff_files(diff: &git2::Diff) -> Vec<(PathBuf, FileStatus)> {
     let mut files = Vec::new();
 
     for delta in diff.deltas() {
@@ -55,7 +442,7 @@ pub fn get_commit_files(repo_path: &Path, oid: &str) -> Result<Vec<(PathBuf, Fil
             .unwrap_or_default();
 
         let status = match delta.status() {
-            git2::Delta::Added => FileStatus::Added,
+            git2::Delta::Added | git2::Delta::Untracked | git2::Delta::Copied => FileStatus::Added,
             git2::Delta::Deleted => FileStatus::Deleted,
             git2::Delta::Renamed => FileStatus::Renamed,
             _ => FileStatus::Modified,
@@ -64,5 +451,106 @@ pub fn get_commit_files(repo_path: &Path, oid: &str) -> Result<Vec<(PathBuf, Fil
         files.push((path, status));
     }
 
-    Ok(files)
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(repo: &Repository, message: &str, parents: &[git2::Oid]) -> git2::Oid {
+        let signature = git2::Signature::new("a", "a@b", &git2::Time::new(0, 0)).unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let parents: Vec<_> = parents
+            .iter()
+            .map(|id| repo.find_commit(*id).unwrap())
+            .collect();
+        let parents: Vec<_> = parents.iter().collect();
+        repo.commit(None, &signature, &signature, message, &tree, &parents)
+            .unwrap()
+    }
+
+    #[test]
+    fn branches_remotes_and_tags_that_contain_a_commit() {
+        let dir = std::env::temp_dir().join(format!("kibitz-refs-{}", std::process::id()));
+        let repo = Repository::init(&dir).unwrap();
+        let root = commit(&repo, "root", &[]);
+        let picked = commit(&repo, "picked", &[root]);
+        let side = commit(&repo, "side", &[root]);
+        let merge = commit(&repo, "merge", &[side, picked]);
+
+        repo.reference("refs/heads/main", merge, true, "").unwrap();
+        repo.reference("refs/heads/side", side, true, "").unwrap();
+        repo.reference("refs/remotes/origin/main", picked, true, "")
+            .unwrap();
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+            true,
+            "",
+        )
+        .unwrap();
+        repo.reference("refs/tags/v1", root, true, "").unwrap();
+        repo.reference("refs/tags/v2", merge, true, "").unwrap();
+
+        let refs = refs_containing(&dir, &picked.to_string()).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(refs, ["main", "origin/main", "v2"]);
+    }
+
+    #[test]
+    fn dates_are_read_in_the_local_time_zone() {
+        // 2024-03-10 12:00 at UTC-5
+        let zone = chrono::FixedOffset::west_opt(5 * 3600).unwrap();
+        let now = zone.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        let at = |y, m, d, h, min| {
+            zone.with_ymd_and_hms(y, m, d, h, min, 0)
+                .unwrap()
+                .timestamp()
+        };
+
+        assert_eq!(parse_date("2024-03-01", &now), Some(at(2024, 3, 1, 0, 0)));
+        assert_eq!(
+            parse_date("2024-03-01 14:30", &now),
+            Some(at(2024, 3, 1, 14, 30))
+        );
+        assert_eq!(
+            parse_date("2024-03-01T14:30:00", &now),
+            Some(at(2024, 3, 1, 14, 30))
+        );
+        assert_eq!(parse_date("2024-02-29", &now), Some(at(2024, 2, 29, 0, 0)));
+        assert_eq!(parse_date(" Today ", &now), Some(at(2024, 3, 10, 0, 0)));
+    }
+
+    #[test]
+    fn relative_dates_count_back_from_now() {
+        let now = chrono::Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        let ago = |seconds| Some(now.timestamp() - seconds);
+
+        assert_eq!(parse_date("now", &now), ago(0));
+        assert_eq!(parse_date("yesterday", &now), ago(DAY));
+        assert_eq!(parse_date("2 weeks ago", &now), ago(14 * DAY));
+        assert_eq!(parse_date("1 hour ago", &now), ago(3600));
+        assert_eq!(parse_date("3 mins ago", &now), ago(180));
+    }
+
+    #[test]
+    fn impossible_dates_are_rejected() {
+        let now = chrono::Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        for input in [
+            "2024-02-31",
+            "2023-02-29",
+            "2024-13-01",
+            "2024-03-01 25:00",
+            "2024-03",
+            "soon",
+            "2 fortnights ago",
+            "many days ago",
+            "",
+        ] {
+            assert_eq!(parse_date(input, &now), None, "{input:?}");
+        }
+    }
 }
@@ -1,3 +1,16 @@
+pub mod attributes;
+pub mod blame;
+pub mod blob;
+pub mod churn;
+pub mod commit;
+pub mod compare;
 pub mod diff;
 pub mod history;
+pub mod owners;
+pub mod patch;
+pub mod range;
+pub mod restore;
+pub mod stage;
+pub mod stash;
 pub mod status;
+pub mod submodule;
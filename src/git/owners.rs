@@ -0,0 +1,156 @@
+use regex::Regex;
+use std::path::Path;
+
+/// Where GitHub looks for a CODEOWNERS file, in the order it checks
+const LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Who owns which files, from the repository's CODEOWNERS
+pub struct CodeOwners {
+    /// Patterns in file order, each with its owners (none for an explicitly
+    /// unowned pattern)
+    rules: Vec<(Regex, Vec<String>)>,
+}
+
+impl CodeOwners {
+    /// Read the repository's CODEOWNERS, if it has one. Lines that don't make a
+    /// valid pattern are skipped.
+    pub fn load(repo_path: &Path) -> Option<Self> {
+        let contents = LOCATIONS
+            .iter()
+            .find_map(|location| std::fs::read_to_string(repo_path.join(location)).ok())?;
+        let rules = contents
+            .lines()
+            .filter_map(|line| {
+                let line = line.split_once('#').map_or(line, |(rule, _)| rule);
+                let mut words = line.split_whitespace();
+                let pattern = pattern_regex(words.next()?)?;
+                Some((pattern, words.map(str::to_string).collect()))
+            })
+            .collect();
+        Some(Self { rules })
+    }
+
+    /// Owners of a repo-relative `path`: those of the last pattern matching it
+    pub fn owners(&self, path: &Path) -> &[String] {
+        let path = path.to_string_lossy().replace('\\', "/");
+        self.rules
+            .iter()
+            .rev()
+            .find(|(pattern, _)| pattern.is_match(&path))
+            .map_or(&[], |(_, owners)| owners)
+    }
+}
+
+/// A CODEOWNERS pattern as a regex over repo-relative paths. Like .gitignore:
+/// a pattern with a slash at the start or in the middle is anchored to the root,
+/// otherwise it matches at any depth; `*` stays within a directory and `**`
+/// crosses them. A pattern matching a directory owns everything in it, except
+/// that `dir/*` owns only the files directly in `dir`.
+fn pattern_regex(pattern: &str) -> Option<Regex> {
+    let anchored = pattern.trim_end_matches('/').contains('/');
+    let body = pattern.trim_start_matches('/').trim_end_matches('/');
+    if body.is_empty() {
+        return None;
+    }
+
+    let mut regex = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+    let mut rest = body;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("**/") {
+            regex.push_str("(?:.*/)?");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("**") {
+            regex.push_str(".*");
+            rest = after;
+        } else {
+            match c {
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if !body.ends_with("/*") {
+        regex.push_str("(?:/.*)?");
+    }
+    regex.push('$');
+    Regex::new(&regex).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_match_like_githubs_examples() {
+        // From GitHub's CODEOWNERS documentation
+        let table: &[(&str, &[&str], &[&str])] = &[
+            ("*", &["README.md", "src/main.rs"], &[]),
+            (
+                "*.js",
+                &["app.js", "src/deep/app.js"],
+                &["app.jsx", "js/app.ts"],
+            ),
+            (
+                "/build/logs/",
+                &["build/logs/today.log", "build/logs/old/1.log"],
+                &["src/build/logs/today.log", "build/logs.txt"],
+            ),
+            (
+                "docs/*",
+                &["docs/getting-started.md"],
+                &["docs/build-app/troubleshooting.md", "src/docs/index.md"],
+            ),
+            ("apps/", &["apps/main.js", "src/apps/main.js"], &["apps.js"]),
+            (
+                "/docs/",
+                &["docs/index.md", "docs/a/b.md"],
+                &["src/docs/index.md"],
+            ),
+            (
+                "**/logs",
+                &["logs/a.log", "build/logs/a.log", "deeply/nested/logs/a.log"],
+                &["mylogs/a.log", "logs.txt"],
+            ),
+            (
+                "/apps/github",
+                &["apps/github", "apps/github/x.js"],
+                &["apps/githubx"],
+            ),
+            (
+                "file?.txt",
+                &["file1.txt", "a/fileA.txt"],
+                &["file/.txt", "file12.txt"],
+            ),
+        ];
+        for (pattern, matching, other) in table {
+            let regex = pattern_regex(pattern).unwrap();
+            for path in *matching {
+                assert!(regex.is_match(path), "{pattern} should match {path}");
+            }
+            for path in *other {
+                assert!(!regex.is_match(path), "{pattern} shouldn't match {path}");
+            }
+        }
+        assert!(pattern_regex("/").is_none());
+    }
+
+    #[test]
+    fn the_last_matching_pattern_wins() {
+        let rule = |pattern, owners: &[&str]| {
+            let owners = owners.iter().map(|owner| owner.to_string()).collect();
+            (pattern_regex(pattern).unwrap(), owners)
+        };
+        let owners = CodeOwners {
+            rules: vec![
+                rule("*", &["@everyone"]),
+                rule("/apps/", &["@octocat"]),
+                rule("/apps/github", &[]),
+            ],
+        };
+        assert_eq!(owners.owners(Path::new("README.md")), ["@everyone"]);
+        assert_eq!(owners.owners(Path::new("apps/main.js")), ["@octocat"]);
+        assert!(owners.owners(Path::new("apps/github/x.js")).is_empty());
+    }
+}
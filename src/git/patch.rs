@@ -0,0 +1,721 @@
+use super::blob;
+use crate::model::Comparison;
+use anyhow::Result;
+use git2::{
+    Delta, Diff, DiffFile, DiffFindOptions, DiffFormat, DiffOptions, Oid, Patch, Repository,
+};
+use std::path::{Path, PathBuf};
+
+/// How deleted and added files are paired up into renames and copies, like
+/// git's `-M`/`-C`: the least similarity in percent, 0 turning it off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenameDetection {
+    pub renames: u16,
+    pub copies: u16,
+}
+
+impl Default for RenameDetection {
+    fn default() -> Self {
+        // git's own defaults: renames at 50%, no copies
+        Self {
+            renames: 50,
+            copies: 0,
+        }
+    }
+}
+
+impl RenameDetection {
+    /// libgit2's equivalent, None when detection is off
+    pub fn find_options(self) -> Option<DiffFindOptions> {
+        if self.renames == 0 && self.copies == 0 {
+            return None;
+        }
+        let mut opts = DiffFindOptions::new();
+        if self.renames > 0 {
+            opts.renames(true).rename_threshold(self.renames);
+        }
+        if self.copies > 0 {
+            opts.copies(true).copy_threshold(self.copies);
+        }
+        Some(opts)
+    }
+
+    /// The git CLI's arguments for it
+    pub fn git_args(self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.renames > 0 {
+            args.push(format!("--find-renames={}%", self.renames));
+        }
+        if self.copies > 0 {
+            args.push(format!("--find-copies={}%", self.copies));
+        }
+        if args.is_empty() {
+            args.push("--no-renames".to_string());
+        }
+        args
+    }
+
+    /// Pair up `diff`'s files as configured
+    pub fn apply(self, diff: &mut Diff) -> Result<()> {
+        if let Some(mut opts) = self.find_options() {
+            diff.find_similar(Some(&mut opts))?;
+        }
+        Ok(())
+    }
+}
+
+/// What a diff shows, independent of how the patch is produced
+pub enum PatchSource {
+    /// Working tree changes not yet staged
+    Unstaged(Vec<PathBuf>),
+    /// Staged changes (index vs HEAD)
+    Staged(Vec<PathBuf>),
+    /// An untracked file, shown as entirely added
+    Untracked(PathBuf),
+    /// A commit compared to its first parent
+    Commit(String, Vec<PathBuf>),
+    /// One commit compared to another (old, new)
+    Range(String, String, Vec<PathBuf>),
+    /// Pairs of arbitrary file versions
+    Compare(Vec<Comparison>),
+}
+
+impl PatchSource {
+    /// Pathspecs limiting the diff (none for comparisons, which name their files directly)
+    pub fn paths(&self) -> &[PathBuf] {
+        match self {
+            PatchSource::Unstaged(paths)
+            | PatchSource::Staged(paths)
+            | PatchSource::Commit(_, paths)
+            | PatchSource::Range(_, _, paths) => paths,
+            PatchSource::Untracked(path) => std::slice::from_ref(path),
+            PatchSource::Compare(_) => &[],
+        }
+    }
+
+    /// The same kind of diff over other files; None for untracked files and
+    /// comparisons, which aren't limited by pathspecs
+    pub fn with_paths(&self, paths: Vec<PathBuf>) -> Option<PatchSource> {
+        match self {
+            PatchSource::Unstaged(_) => Some(PatchSource::Unstaged(paths)),
+            PatchSource::Staged(_) => Some(PatchSource::Staged(paths)),
+            PatchSource::Commit(oid, _) => Some(PatchSource::Commit(oid.clone(), paths)),
+            PatchSource::Range(from, to, _) => {
+                Some(PatchSource::Range(from.clone(), to.clone(), paths))
+            }
+            PatchSource::Untracked(_) | PatchSource::Compare(_) => None,
+        }
+    }
+}
+
+/// Generate a plain unified diff with libgit2 (used when the git CLI isn't available)
+pub fn generate(repo_path: &Path, source: &PatchSource) -> Result<Vec<u8>> {
+    generate_diff(
+        repo_path,
+        source,
+        false,
+        None,
+        false,
+        RenameDetection::default(),
+    )
+}
+
+/// Like `generate`, with `context` lines around changes instead of the default,
+/// whitespace-only changes and blank lines left out when `ignore_whitespace`,
+/// and files paired up as `renames` says
+pub fn generate_with_context(
+    repo_path: &Path,
+    source: &PatchSource,
+    context: Option<u32>,
+    ignore_whitespace: bool,
+    renames: RenameDetection,
+) -> Result<Vec<u8>> {
+    generate_diff(
+        repo_path,
+        source,
+        false,
+        context,
+        ignore_whitespace,
+        renames,
+    )
+}
+
+/// Like `generate_with_context`, but with old and new swapped (e.g. index ->
+/// HEAD for staged changes)
+pub fn generate_reversed(
+    repo_path: &Path,
+    source: &PatchSource,
+    context: Option<u32>,
+) -> Result<Vec<u8>> {
+    generate_diff(
+        repo_path,
+        source,
+        true,
+        context,
+        false,
+        RenameDetection::default(),
+    )
+}
+
+/// Lines of context git shows around changes: `diff.context`, or 3
+pub fn default_context(repo_path: &Path) -> u32 {
+    Repository::open(repo_path)
+        .and_then(|repo| repo.config()?.get_i32("diff.context"))
+        .ok()
+        .and_then(|n| u32::try_from(n).ok())
+        .unwrap_or(3)
+}
+
+fn generate_diff(
+    repo_path: &Path,
+    source: &PatchSource,
+    reverse: bool,
+    context: Option<u32>,
+    ignore_whitespace: bool,
+    renames: RenameDetection,
+) -> Result<Vec<u8>> {
+    let repo = Repository::open(repo_path)?;
+
+    let mut opts = DiffOptions::new();
+    opts.reverse(reverse)
+        .ignore_whitespace(ignore_whitespace)
+        .ignore_blank_lines(ignore_whitespace);
+    if let Some(context) = context {
+        opts.context_lines(context);
+    }
+    // libgit2 honors diff.noprefix / diff.mnemonicPrefix, so pin the standard prefixes
+    opts.disable_pathspec_match(true)
+        .old_prefix("a/")
+        .new_prefix("b/");
+
+    if let PatchSource::Compare(comparisons) = source {
+        let mut patch = Vec::new();
+        for comparison in comparisons {
+            patch.extend(compare_patch(repo_path, comparison, reverse, &mut opts)?);
+        }
+        return Ok(patch);
+    }
+    let diff = git_diff(&repo, source, &mut opts, renames)?;
+
+    let mut patch = Vec::new();
+    diff.print(DiffFormat::Patch, |delta, _, line| {
+        match line.origin() {
+            // Content lines come without their +/-/space prefix
+            '+' | '-' | ' ' => patch.push(line.origin() as u8),
+            // libgit2 names a side whose blob id it didn't compute /dev/null, as it
+            // does for the working tree when reversed, so `git apply` would
+            // take the file for a new one
+            'F' if reverse && delta.status() != Delta::Added => {
+                if let Some(path) = delta.old_file().path() {
+                    let header = String::from_utf8_lossy(line.content());
+                    let old = format!("\n--- b/{}\n", path.display());
+                    patch.extend_from_slice(header.replace("\n--- /dev/null\n", &old).as_bytes());
+                    return true;
+                }
+            }
+            _ => {}
+        }
+        patch.extend_from_slice(line.content());
+        true
+    })?;
+
+    Ok(patch)
+}
+
+/// libgit2's diff for anything but a comparison, with renames paired up
+pub(super) fn git_diff<'r>(
+    repo: &'r Repository,
+    source: &PatchSource,
+    opts: &mut DiffOptions,
+    renames: RenameDetection,
+) -> Result<Diff<'r>> {
+    for path in source.paths() {
+        opts.pathspec(pathspec(path));
+    }
+
+    let mut diff = match source {
+        PatchSource::Unstaged(_) => repo.diff_index_to_workdir(None, Some(opts))?,
+        PatchSource::Staged(_) => {
+            let head = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+            repo.diff_tree_to_index(head.as_ref(), None, Some(opts))?
+        }
+        PatchSource::Untracked(_) => {
+            opts.include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .show_untracked_content(true);
+            repo.diff_index_to_workdir(None, Some(opts))?
+        }
+        PatchSource::Commit(oid, _) => {
+            let commit = repo.find_commit(Oid::from_str(oid)?)?;
+            let parent_tree = commit.parent(0).ok().and_then(|p| p.tree().ok());
+            repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), Some(opts))?
+        }
+        PatchSource::Range(from, to, _) => {
+            let tree = |oid: &str| -> Result<git2::Tree<'_>> {
+                Ok(repo.find_commit(Oid::from_str(oid)?)?.tree()?)
+            };
+            repo.diff_tree_to_tree(Some(&tree(from)?), Some(&tree(to)?), Some(opts))?
+        }
+        PatchSource::Compare(_) => unreachable!("comparisons are diffed from buffers"),
+    };
+    // Pairs a renamed file's old and new path when the pathspecs name both
+    renames.apply(&mut diff)?;
+    Ok(diff)
+}
+
+/// One line per file in `source` standing in for its diff, like
+/// `dist/app.min.js: modified, 1 line, 210KB → 214KB`
+pub fn summarize(repo_path: &Path, source: &PatchSource) -> Result<Vec<String>> {
+    let repo = Repository::open(repo_path)?;
+    let mut opts = DiffOptions::new();
+    // Files summarized in a folder's diff may be untracked
+    opts.disable_pathspec_match(true)
+        .include_untracked(true)
+        .recurse_untracked_dirs(true);
+    let diff = git_diff(&repo, source, &mut opts, RenameDetection::default())?;
+
+    // Working tree files aren't in the object database
+    let new_in_workdir = matches!(source, PatchSource::Unstaged(_) | PatchSource::Untracked(_));
+    let read = |file: DiffFile, workdir: bool| {
+        if !file.exists() {
+            return None;
+        }
+        if let Ok(blob) = repo.find_blob(file.id()) {
+            return Some(blob.content().to_vec());
+        }
+        workdir
+            .then(|| std::fs::read(repo_path.join(file.path()?)).ok())
+            .flatten()
+    };
+
+    Ok(diff
+        .deltas()
+        .map(|delta| {
+            let old = read(delta.old_file(), false);
+            let new = read(delta.new_file(), new_in_workdir);
+            let path = delta.new_file().path().unwrap_or_else(|| Path::new(""));
+            summary_line(path, delta.status(), old.as_deref(), new.as_deref())
+        })
+        .collect())
+}
+
+fn summary_line(path: &Path, status: Delta, old: Option<&[u8]>, new: Option<&[u8]>) -> String {
+    let mut parts = vec![
+        match status {
+            Delta::Added | Delta::Untracked => "added",
+            Delta::Deleted => "deleted",
+            Delta::Renamed => "renamed",
+            _ => "modified",
+        }
+        .to_string(),
+    ];
+    if let Some(new) = new {
+        // Like git, a NUL early on means binary
+        parts.push(if new.iter().take(8000).any(|&b| b == 0) {
+            "binary".to_string()
+        } else {
+            let lines = new.split(|&b| b == b'\n').count() - usize::from(new.ends_with(b"\n"));
+            format!("{lines} line{}", if lines == 1 { "" } else { "s" })
+        });
+    }
+    match (old, new) {
+        (Some(old), Some(new)) => parts.push(format!(
+            "{} \u{2192} {}",
+            format_size(old.len()),
+            format_size(new.len())
+        )),
+        (Some(only), None) | (None, Some(only)) => parts.push(format_size(only.len())),
+        (None, None) => {}
+    }
+    format!("{}: {}", path.display(), parts.join(", "))
+}
+
+fn format_size(bytes: usize) -> String {
+    const KB: usize = 1024;
+    match bytes {
+        0..KB => format!("{bytes}B"),
+        _ if bytes < KB * KB => format!("{}KB", (bytes + KB / 2) / KB),
+        _ => format!("{:.1}MB", bytes as f64 / (KB * KB) as f64),
+    }
+}
+
+/// Diff two file versions read into memory. A side that doesn't exist (a file
+/// added or deleted between compared directories) is diffed as empty.
+fn compare_patch(
+    repo_path: &Path,
+    comparison: &Comparison,
+    reverse: bool,
+    opts: &mut DiffOptions,
+) -> Result<Vec<u8>> {
+    let (old, new) = if reverse {
+        (&comparison.to, &comparison.from)
+    } else {
+        (&comparison.from, &comparison.to)
+    };
+    let (old_content, new_content) = match (
+        blob::read_point(repo_path, old),
+        blob::read_point(repo_path, new),
+    ) {
+        (Err(e), Err(_)) => return Err(e),
+        (old, new) => (old.unwrap_or_default(), new.unwrap_or_default()),
+    };
+    let mut patch = Patch::from_buffers(
+        &old_content,
+        Some(&old.path),
+        &new_content,
+        Some(&new.path),
+        Some(opts),
+    )?;
+    Ok(patch.to_buf()?.to_vec())
+}
+
+/// Tree paths are prefixed with "./", libgit2 pathspecs are relative to the repo root
+fn pathspec(path: &Path) -> &Path {
+    path.strip_prefix(".").unwrap_or(path)
+}
+
+/// A hunk parsed from a unified diff
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    /// The `@@ -a,b +c,d @@ ...` line
+    pub header: String,
+    pub old_start: usize,
+    pub new_start: usize,
+    /// Body lines, each starting with ' ', '+', '-' or '\'
+    pub lines: Vec<String>,
+}
+
+impl Hunk {
+    /// Old-side line numbers the hunk removes, or its context lines when it only
+    /// adds
+    pub fn old_lines_touched(&self) -> Vec<usize> {
+        let mut removed = Vec::new();
+        let mut context = Vec::new();
+        let mut line = self.old_start;
+        for body in &self.lines {
+            match body.as_bytes().first() {
+                Some(b'-') => removed.push(line),
+                Some(b' ') => context.push(line),
+                _ => continue,
+            }
+            line += 1;
+        }
+        if removed.is_empty() { context } else { removed }
+    }
+
+    /// Old-side line numbers the hunk spans
+    pub fn old_range(&self) -> std::ops::Range<usize> {
+        let count = self
+            .lines
+            .iter()
+            .filter(|l| !l.starts_with(['+', '\\']))
+            .count();
+        self.old_start..self.old_start + count
+    }
+
+    /// New-side line numbers the hunk spans
+    pub fn new_range(&self) -> std::ops::Range<usize> {
+        let count = self
+            .lines
+            .iter()
+            .filter(|l| !l.starts_with(['-', '\\']))
+            .count();
+        self.new_start..self.new_start + count
+    }
+
+    /// The header this hunk gets in a patch of its own: other hunks of the
+    /// file no longer shift where its new side starts
+    pub fn standalone_header(&self) -> String {
+        let old_count = self.old_range().len();
+        let new_count = self.new_range().len();
+        // A side without lines names the line before the change
+        let first = if old_count == 0 {
+            self.old_start + 1
+        } else {
+            self.old_start
+        };
+        let new_start = if new_count == 0 {
+            first.saturating_sub(1)
+        } else {
+            first
+        };
+        let range = |start: usize, count: usize| match count {
+            1 => start.to_string(),
+            _ => format!("{start},{count}"),
+        };
+        let section = self
+            .header
+            .get(2..)
+            .and_then(|rest| rest.find("@@").map(|end| &rest[end + 2..]))
+            .unwrap_or_default();
+        format!(
+            "@@ -{} +{} @@{section}",
+            range(self.old_start, old_count),
+            range(new_start, new_count)
+        )
+    }
+
+    /// Number of context lines before the first added or removed line
+    pub fn leading_context(&self) -> usize {
+        self.lines
+            .iter()
+            .take_while(|line| line.starts_with(' '))
+            .count()
+    }
+}
+
+/// One file's section of a unified diff
+#[derive(Debug, Clone)]
+pub struct FilePatch {
+    /// Lines before the first hunk (`diff --git`, `index`, `---`, `+++`, ...)
+    pub header: Vec<String>,
+    /// Old path without the `a/` prefix (None for added files)
+    pub old_path: Option<PathBuf>,
+    /// New path without the `b/` prefix (None for deleted files)
+    pub new_path: Option<PathBuf>,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    /// A patch containing only the file header and one hunk, ready to apply
+    pub fn hunk_patch(&self, idx: usize) -> Option<String> {
+        let hunk = self.hunks.get(idx)?;
+        Some(self.single_hunk_patch(&hunk.header, hunk))
+    }
+
+    /// Like `hunk_patch`, for applying on its own somewhere else with `git
+    /// apply`: the hunk's header is corrected for the hunks left out
+    pub fn standalone_hunk_patch(&self, idx: usize) -> Option<String> {
+        let hunk = self.hunks.get(idx)?;
+        Some(self.single_hunk_patch(&hunk.standalone_header(), hunk))
+    }
+
+    fn single_hunk_patch(&self, header: &str, hunk: &Hunk) -> String {
+        let header = self.header.iter().map(String::as_str).chain([header]);
+        let mut patch = String::new();
+        for line in header.chain(hunk.lines.iter().map(String::as_str)) {
+            patch.push_str(line);
+            patch.push('\n');
+        }
+        patch
+    }
+}
+
+/// Parse a plain (uncolored) unified diff into per-file patches
+pub fn parse(patch: &str) -> Vec<FilePatch> {
+    let mut files: Vec<FilePatch> = Vec::new();
+
+    // Only split on '\n' so a '\r' in CRLF content survives a round trip
+    for line in patch.split_inclusive('\n') {
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        if line.starts_with("diff --git ") || files.is_empty() {
+            files.push(FilePatch {
+                header: Vec::new(),
+                old_path: None,
+                new_path: None,
+                hunks: Vec::new(),
+            });
+        }
+        let Some(file) = files.last_mut() else {
+            continue;
+        };
+
+        if let Some(hunk) = parse_hunk_header(line) {
+            file.hunks.push(hunk);
+        } else if let Some(hunk) = file.hunks.last_mut() {
+            hunk.lines.push(line.to_string());
+        } else {
+            if let Some(path) = line.strip_prefix("--- ") {
+                file.old_path = parse_header_path(path, "a/");
+            } else if let Some(path) = line.strip_prefix("+++ ") {
+                file.new_path = parse_header_path(path, "b/");
+            }
+            file.header.push(line.to_string());
+        }
+    }
+
+    files
+}
+
+fn parse_header_path(path: &str, prefix: &str) -> Option<PathBuf> {
+    let path = path.trim_end();
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(PathBuf::from(path.strip_prefix(prefix).unwrap_or(path)))
+}
+
+/// Parse the start lines of `@@ -a,b +c,d @@` into an empty hunk
+fn parse_hunk_header(line: &str) -> Option<Hunk> {
+    let ranges = line.strip_prefix("@@ -")?;
+    let (ranges, _) = ranges.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+
+    let parse_start = |range: &str| range.split(',').next()?.parse().ok();
+
+    Some(Hunk {
+        header: line.to_string(),
+        old_start: parse_start(old)?,
+        new_start: parse_start(new)?,
+        lines: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_FILES: &str = "\
+diff --git a/added.txt b/added.txt
+new file mode 100644
+index 0000000..e69de29
+--- /dev/null
++++ b/added.txt
+@@ -0,0 +1,2 @@
++one
++two
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,3 @@ fn main() {
+ a
+-b
++B
+ c
+@@ -10,2 +10,3 @@
+ j
+ k
++l
+";
+
+    #[test]
+    fn every_file_of_a_patch_gets_its_header_and_hunks() {
+        let files = parse(TWO_FILES);
+        assert_eq!(files.len(), 2);
+
+        assert_eq!(files[0].old_path, None);
+        assert_eq!(files[0].new_path, Some(PathBuf::from("added.txt")));
+        assert_eq!(files[0].header.len(), 5);
+        assert_eq!(files[0].hunks.len(), 1);
+        assert_eq!(files[0].hunks[0].lines, ["+one", "+two"]);
+
+        let lib = &files[1];
+        assert_eq!(lib.old_path, Some(PathBuf::from("src/lib.rs")));
+        assert_eq!(lib.new_path, lib.old_path);
+        assert_eq!(lib.hunks.len(), 2);
+        assert_eq!((lib.hunks[1].old_start, lib.hunks[1].new_start), (10, 10));
+        assert_eq!(lib.hunks[1].new_range(), 10..13);
+        assert_eq!(
+            lib.hunk_patch(1).unwrap(),
+            "diff --git a/src/lib.rs b/src/lib.rs\n\
+             index 1111111..2222222 100644\n\
+             --- a/src/lib.rs\n\
+             +++ b/src/lib.rs\n\
+             @@ -10,2 +10,3 @@\n \
+             j\n \
+             k\n\
+             +l\n"
+        );
+        assert_eq!(lib.hunk_patch(2), None);
+    }
+
+    #[test]
+    fn carriage_returns_and_missing_newlines_survive() {
+        let patch = "\
+diff --git a/dos.txt b/dos.txt
+--- a/dos.txt
++++ b/dos.txt
+@@ -1,2 +1,2 @@
+ first\r
+-last\r
+\\ No newline at end of file
++last
+";
+        let files = parse(patch);
+        let hunk = &files[0].hunks[0];
+        assert_eq!(
+            hunk.lines,
+            [
+                " first\r",
+                "-last\r",
+                "\\ No newline at end of file",
+                "+last"
+            ]
+        );
+        assert_eq!(hunk.old_range(), 1..3);
+        assert_eq!(hunk.new_range(), 1..3);
+        assert_eq!(files[0].hunk_patch(0).unwrap(), patch);
+    }
+
+    /// A repository with 30 numbered lines committed, then two lines inserted
+    /// after line 3, line 12 changed and lines 25-26 deleted in the working tree
+    fn repo_with_three_changes(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kibitz-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        let lines: Vec<String> = (1..=30).map(|n| format!("{n}\n")).collect();
+        std::fs::write(dir.join("f.txt"), lines.concat()).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("f.txt")).unwrap();
+        index.write().unwrap();
+
+        let mut changed = lines;
+        changed.drain(24..26);
+        changed[11] = "12 changed\n".to_string();
+        changed.splice(3..3, ["new\n".to_string(), "new\n".to_string()]);
+        std::fs::write(dir.join("f.txt"), changed.concat()).unwrap();
+        dir
+    }
+
+    fn unstaged(dir: &Path, context: Option<u32>) -> FilePatch {
+        let source = PatchSource::Unstaged(vec![PathBuf::from("f.txt")]);
+        let patch = generate_with_context(dir, &source, context, false, RenameDetection::default())
+            .unwrap();
+        parse(&String::from_utf8(patch).unwrap()).remove(0)
+    }
+
+    #[test]
+    fn standalone_headers_leave_out_the_other_hunks() {
+        let dir = repo_with_three_changes("standalone-header");
+        let file = unstaged(&dir, Some(0));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let headers =
+            |header: fn(&Hunk) -> String| file.hunks.iter().map(header).collect::<Vec<_>>();
+        assert_eq!(
+            headers(|hunk| hunk.header.clone()),
+            ["@@ -3,0 +4,2 @@", "@@ -12 +14 @@", "@@ -25,2 +26,0 @@"]
+        );
+        // A pure addition, a later hunk, and a pure deletion naming the line before it
+        assert_eq!(
+            headers(Hunk::standalone_header),
+            ["@@ -3,0 +4,2 @@", "@@ -12 +12 @@", "@@ -25,2 +24,0 @@"]
+        );
+    }
+
+    #[test]
+    fn git_applies_each_standalone_hunk_on_its_own() {
+        let dir = repo_with_three_changes("standalone-apply");
+        for context in [Some(0), None] {
+            let file = unstaged(&dir, context);
+            assert_eq!(file.hunks.len(), 3);
+            for idx in 0..file.hunks.len() {
+                let patch = dir.join("hunk.patch");
+                std::fs::write(&patch, file.standalone_hunk_patch(idx).unwrap()).unwrap();
+                let output = std::process::Command::new("git")
+                    .args(["apply", "--cached", "--check", "--unidiff-zero"])
+                    .arg(&patch)
+                    .current_dir(&dir)
+                    .output()
+                    .unwrap();
+                assert!(
+                    output.status.success(),
+                    "hunk {idx} with {context:?} lines of context: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,113 @@
+use super::history;
+use super::patch::RenameDetection;
+use crate::model::{FileTree, RangeKind, RevRange};
+use anyhow::{Context, Result};
+use git2::{Oid, Repository};
+use std::path::Path;
+
+/// Resolve `A..B` (B against A) or `A...B` (B against where it forked from A).
+/// Either side may be left out for HEAD.
+pub fn resolve(repo_path: &Path, spec: &str) -> Result<RevRange> {
+    let (from, to, symmetric) =
+        RevRange::split(spec).with_context(|| format!("{spec} is not a range (A..B or A...B)"))?;
+    let repo = Repository::open(repo_path)?;
+    let commit = |side: &str| {
+        let side = if side.is_empty() { "HEAD" } else { side };
+        repo.revparse_single(side)
+            .and_then(|object| object.peel_to_commit())
+            .map(|commit| commit.id())
+            .with_context(|| format!("Unknown revision: {side}"))
+    };
+    let (mut from_id, to_id) = (commit(from)?, commit(to)?);
+    if symmetric {
+        from_id = repo
+            .merge_base(from_id, to_id)
+            .with_context(|| format!("{spec} has no common ancestor"))?;
+    }
+    let (commits, _) = repo.graph_ahead_behind(to_id, from_id)?;
+    Ok(RevRange {
+        kind: RangeKind::Explicit,
+        spec: spec.to_string(),
+        from: from_id.to_string(),
+        to: to_id.to_string(),
+        commits,
+    })
+}
+
+/// The branch pull requests go into: what `origin/HEAD` points at, or else the
+/// first of origin/main, origin/master, main and master that exists
+pub fn default_branch(repo_path: &Path) -> Result<String> {
+    let repo = Repository::open(repo_path)?;
+    if let Ok(head) = repo.find_reference("refs/remotes/origin/HEAD")
+        && let Some(target) = head.symbolic_target()
+        && let Some(name) = target.strip_prefix("refs/remotes/")
+    {
+        return Ok(name.to_string());
+    }
+    ["origin/main", "origin/master", "main", "master"]
+        .into_iter()
+        .find(|name| repo.revparse_single(name).is_ok())
+        .map(str::to_string)
+        .context("Couldn't find the default branch (try git remote set-head origin --auto)")
+}
+
+/// HEAD against where it forked from the default branch, for reviewing a branch
+/// before pushing it
+pub fn review(repo_path: &Path) -> Result<RevRange> {
+    let range = resolve(repo_path, &format!("{}...HEAD", default_branch(repo_path)?))?;
+    Ok(RevRange {
+        kind: RangeKind::Review,
+        ..range
+    })
+}
+
+/// Pull request `number` as GitHub diffs it: `head`, the commit last pushed to
+/// it, against `merge_base`, where it forked from its base branch
+pub fn pull_request(
+    repo_path: &Path,
+    number: u64,
+    merge_base: &str,
+    head: &str,
+) -> Result<RevRange> {
+    let range = resolve(repo_path, &format!("{merge_base}..{head}"))?;
+    Ok(RevRange {
+        kind: RangeKind::PullRequest,
+        spec: format!("#{number}"),
+        ..range
+    })
+}
+
+/// HEAD against where it forked from its upstream: what pushing would send
+pub fn push(repo_path: &Path) -> Result<RevRange> {
+    let repo = Repository::open(repo_path)?;
+    let head = repo.head()?;
+    let branch = head
+        .shorthand()
+        .filter(|_| head.is_branch())
+        .context("HEAD isn't on a branch")?;
+    let upstream = repo
+        .find_branch(branch, git2::BranchType::Local)?
+        .upstream()
+        .map_err(|_| anyhow::anyhow!("{branch} has no upstream (git push -u sets one)"))?;
+    let upstream = upstream.name()?.context("Upstream name isn't UTF-8")?;
+    let range = resolve(repo_path, &format!("{upstream}...HEAD"))?;
+    Ok(RevRange {
+        kind: RangeKind::Push,
+        ..range
+    })
+}
+
+/// Tree of the files that differ between the two sides of a range
+pub fn changed_files(
+    repo_path: &Path,
+    range: &RevRange,
+    renames: RenameDetection,
+) -> Result<FileTree> {
+    let repo = Repository::open(repo_path)?;
+    let tree = |oid: &str| -> Result<git2::Tree<'_>> {
+        Ok(repo.find_commit(Oid::from_str(oid)?)?.tree()?)
+    };
+    let mut diff =
+        repo.diff_tree_to_tree(Some(&tree(&range.from)?), Some(&tree(&range.to)?), None)?;
+    history::diff_file_tree(&mut diff, renames)
+}
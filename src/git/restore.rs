@@ -0,0 +1,46 @@
+use super::patch::{self, PatchSource};
+use super::stage;
+use anyhow::Result;
+use git2::{ApplyLocation, Repository, build::CheckoutBuilder};
+use std::path::Path;
+
+/// Throw away a file's unstaged changes, like `git restore <path>`. Staged changes
+/// are kept; untracked files are deleted.
+pub fn discard_file(repo_path: &Path, path: &Path, untracked: bool) -> Result<()> {
+    let relative = path.strip_prefix(".").unwrap_or(path);
+    if untracked {
+        let full_path = repo_path.join(relative);
+        if full_path.is_dir() {
+            std::fs::remove_dir_all(full_path)?;
+        } else {
+            std::fs::remove_file(full_path)?;
+        }
+        return Ok(());
+    }
+
+    let repo = Repository::open(repo_path)?;
+    let mut checkout = CheckoutBuilder::new();
+    checkout.force().disable_pathspec_match(true).path(relative);
+    repo.checkout_index(None, Some(&mut checkout))?;
+    Ok(())
+}
+
+/// Throw away one hunk of a file's unstaged changes by applying its reverse to
+/// the working tree. See `stage::apply_hunk` for `expected_hunks` and `context`.
+pub fn discard_hunk(
+    repo_path: &Path,
+    path: &Path,
+    hunk_idx: usize,
+    expected_hunks: usize,
+    context: Option<u32>,
+) -> Result<()> {
+    let source = PatchSource::Unstaged(vec![path.to_path_buf()]);
+    let patch = patch::generate_reversed(repo_path, &source, context)?;
+    stage::apply_single_hunk(
+        repo_path,
+        patch,
+        hunk_idx,
+        expected_hunks,
+        ApplyLocation::WorkDir,
+    )
+}
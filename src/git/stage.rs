@@ -0,0 +1,194 @@
+use super::patch::{self, PatchSource, RenameDetection};
+use anyhow::{Context, Result, bail};
+use git2::{ApplyLocation, Diff, Repository};
+use std::path::Path;
+
+/// Which way a hunk moves between the worktree and the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HunkAction {
+    Stage,
+    Unstage,
+}
+
+/// Stage or unstage one hunk of a file by applying it to the index.
+/// `expected_hunks` is the number of hunks the displayed diff shows for the file. If
+/// libgit2's diff splits the changes differently (e.g. because of diff settings only the
+/// git CLI honors), nothing is applied rather than risking the wrong hunk.
+/// `context` is the lines of context the diff is shown with, which decides
+/// where hunks merge.
+pub fn apply_hunk(
+    repo_path: &Path,
+    path: &Path,
+    untracked: bool,
+    action: HunkAction,
+    hunk_idx: usize,
+    expected_hunks: usize,
+    context: Option<u32>,
+) -> Result<()> {
+    let relative = path.strip_prefix(".").unwrap_or(path);
+    let repo = Repository::open(repo_path)?;
+    // A submodule's one "hunk" is its commit, which no patch can apply
+    if super::status::submodule_paths(&repo).contains(relative) {
+        return move_submodule(&repo, relative, action);
+    }
+    let patch = match action {
+        HunkAction::Stage if untracked => patch::generate_with_context(
+            repo_path,
+            &PatchSource::Untracked(path.to_path_buf()),
+            context,
+            false,
+            RenameDetection::default(),
+        )?,
+        HunkAction::Stage => patch::generate_with_context(
+            repo_path,
+            &PatchSource::Unstaged(vec![path.to_path_buf()]),
+            context,
+            false,
+            RenameDetection::default(),
+        )?,
+        // Applying the index -> HEAD diff to the index undoes the staged hunk
+        HunkAction::Unstage => patch::generate_reversed(
+            repo_path,
+            &PatchSource::Staged(vec![path.to_path_buf()]),
+            context,
+        )?,
+    };
+    apply_single_hunk(
+        repo_path,
+        patch,
+        hunk_idx,
+        expected_hunks,
+        ApplyLocation::Index,
+    )
+}
+
+/// Stage the commit a submodule is checked out at, or put back HEAD's
+fn move_submodule(repo: &Repository, path: &Path, action: HunkAction) -> Result<()> {
+    match action {
+        HunkAction::Stage => {
+            let mut index = repo.index()?;
+            index.add_path(path)?;
+            index.write()?;
+        }
+        HunkAction::Unstage => {
+            let head = repo.head()?.peel_to_commit()?;
+            repo.reset_default(Some(head.as_object()), [path])?;
+        }
+    }
+    Ok(())
+}
+
+/// Apply one hunk of a single-file patch to the index or the working tree,
+/// after checking the patch has the expected number of hunks
+pub(super) fn apply_single_hunk(
+    repo_path: &Path,
+    patch: Vec<u8>,
+    hunk_idx: usize,
+    expected_hunks: usize,
+    location: ApplyLocation,
+) -> Result<()> {
+    let patch = String::from_utf8(patch).context("Can't apply hunks of non-UTF-8 files")?;
+
+    let file = patch::parse(&patch)
+        .into_iter()
+        .next()
+        .context("No changes to apply")?;
+    if file.hunks.len() != expected_hunks {
+        bail!("Hunks don't match the displayed diff; use git for the whole file instead");
+    }
+    let hunk_patch = file
+        .hunk_patch(hunk_idx)
+        .context("No hunk under the cursor")?;
+
+    let repo = Repository::open(repo_path)?;
+    let diff = Diff::from_buffer(hunk_patch.as_bytes())?;
+    repo.apply(&diff, location, None)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::patch::PatchSource;
+    use std::path::PathBuf;
+
+    const LINES: [&str; 20] = [
+        "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15", "16",
+        "17", "18", "19", "20",
+    ];
+
+    fn text(changed: &[usize]) -> String {
+        LINES
+            .iter()
+            .enumerate()
+            .map(|(i, line)| match changed.contains(&i) {
+                true => format!("{line} changed\n"),
+                false => format!("{line}\n"),
+            })
+            .collect()
+    }
+
+    /// A repository with `f.txt` committed, then its 2nd and 19th lines changed
+    /// in the working tree: two hunks
+    fn repo_with_two_hunks(name: &str) -> (PathBuf, Repository) {
+        let dir = std::env::temp_dir().join(format!("kibitz-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        std::fs::write(dir.join("f.txt"), text(&[])).unwrap();
+        {
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("f.txt")).unwrap();
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let signature = git2::Signature::new("a", "a@b", &git2::Time::new(0, 0)).unwrap();
+            repo.commit(Some("HEAD"), &signature, &signature, "f", &tree, &[])
+                .unwrap();
+        }
+        std::fs::write(dir.join("f.txt"), text(&[1, 18])).unwrap();
+        (dir, repo)
+    }
+
+    fn staged(repo: &Repository) -> String {
+        let mut index = repo.index().unwrap();
+        index.read(true).unwrap();
+        let entry = index.get_path(Path::new("f.txt"), 0).unwrap();
+        String::from_utf8(repo.find_blob(entry.id).unwrap().content().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn staging_the_second_hunk_leaves_the_first_unstaged() {
+        let (dir, repo) = repo_with_two_hunks("stage-second");
+        let source = PatchSource::Unstaged(vec![PathBuf::from("f.txt")]);
+        let patch = patch::generate(&dir, &source).unwrap();
+        apply_single_hunk(&dir, patch, 1, 2, ApplyLocation::Index).unwrap();
+
+        let index = staged(&repo);
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(index, text(&[18]));
+    }
+
+    #[test]
+    fn discarding_the_second_hunk_keeps_the_first() {
+        let (dir, _repo) = repo_with_two_hunks("discard-second");
+        let source = PatchSource::Unstaged(vec![PathBuf::from("f.txt")]);
+        let patch = patch::generate_reversed(&dir, &source, None).unwrap();
+        apply_single_hunk(&dir, patch, 1, 2, ApplyLocation::WorkDir).unwrap();
+
+        let worktree = std::fs::read_to_string(dir.join("f.txt")).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(worktree, text(&[1]));
+    }
+
+    #[test]
+    fn a_different_number_of_hunks_applies_nothing() {
+        let (dir, repo) = repo_with_two_hunks("stage-mismatch");
+        let source = PatchSource::Unstaged(vec![PathBuf::from("f.txt")]);
+        let patch = patch::generate(&dir, &source).unwrap();
+        let result = apply_single_hunk(&dir, patch, 1, 3, ApplyLocation::Index);
+
+        let index = staged(&repo);
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(result.is_err());
+        assert_eq!(index, text(&[]));
+    }
+}
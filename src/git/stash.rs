@@ -0,0 +1,60 @@
+use crate::model::StashEntry;
+use anyhow::{Context, Result, anyhow, bail};
+use git2::{ErrorCode, Repository};
+use std::path::Path;
+
+/// Stash entries, newest (stash@{0}) first
+pub fn list(repo_path: &Path) -> Result<Vec<StashEntry>> {
+    let mut repo = Repository::open(repo_path)?;
+    let mut entries = Vec::new();
+    repo.stash_foreach(|index, message, oid| {
+        entries.push(StashEntry {
+            index,
+            message: message.to_string(),
+            oid: oid.to_string(),
+        });
+        true
+    })?;
+    Ok(entries)
+}
+
+/// Stash the working tree and index changes, like `git stash push`
+pub fn save(repo_path: &Path, message: &str) -> Result<()> {
+    let mut repo = Repository::open(repo_path)?;
+    let signature = repo
+        .signature()
+        .context("Set user.name and user.email to stash")?;
+    // No message gets git's default "WIP on <branch>: ..."
+    let message = (!message.is_empty()).then_some(message);
+    repo.stash_save2(&signature, message, None)
+        .map_err(|e| match e.code() {
+            ErrorCode::NotFound => anyhow!("No local changes to stash"),
+            _ => e.into(),
+        })?;
+    Ok(())
+}
+
+/// Apply stash@{index} to the working tree, keeping it (`pop` = false) or dropping it
+pub fn apply(repo_path: &Path, index: usize, pop: bool) -> Result<()> {
+    let mut repo = Repository::open(repo_path)?;
+    let result = if pop {
+        repo.stash_pop(index, None)
+    } else {
+        repo.stash_apply(index, None)
+    };
+    result.map_err(|e| match e.code() {
+        ErrorCode::Conflict => anyhow!("Stash conflicts with local changes; commit or stash them"),
+        _ => e.into(),
+    })
+}
+
+/// Drop stash@{index}, provided it's still the stash with id `oid`
+pub fn drop(repo_path: &Path, index: usize, oid: &str) -> Result<()> {
+    let current = list(repo_path)?.into_iter().find(|e| e.index == index);
+    if current.is_none_or(|e| e.oid != oid) {
+        bail!("The stash list changed; reopen it with z");
+    }
+    let mut repo = Repository::open(repo_path)?;
+    repo.stash_drop(index)?;
+    Ok(())
+}
@@ -1,7 +1,8 @@
-use crate::model::FileStatus;
+use super::patch::RenameDetection;
+use crate::model::{FileStatus, RepoSnapshot};
 use anyhow::{Context, Result};
 use git2::{Repository, StatusOptions};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
 pub type GitStatusResult = (Vec<(PathBuf, FileStatus)>, HashMap<PathBuf, FileStatus>);
@@ -16,6 +17,77 @@ pub fn find_repo_root() -> Result<PathBuf> {
     Ok(workdir.to_path_buf())
 }
 
+/// Overall state of the working tree, as reported by `kibitz --check`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeState {
+    Clean,
+    /// Changed, staged or untracked files
+    Dirty,
+    /// Unresolved merge conflicts
+    Conflicted,
+}
+
+impl TreeState {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            TreeState::Clean => 0,
+            TreeState::Dirty => 1,
+            TreeState::Conflicted => 2,
+        }
+    }
+}
+
+pub fn tree_state(repo_path: &Path) -> Result<TreeState> {
+    let repo = Repository::open(repo_path)?;
+    if repo.index()?.has_conflicts() {
+        return Ok(TreeState::Conflicted);
+    }
+    let (files, _) = get_status(repo_path)?;
+    Ok(if files.is_empty() {
+        TreeState::Clean
+    } else {
+        TreeState::Dirty
+    })
+}
+
+/// Files changed, insertions and deletions in the working tree against HEAD,
+/// like `git diff HEAD --shortstat`
+pub fn diffstat(repo_path: &Path) -> Result<(usize, usize, usize)> {
+    let repo = Repository::open(repo_path)?;
+    let head = repo.head().ok().and_then(|head| head.peel_to_tree().ok());
+    let stats = repo
+        .diff_tree_to_workdir_with_index(head.as_ref(), None)?
+        .stats()?;
+    Ok((stats.files_changed(), stats.insertions(), stats.deletions()))
+}
+
+/// The repository's index file, which lives in the git directory: `.git` is a
+/// file pointing elsewhere in linked worktrees and submodules
+pub fn index_path(repo_path: &Path) -> Result<PathBuf> {
+    Ok(Repository::open(repo_path)?.path().join("index"))
+}
+
+/// `path` (relative to the current directory, or absolute) relative to the repo root
+pub fn repo_relative(repo_path: &Path, path: &Path) -> Result<PathBuf> {
+    let absolute = std::env::current_dir()?.join(path);
+    // The file may not exist (e.g. deleted), but its directory should
+    let (dir, name) = match absolute.file_name() {
+        Some(name) if !absolute.is_dir() => (absolute.parent().unwrap_or(&absolute), Some(name)),
+        _ => (absolute.as_path(), None),
+    };
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("No such directory: {}", dir.display()))?;
+    let root = repo_path.canonicalize()?;
+    let relative = dir
+        .strip_prefix(&root)
+        .with_context(|| format!("{} is outside the repository", path.display()))?;
+    Ok(match name {
+        Some(name) => relative.join(name),
+        None => relative.to_path_buf(),
+    })
+}
+
 pub fn get_status(repo_path: &Path) -> Result<GitStatusResult> {
     let repo = Repository::open(repo_path)?;
 
@@ -25,6 +97,7 @@ pub fn get_status(repo_path: &Path) -> Result<GitStatusResult> {
         .include_ignored(false);
 
     let statuses = repo.statuses(Some(&mut opts))?;
+    let submodules = submodule_paths(&repo);
 
     let mut files = Vec::new();
     let mut file_map = HashMap::new();
@@ -36,7 +109,11 @@ pub fn get_status(repo_path: &Path) -> Result<GitStatusResult> {
         };
 
         let status = entry.status();
-        let file_status = convert_status(status);
+        let file_status = match convert_status(status) {
+            // Its staged pointer change is staged like any file's
+            Some(FileStatus::Modified) if submodules.contains(&path) => Some(FileStatus::Submodule),
+            file_status => file_status,
+        };
 
         if let Some(fs) = file_status {
             files.push((path.clone(), fs));
@@ -47,6 +124,116 @@ pub fn get_status(repo_path: &Path) -> Result<GitStatusResult> {
     Ok((files, file_map))
 }
 
+/// Where the repository's submodules are, from .gitmodules
+pub fn submodule_paths(repo: &Repository) -> HashSet<PathBuf> {
+    // Reading the submodules' config is wasted on most repositories
+    let has_submodules = repo
+        .workdir()
+        .is_some_and(|dir| dir.join(".gitmodules").exists());
+    if !has_submodules {
+        return HashSet::new();
+    }
+    repo.submodules()
+        .map(|submodules| {
+            submodules
+                .iter()
+                .map(|submodule| submodule.path().to_path_buf())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Pair up the staged files of `status` that are renames or copies of others,
+/// returning (new path, old path) pairs. A rename's old path is left out of
+/// the status, its change being shown with the new one. Only the index is
+/// paired up, like `git status` does: a file moved in the working tree is a
+/// deleted and an untracked file until staged.
+pub fn pair_staged_renames(
+    repo_path: &Path,
+    status: &mut GitStatusResult,
+    renames: RenameDetection,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let repo = Repository::open(repo_path)?;
+    let head = repo.head().ok().and_then(|head| head.peel_to_tree().ok());
+    let mut diff = repo.diff_tree_to_index(head.as_ref(), None, None)?;
+    renames.apply(&mut diff)?;
+
+    let (files, file_map) = status;
+    let mut pairs = Vec::new();
+    for delta in diff.deltas() {
+        let (Some(new), Some(old)) = (delta.new_file().path(), delta.old_file().path()) else {
+            continue;
+        };
+        match delta.status() {
+            git2::Delta::Renamed => {
+                // Unless something new was put in its place
+                if file_map.get(old) == Some(&FileStatus::Staged) {
+                    file_map.remove(old);
+                    files.retain(|(path, _)| path != old);
+                }
+            }
+            git2::Delta::Copied => {}
+            _ => continue,
+        }
+        pairs.push((new.to_path_buf(), old.to_path_buf()));
+    }
+    Ok(pairs)
+}
+
+/// Branch, HEAD and changed files with their modification times, for the activity log
+pub fn snapshot(repo_path: &Path) -> Result<RepoSnapshot> {
+    let repo = Repository::open(repo_path)?;
+    let head = repo.head().ok();
+    let branch = head
+        .as_ref()
+        .filter(|h| h.is_branch())
+        .and_then(|h| h.shorthand().map(String::from));
+    let head_commit = head.and_then(|h| h.peel_to_commit().ok());
+    let head_oid = head_commit.as_ref().map(|c| c.id().to_string());
+    let head = head_commit.map(|commit| {
+        let id = commit.as_object().short_id().ok();
+        let id = id.and_then(|id| id.as_str().map(String::from));
+        (
+            id.unwrap_or_default(),
+            commit.summary().unwrap_or("").to_string(),
+        )
+    });
+    let upstream = branch.as_ref().and_then(|name| {
+        let upstream = repo
+            .find_branch(name, git2::BranchType::Local)
+            .ok()?
+            .upstream()
+            .ok()?;
+        let oid = upstream.get().target()?;
+        Some((upstream.name().ok()??.to_string(), oid.to_string()))
+    });
+    let ahead_behind = upstream.as_ref().and_then(|(_, upstream_oid)| {
+        let head = git2::Oid::from_str(head_oid.as_deref()?).ok()?;
+        let upstream = git2::Oid::from_str(upstream_oid).ok()?;
+        repo.graph_ahead_behind(head, upstream).ok()
+    });
+
+    let (files, _) = get_status(repo_path)?;
+    let files = files
+        .into_iter()
+        .map(|(path, status)| {
+            let modified = std::fs::metadata(repo_path.join(&path))
+                .and_then(|m| m.modified())
+                .ok();
+            (path, (status, modified))
+        })
+        .collect();
+
+    Ok(RepoSnapshot {
+        branch,
+        head,
+        head_oid,
+        upstream,
+        ahead_behind,
+        files,
+    })
+}
+
 fn convert_status(status: git2::Status) -> Option<FileStatus> {
     let has_index_change = status.intersects(
         git2::Status::INDEX_NEW
@@ -62,7 +249,9 @@ fn convert_status(status: git2::Status) -> Option<FileStatus> {
             | git2::Status::WT_RENAMED,
     );
 
-    if has_index_change && has_worktree_change {
+    if status.contains(git2::Status::CONFLICTED) {
+        Some(FileStatus::Conflicted)
+    } else if has_index_change && has_worktree_change {
         Some(FileStatus::StagedModified)
     } else if has_index_change {
         Some(FileStatus::Staged)
@@ -0,0 +1,105 @@
+use super::patch::{self, PatchSource, RenameDetection};
+use anyhow::Result;
+use git2::{DiffOptions, FileMode, Oid, Repository, StatusOptions};
+use std::path::Path;
+
+/// Commits listed for a submodule moved further than this are cut off
+const MAX_LOG: usize = 20;
+
+/// A few lines for each submodule whose recorded commit changed in `source`:
+/// its old and new commit and, with `log`, the commits between them like
+/// `git diff --submodule=log`. Empty when no submodule changed.
+pub fn describe(repo_path: &Path, source: &PatchSource, log: bool) -> Result<Vec<String>> {
+    if matches!(source, PatchSource::Untracked(_) | PatchSource::Compare(_)) {
+        return Ok(Vec::new());
+    }
+    let repo = Repository::open(repo_path)?;
+    let mut opts = DiffOptions::new();
+    opts.disable_pathspec_match(true);
+    let diff = patch::git_diff(&repo, source, &mut opts, RenameDetection::default())?;
+
+    let mut lines = Vec::new();
+    for delta in diff.deltas() {
+        let (old, new) = (delta.old_file(), delta.new_file());
+        if old.mode() != FileMode::Commit && new.mode() != FileMode::Commit {
+            continue;
+        }
+        let Some(path) = new.path().or(old.path()) else {
+            continue;
+        };
+        let old = (old.mode() == FileMode::Commit).then(|| old.id());
+        let new = (new.mode() == FileMode::Commit).then(|| new.id());
+        // The submodule's own objects are only there when it's checked out
+        let sub = Repository::open(repo_path.join(path)).ok();
+        let describe = |oid: Oid| commit_line(sub.as_ref(), oid);
+
+        let path = path.display();
+        match (old, new) {
+            (None, Some(new)) => lines.push(format!("Submodule {path} added at {}", describe(new))),
+            (Some(old), None) => lines.push(format!(
+                "Submodule {path} removed, was at {}",
+                describe(old)
+            )),
+            (Some(old), Some(new)) if old != new => {
+                lines.push(format!("Submodule {path} {}..{}", short(old), short(new)));
+                lines.push(format!("  was {}", describe(old)));
+                lines.push(format!("  now {}", describe(new)));
+                if log {
+                    match &sub {
+                        Some(sub) => lines.extend(log_lines(sub, old, new)),
+                        None => lines.push("  (not checked out, no log)".to_string()),
+                    }
+                }
+            }
+            (Some(oid), Some(_)) => lines.push(format!("Submodule {path} at {}", describe(oid))),
+            (None, None) => continue,
+        }
+        if matches!(source, PatchSource::Unstaged(_)) && sub.as_ref().is_some_and(is_dirty) {
+            lines.push("  has uncommitted changes of its own".to_string());
+        }
+    }
+    Ok(lines)
+}
+
+fn short(oid: Oid) -> String {
+    oid.to_string()[..7].to_string()
+}
+
+/// The commit's short id and summary, when the submodule has it
+fn commit_line(sub: Option<&Repository>, oid: Oid) -> String {
+    match sub.and_then(|sub| sub.find_commit(oid).ok()) {
+        Some(commit) => format!("{} {}", short(oid), commit.summary().unwrap_or("")),
+        None => short(oid),
+    }
+}
+
+/// Commits the submodule gained (`>`) and lost (`<`) moving from `old` to `new`
+fn log_lines(sub: &Repository, old: Oid, new: Oid) -> Vec<String> {
+    let walk = |from: Oid, to: Oid| -> Vec<Oid> {
+        let Ok(mut walk) = sub.revwalk() else {
+            return Vec::new();
+        };
+        if walk.push(to).is_err() || walk.hide(from).is_err() {
+            return Vec::new();
+        }
+        walk.filter_map(|oid| oid.ok()).collect()
+    };
+    let mut lines = Vec::new();
+    for (marker, commits) in [('<', walk(new, old)), ('>', walk(old, new))] {
+        for &oid in commits.iter().take(MAX_LOG) {
+            lines.push(format!("  {marker} {}", commit_line(Some(sub), oid)));
+        }
+        if commits.len() > MAX_LOG {
+            lines.push(format!("  {marker} … {} more", commits.len() - MAX_LOG));
+        }
+    }
+    lines
+}
+
+/// Whether the submodule's working tree has changes of its own
+fn is_dirty(sub: &Repository) -> bool {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true);
+    sub.statuses(Some(&mut opts))
+        .is_ok_and(|statuses| !statuses.is_empty())
+}
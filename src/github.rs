@@ -0,0 +1,302 @@
+use crate::git::patch::{FilePatch, Hunk};
+use anyhow::{Context, Result, bail};
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, mpsc};
+use std::thread;
+
+/// The pull request open for the checked-out branch, with its review comments
+#[derive(Debug, Clone)]
+pub struct PullRequest {
+    pub number: u64,
+    /// Full id of the commit last pushed to it, whose diff comments are on
+    pub head: String,
+    /// Full id of where it forked from its base branch, the old side of its
+    /// diff; None until the base branch's commits are fetched
+    pub merge_base: Option<String>,
+    pub comments: Arc<[ReviewComment]>,
+}
+
+/// Which side of the diff a comment's line is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Side {
+    /// The old file
+    Left,
+    /// The new file
+    Right,
+}
+
+/// A review comment on a line of the pull request's diff
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewComment {
+    pub path: PathBuf,
+    pub line: usize,
+    pub side: Side,
+    pub author: String,
+    pub body: String,
+    /// When it was written, as `YYYY-MM-DD`
+    pub date: String,
+    /// Written here and not submitted yet
+    pub draft: bool,
+    /// Full id of the pull request's head commit `line` is a line of its diff at
+    #[serde(default)]
+    pub commit: String,
+}
+
+/// What a submitted review says about the pull request as a whole
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewEvent {
+    Approve,
+    Comment,
+    RequestChanges,
+}
+
+impl ReviewEvent {
+    /// The event for `approve`, `comment` or `changes` (or their first letter)
+    pub fn parse(word: &str) -> Option<Self> {
+        match word {
+            "a" | "approve" => Some(Self::Approve),
+            "c" | "comment" => Some(Self::Comment),
+            "r" | "changes" | "request-changes" => Some(Self::RequestChanges),
+            _ => None,
+        }
+    }
+
+    fn api_name(self) -> &'static str {
+        match self {
+            Self::Approve => "APPROVE",
+            Self::Comment => "COMMENT",
+            Self::RequestChanges => "REQUEST_CHANGES",
+        }
+    }
+}
+
+impl ReviewComment {
+    /// Whether the comment is on a line `hunk` of `file` spans
+    pub fn is_on(&self, file: &FilePatch, hunk: &Hunk) -> bool {
+        let (path, lines) = match self.side {
+            Side::Left => (&file.old_path, hunk.old_range()),
+            Side::Right => (&file.new_path, hunk.new_range()),
+        };
+        path.as_ref() == Some(&self.path) && lines.contains(&self.line)
+    }
+
+    /// A draft comment on the last line `hunk` of `file` changes: its last
+    /// added line, or its last removed one if it only removes
+    pub fn draft(file: &FilePatch, hunk: &Hunk, body: String, commit: String) -> Option<Self> {
+        let (mut old, mut new) = (hunk.old_start, hunk.new_start);
+        let mut anchor = None;
+        for line in &hunk.lines {
+            match line.as_bytes().first() {
+                Some(b'+') => {
+                    anchor = Some((Side::Right, new));
+                    new += 1;
+                }
+                Some(b'-') => {
+                    if !matches!(anchor, Some((Side::Right, _))) {
+                        anchor = Some((Side::Left, old));
+                    }
+                    old += 1;
+                }
+                Some(b' ') => {
+                    old += 1;
+                    new += 1;
+                }
+                _ => {}
+            }
+        }
+        let (side, line) = anchor?;
+        let path = match side {
+            Side::Left => file.old_path.clone(),
+            Side::Right => file.new_path.clone(),
+        }?;
+        Some(Self {
+            path,
+            line,
+            side,
+            author: String::new(),
+            body,
+            date: String::new(),
+            draft: true,
+            commit,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawPullRequest {
+    number: u64,
+    head_ref_oid: String,
+    base_ref_oid: String,
+}
+
+#[derive(Deserialize)]
+struct RawComment {
+    path: PathBuf,
+    /// None once the line is no longer in the diff
+    line: Option<usize>,
+    side: Option<Side>,
+    user: Option<RawUser>,
+    body: String,
+    created_at: String,
+    #[serde(default)]
+    commit_id: String,
+}
+
+#[derive(Deserialize)]
+struct RawUser {
+    login: String,
+}
+
+/// The pull request for the branch checked out in `repo_path` and its review
+/// comments, through the `gh` CLI. None when there's no pull request, or `gh`
+/// isn't installed or logged in.
+pub fn pull_request(repo_path: &Path) -> Result<Option<PullRequest>> {
+    let Some(output) = gh(
+        repo_path,
+        &["pr", "view", "--json", "number,headRefOid,baseRefOid"],
+    )?
+    else {
+        return Ok(None);
+    };
+    let pr: RawPullRequest =
+        serde_json::from_slice(&output).context("Couldn't read gh's pull request")?;
+    let endpoint = format!("repos/{{owner}}/{{repo}}/pulls/{}/comments", pr.number);
+    let output = gh(repo_path, &["api", "--paginate", &endpoint])?
+        .context("Couldn't fetch the pull request's review comments")?;
+    // Each page is its own JSON array
+    let mut comments = Vec::new();
+    for page in serde_json::Deserializer::from_slice(&output).into_iter::<Vec<RawComment>>() {
+        comments.extend(page?.into_iter().filter_map(|raw| {
+            Some(ReviewComment {
+                path: raw.path,
+                line: raw.line?,
+                side: raw.side.unwrap_or(Side::Right),
+                author: raw.user.map_or_else(String::new, |u| u.login),
+                body: raw.body,
+                date: raw.created_at.chars().take(10).collect(),
+                draft: false,
+                commit: raw.commit_id,
+            })
+        }));
+    }
+    // Like GitHub's, the diff is against where the branch forked
+    let merge_base = Repository::open(repo_path).ok().and_then(|repo| {
+        let base = Oid::from_str(&pr.base_ref_oid).ok()?;
+        let head = Oid::from_str(&pr.head_ref_oid).ok()?;
+        repo.merge_base(base, head).ok().map(|oid| oid.to_string())
+    });
+    Ok(Some(PullRequest {
+        number: pr.number,
+        head: pr.head_ref_oid,
+        merge_base,
+        comments: comments.into(),
+    }))
+}
+
+/// Submit `comments` as one review of pull request `number` at its head commit
+/// `commit` in the background
+pub fn submit_review_async(
+    repo_path: &Path,
+    number: u64,
+    commit: String,
+    event: ReviewEvent,
+    body: String,
+    comments: Vec<ReviewComment>,
+) -> mpsc::Receiver<Result<()>> {
+    let repo_path = repo_path.to_path_buf();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(submit_review(
+            &repo_path, number, &commit, event, &body, &comments,
+        ));
+    });
+    rx
+}
+
+fn submit_review(
+    repo_path: &Path,
+    number: u64,
+    commit: &str,
+    event: ReviewEvent,
+    body: &str,
+    comments: &[ReviewComment],
+) -> Result<()> {
+    // Drafts on an earlier push may be on lines that moved since, so they go
+    // in the review's summary instead, saying where they were
+    let (comments, earlier): (Vec<_>, Vec<_>) = comments.iter().partition(|c| c.commit == commit);
+    let mut body = body.to_string();
+    for c in earlier {
+        body.push_str(&format!(
+            "\n\n**{}:{}** (at {:.7}):\n{}",
+            c.path.display(),
+            c.line,
+            c.commit,
+            c.body
+        ));
+    }
+    let comments: Vec<_> = comments
+        .iter()
+        .map(|c| {
+            serde_json::json!({
+                "path": c.path.to_string_lossy().replace('\\', "/"),
+                "line": c.line,
+                "side": match c.side {
+                    Side::Left => "LEFT",
+                    Side::Right => "RIGHT",
+                },
+                "body": c.body,
+            })
+        })
+        .collect();
+    // Lines are placed in this commit's diff, and GitHub turns the review
+    // down if the pull request moved on since
+    let review = serde_json::json!({
+        "commit_id": commit,
+        "event": event.api_name(),
+        "body": body.trim_start(),
+        "comments": comments,
+    });
+    let endpoint = format!("repos/{{owner}}/{{repo}}/pulls/{number}/reviews");
+    let mut child = Command::new("gh")
+        .args(["api", "--method", "POST", &endpoint, "--input", "-"])
+        .current_dir(repo_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Couldn't run gh")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(review.to_string().as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "Couldn't submit the review: {}",
+            stderr.lines().next().unwrap_or("gh failed").trim()
+        );
+    }
+    Ok(())
+}
+
+/// Run `gh` in the repo and return what it printed. None when it failed or
+/// isn't installed.
+fn gh(repo_path: &Path, args: &[&str]) -> Result<Option<Vec<u8>>> {
+    let output = match Command::new("gh")
+        .args(args)
+        .current_dir(repo_path)
+        .stdin(Stdio::null())
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => bail!("Couldn't run gh: {e}"),
+    };
+    Ok(output.status.success().then_some(output.stdout))
+}
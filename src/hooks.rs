@@ -0,0 +1,54 @@
+use crate::config::HooksConfig;
+use crate::shell;
+use std::path::Path;
+use std::process::Stdio;
+use std::thread;
+
+/// Events that can run a `[hooks]` command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    Commit,
+    Stage,
+    FileSelected,
+}
+
+impl Hook {
+    /// Config key, also passed as KIBITZ_EVENT
+    fn name(self) -> &'static str {
+        match self {
+            Hook::Commit => "on_commit",
+            Hook::Stage => "on_stage",
+            Hook::FileSelected => "on_file_selected",
+        }
+    }
+
+    fn command(self, config: &HooksConfig) -> Option<&str> {
+        match self {
+            Hook::Commit => config.on_commit.as_deref(),
+            Hook::Stage => config.on_stage.as_deref(),
+            Hook::FileSelected => config.on_file_selected.as_deref(),
+        }
+        .filter(|command| !command.trim().is_empty())
+    }
+}
+
+/// Run the command configured for `hook`, if any, with KIBITZ_REPO, KIBITZ_EVENT
+/// and `vars` in its environment. Runs in the background; failures are ignored.
+pub fn run(config: &HooksConfig, hook: Hook, repo_path: &Path, vars: &[(&str, String)]) {
+    let Some(command) = hook.command(config) else {
+        return;
+    };
+    let mut cmd = shell::command(command);
+    cmd.current_dir(repo_path)
+        .env("KIBITZ_REPO", repo_path)
+        .env("KIBITZ_EVENT", hook.name())
+        .envs(vars.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    // Wait in the background so the child is reaped without blocking the UI
+    thread::spawn(move || {
+        let _ = cmd.status();
+    });
+}
@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Translations shipped with kibitz, by language code
+const BUNDLED: &[(&str, &str)] = &[("de", include_str!("../locales/de.toml"))];
+
+/// The English text of each translated UI string and its translation
+static TRANSLATIONS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Load the translations for `language` (e.g. `de`), or the one the
+/// environment's locale names when it's None. A `<language>.toml` in the
+/// config directory's `kibitz/locales` adds to or replaces the bundled one.
+/// Nothing is translated for English or a language without translations.
+pub fn init(language: Option<&str>) -> Result<()> {
+    let Some(language) = language.map(String::from).or_else(env_language) else {
+        return Ok(());
+    };
+    let mut translations = HashMap::new();
+    if let Some((_, bundled)) = BUNDLED.iter().find(|(code, _)| *code == language) {
+        translations.extend(parse(bundled).context("Bundled translations are broken")?);
+    }
+    let user_file = user_locale_path(&language).filter(|path| path.exists());
+    if let Some(path) = user_file {
+        let contents = std::fs::read_to_string(&path)?;
+        translations.extend(
+            parse(&contents)
+                .with_context(|| format!("Couldn't load translations {}", path.display()))?,
+        );
+    }
+    let _ = TRANSLATIONS.set(translations);
+    Ok(())
+}
+
+/// `text` in the user's language, or as it is when there's no translation
+pub fn tr(text: &str) -> &str {
+    TRANSLATIONS
+        .get()
+        .and_then(|translations| translations.get(text))
+        .map_or(text, String::as_str)
+}
+
+/// Translate `template`, then fill its `{}`s with `args` in order
+pub fn tr_format(template: &str, args: &[&str]) -> String {
+    let mut parts = tr(template).split("{}");
+    let mut text = parts.next().unwrap_or_default().to_string();
+    for (part, arg) in parts.zip(args.iter().chain(std::iter::repeat(&""))) {
+        text.push_str(arg);
+        text.push_str(part);
+    }
+    text
+}
+
+/// A locale file: `"English text" = "translation"` lines
+fn parse(contents: &str) -> Result<HashMap<String, String>> {
+    Ok(toml::from_str(contents)?)
+}
+
+/// The language of `LC_ALL`, `LC_MESSAGES` or `LANG`, like `de` for
+/// `de_DE.UTF-8`; None for English and the C locale
+fn env_language() -> Option<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())?;
+    let language = locale.split(['_', '.', '@']).next()?.to_lowercase();
+    (!matches!(language.as_str(), "" | "c" | "posix" | "en")).then_some(language)
+}
+
+fn user_locale_path(language: &str) -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| {
+        dir.join("kibitz")
+            .join("locales")
+            .join(format!("{language}.toml"))
+    })
+}
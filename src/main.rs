@@ -1,12 +1,29 @@
 mod app;
+mod cli;
+mod clipboard;
 mod config;
+mod editor;
 mod event;
 mod git;
+mod github;
+mod hooks;
+mod i18n;
 mod model;
+mod notify;
+mod output;
+mod pair;
+mod persist;
+mod plugin;
+mod recovery;
+mod serve;
+mod shell;
 mod ui;
+mod view;
+mod workspace;
 
 use anyhow::Result;
 use app::App;
+use cli::Mode;
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
     execute,
@@ -16,11 +33,14 @@ use ratatui::prelude::*;
 use std::io;
 
 fn main() -> Result<()> {
-    // Check for delta before starting
-    if !git::diff::delta_available() {
-        anyhow::bail!(
-            "delta is required but not found in PATH. Please install delta: https://github.com/dandavison/delta"
-        );
+    let args = cli::Args::parse_or_exit();
+    let mode = args.mode();
+    if mode != Mode::Tui {
+        let code = cli::run_headless(mode, &args).unwrap_or_else(|e| {
+            eprintln!("Error: {e:#}");
+            cli::ERROR_EXIT_CODE
+        });
+        std::process::exit(code);
     }
 
     // Initialize terminal
@@ -30,9 +50,16 @@ fn main() -> Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Create and run app
-    let mut app = App::new()?;
-    let result = app.run(&mut terminal);
+    // Show the panes before reading the repository, which takes a while when
+    // it's huge. Startup errors (bad revspec, config) print once the terminal
+    // is restored.
+    let show_tree = args.file.is_none() && !args.no_tree;
+    let layout = args.layout.unwrap_or_default();
+    terminal.draw(|frame| ui::render_loading(frame, show_tree, layout))?;
+    let result = App::new(args).and_then(|mut app| {
+        app.run(&mut terminal)?;
+        Ok(app.exit_summary())
+    });
 
     // Restore terminal
     disable_raw_mode()?;
@@ -43,5 +70,9 @@ fn main() -> Result<()> {
     )?;
     terminal.show_cursor()?;
 
-    result
+    // On the normal screen, so it stays in the scrollback
+    if let Some(summary) = result? {
+        print!("{summary}");
+    }
+    Ok(())
 }
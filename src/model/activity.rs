@@ -0,0 +1,109 @@
+use super::FileStatus;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Oldest entries are dropped beyond this many
+const MAX_ENTRIES: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct ActivityEntry {
+    /// Local wall-clock time, HH:MM
+    pub time: String,
+    pub text: String,
+}
+
+/// What changed in the repo while kibitz was open, newest last
+#[derive(Debug, Default)]
+pub struct ActivityLog {
+    entries: VecDeque<ActivityEntry>,
+}
+
+impl ActivityLog {
+    pub fn push(&mut self, text: String) {
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(ActivityEntry {
+            time: chrono::Local::now().format("%H:%M").to_string(),
+            text,
+        });
+    }
+
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &ActivityEntry> + ExactSizeIterator {
+        self.entries.iter()
+    }
+}
+
+/// State of the repo at one refresh, compared against the next to log what changed
+#[derive(Debug, Default)]
+pub struct RepoSnapshot {
+    /// Checked-out branch (None when detached or unborn)
+    pub branch: Option<String>,
+    /// Short id and summary of HEAD
+    pub head: Option<(String, String)>,
+    /// Full id of HEAD, to find the commits added since
+    pub head_oid: Option<String>,
+    /// Name and full id of the checked-out branch's upstream (e.g. origin/main)
+    pub upstream: Option<(String, String)>,
+    /// Commits HEAD is ahead of and behind its upstream
+    pub ahead_behind: Option<(usize, usize)>,
+    /// Changed files with their status and modification time
+    pub files: HashMap<PathBuf, (FileStatus, Option<SystemTime>)>,
+}
+
+impl RepoSnapshot {
+    /// Human-readable changes from `self` to `next`, e.g. "src/app.rs modified"
+    pub fn changes(&self, next: &RepoSnapshot) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if self.branch != next.branch {
+            changes.push(match (&next.branch, &next.head) {
+                (Some(branch), _) => format!("branch switched to {branch}"),
+                (None, Some((id, _))) => format!("HEAD detached at {id}"),
+                (None, None) => "HEAD moved".to_string(),
+            });
+        } else if self.head != next.head
+            && let Some((id, summary)) = &next.head
+        {
+            changes.push(format!("HEAD now at {id} {summary}"));
+        }
+
+        let mut paths: Vec<_> = self.files.keys().chain(next.files.keys()).collect();
+        paths.sort();
+        paths.dedup();
+        for path in paths {
+            let path_display = path.display();
+            match (self.files.get(path), next.files.get(path)) {
+                (Some(_), None) => changes.push(format!("{path_display} clean")),
+                (None, Some((status, _))) => {
+                    changes.push(format!("{path_display} {}", status_word(*status)));
+                }
+                (Some((old_status, old_time)), Some((status, time))) => {
+                    if old_status != status {
+                        changes.push(format!("{path_display} {}", status_word(*status)));
+                    } else if old_time != time {
+                        changes.push(format!("{path_display} modified"));
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+
+        changes
+    }
+}
+
+fn status_word(status: FileStatus) -> &'static str {
+    match status {
+        FileStatus::Modified => "modified",
+        FileStatus::Added => "added",
+        FileStatus::Deleted => "deleted",
+        FileStatus::Renamed => "renamed",
+        FileStatus::Untracked => "created",
+        FileStatus::Staged => "staged",
+        FileStatus::StagedModified => "partly staged",
+        FileStatus::Conflicted => "conflicted",
+        FileStatus::Submodule => "moved to another commit",
+    }
+}
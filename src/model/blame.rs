@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+/// Lines of a file owned by one author
+#[derive(Debug, Clone)]
+pub struct AuthorShare {
+    pub name: String,
+    pub lines: usize,
+}
+
+/// Per-author ownership of a file's lines, derived from git blame
+#[derive(Debug, Clone, Default)]
+pub struct BlameOverview {
+    /// File the overview belongs to
+    pub path: PathBuf,
+    /// Authors sorted by number of lines owned, most first
+    pub authors: Vec<AuthorShare>,
+    /// Index into `authors` for each line of the file
+    pub line_authors: Vec<usize>,
+}
+
+impl BlameOverview {
+    /// Split the file into `rows` equal slices and return the dominant author of each
+    pub fn bands(&self, rows: usize) -> Vec<Option<usize>> {
+        let total = self.line_authors.len();
+        if total == 0 {
+            return vec![None; rows];
+        }
+
+        (0..rows)
+            .map(|row| {
+                let start = row * total / rows;
+                let end = ((row + 1) * total / rows).max(start + 1).min(total);
+                let mut counts = vec![0usize; self.authors.len()];
+                for &author in &self.line_authors[start..end] {
+                    counts[author] += 1;
+                }
+                counts
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|&(i, &count)| (count, std::cmp::Reverse(i)))
+                    .map(|(i, _)| i)
+            })
+            .collect()
+    }
+
+    /// Share of the file's lines owned by an author, in percent
+    pub fn percent(&self, author: usize) -> usize {
+        let total = self.line_authors.len().max(1);
+        self.authors[author].lines * 100 / total
+    }
+}
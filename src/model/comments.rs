@@ -0,0 +1,9 @@
+use crate::github::ReviewComment;
+
+/// The review comments on one hunk, shown in a popup (`i`)
+pub struct CommentThread {
+    /// Where the hunk is, e.g. `src/app.rs:120`
+    pub title: String,
+    pub comments: Vec<ReviewComment>,
+    pub scroll: u16,
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A file as of a revision, or in the working tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComparePoint {
+    /// Path relative to the repo root
+    pub path: PathBuf,
+    /// Revision (anything revparse accepts); None for the working tree
+    pub rev: Option<String>,
+}
+
+impl ComparePoint {
+    /// Parse `path@rev`, `path` (working tree) or `@rev` (uses `default_path`)
+    pub fn parse(spec: &str, default_path: Option<&Path>) -> Option<Self> {
+        let (path, rev) = match spec.rsplit_once('@') {
+            Some((path, rev)) if !rev.is_empty() => (path, Some(rev.to_string())),
+            Some((path, _)) => (path, None),
+            None => (spec, None),
+        };
+        let path = if path.is_empty() {
+            default_path?.to_path_buf()
+        } else {
+            PathBuf::from(path)
+        };
+        let path = path.strip_prefix(".").unwrap_or(&path).to_path_buf();
+        Some(Self { path, rev })
+    }
+}
+
+impl fmt::Display for ComparePoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The repo root is an empty path
+        let path = if self.path.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &self.path
+        };
+        match &self.rev {
+            Some(rev) => write!(f, "{}@{rev}", path.display()),
+            None => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Two points diffed against each other in place of the status or commit diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    pub from: ComparePoint,
+    pub to: ComparePoint,
+    /// The points are directories, browsed as a tree of the files that differ
+    pub dirs: bool,
+}
+
+impl Comparison {
+    /// Parse two whitespace-separated points, e.g. `src/app.rs@HEAD~5 src/app.rs`.
+    /// Points without a path refer to `default_path`.
+    pub fn parse(input: &str, default_path: Option<&Path>) -> Option<Self> {
+        let mut points = input.split_whitespace();
+        let from = ComparePoint::parse(points.next()?, default_path)?;
+        let to = ComparePoint::parse(points.next()?, default_path)?;
+        if points.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            from,
+            to,
+            dirs: false,
+        })
+    }
+
+    /// Comparison of one file within compared directories (`relative` to both)
+    pub fn for_file(&self, relative: &Path) -> Comparison {
+        let point = |p: &ComparePoint| ComparePoint {
+            path: p.path.join(relative),
+            rev: p.rev.clone(),
+        };
+        Comparison {
+            from: point(&self.from),
+            to: point(&self.to),
+            dirs: false,
+        }
+    }
+}
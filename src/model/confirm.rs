@@ -0,0 +1,29 @@
+use std::path::PathBuf;
+
+/// Something destructive waiting for a yes/no answer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfirmAction {
+    DiscardFile {
+        path: PathBuf,
+        untracked: bool,
+    },
+    DiscardHunk {
+        path: PathBuf,
+        index: usize,
+        /// Hunks shown for the file, to check against the regenerated patch
+        count: usize,
+    },
+    DropStash {
+        index: usize,
+        /// Id of the stash, in case the list changed since
+        oid: String,
+    },
+}
+
+/// A pending confirmation, shown as a popup until answered
+#[derive(Debug, Clone)]
+pub struct Confirmation {
+    pub title: String,
+    pub message: String,
+    pub action: ConfirmAction,
+}
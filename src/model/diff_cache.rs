@@ -0,0 +1,181 @@
+use super::DiffState;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Lines of all the diffs kept by `DiffCache` together; a diff longer than
+/// this isn't kept at all
+const MAX_CACHED_LINES: usize = 200_000;
+
+/// What a diff was requested for. Equal keys give the same diff as long as the
+/// repository doesn't change.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DiffKey {
+    /// The commit, range or comparison shown, or the working tree
+    pub view: String,
+    /// Whether the view can change under us (the working tree, comparisons
+    /// with it), rather than being fixed history
+    pub live: bool,
+    /// Selected tree node
+    pub path: Option<PathBuf>,
+    /// Showing the staged side of files with both
+    pub staged: bool,
+    pub width: usize,
+    pub side_by_side: bool,
+    pub line_numbers: bool,
+    pub show_generated: bool,
+    /// Loaded past the size limit
+    pub full: bool,
+    /// Lines of context, when more than git's default
+    pub context: Option<u32>,
+    pub word_diff: bool,
+    pub ignore_whitespace: bool,
+}
+
+/// Recently loaded diffs, so going back to a file shows it right away. Live
+/// diffs are dropped when files change or the index is rewritten; diffs of
+/// history stay until pushed out by newer ones.
+#[derive(Default)]
+pub struct DiffCache {
+    entries: HashMap<DiffKey, Arc<DiffState>>,
+    /// Keys oldest first
+    order: VecDeque<DiffKey>,
+    /// Lines of the diffs in `entries`
+    lines: usize,
+    index_modified: Option<SystemTime>,
+    /// When each file configuring diffs was last modified, as of the last check
+    settings_modified: Option<Vec<Option<SystemTime>>>,
+}
+
+impl DiffCache {
+    pub fn get(&self, key: &DiffKey) -> Option<Arc<DiffState>> {
+        self.entries.get(key).cloned()
+    }
+
+    pub fn contains(&self, key: &DiffKey) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Keep `diff`, dropping the oldest diffs once they're over
+    /// `MAX_CACHED_LINES` together
+    pub fn insert(&mut self, key: DiffKey, diff: Arc<DiffState>) {
+        self.remove(&key);
+        if diff.total_lines > MAX_CACHED_LINES {
+            return;
+        }
+        self.lines += diff.total_lines;
+        self.entries.insert(key.clone(), diff);
+        self.order.push_back(key);
+        while self.lines > MAX_CACHED_LINES {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(diff) = self.entries.remove(&oldest) {
+                self.lines -= diff.total_lines;
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &DiffKey) {
+        if let Some(diff) = self.entries.remove(key) {
+            self.lines -= diff.total_lines;
+            self.order.retain(|k| k != key);
+        }
+    }
+
+    /// Forget every diff, when what's drawn on top of them changed
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.lines = 0;
+    }
+
+    /// Forget diffs of the working tree, which may have changed
+    pub fn invalidate_live(&mut self) {
+        self.entries.retain(|key, _| !key.live);
+        self.order.retain(|key| !key.live);
+        self.lines = self.entries.values().map(|diff| diff.total_lines).sum();
+    }
+
+    /// Forget live diffs if the index at `index_path` was rewritten since the
+    /// last check (staging from another terminal, a commit)
+    pub fn check_index(&mut self, index_path: &Path) {
+        let modified = std::fs::metadata(index_path)
+            .and_then(|m| m.modified())
+            .ok();
+        if modified != self.index_modified {
+            self.index_modified = modified;
+            self.invalidate_live();
+        }
+    }
+
+    /// Forget every diff, history's too, if one of the files configuring diffs
+    /// (`.gitattributes`, textconv and diff drivers) changed since the last
+    /// check. Returns whether it did.
+    pub fn check_settings(&mut self, files: &[PathBuf]) -> bool {
+        let modified: Vec<_> = files
+            .iter()
+            .map(|file| std::fs::metadata(file).and_then(|m| m.modified()).ok())
+            .collect();
+        let changed = self
+            .settings_modified
+            .as_ref()
+            .is_some_and(|before| *before != modified);
+        self.settings_modified = Some(modified);
+        if changed {
+            self.clear();
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(view: &str) -> DiffKey {
+        DiffKey {
+            view: view.to_string(),
+            live: false,
+            path: None,
+            staged: false,
+            width: 80,
+            side_by_side: false,
+            line_numbers: false,
+            show_generated: false,
+            full: false,
+            context: None,
+            word_diff: false,
+            ignore_whitespace: false,
+        }
+    }
+
+    fn diff(total_lines: usize) -> Arc<DiffState> {
+        Arc::new(DiffState {
+            total_lines,
+            ..DiffState::new()
+        })
+    }
+
+    #[test]
+    fn the_oldest_diffs_go_once_over_the_line_limit() {
+        let mut cache = DiffCache::default();
+        cache.insert(key("a"), diff(MAX_CACHED_LINES / 2));
+        cache.insert(key("b"), diff(MAX_CACHED_LINES / 2));
+        cache.insert(key("a"), diff(MAX_CACHED_LINES / 2));
+        assert!(cache.contains(&key("a")) && cache.contains(&key("b")));
+        cache.insert(key("c"), diff(1));
+        assert!(!cache.contains(&key("b")));
+        assert!(cache.contains(&key("a")) && cache.contains(&key("c")));
+    }
+
+    #[test]
+    fn a_diff_over_the_limit_isnt_kept() {
+        let mut cache = DiffCache::default();
+        cache.insert(key("a"), diff(1));
+        cache.insert(key("b"), diff(MAX_CACHED_LINES + 1));
+        assert!(cache.contains(&key("a")));
+        assert!(!cache.contains(&key("b")));
+    }
+}
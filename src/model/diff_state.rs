@@ -1,4 +1,5 @@
-use ratatui::text::Text;
+use ratatui::text::{Line, Text};
+use std::sync::Arc;
 
 /// Height of the sticky file header (file name line + divider line)
 pub const STICKY_FILE_HEADER_HEIGHT: usize = 2;
@@ -6,39 +7,142 @@ pub const STICKY_FILE_HEADER_HEIGHT: usize = 2;
 /// Height of the sticky hunk header (box top + marker + box bottom)
 pub const STICKY_HUNK_HEADER_HEIGHT: usize = 3;
 
+/// A hunk's position within its file's part of the diff
+pub struct HunkLocation<'a> {
+    /// File name from the header, None when the diff has no recognizable file headers
+    pub file: Option<&'a str>,
+    /// Index of the hunk within the file
+    pub index: usize,
+    /// Number of hunks shown for the file
+    pub count: usize,
+}
+
+/// A `/` search over the diff's text
+#[derive(Debug, Clone)]
+pub struct DiffSearch {
+    pub query: String,
+    /// Hits in diff order
+    pub matches: Vec<SearchMatch>,
+    /// Index into `matches` of the hit last jumped to
+    pub current: Option<usize>,
+}
+
+/// Where a search hit is: its line and the char range within the line's text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Lines a slow formatter added to a diff since they were last shown, with
+/// the hunks and files found among them
+#[derive(Debug, Default)]
+pub struct DiffChunk {
+    pub has_both: bool,
+    pub showing_staged: bool,
+    pub lines: Vec<Line<'static>>,
+    pub raw_lines: Vec<String>,
+    pub hunk_positions: Vec<usize>,
+    pub file_header_positions: Vec<usize>,
+    pub file_names: Vec<String>,
+    pub hunk_marker_positions: Vec<usize>,
+}
+
+impl DiffChunk {
+    /// Add `later`, the lines that followed these
+    pub fn append(&mut self, later: DiffChunk) {
+        self.lines.extend(later.lines);
+        self.raw_lines.extend(later.raw_lines);
+        self.hunk_positions.extend(later.hunk_positions);
+        self.file_header_positions
+            .extend(later.file_header_positions);
+        self.file_names.extend(later.file_names);
+        self.hunk_marker_positions
+            .extend(later.hunk_marker_positions);
+    }
+}
+
+/// A loaded diff and where it's scrolled to. Cloning it shares the lines, so
+/// the diff cache and the view can hold the same diff.
+#[derive(Clone)]
 pub struct DiffState {
-    pub content: Text<'static>,
+    pub content: Arc<Text<'static>>,
+    /// Delta's output line by line, escapes included, for the ANSI inspector
+    pub raw_lines: Arc<Vec<String>>,
     pub scroll_offset: usize,
+    /// Columns scrolled to the right; lines aren't wrapped while it's above 0
+    pub x_offset: usize,
+    /// Wrap long lines instead of cutting them off at the pane's edge (`w`)
+    pub wrap: bool,
     pub hunk_positions: Vec<usize>, // Navigation targets for hunk jumping
     pub file_header_positions: Vec<usize>, // Line positions of file headers (Δ, added:, etc.)
+    pub file_names: Vec<String>,    // File name for each entry in file_header_positions
     pub hunk_marker_positions: Vec<usize>, // Line positions of hunk markers (•)
     pub current_hunk: usize,
     pub total_lines: usize,
     pub has_both: bool,       // Has both staged and unstaged changes
     pub showing_staged: bool, // Currently showing staged diff
+    pub search: Option<DiffSearch>,
+    /// Lines past `diff.max_lines` were left out until `A` loads them
+    pub truncated: bool,
+    /// Old and new line number of each line for the gutter, None for lines
+    /// outside hunks and the side a line isn't on. Empty without the gutter.
+    pub line_numbers: Vec<(Option<usize>, Option<usize>)>,
 }
 
 impl DiffState {
     pub fn new() -> Self {
         Self {
-            content: Text::default(),
+            content: Arc::default(),
+            raw_lines: Arc::default(),
             scroll_offset: 0,
+            x_offset: 0,
+            wrap: true,
             hunk_positions: Vec::new(),
             file_header_positions: Vec::new(),
+            file_names: Vec::new(),
             hunk_marker_positions: Vec::new(),
             current_hunk: 0,
             total_lines: 0,
             has_both: false,
             showing_staged: false,
+            search: None,
+            truncated: false,
+            line_numbers: Vec::new(),
         }
     }
 
+    /// Add the lines that followed the ones shown so far
+    pub fn extend(&mut self, chunk: DiffChunk) {
+        Arc::make_mut(&mut self.content).lines.extend(chunk.lines);
+        Arc::make_mut(&mut self.raw_lines).extend(chunk.raw_lines);
+        self.hunk_positions.extend(chunk.hunk_positions);
+        self.file_header_positions
+            .extend(chunk.file_header_positions);
+        self.file_names.extend(chunk.file_names);
+        self.hunk_marker_positions
+            .extend(chunk.hunk_marker_positions);
+        self.total_lines = self.content.lines.len();
+    }
+
     pub fn scroll_down(&mut self, amount: usize) {
         let max_scroll = self.total_lines.saturating_sub(1);
         self.scroll_offset = (self.scroll_offset + amount).min(max_scroll);
         self.update_current_hunk();
     }
 
+    /// Scroll right, no further than where the longest line ends
+    pub fn scroll_right(&mut self, amount: usize) {
+        let widest = self.content.lines.iter().map(|l| l.width()).max();
+        let max = widest.unwrap_or(0).saturating_sub(1);
+        self.x_offset = (self.x_offset + amount).min(max);
+    }
+
+    pub fn scroll_left(&mut self, amount: usize) {
+        self.x_offset = self.x_offset.saturating_sub(amount);
+    }
+
     pub fn scroll_up(&mut self, amount: usize) {
         self.scroll_offset = self.scroll_offset.saturating_sub(amount);
         self.update_current_hunk();
@@ -56,18 +160,121 @@ impl DiffState {
 
     pub fn next_hunk(&mut self) {
         if self.current_hunk + 1 < self.hunk_positions.len() {
-            self.current_hunk += 1;
-            let target = self.hunk_positions[self.current_hunk];
-            self.scroll_offset = self.adjusted_scroll_for_sticky(target);
+            self.select_hunk(self.current_hunk + 1);
         }
     }
 
     pub fn prev_hunk(&mut self) {
         if self.current_hunk > 0 {
-            self.current_hunk -= 1;
-            let target = self.hunk_positions[self.current_hunk];
-            self.scroll_offset = self.adjusted_scroll_for_sticky(target);
+            self.select_hunk(self.current_hunk - 1);
+        }
+    }
+
+    /// Find `query` in the diff's text, ignoring case unless it has capitals.
+    /// Doesn't move; see `next_match`.
+    pub fn set_search(&mut self, query: String) {
+        let smart_case = query.chars().any(char::is_uppercase);
+        let needle: Vec<char> = query.chars().collect();
+        let mut matches = Vec::new();
+        for (line, text) in self.content.lines.iter().enumerate() {
+            let hay: Vec<char> = text
+                .spans
+                .iter()
+                .flat_map(|span| span.content.chars())
+                .collect();
+            let mut start = 0;
+            while !needle.is_empty() && start + needle.len() <= hay.len() {
+                let window = &hay[start..start + needle.len()];
+                let found = window.iter().zip(&needle).all(|(&h, &n)| {
+                    if smart_case {
+                        h == n
+                    } else {
+                        h.to_lowercase().eq(n.to_lowercase())
+                    }
+                });
+                if found {
+                    let end = start + needle.len();
+                    matches.push(SearchMatch { line, start, end });
+                    start = end;
+                } else {
+                    start += 1;
+                }
+            }
+        }
+        self.search = Some(DiffSearch {
+            query,
+            matches,
+            current: None,
+        });
+    }
+
+    /// Jump to the next hit below the top of the view (or the previous one above
+    /// it), wrapping around the ends. Returns whether it wrapped, or None without hits.
+    pub fn next_match(&mut self, forward: bool) -> Option<bool> {
+        let search = self.search.as_ref()?;
+        let current_line = search
+            .current
+            .map(|i| search.matches[i].line)
+            .unwrap_or(self.scroll_offset);
+        let (idx, wrapped) = if forward {
+            // Before the first jump, a hit on the top line counts as next
+            let after = |m: &SearchMatch| match search.current {
+                Some(_) => m.line > current_line,
+                None => m.line >= current_line,
+            };
+            match search.matches.iter().position(after) {
+                Some(idx) => (idx, false),
+                None if search.matches.is_empty() => return None,
+                None => (0, true),
+            }
+        } else {
+            match search.matches.iter().rposition(|m| m.line < current_line) {
+                Some(idx) => (idx, false),
+                None if search.matches.is_empty() => return None,
+                None => (search.matches.len() - 1, true),
+            }
+        };
+        let line = search.matches[idx].line;
+        if let Some(search) = &mut self.search {
+            search.current = Some(idx);
         }
+        self.scroll_to_line(line);
+        Some(wrapped)
+    }
+
+    /// Jump to the hit at the same place as `hit`, if the diff still has one there
+    pub fn jump_to_match(&mut self, hit: SearchMatch) -> bool {
+        let Some(search) = &mut self.search else {
+            return false;
+        };
+        let Some(idx) = search.matches.iter().position(|&m| m == hit) else {
+            return false;
+        };
+        search.current = Some(idx);
+        self.scroll_to_line(hit.line);
+        true
+    }
+
+    /// Scroll so `line` sits just below whatever sticky headers would cover it
+    pub fn scroll_to_line(&mut self, line: usize) {
+        for back in 0..=STICKY_FILE_HEADER_HEIGHT + STICKY_HUNK_HEADER_HEIGHT {
+            self.scroll_offset = line.saturating_sub(back);
+            if back >= self.sticky_rows() || self.scroll_offset == 0 {
+                break;
+            }
+        }
+        self.update_current_hunk();
+    }
+
+    /// Rows at the top of the view taken by sticky headers
+    pub fn sticky_rows(&self) -> usize {
+        let file = self
+            .sticky_file_header()
+            .map_or(0, |_| STICKY_FILE_HEADER_HEIGHT);
+        let hunk = self
+            .sticky_hunk_header()
+            .map_or(0, |_| STICKY_HUNK_HEADER_HEIGHT);
+        file + hunk
     }
 
     /// Adjust scroll position to account for sticky headers.
@@ -94,6 +301,66 @@ impl DiffState {
         self.current_hunk = 0;
     }
 
+    /// Name of the file whose diff is at the current scroll position.
+    /// Before the first file header, this is the first file.
+    pub fn current_file_name(&self) -> Option<&str> {
+        let idx = self
+            .file_header_positions
+            .iter()
+            .rposition(|&pos| pos <= self.scroll_offset)
+            .unwrap_or(0);
+        self.file_names.get(idx).map(String::as_str)
+    }
+
+    /// Line of the hunk marker (•) of the current hunk
+    pub fn current_hunk_marker(&self) -> Option<usize> {
+        self.hunk_marker_positions.get(self.current_hunk).copied()
+    }
+
+    /// Make `idx` (clamped to the last hunk) the current hunk and scroll to it
+    pub fn select_hunk(&mut self, idx: usize) {
+        let Some(last) = self.hunk_positions.len().checked_sub(1) else {
+            return;
+        };
+        self.current_hunk = idx.min(last);
+        let target = self.hunk_positions[self.current_hunk];
+        self.scroll_offset = self.adjusted_scroll_for_sticky(target);
+    }
+
+    /// Where the current hunk sits within its file's diff
+    pub fn current_file_hunk(&self) -> Option<HunkLocation<'_>> {
+        let marker = self.current_hunk_marker()?;
+        let Some(file_idx) = self
+            .file_header_positions
+            .iter()
+            .rposition(|&pos| pos <= marker)
+        else {
+            return Some(HunkLocation {
+                file: None,
+                index: self.current_hunk,
+                count: self.hunk_marker_positions.len(),
+            });
+        };
+
+        let start = self.file_header_positions[file_idx];
+        let end = self
+            .file_header_positions
+            .get(file_idx + 1)
+            .copied()
+            .unwrap_or(usize::MAX);
+        let in_file = |pos: &&usize| **pos > start && **pos < end;
+        Some(HunkLocation {
+            file: self.file_names.get(file_idx).map(String::as_str),
+            index: self
+                .hunk_marker_positions
+                .iter()
+                .filter(in_file)
+                .filter(|&&pos| pos < marker)
+                .count(),
+            count: self.hunk_marker_positions.iter().filter(in_file).count(),
+        })
+    }
+
     /// Get the file header line index that should be shown as sticky header.
     /// Returns Some(line_index) if we've scrolled past a file header,
     /// or None if the file header is still visible.
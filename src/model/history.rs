@@ -0,0 +1,240 @@
+use super::CommitInfo;
+use anyhow::Result;
+use regex::Regex;
+use std::path::PathBuf;
+
+/// A commit as listed in the history pane
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub commit: CommitInfo,
+    pub author: String,
+    /// Author date, formatted in local time
+    pub date: String,
+    /// Full ids of the parent commits, first parent first
+    pub parents: Vec<String>,
+    /// Commit graph cells drawn left of the commit (filled in by `HistoryList`)
+    pub graph: String,
+    /// The file's path in this commit, when listing one file's history (it may
+    /// have been renamed since)
+    pub path: Option<PathBuf>,
+}
+
+/// What `/` in the commit log pane looks for in subjects and author names
+#[derive(Debug, Clone)]
+pub enum CommitFilter {
+    /// Case-insensitive substring, stored lowercase
+    Text(String),
+    Regex(Regex),
+}
+
+impl CommitFilter {
+    /// `/regex/` is a regex; anything else is plain text
+    pub fn parse(query: &str) -> Result<Self> {
+        match query.strip_prefix('/').and_then(|q| q.strip_suffix('/')) {
+            Some(regex) if !regex.is_empty() => Ok(Self::Regex(Regex::new(regex)?)),
+            _ => Ok(Self::Text(query.to_lowercase())),
+        }
+    }
+
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        let fields = [entry.commit.message.as_str(), entry.author.as_str()];
+        match self {
+            Self::Text(text) => fields.iter().any(|f| f.to_lowercase().contains(text)),
+            Self::Regex(regex) => fields.iter().any(|f| regex.is_match(f)),
+        }
+    }
+}
+
+/// Scrollable list of commits reachable from HEAD in topological order, loaded a
+/// page at a time
+#[derive(Debug, Clone, Default)]
+pub struct HistoryList {
+    pub entries: Vec<LogEntry>,
+    pub selected: usize,
+    /// The walk reached the root (or shallow boundary); nothing more to load
+    pub complete: bool,
+    /// File whose history this is, or None for the whole branch
+    pub file: Option<PathBuf>,
+    /// Pickaxe query the commits were found with
+    pub search: Option<String>,
+    /// Message or author query the list was narrowed down with
+    pub filter: Option<String>,
+    graph: CommitGraph,
+}
+
+impl HistoryList {
+    pub fn new(entries: Vec<LogEntry>, complete: bool) -> Self {
+        let mut list = Self {
+            complete,
+            ..Self::default()
+        };
+        list.extend(entries);
+        list
+    }
+
+    /// One file's history, complete. It skips commits that don't touch the file,
+    /// so there's no graph.
+    pub fn for_file(file: PathBuf, entries: Vec<LogEntry>) -> Self {
+        Self {
+            entries,
+            complete: true,
+            file: Some(file),
+            ..Self::default()
+        }
+    }
+
+    /// Commits found by a pickaxe search, complete and without a graph
+    pub fn for_search(query: String, entries: Vec<LogEntry>) -> Self {
+        Self {
+            entries,
+            complete: true,
+            search: Some(query),
+            ..Self::default()
+        }
+    }
+
+    /// Keep only the commits matching `filter`, narrowing down any earlier
+    /// filter. The graph no longer connects, so it goes.
+    pub fn retain(&mut self, query: &str, filter: &CommitFilter) {
+        self.entries.retain(|entry| filter.matches(entry));
+        for entry in &mut self.entries {
+            entry.graph.clear();
+        }
+        self.filter = Some(match self.filter.take() {
+            Some(earlier) => format!("{earlier}, {query}"),
+            None => query.to_string(),
+        });
+    }
+
+    /// Select the commit `oid`, or the first one if it isn't listed
+    pub fn select(&mut self, oid: Option<&str>) {
+        self.selected = self
+            .entries
+            .iter()
+            .position(|e| Some(e.commit.oid_full.as_str()) == oid)
+            .unwrap_or(0);
+    }
+
+    /// Append the next page of commits
+    pub fn extend(&mut self, entries: Vec<LogEntry>) {
+        for mut entry in entries {
+            entry.graph = self.graph.row(&entry.commit.oid_full, &entry.parents);
+            self.entries.push(entry);
+        }
+    }
+
+    pub fn move_by(&mut self, delta: isize) {
+        let last = self.entries.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    pub fn move_to_top(&mut self) {
+        self.selected = 0;
+    }
+
+    pub fn move_to_bottom(&mut self) {
+        self.selected = self.entries.len().saturating_sub(1);
+    }
+
+    pub fn selected_entry(&self) -> Option<&LogEntry> {
+        self.entries.get(self.selected)
+    }
+
+    /// Whether the selection is close enough to the end to load the next page
+    pub fn wants_more(&self, margin: usize) -> bool {
+        !self.complete && self.selected + margin >= self.entries.len()
+    }
+}
+
+/// Lanes of a `git log --graph` style drawing, one row per commit. Commits must
+/// come children first.
+#[derive(Debug, Clone, Default)]
+struct CommitGraph {
+    /// The commit each lane is waiting for
+    lanes: Vec<Option<String>>,
+}
+
+impl CommitGraph {
+    fn free_lane(&mut self) -> usize {
+        match self.lanes.iter().position(Option::is_none) {
+            Some(lane) => lane,
+            None => {
+                self.lanes.push(None);
+                self.lanes.len() - 1
+            }
+        }
+    }
+
+    /// Draw the row for `oid` and route its parents into lanes
+    fn row(&mut self, oid: &str, parents: &[String]) -> String {
+        let waiting = |lane: &Option<String>| lane.as_deref() == Some(oid);
+        let col = match self.lanes.iter().position(waiting) {
+            Some(col) => col,
+            None => self.free_lane(),
+        };
+        let before: Vec<bool> = self.lanes.iter().map(Option::is_some).collect();
+
+        // Other lanes waiting for this commit are branches that fork off here
+        let joins: Vec<usize> = (0..self.lanes.len())
+            .filter(|&i| i != col && waiting(&self.lanes[i]))
+            .collect();
+        for &lane in &joins {
+            self.lanes[lane] = None;
+        }
+
+        // The first parent continues this lane; the others of a merge get new ones
+        self.lanes[col] = parents.first().cloned();
+        let mut merges = Vec::new();
+        for parent in parents.iter().skip(1) {
+            if self.lanes.iter().any(|l| l.as_ref() == Some(parent)) {
+                continue;
+            }
+            let lane = self.free_lane();
+            self.lanes[lane] = Some(parent.clone());
+            merges.push(lane);
+        }
+
+        let width = self.lanes.len().max(before.len());
+        let mut glyphs: Vec<char> = (0..width)
+            .map(|i| {
+                let active = before.get(i).copied().unwrap_or(false)
+                    || self.lanes.get(i).is_some_and(Option::is_some);
+                if active { '\u{2502}' } else { ' ' }
+            })
+            .collect();
+        let mut spacers = vec![' '; width];
+
+        for (&lane, left, right) in joins
+            .iter()
+            .map(|l| (l, '\u{2570}', '\u{256f}'))
+            .chain(merges.iter().map(|l| (l, '\u{256d}', '\u{256e}')))
+        {
+            let (from, to) = (col.min(lane), col.max(lane));
+            for glyph in &mut glyphs[from + 1..to] {
+                // Crossing a lane that carries on below
+                *glyph = if *glyph == '\u{2502}' {
+                    '\u{253c}'
+                } else {
+                    '\u{2500}'
+                };
+            }
+            for spacer in &mut spacers[from..to] {
+                *spacer = '\u{2500}';
+            }
+            glyphs[lane] = if lane < col { left } else { right };
+        }
+        glyphs[col] = '\u{25cf}';
+
+        while self.lanes.last().is_some_and(Option::is_none) {
+            self.lanes.pop();
+        }
+
+        let mut row: String = glyphs
+            .iter()
+            .zip(&spacers)
+            .flat_map(|(&glyph, &spacer)| [glyph, spacer])
+            .collect();
+        row.truncate(row.trim_end().len());
+        row
+    }
+}
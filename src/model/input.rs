@@ -0,0 +1,269 @@
+/// Single-line text input with a cursor
+#[derive(Debug, Clone, Default)]
+pub struct TextInput {
+    text: String,
+    /// Cursor position in chars
+    cursor: usize,
+}
+
+impl TextInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Cursor position in chars from the start of the text
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    fn byte_index(&self, char_idx: usize) -> usize {
+        byte_index(&self.text, char_idx)
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let idx = self.byte_index(self.cursor);
+        self.text.insert(idx, c);
+        self.cursor += 1;
+    }
+
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            let idx = self.byte_index(self.cursor);
+            self.text.remove(idx);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.cursor < self.text.chars().count() {
+            let idx = self.byte_index(self.cursor);
+            self.text.remove(idx);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.text.chars().count());
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.text.chars().count();
+    }
+}
+
+/// Byte offset of the `char_idx`th char, or the end of the string
+fn byte_index(s: &str, char_idx: usize) -> usize {
+    s.char_indices().nth(char_idx).map_or(s.len(), |(i, _)| i)
+}
+
+/// Multi-line text input with a cursor
+#[derive(Debug, Clone)]
+pub struct TextArea {
+    /// Always at least one (possibly empty) line
+    lines: Vec<String>,
+    row: usize,
+    /// Cursor column in chars
+    col: usize,
+}
+
+impl Default for TextArea {
+    fn default() -> Self {
+        Self {
+            lines: vec![String::new()],
+            row: 0,
+            col: 0,
+        }
+    }
+}
+
+impl TextArea {
+    /// Text area prefilled with `text`, cursor at the end
+    pub fn from_text(text: &str) -> Self {
+        let lines: Vec<String> = text.split('\n').map(String::from).collect();
+        let row = lines.len() - 1;
+        let col = lines[row].chars().count();
+        Self { lines, row, col }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Cursor position as (row, column in chars)
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    fn line_len(&self) -> usize {
+        self.lines[self.row].chars().count()
+    }
+
+    fn cursor_byte(&self) -> usize {
+        byte_index(&self.lines[self.row], self.col)
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let idx = self.cursor_byte();
+        self.lines[self.row].insert(idx, c);
+        self.col += 1;
+    }
+
+    /// Split the line at the cursor
+    pub fn newline(&mut self) {
+        let idx = self.cursor_byte();
+        let rest = self.lines[self.row].split_off(idx);
+        self.row += 1;
+        self.col = 0;
+        self.lines.insert(self.row, rest);
+    }
+
+    pub fn backspace(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+            let idx = self.cursor_byte();
+            self.lines[self.row].remove(idx);
+        } else if self.row > 0 {
+            // Join with the previous line
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.line_len();
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.col < self.line_len() {
+            let idx = self.cursor_byte();
+            self.lines[self.row].remove(idx);
+        } else if self.row + 1 < self.lines.len() {
+            let next = self.lines.remove(self.row + 1);
+            self.lines[self.row].push_str(&next);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.line_len();
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if self.col < self.line_len() {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        if self.row > 0 {
+            self.row -= 1;
+            self.col = self.col.min(self.line_len());
+        }
+    }
+
+    pub fn move_down(&mut self) {
+        if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = self.col.min(self.line_len());
+        }
+    }
+
+    pub fn move_home(&mut self) {
+        self.col = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.col = self.line_len();
+    }
+}
+
+/// Commit message being written in the commit modal
+#[derive(Debug, Clone)]
+pub struct CommitDraft {
+    /// Replace HEAD instead of creating a new commit
+    pub amend: bool,
+    pub message: TextArea,
+}
+
+/// What a prompt's input is used for once submitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    DateJump,
+    RevJump,
+    Compare,
+    Stash,
+    OutputCommand,
+    Pickaxe,
+    DiffSearch,
+    LineJump,
+    CommitFilter,
+    ReviewComment,
+    SubmitReview,
+}
+
+impl PromptKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            PromptKind::DateJump => "Jump to commit before date",
+            PromptKind::RevJump => {
+                "Jump to commit (SHA, branch, tag, HEAD~3, ...) or range (A..B, A...B)"
+            }
+            PromptKind::Compare => "Compare (path@rev path@rev, no @rev = working tree)",
+            PromptKind::Stash => "Stash changes with message (empty for default)",
+            PromptKind::OutputCommand => "Command to run in the output pane",
+            PromptKind::Pickaxe => {
+                "Find commits adding or removing text (/regex/ for changed lines)"
+            }
+            PromptKind::DiffSearch => "Search the diff (case-sensitive with capitals)",
+            PromptKind::LineJump => "Jump to line of the new file",
+            PromptKind::CommitFilter => "Filter commits by subject or author (/regex/ for a regex)",
+            PromptKind::ReviewComment => "Comment on this hunk (kept as a draft until U submits)",
+            PromptKind::SubmitReview => {
+                "Submit review: approve / comment / changes, then an optional summary"
+            }
+        }
+    }
+}
+
+/// A one-line prompt shown in place of the hint line
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: TextInput,
+}
+
+impl Prompt {
+    pub fn new(kind: PromptKind) -> Self {
+        Self {
+            kind,
+            input: TextInput::new(),
+        }
+    }
+
+    /// Prompt prefilled with `text`, cursor at the end
+    pub fn with_text(kind: PromptKind, text: &str) -> Self {
+        let mut input = TextInput::new();
+        text.chars().for_each(|c| input.insert(c));
+        Self { kind, input }
+    }
+}
@@ -1,7 +1,39 @@
+mod activity;
+mod blame;
+mod comments;
 mod commit;
+mod compare;
+mod confirm;
+mod diff_cache;
 mod diff_state;
+mod history;
+mod input;
+mod nav;
+mod output;
+mod queue;
+mod range;
+mod repeat;
+mod session;
+mod stash;
 mod tree;
+mod undo;
 
+pub use activity::{ActivityLog, RepoSnapshot};
+pub use blame::{AuthorShare, BlameOverview};
+pub use comments::CommentThread;
 pub use commit::CommitInfo;
-pub use diff_state::{DiffState, STICKY_FILE_HEADER_HEIGHT};
-pub use tree::{FileStatus, FileTree, HorizontalItem};
+pub use compare::{ComparePoint, Comparison};
+pub use confirm::{ConfirmAction, Confirmation};
+pub use diff_cache::{DiffCache, DiffKey};
+pub use diff_state::{DiffChunk, DiffState, STICKY_FILE_HEADER_HEIGHT};
+pub use history::{CommitFilter, HistoryList, LogEntry};
+pub use input::{CommitDraft, Prompt, PromptKind, TextArea};
+pub use nav::{NavEntry, NavStack, counterpart};
+pub use output::OutputPane;
+pub use queue::ReviewQueue;
+pub use range::{RangeKind, RevRange};
+pub use repeat::RepeatAction;
+pub use session::SessionLog;
+pub use stash::{StashEntry, StashPicker};
+pub use tree::{FileStatus, FileTree, HorizontalItem, StatusSection};
+pub use undo::{UiState, UndoHistory};
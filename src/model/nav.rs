@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Commits (and stops at the working tree) kept by `NavStack`
+const NAV_DEPTH: usize = 100;
+
+/// Where the user was in the working tree or a commit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NavEntry {
+    /// Full id of the commit; None for the working tree
+    pub commit: Option<String>,
+    /// Selected tree node, as `FileTree::selected_tree_path` gives it
+    pub file: Option<PathBuf>,
+    pub scroll: usize,
+    pub hunk: usize,
+}
+
+/// The views left while browsing history, newest last, so `Backspace` can
+/// return to each exactly as it was
+#[derive(Debug, Default)]
+pub struct NavStack {
+    entries: Vec<NavEntry>,
+}
+
+impl NavStack {
+    /// Record leaving a view. The oldest entries fall off past `NAV_DEPTH`.
+    pub fn push(&mut self, entry: NavEntry) {
+        if self.entries.len() == NAV_DEPTH {
+            self.entries.remove(0);
+        }
+        self.entries.push(entry);
+    }
+
+    /// Every entry, oldest first
+    pub fn entries(&self) -> &[NavEntry] {
+        &self.entries
+    }
+
+    pub fn pop(&mut self) -> Option<NavEntry> {
+        self.entries.pop()
+    }
+
+    /// The last place the user was in `commit` (None: the working tree)
+    pub fn spot(&self, commit: Option<&str>) -> Option<&NavEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.commit.as_deref() == commit)
+    }
+}
+
+/// The file `pairs` (source and test patterns) pair `path` with, in either
+/// direction, if it's one of `files`. Patterns match from any directory, so
+/// `src/{}.rs` pairs `crates/a/src/x.rs` with `crates/a/tests/x.rs`.
+pub fn counterpart(pairs: &[(String, String)], path: &Path, files: &[PathBuf]) -> Option<PathBuf> {
+    let path = path.to_string_lossy().replace('\\', "/");
+    // Where a pattern can start: the root and after each `/`
+    let starts = std::iter::once(0).chain(path.match_indices('/').map(|(i, _)| i + 1));
+    let starts: Vec<usize> = starts.collect();
+    pairs
+        .iter()
+        .flat_map(|(source, test)| [(source, test), (test, source)])
+        .flat_map(|(from, to)| starts.iter().map(move |&start| (from, to, start)))
+        .filter_map(|(from, to, start)| {
+            let (dir, rest) = path.split_at(start);
+            let shared = shared_part(from, rest)?;
+            Some(PathBuf::from(format!(
+                "{dir}{}",
+                to.replacen("{}", shared, 1)
+            )))
+        })
+        .find(|candidate| files.contains(candidate))
+}
+
+/// What `{}` stands for when `pattern` matches `path` as a whole
+fn shared_part<'a>(pattern: &str, path: &'a str) -> Option<&'a str> {
+    let (prefix, suffix) = pattern.split_once("{}")?;
+    let shared = path.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    (!shared.is_empty()).then_some(shared)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ReviewConfig;
+
+    fn jump(path: &str, changed: &[&str]) -> Option<PathBuf> {
+        let files: Vec<PathBuf> = changed.iter().map(PathBuf::from).collect();
+        counterpart(&ReviewConfig::default().test_pairs, Path::new(path), &files)
+    }
+
+    #[test]
+    fn sources_and_tests_pair_up_both_ways() {
+        let nested = ["crates/a/src/x.rs", "crates/a/tests/x.rs", "src/x.rs"];
+        assert_eq!(
+            jump("crates/a/src/x.rs", &nested),
+            Some(PathBuf::from("crates/a/tests/x.rs"))
+        );
+        assert_eq!(
+            jump("crates/a/tests/x.rs", &nested),
+            Some(PathBuf::from("crates/a/src/x.rs"))
+        );
+
+        let web = ["web/foo.ts", "web/foo.test.ts"];
+        assert_eq!(
+            jump("web/foo.ts", &web),
+            Some(PathBuf::from("web/foo.test.ts"))
+        );
+        assert_eq!(
+            jump("web/foo.test.ts", &web),
+            Some(PathBuf::from("web/foo.ts"))
+        );
+    }
+
+    #[test]
+    fn an_unchanged_counterpart_isnt_jumped_to() {
+        assert_eq!(jump("src/x.rs", &["src/x.rs", "tests/y.rs"]), None);
+        assert_eq!(jump("foo.test.ts", &["foo.test.ts", "bar.ts"]), None);
+        assert_eq!(jump("README.md", &["README.md"]), None);
+    }
+}
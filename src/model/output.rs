@@ -0,0 +1,54 @@
+use std::collections::VecDeque;
+
+/// Oldest lines are dropped beyond this many
+const MAX_LINES: usize = 5000;
+
+/// Output of the command tailed in the output pane
+#[derive(Debug, Clone)]
+pub struct OutputPane {
+    pub command: String,
+    lines: VecDeque<String>,
+    /// Lines scrolled up from the bottom (0 = following new output)
+    pub scroll: usize,
+    /// How the command exited, once it has
+    pub status: Option<String>,
+}
+
+impl OutputPane {
+    pub fn new(command: String) -> Self {
+        Self {
+            command,
+            lines: VecDeque::new(),
+            scroll: 0,
+            status: None,
+        }
+    }
+
+    pub fn push(&mut self, line: String) {
+        if self.lines.len() == MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+        // Keep the view still while scrolled up
+        if self.scroll > 0 {
+            self.scroll = (self.scroll + 1).min(self.lines.len());
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.scroll = 0;
+    }
+
+    pub fn lines(&self) -> &VecDeque<String> {
+        &self.lines
+    }
+
+    pub fn scroll_up(&mut self, amount: usize) {
+        self.scroll = (self.scroll + amount).min(self.lines.len().saturating_sub(1));
+    }
+
+    pub fn scroll_down(&mut self, amount: usize) {
+        self.scroll = self.scroll.saturating_sub(amount);
+    }
+}
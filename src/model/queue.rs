@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+
+/// The order files are reviewed in with `f`/`F`. Starts as tree order; files
+/// moved with `<`/`>`/`m` keep their place, even while browsing other trees.
+#[derive(Debug, Default)]
+pub struct ReviewQueue {
+    /// Every file seen so far, in review order
+    order: Vec<PathBuf>,
+}
+
+impl ReviewQueue {
+    /// A queue picking up from `order`, as `order()` gave it
+    pub fn from_order(order: Vec<PathBuf>) -> Self {
+        Self { order }
+    }
+
+    /// Every file seen so far, in review order
+    pub fn order(&self) -> &[PathBuf] {
+        &self.order
+    }
+
+    /// The queue as far as `files` (the tree's files, in tree order) go. Files
+    /// not seen before join at the end.
+    pub fn sync(&mut self, files: &[PathBuf]) -> Vec<PathBuf> {
+        for path in files {
+            if !self.order.contains(path) {
+                self.order.push(path.clone());
+            }
+        }
+        self.order
+            .iter()
+            .filter(|path| files.contains(path))
+            .cloned()
+            .collect()
+    }
+
+    /// Move `path` `by` places towards the end of the queue of `files` (negative:
+    /// the front), stopping at the ends. Returns its new position and the length.
+    pub fn shift(&mut self, files: &[PathBuf], path: &Path, by: isize) -> Option<(usize, usize)> {
+        let mut queue = self.sync(files);
+        let from = queue.iter().position(|p| p == path)?;
+        let to = from.saturating_add_signed(by).min(queue.len() - 1);
+        let moved = queue.remove(from);
+        queue.insert(to, moved);
+
+        // Write the new order back into the slots the files held
+        let mut reordered = queue.iter();
+        for slot in self.order.iter_mut().filter(|p| files.contains(p)) {
+            if let Some(path) = reordered.next() {
+                slot.clone_from(path);
+            }
+        }
+        Some((to, queue.len()))
+    }
+
+    /// The file after (or before) `path` in the queue of `files`. From a folder
+    /// or nothing selected, the first (or last) one.
+    pub fn next(
+        &mut self,
+        files: &[PathBuf],
+        path: Option<&Path>,
+        forward: bool,
+    ) -> Option<PathBuf> {
+        let mut queue = self.sync(files);
+        let current = path.and_then(|p| queue.iter().position(|q| q == p));
+        let target = match (current, forward) {
+            (Some(pos), true) => pos + 1,
+            (Some(pos), false) => pos.checked_sub(1)?,
+            (None, true) => 0,
+            (None, false) => queue.len().checked_sub(1)?,
+        };
+        (target < queue.len()).then(|| queue.swap_remove(target))
+    }
+}
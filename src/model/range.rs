@@ -0,0 +1,49 @@
+/// Why a range is being shown, for its title
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeKind {
+    /// Given on the command line or to `o`
+    Explicit,
+    /// The branch against the default branch
+    Review,
+    /// HEAD against its upstream: what a push would send
+    Push,
+    /// The branch's pull request as GitHub diffs it, at its last pushed commit
+    PullRequest,
+}
+
+impl RangeKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            RangeKind::Explicit => "Range",
+            RangeKind::Review => "Review",
+            RangeKind::Push => "To push",
+            RangeKind::PullRequest => "Pull request",
+        }
+    }
+}
+
+/// Two commits diffed as a whole, from `kibitz A..B` or `A...B`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevRange {
+    pub kind: RangeKind,
+    /// The range as given, e.g. `main...feature`
+    pub spec: String,
+    /// Full id of the old side (the merge base for `A...B`)
+    pub from: String,
+    /// Full id of the new side
+    pub to: String,
+    /// Commits reachable from the new side but not the old
+    pub commits: usize,
+}
+
+impl RevRange {
+    /// Split `A..B` / `A...B` into its sides (empty for HEAD) and whether it's the
+    /// three-dot form. None if `spec` isn't a range.
+    pub fn split(spec: &str) -> Option<(&str, &str, bool)> {
+        if let Some((from, to)) = spec.split_once("...") {
+            return Some((from, to, true));
+        }
+        let (from, to) = spec.split_once("..")?;
+        Some((from, to, false))
+    }
+}
@@ -0,0 +1,8 @@
+/// A change to the working tree that `.` can make again on the current target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatAction {
+    StageHunk,
+    UnstageHunk,
+    DiscardHunk,
+    DiscardFile,
+}
@@ -0,0 +1,71 @@
+use super::CommitInfo;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Files and commits listed in full in the summary; the rest are counted
+const MAX_LISTED: usize = 20;
+
+/// What was looked at while kibitz was open, for the summary printed on exit
+#[derive(Debug, Default)]
+pub struct SessionLog {
+    /// Files whose diff was shown, in the order they were first seen
+    files: Vec<PathBuf>,
+    /// Commits browsed, in the order they were first seen
+    commits: Vec<CommitInfo>,
+}
+
+impl SessionLog {
+    pub fn view_file(&mut self, path: &Path) {
+        if !self.files.iter().any(|f| f == path) {
+            self.files.push(path.to_path_buf());
+        }
+    }
+
+    /// Files whose diff was shown, in the order they were first seen
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn view_commit(&mut self, commit: &CommitInfo) {
+        if !self.commits.iter().any(|c| c.oid_full == commit.oid_full) {
+            self.commits.push(commit.clone());
+        }
+    }
+
+    /// Plain text saying what was reviewed, ending with `diffstat` (the working
+    /// tree's files changed, insertions and deletions against HEAD)
+    pub fn summary(&self, diffstat: Option<(usize, usize, usize)>) -> String {
+        let plural = |n: usize, word: &str| format!("{n} {word}{}", if n == 1 { "" } else { "s" });
+        let mut out = format!(
+            "kibitz: reviewed {}, browsed {}\n",
+            plural(self.files.len(), "file"),
+            plural(self.commits.len(), "commit")
+        );
+        for file in self.files.iter().take(MAX_LISTED) {
+            let _ = writeln!(out, "  {}", file.display());
+        }
+        if self.files.len() > MAX_LISTED {
+            let _ = writeln!(out, "  \u{2026} and {} more", self.files.len() - MAX_LISTED);
+        }
+        for commit in self.commits.iter().take(MAX_LISTED) {
+            let _ = writeln!(out, "  {} {}", commit.oid, commit.message);
+        }
+        if self.commits.len() > MAX_LISTED {
+            let _ = writeln!(
+                out,
+                "  \u{2026} and {} more",
+                self.commits.len() - MAX_LISTED
+            );
+        }
+        if let Some((files, insertions, deletions)) = diffstat {
+            let _ = writeln!(
+                out,
+                "  {} changed, {}(+), {}(-)",
+                plural(files, "file"),
+                plural(insertions, "insertion"),
+                plural(deletions, "deletion")
+            );
+        }
+        out
+    }
+}
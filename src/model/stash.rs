@@ -0,0 +1,53 @@
+use super::CommitInfo;
+
+/// An entry of `git stash list`
+#[derive(Debug, Clone)]
+pub struct StashEntry {
+    /// N in stash@{N}
+    pub index: usize,
+    pub message: String,
+    pub oid: String,
+}
+
+impl StashEntry {
+    pub fn name(&self) -> String {
+        format!("stash@{{{}}}", self.index)
+    }
+
+    /// The stash commit, whose diff against its first parent is the stashed changes
+    pub fn commit_info(&self) -> CommitInfo {
+        CommitInfo {
+            oid: self.oid.chars().take(7).collect(),
+            oid_full: self.oid.clone(),
+            message: format!("{}: {}", self.name(), self.message),
+        }
+    }
+}
+
+/// Popup listing the stashes to pick one to view
+#[derive(Debug, Clone)]
+pub struct StashPicker {
+    pub entries: Vec<StashEntry>,
+    pub selected: usize,
+}
+
+impl StashPicker {
+    pub fn new(entries: Vec<StashEntry>, selected: usize) -> Self {
+        let selected = selected.min(entries.len().saturating_sub(1));
+        Self { entries, selected }
+    }
+
+    pub fn move_up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn move_down(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&StashEntry> {
+        self.entries.get(self.selected)
+    }
+}
@@ -1,5 +1,7 @@
-use anyhow::Result;
-use std::collections::HashMap;
+use crate::git::churn::Churn;
+use crate::git::status::GitStatusResult;
+use crate::workspace::Package;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -12,9 +14,26 @@ pub enum FileStatus {
     Untracked,
     Staged,
     StagedModified, // Has both staged and unstaged changes
+    Conflicted,     // Unresolved merge conflict
+    Submodule,      // Checked out at another commit than recorded, or changed inside
 }
 
 impl FileStatus {
+    /// Lowercase name, as shown to plugins
+    pub fn name(self) -> &'static str {
+        match self {
+            FileStatus::Modified => "modified",
+            FileStatus::Added => "added",
+            FileStatus::Deleted => "deleted",
+            FileStatus::Renamed => "renamed",
+            FileStatus::Untracked => "untracked",
+            FileStatus::Staged => "staged",
+            FileStatus::StagedModified => "staged_modified",
+            FileStatus::Conflicted => "conflicted",
+            FileStatus::Submodule => "submodule",
+        }
+    }
+
     pub fn has_staged(&self) -> bool {
         matches!(self, FileStatus::Staged | FileStatus::StagedModified)
     }
@@ -24,6 +43,59 @@ impl FileStatus {
     }
 }
 
+/// A top-level group of the working tree grouped by status, like `git status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusSection {
+    Staged,
+    Unstaged,
+    Untracked,
+    Conflicted,
+}
+
+impl StatusSection {
+    pub const ALL: [StatusSection; 4] = [
+        StatusSection::Staged,
+        StatusSection::Unstaged,
+        StatusSection::Untracked,
+        StatusSection::Conflicted,
+    ];
+
+    /// Name shown in the tree, also the section's root path
+    pub fn label(self) -> &'static str {
+        match self {
+            StatusSection::Staged => "Staged",
+            StatusSection::Unstaged => "Unstaged",
+            StatusSection::Untracked => "Untracked",
+            StatusSection::Conflicted => "Conflicted",
+        }
+    }
+
+    /// Files with both staged and unstaged changes are in both sections
+    pub fn contains(self, status: FileStatus) -> bool {
+        match self {
+            StatusSection::Staged => status.has_staged(),
+            StatusSection::Unstaged => matches!(
+                status,
+                FileStatus::Modified
+                    | FileStatus::Deleted
+                    | FileStatus::Renamed
+                    | FileStatus::StagedModified
+                    | FileStatus::Submodule
+            ),
+            StatusSection::Untracked => status == FileStatus::Untracked,
+            StatusSection::Conflicted => status == FileStatus::Conflicted,
+        }
+    }
+
+    /// The section a tree path belongs to, from its first component
+    fn of(path: &Path) -> Option<Self> {
+        let first = path.components().next()?;
+        Self::ALL
+            .into_iter()
+            .find(|section| first.as_os_str() == section.label())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TreeNode {
     pub name: String,
@@ -63,6 +135,14 @@ pub struct FileTree {
     pub selected_index: usize,
     flat_list: Vec<FlatNode>,
     file_statuses: HashMap<PathBuf, FileStatus>,
+    /// Where renamed files came from, both paths prefixed like the tree's
+    renamed_from: HashMap<PathBuf, PathBuf>,
+    /// Files .gitattributes marks as generated, as repo paths
+    generated: HashSet<PathBuf>,
+    /// CODEOWNERS owners of each owned file, as repo paths
+    owners: HashMap<PathBuf, Vec<String>>,
+    /// How often each file changed before, as repo paths
+    churn: HashMap<PathBuf, Churn>,
     /// Tracks the last visited child path for each folder (for navigation memory)
     last_visited_child: HashMap<PathBuf, PathBuf>,
 }
@@ -75,6 +155,12 @@ pub struct VisibleNode {
     pub is_dir: bool,
     pub expanded: bool,
     pub status: Option<FileStatus>,
+    /// Set on the header of a status section
+    pub section: Option<StatusSection>,
+    pub generated: bool,
+    /// The file's owners from CODEOWNERS, space-separated
+    pub owners: Option<String>,
+    pub churn: Option<Churn>,
 }
 
 /// A row in the horizontal tree view
@@ -93,6 +179,10 @@ pub struct HorizontalItem {
     pub path: PathBuf,
     pub is_dir: bool,
     pub status: Option<FileStatus>,
+    pub section: Option<StatusSection>, // set on the header of a status section
+    pub generated: bool,
+    pub owners: Option<String>,
+    pub churn: Option<Churn>,
     pub is_on_path: bool,  // is this item an ancestor of selected?
     pub is_selected: bool, // is this the actual selected item?
 }
@@ -108,9 +198,41 @@ struct FlatNode {
 }
 
 impl FileTree {
-    pub fn from_git_status(repo_path: &Path) -> Result<Self> {
-        let (files, file_statuses) = crate::git::status::get_status(repo_path)?;
-        Ok(Self::from_files(files, file_statuses))
+    /// The working tree's changes from `git::status::get_status`, in one tree or
+    /// grouped into status sections
+    pub fn from_status(status: GitStatusResult, by_status: bool) -> Self {
+        let (files, file_statuses) = status;
+        if by_status {
+            Self::from_sections(files, file_statuses)
+        } else {
+            Self::from_files(files, file_statuses)
+        }
+    }
+
+    /// One root per non-empty section, named with its file count, each holding a
+    /// tree of the section's files
+    fn from_sections(
+        files: Vec<(PathBuf, FileStatus)>,
+        file_statuses: HashMap<PathBuf, FileStatus>,
+    ) -> Self {
+        let mut roots = Vec::new();
+        for section in StatusSection::ALL {
+            let root = PathBuf::from(section.label());
+            let mut children = Vec::new();
+            let mut count = 0;
+            for (path, status) in files.iter().filter(|(_, s)| section.contains(*s)) {
+                Self::insert_path(&mut children, &root, path, *status);
+                count += 1;
+            }
+            if count == 0 {
+                continue;
+            }
+            Self::sort_tree(&mut children);
+            let mut node = TreeNode::new_dir(format!("{} ({count})", section.label()), root);
+            node.children = children;
+            roots.push(node);
+        }
+        Self::with_roots(roots, file_statuses)
     }
 
     /// Build a FileTree from a list of files (used for commit file views)
@@ -119,6 +241,96 @@ impl FileTree {
         Self::from_files(files, file_statuses)
     }
 
+    /// Note where renamed files came from, as (new path, old path) pairs
+    pub fn with_renames(mut self, renames: Vec<(PathBuf, PathBuf)>) -> Self {
+        let root = Path::new(".");
+        self.renamed_from = renames
+            .into_iter()
+            .map(|(new, old)| (root.join(new), root.join(old)))
+            .collect();
+        self
+    }
+
+    /// Regroup the files into one root per workspace package holding any of them,
+    /// then "." for the rest. Files belong to the innermost package containing
+    /// them. Roots keep their repo paths, so every path in the tree stays the
+    /// same; a tree grouped by status is left alone.
+    pub fn grouped_by_package(mut self, packages: &[Package]) -> Self {
+        if packages.is_empty() || !matches!(&self.root[..], [root] if root.path == Path::new(".")) {
+            return self;
+        }
+        let selected = self.selected_tree_path();
+        let mut groups: Vec<Vec<TreeNode>> = vec![Vec::new(); packages.len()];
+        let mut rest = Vec::new();
+        for (path, status) in &self.file_statuses {
+            let relative = path.strip_prefix(".").unwrap_or(path);
+            let package = packages
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| relative.starts_with(&p.dir))
+                .max_by_key(|(_, p)| p.dir.components().count());
+            match package {
+                Some((i, p)) => {
+                    let root = Path::new(".").join(&p.dir);
+                    let inner = relative.strip_prefix(&p.dir).unwrap_or(relative);
+                    Self::insert_path(&mut groups[i], &root, inner, *status);
+                }
+                None => Self::insert_path(&mut rest, Path::new("."), relative, *status),
+            }
+        }
+
+        let mut roots = Vec::new();
+        for (package, mut children) in packages.iter().zip(groups) {
+            if children.is_empty() {
+                continue;
+            }
+            Self::sort_tree(&mut children);
+            let name = format!("{} ({})", package.name, package.dir.display());
+            let mut node = TreeNode::new_dir(name, Path::new(".").join(&package.dir));
+            node.children = children;
+            roots.push(node);
+        }
+        if !rest.is_empty() {
+            Self::sort_tree(&mut rest);
+            let mut node = TreeNode::new_dir(".".to_string(), PathBuf::from("."));
+            node.children = rest;
+            roots.push(node);
+        }
+        self.root = roots;
+        self.selected_index = 0;
+        self.last_visited_child.clear();
+        self.rebuild_flat_list();
+        if let Some(path) = selected {
+            self.reveal(&path);
+        }
+        self
+    }
+
+    /// Note which files are generated, as repo paths
+    pub fn with_generated(mut self, generated: HashSet<PathBuf>) -> Self {
+        self.generated = generated;
+        self
+    }
+
+    /// Note who owns each file, keyed by repo path
+    pub fn with_owners(mut self, owners: HashMap<PathBuf, Vec<String>>) -> Self {
+        self.owners = owners;
+        self
+    }
+
+    /// Note how often the tree's files changed before, from churn keyed by
+    /// paths relative to the repository
+    pub fn set_churn(&mut self, churn: &HashMap<PathBuf, Churn>) {
+        self.churn = self
+            .file_statuses
+            .keys()
+            .filter_map(|path| {
+                let relative = path.strip_prefix(".").unwrap_or(path);
+                Some((path.clone(), *churn.get(relative)?))
+            })
+            .collect();
+    }
+
     fn from_files(
         files: Vec<(PathBuf, FileStatus)>,
         file_statuses: HashMap<PathBuf, FileStatus>,
@@ -126,7 +338,7 @@ impl FileTree {
         let mut children = Vec::new();
 
         for (path, status) in &files {
-            Self::insert_path(&mut children, path, *status);
+            Self::insert_path(&mut children, Path::new("."), path, *status);
         }
 
         Self::sort_tree(&mut children);
@@ -140,7 +352,10 @@ impl FileTree {
             status: None,
             children,
         };
+        Self::with_roots(vec![root_node], file_statuses)
+    }
 
+    fn with_roots(root: Vec<TreeNode>, file_statuses: HashMap<PathBuf, FileStatus>) -> Self {
         // Prefix all file_statuses keys with "./" to match the tree paths
         let prefixed_statuses: HashMap<PathBuf, FileStatus> = file_statuses
             .into_iter()
@@ -148,10 +363,14 @@ impl FileTree {
             .collect();
 
         let mut tree = Self {
-            root: vec![root_node],
+            root,
             selected_index: 0,
             flat_list: Vec::new(),
             file_statuses: prefixed_statuses,
+            renamed_from: HashMap::new(),
+            generated: HashSet::new(),
+            owners: HashMap::new(),
+            churn: HashMap::new(),
             last_visited_child: HashMap::new(),
         };
 
@@ -160,15 +379,15 @@ impl FileTree {
         tree
     }
 
-    fn insert_path(nodes: &mut Vec<TreeNode>, path: &Path, status: FileStatus) {
+    /// Add `path` under `root` ("." or a section), creating folders on the way
+    fn insert_path(nodes: &mut Vec<TreeNode>, root: &Path, path: &Path, status: FileStatus) {
         let components: Vec<_> = path.components().collect();
         if components.is_empty() {
             return;
         }
 
         let mut current = nodes;
-        // Start with "." so all paths are children of the root "." folder
-        let mut current_path = PathBuf::from(".");
+        let mut current_path = root.to_path_buf();
 
         for (i, component) in components.iter().enumerate() {
             let name = component.as_os_str().to_string_lossy().to_string();
@@ -227,15 +446,32 @@ impl FileTree {
         }
     }
 
+    /// A node's name as shown, with where it was renamed from
+    fn display_name(&self, path: &Path, name: &str) -> String {
+        let Some(old) = self.renamed_from.get(path) else {
+            return name.to_string();
+        };
+        let old = if old.parent() == path.parent() {
+            old.file_name().unwrap_or_default()
+        } else {
+            old.strip_prefix(".").unwrap_or(old).as_os_str()
+        };
+        format!("{} \u{2192} {name}", old.to_string_lossy())
+    }
+
     pub fn visible_items(&self) -> Vec<VisibleNode> {
         self.flat_list
             .iter()
             .map(|n| VisibleNode {
-                name: n.name.clone(),
+                name: self.display_name(&n.path, &n.name),
                 depth: n.depth,
                 is_dir: n.is_dir,
                 expanded: n.expanded,
                 status: n.status,
+                section: (n.depth == 0).then(|| StatusSection::of(&n.path)).flatten(),
+                generated: !n.is_dir && self.is_generated(&Self::repo_path(&n.path)),
+                owners: self.owners_label(&n.path, n.is_dir),
+                churn: self.churn.get(&Self::repo_path(&n.path)).copied(),
             })
             .collect()
     }
@@ -252,12 +488,20 @@ impl FileTree {
         }
     }
 
+    /// Positions of the closest files shown after and before the selection,
+    /// the ones most likely to be moved to next
+    pub fn adjacent_files(&self) -> Vec<usize> {
+        let is_file = |&i: &usize| !self.flat_list[i].is_dir;
+        let next = (self.selected_index + 1..self.flat_list.len()).find(is_file);
+        let prev = (0..self.selected_index).rev().find(is_file);
+        next.into_iter().chain(prev).collect()
+    }
+
     pub fn expand(&mut self) {
         if let Some(node) = self.flat_list.get(self.selected_index) {
             if node.is_dir && !node.expanded {
                 let path = node.path.clone();
-                Self::set_expanded(&mut self.root, &path, true);
-                self.rebuild_flat_list();
+                self.expand_path(&path);
             } else {
                 self.move_down();
             }
@@ -268,8 +512,7 @@ impl FileTree {
         if let Some(node) = self.flat_list.get(self.selected_index) {
             if node.is_dir && node.expanded {
                 let path = node.path.clone();
-                Self::set_expanded(&mut self.root, &path, false);
-                self.rebuild_flat_list();
+                self.collapse_path(&path);
             } else if !node.is_dir || !node.expanded {
                 // Go to parent directory
                 if let Some(parent) = node.path.parent()
@@ -284,6 +527,104 @@ impl FileTree {
         }
     }
 
+    /// Expand the folder at `path`, keeping the current selection
+    pub fn expand_path(&mut self, path: &Path) {
+        self.set_expanded_preserving_selection(path, true);
+    }
+
+    /// Collapse the folder at `path`. If the selection was inside it, the folder becomes selected.
+    pub fn collapse_path(&mut self, path: &Path) {
+        self.set_expanded_preserving_selection(path, false);
+    }
+
+    /// Expand all ancestors of `path` and select it.
+    /// Returns false if the path isn't in the tree.
+    pub fn reveal(&mut self, path: &Path) -> bool {
+        let Some(path) = self.tree_path(path) else {
+            return false;
+        };
+        let path = path.as_path();
+        for ancestor in path.ancestors().skip(1) {
+            if ancestor.as_os_str().is_empty() {
+                break;
+            }
+            Self::set_expanded(&mut self.root, ancestor, true);
+        }
+        self.rebuild_flat_list();
+        if let Some(idx) = self.flat_list.iter().position(|n| n.path == path) {
+            self.selected_index = idx;
+        }
+        true
+    }
+
+    /// Every file in tree order, as repo paths. A file in two status sections is
+    /// listed once.
+    pub fn file_paths(&self) -> Vec<PathBuf> {
+        fn collect(nodes: &[TreeNode], seen: &mut HashSet<PathBuf>, out: &mut Vec<PathBuf>) {
+            for node in nodes {
+                if node.is_dir {
+                    collect(&node.children, seen, out);
+                } else {
+                    let path = FileTree::repo_path(&node.path);
+                    if seen.insert(path.clone()) {
+                        out.push(path);
+                    }
+                }
+            }
+        }
+        let mut paths = Vec::new();
+        collect(&self.root, &mut HashSet::new(), &mut paths);
+        paths
+    }
+
+    /// Paths of all collapsed folders, for restoring expansion state after a rebuild
+    pub fn collapsed_paths(&self) -> Vec<PathBuf> {
+        fn collect(nodes: &[TreeNode], out: &mut Vec<PathBuf>) {
+            for node in nodes.iter().filter(|n| n.is_dir) {
+                if !node.expanded {
+                    out.push(node.path.clone());
+                }
+                collect(&node.children, out);
+            }
+        }
+        let mut paths = Vec::new();
+        collect(&self.root, &mut paths);
+        paths
+    }
+
+    fn set_expanded_preserving_selection(&mut self, path: &Path, expanded: bool) {
+        let selected = self
+            .flat_list
+            .get(self.selected_index)
+            .map(|n| n.path.clone());
+        Self::set_expanded(&mut self.root, path, expanded);
+        self.rebuild_flat_list();
+
+        // Re-select the previously selected node, or its closest visible ancestor
+        if let Some(selected) = selected
+            && let Some(idx) = selected
+                .ancestors()
+                .find_map(|p| self.flat_list.iter().position(|n| n.path == p))
+        {
+            self.selected_index = idx;
+        }
+    }
+
+    fn find_node<'a>(nodes: &'a [TreeNode], path: &Path) -> Option<&'a TreeNode> {
+        for node in nodes {
+            if node.path == path {
+                return Some(node);
+            }
+            if node.is_dir
+                && path.starts_with(&node.path)
+                && let Some(found) = Self::find_node(&node.children, path)
+            {
+                return Some(found);
+            }
+        }
+        None
+    }
+
     fn set_expanded(nodes: &mut [TreeNode], path: &Path, expanded: bool) {
         for node in nodes {
             if node.path == path {
@@ -296,17 +637,68 @@ impl FileTree {
         }
     }
 
+    /// Where `path` is in the tree. A path in one status section may be looked up
+    /// as `./path` (finding the first section holding it) or from another section.
+    fn tree_path(&self, path: &Path) -> Option<PathBuf> {
+        if Self::find_node(&self.root, path).is_some() {
+            return Some(path.to_path_buf());
+        }
+        let relative = Self::repo_path(path);
+        let relative = relative.strip_prefix(".").ok()?;
+        StatusSection::ALL
+            .into_iter()
+            .map(|section| Path::new(section.label()).join(relative))
+            .chain([Path::new(".").join(relative)])
+            .find(|candidate| Self::find_node(&self.root, candidate).is_some())
+    }
+
+    /// A tree path as a `./`-prefixed repo path, dropping its status section
+    fn repo_path(path: &Path) -> PathBuf {
+        if StatusSection::of(path).is_none() {
+            return path.to_path_buf();
+        }
+        let mut components = path.components();
+        components.next();
+        Path::new(".").join(components.as_path())
+    }
+
     pub fn selected_file_path(&self) -> Option<PathBuf> {
-        self.flat_list
-            .get(self.selected_index)
-            .and_then(|n| if n.is_dir { None } else { Some(n.path.clone()) })
+        self.flat_list.get(self.selected_index).and_then(|n| {
+            if n.is_dir {
+                None
+            } else {
+                Some(Self::repo_path(&n.path))
+            }
+        })
     }
 
-    /// Returns the selected path (file or folder)
-    pub fn selected_path(&self) -> Option<(PathBuf, bool)> {
+    /// The path of the node at `index` of the shown tree, and whether it's a
+    /// folder
+    pub fn path_at(&self, index: usize) -> Option<(PathBuf, bool)> {
         self.flat_list
-            .get(self.selected_index)
-            .map(|n| (n.path.clone(), n.is_dir))
+            .get(index)
+            .map(|n| (Self::repo_path(&n.path), n.is_dir))
+    }
+
+    /// The selected node's own path, which tells apart the same file in two
+    /// status sections. `reveal` takes it back.
+    pub fn selected_tree_path(&self) -> Option<PathBuf> {
+        self.tree_path_at(self.selected_index)
+    }
+
+    /// The own path of the node at `index`, like `selected_tree_path`
+    pub fn tree_path_at(&self, index: usize) -> Option<PathBuf> {
+        self.flat_list.get(index).map(|n| n.path.clone())
+    }
+
+    /// The status section the selection is in, when grouped by status
+    pub fn selected_section(&self) -> Option<StatusSection> {
+        self.section_at(self.selected_index)
+    }
+
+    /// The status section of the node at `index`, when grouped by status
+    pub fn section_at(&self, index: usize) -> Option<StatusSection> {
+        StatusSection::of(&self.flat_list.get(index)?.path)
     }
 
     /// Get all file paths under a folder (recursively)
@@ -318,10 +710,75 @@ impl FileTree {
             .collect()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.file_statuses.is_empty()
+    }
+
     pub fn get_file_status(&self, path: &Path) -> Option<FileStatus> {
         self.file_statuses.get(path).copied()
     }
 
+    pub fn renamed_from(&self, path: &Path) -> Option<&Path> {
+        self.renamed_from.get(path).map(PathBuf::as_path)
+    }
+
+    pub fn is_generated(&self, path: &Path) -> bool {
+        self.generated.contains(path)
+    }
+
+    /// A file node's owners as shown after its name
+    fn owners_label(&self, tree_path: &Path, is_dir: bool) -> Option<String> {
+        if is_dir {
+            return None;
+        }
+        let owners = self.owners.get(&Self::repo_path(tree_path))?;
+        Some(owners.join(" "))
+    }
+
+    /// How many of the tree's files each owner owns, most first, then the
+    /// number of files nobody owns
+    pub fn owner_counts(&self) -> (Vec<(String, usize)>, usize) {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        let mut unowned = 0;
+        for path in self.file_statuses.keys() {
+            match self.owners.get(path) {
+                Some(owners) => {
+                    for owner in owners {
+                        *counts.entry(owner).or_default() += 1;
+                    }
+                }
+                None => unowned += 1,
+            }
+        }
+        let mut counts: Vec<(String, usize)> = counts
+            .into_iter()
+            .map(|(owner, n)| (owner.to_string(), n))
+            .collect();
+        counts.sort_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+        (counts, unowned)
+    }
+
+    /// Pathspecs for diffing the selected file or folder: its files (without
+    /// generated ones when `skip_generated`), plus the old paths of renamed ones
+    /// so git pairs them up
+    pub fn diff_paths(&self, path: &Path, is_dir: bool, skip_generated: bool) -> Vec<PathBuf> {
+        let mut files = if is_dir {
+            self.files_under_path(path)
+        } else {
+            vec![path.to_path_buf()]
+        };
+        if skip_generated {
+            files.retain(|file| !self.is_generated(file));
+        }
+        let sources: Vec<_> = files
+            .iter()
+            .filter_map(|file| self.renamed_from(file))
+            .map(Path::to_path_buf)
+            .collect();
+        files.extend(sources);
+        files
+    }
+
     // === Horizontal navigation methods ===
 
     /// Build the rows for horizontal tree display
@@ -386,10 +843,16 @@ impl FileTree {
             }
 
             items.push(HorizontalItem {
-                name: node.name.clone(),
+                name: self.display_name(&node.path, &node.name),
                 path: node.path.clone(),
                 is_dir: node.is_dir,
                 status: node.status,
+                section: (depth == 0)
+                    .then(|| StatusSection::of(&node.path))
+                    .flatten(),
+                generated: !node.is_dir && self.is_generated(&Self::repo_path(&node.path)),
+                owners: self.owners_label(&node.path, node.is_dir),
+                churn: self.churn.get(&Self::repo_path(&node.path)).copied(),
                 is_on_path,
                 is_selected,
             });
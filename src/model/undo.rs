@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+
+/// Changes kept by `UndoHistory`
+const MAX_UNDO: usize = 100;
+
+/// How the tree and diff are arranged: what `u` and Ctrl-r step back and forth
+/// through. Nothing in it touches the repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UiState {
+    /// The view it applies to (`DiffKey::view`); folders differ between views
+    pub view: String,
+    pub collapsed: Vec<PathBuf>,
+    pub show_tree: bool,
+    pub group_by_status: bool,
+    pub group_by_package: bool,
+    pub show_generated: bool,
+    pub side_by_side: bool,
+    pub line_numbers: bool,
+    pub wrap: bool,
+    pub show_blame: bool,
+    pub show_activity: bool,
+}
+
+/// Arrangements left by each change, newest last, and the ones undone since
+#[derive(Debug, Default)]
+pub struct UndoHistory {
+    undo: Vec<UiState>,
+    redo: Vec<UiState>,
+}
+
+impl UndoHistory {
+    /// Record the arrangement a change left. Whatever was undone can't be
+    /// redone after it.
+    pub fn record(&mut self, before: UiState) {
+        if self.undo.len() == MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.undo.push(before);
+        self.redo.clear();
+    }
+
+    /// The arrangement to go back to from `current`
+    pub fn undo(&mut self, current: UiState) -> Option<UiState> {
+        let state = self.undo.pop()?;
+        self.redo.push(current);
+        Some(state)
+    }
+
+    /// The arrangement last undone, going forward from `current`
+    pub fn redo(&mut self, current: UiState) -> Option<UiState> {
+        let state = self.redo.pop()?;
+        self.undo.push(current);
+        Some(state)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
@@ -0,0 +1,105 @@
+use crate::config::{Cue, NotifyConfig};
+use crate::model::CommitInfo;
+use crate::shell;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Commit summaries listed in a notification before the rest are counted
+const MAX_LISTED: usize = 3;
+
+/// Announce `commits` (newest first) that just landed on `ref_name`, with the configured
+/// cue and, when enabled, command or desktop notification. Runs in the background;
+/// failures are ignored.
+pub fn new_commits(
+    config: &NotifyConfig,
+    repo_path: &Path,
+    ref_name: &str,
+    commits: &[CommitInfo],
+) {
+    let repo_name = repo_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let plural = if commits.len() == 1 { "" } else { "s" };
+    let title = format!(
+        "{repo_name}: {} new commit{plural} on {ref_name}",
+        commits.len()
+    );
+    let mut body: Vec<String> = commits
+        .iter()
+        .take(MAX_LISTED)
+        .map(|c| format!("{} {}", c.oid, c.message))
+        .collect();
+    if commits.len() > MAX_LISTED {
+        body.push(format!("+{} more", commits.len() - MAX_LISTED));
+    }
+    let body = body.join("\n");
+
+    cue(config.commits_cue, &title);
+    if !config.enabled {
+        return;
+    }
+
+    let mut cmd = match &config.command {
+        Some(command) => {
+            let mut cmd = shell::command(command);
+            cmd.current_dir(repo_path)
+                .env("KIBITZ_REPO", repo_path)
+                .env("KIBITZ_REF", ref_name)
+                .env("KIBITZ_COUNT", commits.len().to_string())
+                .env("KIBITZ_TITLE", &title)
+                .env("KIBITZ_BODY", &body);
+            cmd
+        }
+        None => desktop_notification(&title, &body),
+    };
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    // Wait in the background so the child is reaped without blocking the UI
+    thread::spawn(move || {
+        let _ = cmd.status();
+    });
+}
+
+/// Give `cue` on the terminal, `message` being the text of an OSC 9 notification
+pub fn cue(cue: Cue, message: &str) {
+    let sequence = match cue {
+        Cue::None => return,
+        Cue::Bell => "\x07".to_string(),
+        Cue::Osc9 => {
+            // A control character would end the sequence early
+            let message: String = message.chars().filter(|c| !c.is_control()).collect();
+            format!("\x1b]9;{message}\x07")
+        }
+    };
+    let mut stdout = std::io::stdout();
+    let _ = stdout
+        .write_all(sequence.as_bytes())
+        .and_then(|()| stdout.flush());
+}
+
+fn desktop_notification(title: &str, body: &str) -> Command {
+    if cfg!(target_os = "macos") {
+        // Passing the text as arguments avoids quoting it into the script
+        let mut cmd = Command::new("osascript");
+        cmd.args([
+            "-e",
+            "on run argv",
+            "-e",
+            "display notification (item 2 of argv) with title (item 1 of argv)",
+            "-e",
+            "end run",
+            title,
+            body,
+        ]);
+        cmd
+    } else {
+        let mut cmd = Command::new("notify-send");
+        cmd.args(["--app-name=kibitz", title, body]);
+        cmd
+    }
+}
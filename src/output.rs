@@ -0,0 +1,239 @@
+use crate::shell;
+use anyhow::Result;
+use portable_pty::{ChildKiller, MasterPty, PtySize, native_pty_system};
+use std::io::Read;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+
+/// What the output pane's command produced
+pub enum OutputEvent {
+    /// A complete line, with only color escape codes left in
+    Line(String),
+    /// The command cleared the screen (e.g. `cargo watch -c` between runs)
+    Clear,
+    /// The command exited, with a description of how
+    Exited(String),
+}
+
+/// A command running in a pseudo-terminal (so it keeps its colors) for the output
+/// pane. Killed when dropped.
+pub struct OutputProcess {
+    master: Box<dyn MasterPty + Send>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    pub events: mpsc::Receiver<OutputEvent>,
+}
+
+impl OutputProcess {
+    pub fn spawn(command: &str, cwd: &Path, cols: u16, rows: u16) -> Result<Self> {
+        let pair = native_pty_system().openpty(pty_size(cols, rows))?;
+        let mut cmd = shell::pty_command(command);
+        cmd.cwd(cwd);
+        let mut child = pair.slave.spawn_command(cmd)?;
+        // Only the child should hold the slave end, so reads end when it exits
+        drop(pair.slave);
+
+        let killer = child.clone_killer();
+        let mut reader = pair.master.try_clone_reader()?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut lines = LineSplitter::default();
+            let mut buf = [0; 4096];
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                for event in lines.feed(&buf[..n]) {
+                    if tx.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+            if let Some(event) = lines.finish() {
+                let _ = tx.send(event);
+            }
+            let status = match child.wait() {
+                Ok(status) if status.success() => "exited 0".to_string(),
+                Ok(status) => match status.signal() {
+                    Some(signal) => format!("killed ({signal})"),
+                    None => format!("exited {}", status.exit_code()),
+                },
+                Err(e) => format!("failed: {e}"),
+            };
+            let _ = tx.send(OutputEvent::Exited(status));
+        });
+
+        Ok(Self {
+            master: pair.master,
+            killer,
+            events: rx,
+        })
+    }
+
+    /// Tell the command the pane's new size
+    pub fn resize(&self, cols: u16, rows: u16) {
+        let _ = self.master.resize(pty_size(cols, rows));
+    }
+}
+
+impl Drop for OutputProcess {
+    fn drop(&mut self) {
+        let _ = self.killer.kill();
+    }
+}
+
+fn pty_size(cols: u16, rows: u16) -> PtySize {
+    PtySize {
+        rows: rows.max(1),
+        cols: cols.max(1),
+        ..PtySize::default()
+    }
+}
+
+/// Longest line kept; output that goes on longer without a newline is broken
+/// into lines of this length
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+/// Splits terminal output into lines, following carriage returns (progress bars)
+/// and screen clears, and dropping escape codes other than colors
+#[derive(Default)]
+struct LineSplitter {
+    line: Vec<u8>,
+    /// A `\r` was seen; the next byte starts the line over unless it's `\n`
+    carriage_return: bool,
+}
+
+impl LineSplitter {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<OutputEvent> {
+        let mut events = Vec::new();
+        for &byte in bytes {
+            match byte {
+                b'\n' => {
+                    events.push(OutputEvent::Line(clean_line(&self.line)));
+                    self.line.clear();
+                    self.carriage_return = false;
+                }
+                b'\r' => self.carriage_return = true,
+                _ => {
+                    if self.carriage_return {
+                        self.line.clear();
+                        self.carriage_return = false;
+                    }
+                    self.line.push(byte);
+                    if self.line.ends_with(b"\x1b[2J") || self.line.ends_with(b"\x1bc") {
+                        self.line.clear();
+                        events.push(OutputEvent::Clear);
+                    } else if self.line.len() >= MAX_LINE_BYTES {
+                        events.push(OutputEvent::Line(clean_line(&self.line)));
+                        self.line.clear();
+                    }
+                }
+            }
+        }
+        events
+    }
+
+    /// The last line, if the output didn't end with a newline
+    fn finish(&mut self) -> Option<OutputEvent> {
+        (!self.line.is_empty()).then(|| OutputEvent::Line(clean_line(&self.line)))
+    }
+}
+
+/// Keep text and color (SGR) codes; drop cursor movement, titles and the like
+fn clean_line(line: &[u8]) -> String {
+    let mut out = Vec::with_capacity(line.len());
+    let mut i = 0;
+    while i < line.len() {
+        if line[i] != 0x1b {
+            out.push(line[i]);
+            i += 1;
+            continue;
+        }
+        match line.get(i + 1) {
+            // CSI: parameters, then a final byte in @..~
+            Some(b'[') => {
+                let end = line[i + 2..]
+                    .iter()
+                    .position(|b| (0x40..=0x7e).contains(b))
+                    .map_or(line.len(), |p| i + 2 + p);
+                if line.get(end) == Some(&b'm') {
+                    out.extend_from_slice(&line[i..=end]);
+                }
+                i = end + 1;
+            }
+            // OSC: up to BEL or ESC \
+            Some(b']') => {
+                let rest = &line[i + 2..];
+                let end = rest
+                    .iter()
+                    .position(|&b| b == 0x07 || b == 0x1b)
+                    .map_or(rest.len(), |p| p + 1);
+                i += 2 + end;
+                if line.get(i) == Some(&b'\\') {
+                    i += 1;
+                }
+            }
+            _ => i += 2,
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `feed` made of `chunks`, with clears as `<clear>`
+    fn split(chunks: &[&[u8]]) -> Vec<String> {
+        let mut lines = LineSplitter::default();
+        chunks
+            .iter()
+            .flat_map(|chunk| lines.feed(chunk))
+            .map(|event| match event {
+                OutputEvent::Line(line) => line,
+                OutputEvent::Clear => "<clear>".to_string(),
+                OutputEvent::Exited(status) => status,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_carriage_return_starts_the_line_over() {
+        assert_eq!(split(&[b"50%\r100%\n"]), ["100%"]);
+        assert_eq!(split(&[b"50%\r", b"100%\n"]), ["100%"]);
+        assert_eq!(split(&[b"done\r\n"]), ["done"]);
+    }
+
+    #[test]
+    fn clearing_the_screen() {
+        assert_eq!(split(&[b"old\x1b[2Jnew\n"]), ["<clear>", "new"]);
+        assert_eq!(split(&[b"old\x1b[2", b"Jnew\n"]), ["<clear>", "new"]);
+        assert_eq!(split(&[b"\x1bcnew\n"]), ["<clear>", "new"]);
+    }
+
+    #[test]
+    fn only_colors_are_kept() {
+        assert_eq!(clean_line(b"\x1b[31mred\x1b[0m"), "\x1b[31mred\x1b[0m");
+        assert_eq!(clean_line(b"\x1b[2K\x1b[1Gtext"), "text");
+        assert_eq!(clean_line(b"\x1b]0;title\x07text"), "text");
+        assert_eq!(clean_line(b"\x1b]0;title\x1b\\text"), "text");
+        assert_eq!(clean_line(b"\x1b=text"), "text");
+    }
+
+    #[test]
+    fn cut_off_escapes_are_dropped() {
+        assert_eq!(clean_line(b"text\x1b[31"), "text");
+        assert_eq!(clean_line(b"text\x1b]0;tit"), "text");
+        assert_eq!(clean_line(b"text\x1b]0;title\x1b"), "text");
+        assert_eq!(clean_line(b"text\x1b"), "text");
+    }
+
+    #[test]
+    fn a_line_without_a_newline_is_broken_up() {
+        let long = vec![b'x'; MAX_LINE_BYTES + 10];
+        let lines = split(&[&long, b"\n"]);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), MAX_LINE_BYTES);
+        assert_eq!(lines[1].len(), 10);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::model::NavEntry;
+use crate::persist;
+use crate::serve;
+use anyhow::{Context, Result, bail};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How often the follower asks the leader where it is
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long either side waits on the other before giving up on a request
+const IO_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Which side of a pair review this instance is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PairRole {
+    /// Publish the selection and scroll position
+    Lead,
+    /// Move to wherever the leader is
+    Follow,
+}
+
+/// The shared cursor of a pair review on one repository. The leader serves it
+/// over HTTP on localhost, like `--serve` serves diffs, and leaves its address
+/// and a token in a directory only the user can read; the follower finds it
+/// there and asks for the cursor a few times a second. Requests without the
+/// token are turned away, so other users on the machine can neither read the
+/// cursor nor stand in for the leader.
+pub struct PairLink {
+    pub role: PairRole,
+    /// Where the leader's address is written
+    path: PathBuf,
+    /// Last spot published or followed
+    last: Option<NavEntry>,
+    /// The leader's published spot, which its server reads
+    shared: Arc<Mutex<String>>,
+    /// Spots the follower's poller found the leader at
+    spots: Option<Receiver<NavEntry>>,
+}
+
+impl PairLink {
+    pub fn new(role: PairRole, repo_path: &Path) -> Result<Self> {
+        let dir = persist::private_dir().context("No directory to pair through")?;
+        let mut link = Self {
+            role,
+            path: dir.join(format!("pair-{}", persist::repo_key(repo_path))),
+            last: None,
+            shared: Arc::new(Mutex::new(String::new())),
+            spots: None,
+        };
+        match role {
+            PairRole::Lead => link.lead()?,
+            PairRole::Follow => link.spots = Some(follow(link.path.clone())),
+        }
+        Ok(link)
+    }
+
+    fn lead(&self) -> Result<()> {
+        let listener =
+            TcpListener::bind("127.0.0.1:0").context("Couldn't listen for a follower")?;
+        let addr = listener.local_addr()?;
+        let token = serve::random_token();
+        persist::atomic_write(&self.path, format!("{addr} {token}\n"))?;
+
+        let shared = Arc::clone(&self.shared);
+        thread::spawn(move || {
+            let cursor_path = format!("/cursor/{token}");
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+                let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+                let Ok(request) = serve::read_request(&stream) else {
+                    continue;
+                };
+                let _ = if !serve::host_allowed(request.host.as_deref(), addr.port()) {
+                    serve::respond(&mut stream, "421 Misdirected Request", "text/plain", "")
+                } else if request.path != cursor_path {
+                    serve::respond(&mut stream, "404 Not Found", "text/plain", "")
+                } else {
+                    let cursor = shared.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                    serve::respond(&mut stream, "200 OK", "text/plain", &cursor)
+                };
+            }
+        });
+        Ok(())
+    }
+
+    /// Share where the leader is, if it moved
+    pub fn publish(&mut self, entry: NavEntry) {
+        if self.last.as_ref() == Some(&entry) {
+            return;
+        }
+        let mut contents = String::new();
+        if let Some(commit) = &entry.commit {
+            contents.push_str(&format!("commit={commit}\n"));
+        }
+        if let Some(file) = &entry.file {
+            contents.push_str(&format!("file={}\n", file.display()));
+        }
+        contents.push_str(&format!("scroll={}\nhunk={}\n", entry.scroll, entry.hunk));
+        self.last = Some(entry);
+        *self.shared.lock().unwrap_or_else(|e| e.into_inner()) = contents;
+    }
+
+    /// Where the leader moved to since the last check, if anywhere
+    pub fn poll(&mut self) -> Option<NavEntry> {
+        let entry = self.spots.as_ref()?.try_iter().last()?;
+        if self.last.as_ref() == Some(&entry) {
+            return None;
+        }
+        self.last = Some(entry.clone());
+        Some(entry)
+    }
+}
+
+impl Drop for PairLink {
+    fn drop(&mut self) {
+        if self.role == PairRole::Lead {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Ask the leader whose address is at `path` for its spot until this instance
+/// exits, passing on each one that differs from the last
+fn follow(path: PathBuf) -> Receiver<NavEntry> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut last = None;
+        loop {
+            // The address is read every time, so a leader that starts later or
+            // restarts is found. Its spot is empty until it first publishes one.
+            if let Ok(cursor) = fetch_cursor(&path)
+                && !cursor.is_empty()
+                && last.as_ref() != Some(&cursor)
+            {
+                if tx.send(parse(&cursor)).is_err() {
+                    return;
+                }
+                last = Some(cursor);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+    rx
+}
+
+fn fetch_cursor(path: &Path) -> Result<String> {
+    let contents = std::fs::read_to_string(path)?;
+    let (addr, token) = contents
+        .trim()
+        .split_once(' ')
+        .context("Malformed pair address")?;
+    let addr: SocketAddr = addr.parse()?;
+    let mut stream = TcpStream::connect_timeout(&addr, IO_TIMEOUT)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    write!(
+        stream,
+        "GET /cursor/{token} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .context("Malformed response")?;
+    if !head.starts_with("HTTP/1.1 200") {
+        bail!(
+            "Leader refused: {}",
+            head.lines().next().unwrap_or_default()
+        );
+    }
+    Ok(body.to_string())
+}
+
+fn parse(contents: &str) -> NavEntry {
+    let mut entry = NavEntry {
+        commit: None,
+        file: None,
+        scroll: 0,
+        hunk: 0,
+    };
+    for line in contents.lines() {
+        match line.split_once('=') {
+            Some(("commit", oid)) => entry.commit = Some(oid.to_string()),
+            Some(("file", file)) => entry.file = Some(PathBuf::from(file)),
+            Some(("scroll", n)) => entry.scroll = n.parse().unwrap_or(0),
+            Some(("hunk", n)) => entry.hunk = n.parse().unwrap_or(0),
+            _ => {}
+        }
+    }
+    entry
+}
@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use git2::{ObjectType, Oid};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Replace `path` with `contents` in one step, so a crash mid-write leaves the
+/// last complete version and other readers never see half of it. The temporary
+/// file is named after this process and write, so instances writing the same
+/// file at once don't write into each other's, and is created fresh rather
+/// than followed if something is already there.
+pub fn atomic_write(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension(format!(
+        "{}-{}.partial",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&partial)
+        .and_then(|mut file| file.write_all(contents.as_ref()));
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e).with_context(|| format!("Couldn't write {}", partial.display()));
+    }
+    std::fs::rename(&partial, path).with_context(|| format!("Couldn't replace {}", path.display()))
+}
+
+/// Names what kibitz keeps about the repository at `repo_path` (review
+/// progress, caches) outside it. A SHA-1 of its canonical path, which unlike
+/// std's hashers stays the same from one Rust release to the next.
+pub fn repo_key(repo_path: &Path) -> String {
+    let path = repo_path
+        .canonicalize()
+        .unwrap_or_else(|_| repo_path.to_path_buf());
+    stable_hash(path.as_os_str().as_encoded_bytes())
+}
+
+/// First 16 hex digits of the SHA-1 of `bytes`, as git would hash a blob of them
+pub fn stable_hash(bytes: &[u8]) -> String {
+    match Oid::hash_object(ObjectType::Blob, bytes) {
+        Ok(oid) => oid.to_string()[..16].to_string(),
+        // Only fails if libgit2 can't initialize, when nothing else works either
+        Err(_) => String::from("0"),
+    }
+}
+
+/// A directory only the current user can get into, for files other users
+/// mustn't read or plant: the runtime directory where there is one, kibitz's
+/// data directory otherwise
+pub fn private_dir() -> Option<PathBuf> {
+    let dir = dirs::runtime_dir()
+        .or_else(dirs::data_local_dir)?
+        .join("kibitz");
+    std::fs::create_dir_all(&dir).ok()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).ok()?;
+    }
+    Some(dir)
+}
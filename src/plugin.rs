@@ -0,0 +1,269 @@
+use anyhow::{Context as _, Result, anyhow, bail};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use mlua::{Function, Lua, RegistryKey, Table};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// A key registered by a plugin with `kibitz.bind`
+pub struct Binding {
+    /// Keys as the plugin wrote them, for the help popup
+    pub keys: String,
+    pub description: String,
+    key: KeyEvent,
+    func: RegistryKey,
+}
+
+/// What the app looked like when a plugin key was pressed, passed to the
+/// callback as its `ctx` table
+#[derive(Debug, Default)]
+pub struct Context {
+    pub repo: PathBuf,
+    /// Selected file, relative to the repo root
+    pub file: Option<PathBuf>,
+    pub status: Option<&'static str>,
+    /// Full id of the commit or stash being viewed
+    pub commit: Option<String>,
+    /// Old and new commit of the range being viewed
+    pub range: Option<(String, String)>,
+    /// Index and count of the hunk under the cursor within its file
+    pub hunk: Option<(usize, usize)>,
+    /// Plain unified diff of the selected file
+    pub diff: String,
+}
+
+/// What a plugin asked for while it ran
+#[derive(Debug, Default)]
+pub struct Effects {
+    pub message: Option<String>,
+    /// Keys to press afterwards, handled like a macro replay
+    pub keys: Vec<KeyEvent>,
+}
+
+/// Lua scripts from the `[plugins]` config and the keys they bound
+pub struct Plugins {
+    lua: Lua,
+    bindings: Rc<RefCell<Vec<Binding>>>,
+    effects: Rc<RefCell<Effects>>,
+}
+
+impl Plugins {
+    /// Run each script, collecting the bindings they make. A script that fails is
+    /// skipped and reported in the returned errors.
+    pub fn load(scripts: &[PathBuf]) -> (Self, Vec<String>) {
+        let plugins = Self {
+            lua: Lua::new(),
+            bindings: Rc::default(),
+            effects: Rc::default(),
+        };
+        let mut errors = Vec::new();
+        if let Err(e) = plugins.install_api() {
+            errors.push(format!("plugin API: {e}"));
+            return (plugins, errors);
+        }
+        for script in scripts {
+            if let Err(e) = plugins.run_script(script) {
+                errors.push(format!("{}: {e:#}", script.display()));
+            }
+        }
+        (plugins, errors)
+    }
+
+    /// The `kibitz` table scripts talk to
+    fn install_api(&self) -> mlua::Result<()> {
+        let lua = &self.lua;
+        let api = lua.create_table()?;
+
+        let bindings = Rc::clone(&self.bindings);
+        api.set(
+            "bind",
+            lua.create_function(
+                move |lua, (keys, description, func): (String, String, Function)| {
+                    let key = match parse_keys(&keys).map_err(mlua::Error::external)?[..] {
+                        [key] => key,
+                        _ => return Err(mlua::Error::external(anyhow!("bind takes one key"))),
+                    };
+                    bindings.borrow_mut().push(Binding {
+                        keys,
+                        description,
+                        key,
+                        func: lua.create_registry_value(func)?,
+                    });
+                    Ok(())
+                },
+            )?,
+        )?;
+
+        let effects = Rc::clone(&self.effects);
+        api.set(
+            "message",
+            lua.create_function(move |_, text: String| {
+                effects.borrow_mut().message = Some(text);
+                Ok(())
+            })?,
+        )?;
+
+        let effects = Rc::clone(&self.effects);
+        api.set(
+            "press",
+            lua.create_function(move |_, keys: String| {
+                let keys = parse_keys(&keys).map_err(mlua::Error::external)?;
+                effects.borrow_mut().keys.extend(keys);
+                Ok(())
+            })?,
+        )?;
+
+        lua.globals().set("kibitz", api)
+    }
+
+    fn run_script(&self, path: &Path) -> Result<()> {
+        let source = std::fs::read_to_string(path).context("couldn't read script")?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.lua
+            .load(source)
+            .set_name(format!("@{name}"))
+            .exec()
+            .map_err(lua_error)
+    }
+
+    pub fn bindings(&self) -> std::cell::Ref<'_, Vec<Binding>> {
+        self.bindings.borrow()
+    }
+
+    pub fn is_bound(&self, key: KeyEvent) -> bool {
+        self.bindings
+            .borrow()
+            .iter()
+            .any(|b| key_matches(b.key, key))
+    }
+
+    /// Call the callback bound to `key` with `ctx`. A string it returns becomes the
+    /// message.
+    pub fn handle(&self, key: KeyEvent, ctx: &Context) -> Result<Effects> {
+        // Not borrowed during the call, so callbacks can bind more keys
+        let func: Function = {
+            let bindings = self.bindings.borrow();
+            let binding = bindings
+                .iter()
+                .find(|b| key_matches(b.key, key))
+                .context("no plugin binding for this key")?;
+            self.lua.registry_value(&binding.func).map_err(lua_error)?
+        };
+        let ctx = self.context_table(ctx).map_err(lua_error)?;
+        // Drop anything a previous callback asked for before it failed
+        self.effects.take();
+        let returned: Option<String> = func.call(ctx).map_err(lua_error)?;
+
+        let mut effects = std::mem::take(&mut *self.effects.borrow_mut());
+        if returned.is_some() {
+            effects.message = returned;
+        }
+        Ok(effects)
+    }
+
+    fn context_table(&self, ctx: &Context) -> mlua::Result<Table> {
+        let lua = &self.lua;
+        let path = |p: &Path| p.to_string_lossy().into_owned();
+        let table = lua.create_table()?;
+        table.set("repo", path(&ctx.repo))?;
+        table.set("file", ctx.file.as_deref().map(path))?;
+        table.set("status", ctx.status)?;
+        table.set("commit", ctx.commit.clone())?;
+        if let Some((from, to)) = &ctx.range {
+            let range = lua.create_table()?;
+            range.set("from", from.clone())?;
+            range.set("to", to.clone())?;
+            table.set("range", range)?;
+        }
+        if let Some((index, count)) = ctx.hunk {
+            let hunk = lua.create_table()?;
+            // 1-based, like everything else in Lua
+            hunk.set("index", index + 1)?;
+            hunk.set("count", count)?;
+            table.set("hunk", hunk)?;
+        }
+        table.set("diff", ctx.diff.clone())?;
+        Ok(table)
+    }
+}
+
+/// Lua errors end in a stack traceback, which doesn't fit the message line
+fn lua_error(e: mlua::Error) -> anyhow::Error {
+    let text = e.to_string();
+    anyhow!("{}", text.lines().next().unwrap_or_default())
+}
+
+/// Keys in vim notation: plain characters, or `<Esc>`, `<CR>`, `<C-j>`, `<A-k>`,
+/// `<S-Down>`, `<lt>` (for `<`) and the like
+pub fn parse_keys(spec: &str) -> Result<Vec<KeyEvent>> {
+    let mut keys = Vec::new();
+    let mut rest = spec;
+    while let Some(c) = rest.chars().next() {
+        if c == '<'
+            && let Some(end) = rest.find('>')
+            && end > 1
+        {
+            keys.push(parse_special(&rest[1..end])?);
+            rest = &rest[end + 1..];
+            continue;
+        }
+        let modifiers = if c.is_uppercase() {
+            KeyModifiers::SHIFT
+        } else {
+            KeyModifiers::NONE
+        };
+        keys.push(KeyEvent::new(KeyCode::Char(c), modifiers));
+        rest = &rest[c.len_utf8()..];
+    }
+    Ok(keys)
+}
+
+fn parse_special(name: &str) -> Result<KeyEvent> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut name = name;
+    while let Some((prefix, rest)) = name.split_once('-')
+        && !rest.is_empty()
+    {
+        modifiers |= match prefix.to_ascii_uppercase().as_str() {
+            "C" => KeyModifiers::CONTROL,
+            "A" | "M" => KeyModifiers::ALT,
+            "S" => KeyModifiers::SHIFT,
+            _ => bail!("unknown modifier in <{name}>"),
+        };
+        name = rest;
+    }
+    let code = match name.to_ascii_lowercase().as_str() {
+        "esc" => KeyCode::Esc,
+        "cr" | "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Char(' '),
+        "bs" | "backspace" => KeyCode::Backspace,
+        "lt" => KeyCode::Char('<'),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        _ => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => KeyCode::Char(c),
+                _ => bail!("unknown key <{name}>"),
+            }
+        }
+    };
+    Ok(KeyEvent::new(code, modifiers))
+}
+
+/// Terminals report Shift on uppercase letters inconsistently, so it only counts
+/// for keys that aren't characters
+fn key_matches(bound: KeyEvent, pressed: KeyEvent) -> bool {
+    let relevant = |key: KeyEvent| match key.code {
+        KeyCode::Char(_) => key.modifiers - KeyModifiers::SHIFT,
+        _ => key.modifiers,
+    };
+    bound.code == pressed.code && relevant(bound) == relevant(pressed)
+}
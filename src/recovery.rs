@@ -0,0 +1,116 @@
+use crate::github::ReviewComment;
+use crate::model::NavEntry;
+use crate::persist;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// Review progress written to disk while kibitz runs, so a crash or a dropped
+/// SSH connection doesn't lose it. Removed again on a clean exit.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewState {
+    /// Files whose diff was shown, in the order they were first seen
+    pub viewed: Vec<PathBuf>,
+    /// Review order of every file seen (`<`/`>`/`m`)
+    pub queue: Vec<PathBuf>,
+    /// Review comments written and not submitted yet
+    pub drafts: Vec<ReviewComment>,
+    /// Views left while browsing history, oldest first
+    pub nav: Vec<NavEntry>,
+}
+
+impl ReviewState {
+    pub fn is_empty(&self) -> bool {
+        self.viewed.is_empty()
+            && self.queue.is_empty()
+            && self.drafts.is_empty()
+            && self.nav.is_empty()
+    }
+}
+
+/// The progress a session in `repo_path` left behind without exiting cleanly
+pub fn load(repo_path: &Path) -> Option<ReviewState> {
+    let contents = std::fs::read(state_path(repo_path)?).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+pub fn save(repo_path: &Path, state: &ReviewState) -> Result<()> {
+    let Some(path) = state_path(repo_path) else {
+        return Ok(());
+    };
+    persist::atomic_write(&path, serde_json::to_vec(state)?)
+}
+
+/// Forget the saved progress, once the session it's from ended cleanly
+pub fn clear(repo_path: &Path) {
+    if let Some(path) = state_path(repo_path) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Claim on writing a repository's review progress, so two kibitz instances on
+/// it don't overwrite each other's: an OS lock on a file next to the progress.
+/// The OS lets go of it when its holder exits, crashed or not, and a holder
+/// busy elsewhere (in `$EDITOR`, suspended) keeps it, so there's nothing to renew.
+pub struct InstanceLock {
+    path: Option<PathBuf>,
+    /// Open while holding the lock, which closing it releases
+    file: Option<File>,
+}
+
+impl InstanceLock {
+    /// Take the lock for `repo_path` unless another running kibitz holds it
+    pub fn acquire(repo_path: &Path) -> Self {
+        let mut lock = Self {
+            path: state_path(repo_path).map(|path| path.with_extension("lock")),
+            file: None,
+        };
+        lock.retry();
+        lock
+    }
+
+    pub fn held(&self) -> bool {
+        // Nowhere to save progress, so nothing to share either
+        self.file.is_some() || self.path.is_none()
+    }
+
+    /// Take the lock if its holder has exited since. Returns whether this
+    /// instance holds it now and didn't before.
+    pub fn retry(&mut self) -> bool {
+        if self.held() {
+            return false;
+        }
+        self.file = self.path.as_deref().and_then(|path| lock_file(path).ok());
+        self.file.is_some()
+    }
+
+    /// Let the next instance take the lock right away
+    pub fn release(&mut self) {
+        // Left in place: removing it would let one instance lock the old file
+        // and another a new one at the same time
+        self.file = None;
+    }
+}
+
+fn lock_file(path: &Path) -> Result<File> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(path)?;
+    file.try_lock()?;
+    Ok(file)
+}
+
+/// Where this repository's progress is kept
+fn state_path(repo_path: &Path) -> Option<PathBuf> {
+    Some(
+        dirs::data_local_dir()?
+            .join("kibitz")
+            .join(format!("review-{}.json", persist::repo_key(repo_path))),
+    )
+}
@@ -0,0 +1,621 @@
+use crate::event::watcher::FileWatcher;
+use crate::git::attributes;
+use crate::git::patch::{self, FilePatch, Hunk, PatchSource};
+use crate::git::status;
+use crate::github::{ReviewComment, Side};
+use crate::model::{DiffState, FileStatus};
+use crate::persist;
+use crate::recovery::{self, ReviewState};
+use crate::view::{Browsing, View};
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::hash::{BuildHasher, RandomState};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Where `--serve` listens without an address: this machine only, reachable from
+/// elsewhere through an SSH tunnel (`ssh -L 7070:localhost:7070 host`)
+pub const DEFAULT_ADDR: &str = "127.0.0.1:7070";
+
+/// Milliseconds between the page's checks for changes
+const POLL_INTERVAL_MS: u32 = 2000;
+
+/// Connections handled at once; more are turned away until one finishes
+const MAX_CONNECTIONS: usize = 16;
+
+/// How long a client gets to send its request, or to take the response
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a rendered page is served before rendering it again, for changes
+/// in directories the watcher doesn't cover
+const PAGE_MAX_AGE: Duration = Duration::from_secs(30);
+
+/// A rendered page with its version, kept until the watcher reports a change
+/// or the review progress it shows changes
+struct Rendered {
+    html: String,
+    version: String,
+    review: ReviewState,
+    at: Instant,
+}
+
+struct Server {
+    repo_path: PathBuf,
+    port: u16,
+    /// Secret first segment of every path, so only those given the address
+    /// can read the diffs
+    token: String,
+    page: Mutex<Option<Rendered>>,
+    watcher: Mutex<FileWatcher>,
+    /// Directories of changed files the watcher was told about
+    watched: Mutex<HashSet<PathBuf>>,
+    connections: AtomicUsize,
+}
+
+/// Serve a read-only web view of the working tree's changes until killed
+pub fn run(repo_path: &Path, addr: &str) -> Result<i32> {
+    let listener = TcpListener::bind(addr).with_context(|| format!("Couldn't listen on {addr}"))?;
+    let local_addr = listener.local_addr()?;
+    let token = random_token();
+    eprintln!(
+        "Serving {} at http://{local_addr}/{token}/",
+        repo_path.display()
+    );
+
+    let (tx, rx) = mpsc::channel();
+    let server = Arc::new(Server {
+        repo_path: repo_path.to_path_buf(),
+        port: local_addr.port(),
+        token,
+        page: Mutex::new(None),
+        watcher: Mutex::new(FileWatcher::new(repo_path, tx)?),
+        watched: Mutex::new(HashSet::new()),
+        connections: AtomicUsize::new(0),
+    });
+    {
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            while rx.recv().is_ok() {
+                *lock(&server.page) = None;
+            }
+        });
+    }
+
+    for mut stream in listener.incoming().flatten() {
+        let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+        if server.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            server.connections.fetch_sub(1, Ordering::SeqCst);
+            let _ = respond(&mut stream, "503 Service Unavailable", "text/plain", "Busy");
+            continue;
+        }
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            let _ = handle(stream, &server);
+            server.connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(0)
+}
+
+fn handle(mut stream: TcpStream, server: &Server) -> Result<()> {
+    let request = read_request(&stream)?;
+    if !host_allowed(request.host.as_deref(), server.port) {
+        return respond(
+            &mut stream,
+            "421 Misdirected Request",
+            "text/plain",
+            "Unknown host",
+        );
+    }
+    let path = request
+        .path
+        .strip_prefix('/')
+        .and_then(|path| path.strip_prefix(server.token.as_str()));
+    let (status, content_type, body) = match (request.method.as_str(), path) {
+        ("GET", Some("/")) => match rendered(server, |page| page.html.clone()) {
+            Ok(page) => ("200 OK", "text/html; charset=utf-8", page),
+            Err(e) => error_response(&e),
+        },
+        ("GET", Some("/version")) => match rendered(server, |page| page.version.clone()) {
+            Ok(version) => ("200 OK", "text/plain", version),
+            Err(e) => error_response(&e),
+        },
+        ("GET", _) => ("404 Not Found", "text/plain", "Not found".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Read-only".to_string(),
+        ),
+    };
+    respond(&mut stream, status, content_type, &body)
+}
+
+/// The first line and Host header of an HTTP request
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub host: Option<String>,
+}
+
+pub fn read_request(stream: &TcpStream) -> Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    // The rest of the headers are read so the client isn't cut off mid-send
+    let mut host = None;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("host")
+        {
+            host = Some(value.trim().to_string());
+        }
+        header.clear();
+    }
+    Ok(Request { method, path, host })
+}
+
+/// A secret for a URL or a request, seeded from the OS's randomness, unlike
+/// anything else at hand
+pub fn random_token() -> String {
+    let random = || RandomState::new().hash_one(std::process::id());
+    format!("{:016x}{:016x}", random(), random())
+}
+
+/// Whether a request's Host names this server by IP address or as localhost.
+/// A web page can't get a browser to send anything else to it, which keeps
+/// sites that rebind their own domain name to 127.0.0.1 from reading it.
+pub fn host_allowed(host: Option<&str>, port: u16) -> bool {
+    let Some((name, host_port)) = host.and_then(|host| host.rsplit_once(':')) else {
+        return false;
+    };
+    let name = name.trim_start_matches('[').trim_end_matches(']');
+    host_port.parse() == Ok(port)
+        && (name.eq_ignore_ascii_case("localhost") || name.parse::<IpAddr>().is_ok())
+}
+
+pub fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    Ok(())
+}
+
+fn error_response(e: &anyhow::Error) -> (&'static str, &'static str, String) {
+    ("500 Internal Server Error", "text/plain", format!("{e:#}"))
+}
+
+/// Read from the rendered page, rendering it first if something changed since
+fn rendered<T>(server: &Server, read: impl FnOnce(&Rendered) -> T) -> Result<T> {
+    // Held while rendering, so connections arriving meanwhile wait for it
+    // rather than all rendering the same page
+    let mut cached = lock(&server.page);
+    // Saved every second by a kibitz running in the repository
+    let review = recovery::load(&server.repo_path).unwrap_or_default();
+    if let Some(page) = cached
+        .as_ref()
+        .filter(|page| page.at.elapsed() < PAGE_MAX_AGE && page.review == review)
+    {
+        return Ok(read(page));
+    }
+    let (html, files) = page(&server.repo_path, &review)?;
+    watch_dirs(server, &files);
+    let page = cached.insert(Rendered {
+        version: version(&html),
+        html,
+        review,
+        at: Instant::now(),
+    });
+    Ok(read(page))
+}
+
+/// Watch the directories of changed files, since only the top of the working
+/// tree is watched to begin with
+fn watch_dirs(server: &Server, files: &[PathBuf]) {
+    let mut watched = lock(&server.watched);
+    let mut watcher = lock(&server.watcher);
+    for dir in files.iter().filter_map(|file| file.parent()) {
+        if !dir.as_os_str().is_empty() && watched.insert(dir.to_path_buf()) {
+            let _ = watcher.watch_dir(&server.repo_path.join(dir));
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Identifies a rendering of the page so the browser reloads only on changes
+fn version(page: &str) -> String {
+    // The page embeds its own version, so hash what comes before it
+    let content = page.split("<script>").next().unwrap_or(page);
+    persist::stable_hash(content.as_bytes())
+}
+
+/// The changed files, then the staged, unstaged and untracked diffs of each,
+/// in the review's order and with its progress and draft comments. Also
+/// returns the changed files' paths.
+fn page(repo_path: &Path, review: &ReviewState) -> Result<(String, Vec<PathBuf>)> {
+    let (mut files, _) = status::get_status(repo_path)?;
+    files.sort_by(|(a, _), (b, _)| review_order(review, a).cmp(&review_order(review, b)));
+    let paths: Vec<PathBuf> = files.iter().map(|(path, _)| path.clone()).collect();
+    let generated = attributes::generated_files(repo_path, &paths);
+    let name = repo_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!doctype html><html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width\">\
+         <title>kibitz: {}</title><style>{STYLE}</style></head><body>\
+         <h1>{}</h1>",
+        escape(&name),
+        escape(&name)
+    );
+    let ahead_behind = status::snapshot(repo_path)
+        .ok()
+        .and_then(|snapshot| snapshot.ahead_behind);
+    render_view(&working_tree_view(ahead_behind), &mut html);
+
+    if files.is_empty() {
+        html.push_str("<p class=\"muted\">No changes</p>");
+    } else {
+        let viewed = files
+            .iter()
+            .filter(|(path, _)| review.viewed.contains(path))
+            .count();
+        let _ = write!(
+            html,
+            "<p class=\"muted\">{viewed} of {} files viewed</p><ul class=\"files\">",
+            files.len()
+        );
+        for (path, status) in &files {
+            let mark = if review.viewed.contains(path) {
+                "\u{2713}"
+            } else {
+                ""
+            };
+            let _ = write!(
+                html,
+                "<li><span class=\"viewed\">{mark}</span><span class=\"status {0}\">{0}</span> \
+                 <a href=\"#{1}\">{1}</a></li>",
+                status.name(),
+                escape(&path.to_string_lossy())
+            );
+        }
+        html.push_str("</ul>");
+    }
+
+    let untracked: Vec<PathBuf> = files
+        .iter()
+        .filter(|(_, status)| *status == FileStatus::Untracked)
+        .map(|(path, _)| path.clone())
+        .collect();
+    let mut sections = vec![
+        (
+            "Staged",
+            patch::generate(repo_path, &PatchSource::Staged(Vec::new()))?,
+        ),
+        (
+            "Unstaged",
+            patch::generate(repo_path, &PatchSource::Unstaged(Vec::new()))?,
+        ),
+    ];
+    let mut untracked_patch = Vec::new();
+    for path in untracked {
+        untracked_patch.extend(patch::generate(repo_path, &PatchSource::Untracked(path))?);
+    }
+    sections.push(("Untracked", untracked_patch));
+
+    for (title, patch) in sections {
+        let files = patch::parse(&String::from_utf8_lossy(&patch));
+        let mut files: Vec<_> = files
+            .iter()
+            .filter(|f| f.old_path.is_some() || f.new_path.is_some())
+            .collect();
+        if files.is_empty() {
+            continue;
+        }
+        files.sort_by(|a, b| {
+            let order = |path: &Path| review_order(review, path).0;
+            patch_path(a)
+                .map(order)
+                .cmp(&patch_path(b).map(order))
+                .then_with(|| patch_path(a).cmp(&patch_path(b)))
+        });
+        let _ = write!(html, "<h2>{title}</h2>");
+        for file in files {
+            render_file(file, &generated, &review.drafts, &mut html);
+        }
+    }
+
+    let _ = write!(
+        html,
+        "<script>const version = \"{}\";\
+         setInterval(() => fetch(\"version\").then(r => r.text())\
+         .then(v => {{ if (v !== version) location.reload(); }}).catch(() => {{}}), {POLL_INTERVAL_MS});\
+         </script></body></html>",
+        version(&html)
+    );
+    Ok((html, paths))
+}
+
+/// Where `path` comes in the review: by its place in the queue (`<`/`>`/`m`),
+/// then by name for files the review hasn't reached
+fn review_order<'a>(review: &ReviewState, path: &'a Path) -> (usize, &'a Path) {
+    let place = review.queue.iter().position(|queued| queued == path);
+    (place.unwrap_or(usize::MAX), path)
+}
+
+/// The file a patch is of: its new path, or its old one when it's deleted
+fn patch_path(file: &FilePatch) -> Option<&Path> {
+    file.new_path.as_deref().or(file.old_path.as_deref())
+}
+
+/// What the TUI's main screen would say about the working tree
+fn working_tree_view(ahead_behind: Option<(usize, usize)>) -> View {
+    View::new(&Browsing {
+        diff: &DiffState::new(),
+        commit: None,
+        comparison: None,
+        range: None,
+        file_log: None,
+        stash_index: None,
+        commit_refs: None,
+        ignoring_whitespace: false,
+        blame: false,
+        pair: None,
+        not_saving: false,
+        message: None,
+        recording: None,
+        ahead_behind,
+    })
+}
+
+/// The mode badges and the sync state with the upstream branch
+fn render_view(view: &View, html: &mut String) {
+    html.push_str("<p class=\"modes\">");
+    for mode in &view.modes {
+        let _ = write!(html, "<span class=\"mode\">{}</span> ", escape(&mode.label));
+    }
+    if let Some((ahead, behind)) = view.ahead_behind {
+        let _ = write!(
+            html,
+            "<span class=\"muted\">\u{2191}{ahead} \u{2193}{behind}</span>"
+        );
+    }
+    html.push_str("</p>");
+}
+
+fn render_file(
+    file: &FilePatch,
+    generated: &HashSet<PathBuf>,
+    drafts: &[ReviewComment],
+    html: &mut String,
+) {
+    let display = |path: &Option<PathBuf>| {
+        path.as_deref()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let path = patch_path(file);
+    let anchor = path
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let title = match (&file.old_path, &file.new_path) {
+        (None, _) => format!("added: {}", display(&file.new_path)),
+        (_, None) => format!("removed: {}", display(&file.old_path)),
+        (old, new) if old != new => {
+            format!("renamed: {} \u{2192} {}", display(old), display(new))
+        }
+        _ => display(&file.new_path),
+    };
+    let _ = write!(
+        html,
+        "<section id=\"{}\"><h3>{}</h3>",
+        escape(&anchor),
+        escape(&title)
+    );
+
+    if path.is_some_and(|p| generated.contains(p)) {
+        html.push_str("<p class=\"muted\">Generated file, not shown</p></section>");
+        return;
+    }
+    if file.hunks.is_empty() {
+        let note = if file.header.iter().any(|l| l.starts_with("Binary files")) {
+            "Binary file differs"
+        } else {
+            "No content changes"
+        };
+        let _ = write!(html, "<p class=\"muted\">{note}</p></section>");
+        return;
+    }
+
+    let drafts: Vec<_> = drafts
+        .iter()
+        .filter(|draft| path.is_some_and(|path| *path == draft.path))
+        .collect();
+    let mut shown = vec![false; drafts.len()];
+    html.push_str("<table>");
+    for hunk in &file.hunks {
+        render_hunk(hunk, &drafts, &mut shown, html);
+    }
+    html.push_str("</table>");
+    // Drafts on lines the diff no longer shows
+    for (draft, _) in drafts.iter().zip(&shown).filter(|(_, shown)| !**shown) {
+        render_draft(draft, html);
+    }
+    html.push_str("</section>");
+}
+
+fn render_draft(draft: &ReviewComment, html: &mut String) {
+    let side = match draft.side {
+        Side::Left => "old",
+        Side::Right => "new",
+    };
+    let _ = write!(
+        html,
+        "<div class=\"draft\"><span class=\"muted\">Draft on {side} line {}</span>\
+         <pre>{}</pre></div>",
+        draft.line,
+        escape(&draft.body)
+    );
+}
+
+/// `drafts` are shown below their lines, and marked in `shown`
+fn render_hunk(hunk: &Hunk, drafts: &[&ReviewComment], shown: &mut [bool], html: &mut String) {
+    let _ = write!(
+        html,
+        "<tr class=\"hunk\"><td colspan=\"3\">{}</td></tr>",
+        escape(&hunk.header)
+    );
+    let mut old_line = hunk.old_start;
+    let mut new_line = hunk.new_start;
+    for line in &hunk.lines {
+        let (origin, text) = line.split_at(line.chars().next().map_or(0, char::len_utf8));
+        let (class, old, new) = match origin {
+            "-" => {
+                old_line += 1;
+                ("del", Some(old_line - 1), None)
+            }
+            "+" => {
+                new_line += 1;
+                ("add", None, Some(new_line - 1))
+            }
+            " " => {
+                old_line += 1;
+                new_line += 1;
+                ("ctx", Some(old_line - 1), Some(new_line - 1))
+            }
+            // "\ No newline at end of file"
+            _ => continue,
+        };
+        let number = |n: Option<usize>| n.map(|n| n.to_string()).unwrap_or_default();
+        let _ = write!(
+            html,
+            "<tr class=\"{class}\"><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+             <td><pre>{}</pre></td></tr>",
+            number(old),
+            number(new),
+            escape(text.trim_end_matches('\r'))
+        );
+        for (i, draft) in drafts.iter().enumerate() {
+            let line = match draft.side {
+                Side::Left => old,
+                Side::Right => new,
+            };
+            if !shown[i] && line == Some(draft.line) {
+                shown[i] = true;
+                html.push_str("<tr><td colspan=\"3\">");
+                render_draft(draft, html);
+                html.push_str("</td></tr>");
+            }
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const STYLE: &str = "\
+body{background:#1e1e1e;color:#d4d4d4;font-family:sans-serif;margin:1em}\
+h1,h2{font-weight:normal}h2{color:#c586c0;border-bottom:1px solid #444}\
+h3{color:#569cd6;font-size:1em;margin:1.5em 0 .3em}\
+a{color:#d4d4d4}.muted{color:#808080}\
+.files{list-style:none;padding:0;font-family:monospace}\
+.status{display:inline-block;width:9em;color:#808080}\
+.added,.untracked{color:#6a9955}.deleted,.conflicted{color:#f44747}\
+.staged,.staged_modified{color:#dcdcaa}.modified,.renamed{color:#ce9178}\
+table{border-collapse:collapse;width:100%;font-family:monospace}\
+td{padding:0 .5em;vertical-align:top}pre{margin:0;white-space:pre-wrap;word-break:break-all}\
+.num{color:#808080;text-align:right;width:1%;white-space:nowrap;user-select:none}\
+.add{background:#1f3a1f}.del{background:#4a1e1e}\
+.hunk td{color:#569cd6;background:#252540;padding:.2em .5em}\
+.viewed{display:inline-block;width:1.5em;color:#6a9955}\
+.mode{color:#000;background:#569cd6;font-weight:bold;padding:0 .4em}\
+.draft{border-left:3px solid #dcdcaa;margin:.3em 0;padding:.2em .5em;background:#2a2a20}";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draft(line: usize, side: Side, body: &str) -> ReviewComment {
+        ReviewComment {
+            path: PathBuf::from("src/a.rs"),
+            line,
+            side,
+            author: String::new(),
+            body: body.to_string(),
+            date: String::new(),
+            draft: true,
+            commit: String::new(),
+        }
+    }
+
+    #[test]
+    fn the_review_queue_comes_first() {
+        let review = ReviewState {
+            queue: vec![PathBuf::from("z.rs"), PathBuf::from("b.rs")],
+            ..ReviewState::default()
+        };
+        let mut files = ["a.rs", "b.rs", "c.rs", "z.rs"].map(Path::new);
+        files.sort_by_key(|path| review_order(&review, path));
+        assert_eq!(files, ["z.rs", "b.rs", "a.rs", "c.rs"].map(Path::new));
+    }
+
+    #[test]
+    fn drafts_show_below_their_lines() {
+        let patch = "diff --git a/src/a.rs b/src/a.rs\n--- a/src/a.rs\n+++ b/src/a.rs\n\
+                     @@ -1,2 +1,2 @@\n one\n-two\n+three\n";
+        let file = &patch::parse(patch)[0];
+        let drafts = [
+            draft(2, Side::Left, "old two"),
+            draft(2, Side::Right, "new <three>"),
+            draft(9, Side::Right, "elsewhere"),
+        ];
+        let mut html = String::new();
+        render_file(file, &HashSet::new(), &drafts, &mut html);
+
+        let at = |text: &str| html.find(text).unwrap();
+        assert!(at("<pre>two") < at("old two"));
+        assert!(at("old two") < at("<pre>three"));
+        assert!(at("<pre>three") < at("new &lt;three&gt;"));
+        // Off the diff, after it
+        assert!(at("</table>") < at("elsewhere"));
+    }
+
+    #[test]
+    fn the_working_tree_badge_and_sync_state() {
+        let mut html = String::new();
+        render_view(&working_tree_view(Some((2, 1))), &mut html);
+        assert!(html.contains(">WORKTREE<"));
+        assert!(html.contains("\u{2191}2 \u{2193}1"));
+    }
+}
@@ -0,0 +1,130 @@
+use portable_pty::CommandBuilder;
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+
+/// The platform's shell and its flag for running a command line
+#[cfg(not(windows))]
+const SHELL: [&str; 2] = ["sh", "-c"];
+#[cfg(windows)]
+const SHELL: [&str; 2] = ["cmd", "/C"];
+
+/// Run `line` through the platform's shell: `sh -c` on Unix, `cmd /C` on Windows
+pub fn command(line: &str) -> Command {
+    let mut cmd = Command::new(SHELL[0]);
+    cmd.args([SHELL[1], line]);
+    cmd
+}
+
+/// Like `command`, for running in a pseudo-terminal (ConPTY on Windows)
+pub fn pty_command(line: &str) -> CommandBuilder {
+    let mut cmd = CommandBuilder::new(SHELL[0]);
+    cmd.args([SHELL[1], line]);
+    cmd
+}
+
+/// Split a configured command line into arguments at whitespace, keeping
+/// anything in single or double quotes together (`--theme "Monokai Extended"`).
+/// There are no escapes, so the result is the same on every platform, and a
+/// quote left open runs to the end of the line.
+pub fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.get_or_insert_default().push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => args.extend(current.take()),
+            (None, c) => current.get_or_insert_default().push(c),
+        }
+    }
+    args.extend(current);
+    args
+}
+
+/// Whether `program` can be run: an existing path, or a name found on PATH.
+/// On Windows the name may leave off an extension from PATHEXT (`delta` for
+/// `delta.exe`).
+pub fn program_exists(program: &str) -> bool {
+    if program.contains(['/', std::path::MAIN_SEPARATOR]) {
+        return Path::new(program).is_file();
+    }
+    let extensions = executable_extensions();
+    std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| {
+            extensions.iter().any(|ext| {
+                let mut name = OsString::from(program);
+                name.push(ext);
+                dir.join(name).is_file()
+            })
+        })
+    })
+}
+
+#[cfg(not(windows))]
+fn executable_extensions() -> Vec<OsString> {
+    vec![OsString::new()]
+}
+
+#[cfg(windows)]
+fn executable_extensions() -> Vec<OsString> {
+    let pathext = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    std::iter::once(OsString::new())
+        .chain(
+            pathext
+                .split(';')
+                .filter(|e| !e.is_empty())
+                .map(OsString::from),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_args;
+
+    #[test]
+    fn splits_at_whitespace() {
+        assert_eq!(
+            split_args("  delta\t--paging=never  -s "),
+            ["delta", "--paging=never", "-s"]
+        );
+        assert!(split_args("   ").is_empty());
+    }
+
+    #[test]
+    fn quotes_keep_text_together() {
+        assert_eq!(
+            split_args(r#"--theme "Monokai Extended" --pager 'less -R'"#),
+            ["--theme", "Monokai Extended", "--pager", "less -R"]
+        );
+        assert_eq!(split_args(r#"'say "hi"'"#), [r#"say "hi""#]);
+    }
+
+    #[test]
+    fn empty_quotes_are_an_empty_argument() {
+        assert_eq!(split_args(r#"--prefix "" ''"#), ["--prefix", "", ""]);
+    }
+
+    #[test]
+    fn quoted_and_unquoted_text_next_to_each_other_are_one_argument() {
+        assert_eq!(
+            split_args(r#"--theme="Monokai Extended" a'b'"c""#),
+            ["--theme=Monokai Extended", "abc"]
+        );
+    }
+
+    #[test]
+    fn an_unterminated_quote_runs_to_the_end() {
+        assert_eq!(
+            split_args(r#"--theme "Monokai Extended"#),
+            ["--theme", "Monokai Extended"]
+        );
+        assert_eq!(split_args("a 'b  c"), ["a", "b  c"]);
+    }
+}
@@ -0,0 +1,38 @@
+use crate::config::ColorConfig;
+use crate::i18n::tr;
+use crate::model::ActivityLog;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+};
+
+/// Rows taken by the activity panel, including its border
+pub const ACTIVITY_PANEL_HEIGHT: u16 = 8;
+
+/// Render the most recent activity entries, newest at the bottom
+pub fn render(frame: &mut Frame, area: Rect, log: &ActivityLog, colors: &ColorConfig) {
+    let block = Block::default()
+        .borders(Borders::TOP)
+        .title(format!(" {} ", tr("Activity")));
+    let visible = block.inner(area).height as usize;
+
+    let entries = log.entries();
+    let skip = entries.len().saturating_sub(visible);
+    let lines: Vec<Line> = entries
+        .skip(skip)
+        .map(|entry| {
+            Line::from(vec![
+                Span::styled(
+                    format!(" {} ", entry.time),
+                    Style::default().fg(colors.text_muted),
+                ),
+                Span::styled(entry.text.as_str(), Style::default().fg(colors.text)),
+            ])
+        })
+        .collect();
+
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
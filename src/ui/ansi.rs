@@ -0,0 +1,109 @@
+use crate::config::ColorConfig;
+use crate::model::DiffState;
+use crate::ui::layout;
+use ratatui::{
+    Frame,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+/// Render the ANSI inspector: delta's raw bytes for diff line `index` and the
+/// spans they were parsed into
+pub fn render(frame: &mut Frame, diff: &DiffState, index: usize, colors: &ColorConfig) {
+    let area = layout::centered_rect(80, 60, frame.area());
+    frame.render_widget(Clear, area);
+
+    let heading = Style::default()
+        .add_modifier(Modifier::BOLD)
+        .fg(colors.accent);
+    let muted = Style::default().fg(colors.text_muted);
+
+    let mut lines = vec![Line::styled("Raw", heading)];
+    lines.push(match diff.raw_lines.get(index) {
+        Some(raw) => Line::raw(escape(raw)),
+        None => Line::styled("(no raw output)", muted),
+    });
+    lines.push(Line::from(""));
+    lines.push(Line::styled("Spans", heading));
+    match diff.content.lines.get(index) {
+        Some(line) if !line.spans.is_empty() => {
+            if line.style != Style::default() {
+                lines.push(Line::styled(
+                    format!("line {}", describe(line.style)),
+                    muted,
+                ));
+            }
+            for span in &line.spans {
+                lines.push(Line::from(vec![
+                    Span::styled(format!("{:?}", span.content), span.style),
+                    Span::styled(format!("  {}", describe(span.style)), muted),
+                ]));
+            }
+        }
+        Some(_) => lines.push(Line::styled("(empty line)", muted)),
+        None => lines.push(Line::styled("(no line)", muted)),
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::styled(
+        "j/k move a line \u{b7} ` or Esc to close",
+        muted,
+    ));
+
+    let popup = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .title(format!(
+                " ANSI inspector: line {} of {} ",
+                index + 1,
+                diff.total_lines
+            ))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(colors.accent)),
+    );
+    frame.render_widget(popup, area);
+}
+
+/// Make escape sequences and other control characters visible
+fn escape(raw: &str) -> String {
+    raw.chars()
+        .map(|c| match c {
+            '\x1b' => "\\e".to_string(),
+            '\t' => "\\t".to_string(),
+            '\r' => "\\r".to_string(),
+            c if c.is_control() => format!("\\x{:02x}", c as u32),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// Colors and modifiers of a style, e.g. "fg=green bg=#1e1e1e bold"
+fn describe(style: Style) -> String {
+    let mut parts = Vec::new();
+    let color = |color: Color| match color {
+        Color::Rgb(r, g, b) => format!("#{r:02x}{g:02x}{b:02x}"),
+        Color::Indexed(n) => n.to_string(),
+        other => format!("{other:?}").to_lowercase(),
+    };
+    if let Some(fg) = style.fg {
+        parts.push(format!("fg={}", color(fg)));
+    }
+    if let Some(bg) = style.bg {
+        parts.push(format!("bg={}", color(bg)));
+    }
+    for (name, _) in style.add_modifier.iter_names() {
+        parts.push(name.to_lowercase());
+    }
+    // A reset (`\e[0m`) removes every modifier; don't list them all
+    if style.sub_modifier == Modifier::all() {
+        parts.push("-all".to_string());
+    } else {
+        for (name, _) in style.sub_modifier.iter_names() {
+            parts.push(format!("-{}", name.to_lowercase()));
+        }
+    }
+    if parts.is_empty() {
+        "(default)".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
@@ -0,0 +1,76 @@
+use crate::config::ColorConfig;
+use crate::model::BlameOverview;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::Paragraph,
+};
+
+/// Width of the ownership band next to the diff
+pub const BLAME_BAND_WIDTH: u16 = 1;
+
+/// Legend entries shown before the rest are summarized as "+N"
+const MAX_LEGEND_AUTHORS: usize = 4;
+
+/// Distinct color per author rank, cycling for large author counts
+fn author_color(author: usize, colors: &ColorConfig) -> Color {
+    let palette = [
+        colors.accent,
+        colors.success,
+        colors.warning,
+        colors.info,
+        colors.error,
+        Color::Indexed(5),
+    ];
+    palette[author % palette.len()]
+}
+
+/// Render the ownership band into `band_area` and an author legend on the bottom row of `diff_area`
+pub fn render(
+    frame: &mut Frame,
+    band_area: Rect,
+    diff_area: Rect,
+    overview: &BlameOverview,
+    colors: &ColorConfig,
+) {
+    let bands = overview.bands(band_area.height as usize);
+    let lines: Vec<Line> = bands
+        .into_iter()
+        .map(|band| match band {
+            Some(author) => Line::styled("█", Style::default().fg(author_color(author, colors))),
+            None => Line::from(" "),
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(lines), band_area);
+
+    let mut spans = Vec::new();
+    for (i, author) in overview.authors.iter().enumerate().take(MAX_LEGEND_AUTHORS) {
+        spans.push(Span::styled(
+            "█ ",
+            Style::default().fg(author_color(i, colors)),
+        ));
+        spans.push(Span::raw(format!(
+            "{} {}%  ",
+            author.name,
+            overview.percent(i)
+        )));
+    }
+    if overview.authors.len() > MAX_LEGEND_AUTHORS {
+        spans.push(Span::styled(
+            format!("+{} ", overview.authors.len() - MAX_LEGEND_AUTHORS),
+            Style::default().fg(colors.text_muted),
+        ));
+    }
+
+    let legend = Line::from(spans);
+    let width = (legend.width() as u16).min(diff_area.width);
+    let legend_area = Rect {
+        x: diff_area.right().saturating_sub(width),
+        y: diff_area.bottom().saturating_sub(1),
+        width,
+        height: 1,
+    };
+    frame.render_widget(Paragraph::new(legend), legend_area);
+}
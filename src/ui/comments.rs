@@ -0,0 +1,57 @@
+use crate::config::ColorConfig;
+use crate::model::CommentThread;
+use crate::ui::layout;
+use ratatui::{
+    Frame,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+/// Render the review comments on a hunk, oldest first
+pub fn render(frame: &mut Frame, thread: &CommentThread, colors: &ColorConfig) {
+    let area = layout::centered_rect(70, 60, frame.area());
+    frame.render_widget(Clear, area);
+
+    let author = Style::default()
+        .add_modifier(Modifier::BOLD)
+        .fg(colors.accent);
+    let muted = Style::default().fg(colors.text_muted);
+
+    let mut lines = Vec::new();
+    for comment in &thread.comments {
+        lines.push(Line::from(if comment.draft {
+            vec![
+                Span::styled("Your draft", author),
+                Span::styled(format!("  line {}, not submitted", comment.line), muted),
+            ]
+        } else {
+            vec![
+                Span::styled(comment.author.clone(), author),
+                Span::styled(format!("  line {}, {}", comment.line, comment.date), muted),
+            ]
+        }));
+        lines.extend(comment.body.lines().map(|line| Line::raw(line.to_string())));
+        lines.push(Line::from(""));
+    }
+    lines.push(Line::styled(
+        "j/k scroll \u{b7} d drop drafts \u{b7} i or Esc to close",
+        muted,
+    ));
+
+    let plural = if thread.comments.len() == 1 { "" } else { "s" };
+    let popup = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .scroll((thread.scroll, 0))
+        .block(
+            Block::default()
+                .title(format!(
+                    " {} comment{plural} on {} ",
+                    thread.comments.len(),
+                    thread.title
+                ))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(colors.accent)),
+        );
+    frame.render_widget(popup, area);
+}
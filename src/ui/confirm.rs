@@ -0,0 +1,39 @@
+use crate::config::ColorConfig;
+use crate::model::Confirmation;
+use crate::ui::layout;
+use ratatui::{
+    Frame,
+    layout::Alignment,
+    style::Style,
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+/// Render a yes/no confirmation popup
+pub fn render(frame: &mut Frame, confirmation: &Confirmation, colors: &ColorConfig) {
+    let area = layout::centered_fixed(60, 7, frame.area());
+    frame.render_widget(Clear, area);
+
+    let lines = vec![
+        Line::from(""),
+        Line::styled(
+            confirmation.message.as_str(),
+            Style::default().fg(colors.text),
+        ),
+        Line::from(""),
+        Line::styled(
+            "y to confirm \u{b7} n or Esc to cancel",
+            Style::default().fg(colors.text_muted),
+        ),
+    ];
+    let popup = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true })
+        .block(
+            Block::default()
+                .title(format!(" {} ", confirmation.title))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(colors.error)),
+        );
+    frame.render_widget(popup, area);
+}
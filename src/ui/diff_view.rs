@@ -1,61 +1,104 @@
 use crate::config::ColorConfig;
-use crate::model::{CommitInfo, DiffState, STICKY_FILE_HEADER_HEIGHT};
+use crate::i18n::{tr, tr_format};
+use crate::model::{DiffState, STICKY_FILE_HEADER_HEIGHT};
+use crate::view::View;
 use ratatui::{
     Frame,
-    layout::Rect,
-    style::Style,
-    text::{Line, Text},
+    layout::{Alignment, Constraint, Flex, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span, Text},
     widgets::{Block, Borders, Clear, Paragraph, Wrap},
 };
+use std::path::Path;
 
-pub fn render(
-    frame: &mut Frame,
-    area: Rect,
-    state: &DiffState,
-    commit: Option<&CommitInfo>,
-    colors: &ColorConfig,
-) {
-    let title = build_title(state, commit);
-
-    let hunk_info = if !state.hunk_positions.is_empty() {
-        format!(
-            " Hunk {}/{} ",
-            state.current_hunk + 1,
-            state.hunk_positions.len()
-        )
-    } else {
-        String::new()
-    };
+/// Width of the line number gutter left of the diff: two 5-digit columns
+pub const LINE_NUMBER_GUTTER_WIDTH: u16 = 12;
 
-    let block = Block::default()
+pub fn render(frame: &mut Frame, area: Rect, state: &DiffState, view: &View, colors: &ColorConfig) {
+    let mut block = Block::default()
         .borders(Borders::NONE)
-        .title(title)
-        .title_bottom(hunk_info);
+        .title(view.diff_title.as_str())
+        .title_bottom(view.diff_status.as_str());
+
+    if let Some(side_title) = &view.diff_side_title {
+        let side_title = Line::styled(side_title, Style::default().fg(colors.text_muted));
+        block = block.title(side_title.right_aligned());
+    }
 
     let inner_area = block.inner(area);
+    frame.render_widget(block, area);
+
+    // Line numbers take the left edge; the rest is laid out as without them
+    let gutter = !state.line_numbers.is_empty();
+    let (gutter_area, inner_area) = if gutter {
+        let [gutter_area, text_area] = Layout::horizontal([
+            Constraint::Length(LINE_NUMBER_GUTTER_WIDTH),
+            Constraint::Min(1),
+        ])
+        .areas(inner_area);
+        (Some(gutter_area), text_area)
+    } else {
+        (None, inner_area)
+    };
 
     // Check if we need sticky headers
     let sticky_file_header = state.sticky_file_header();
     let sticky_hunk_header = state.sticky_hunk_header();
 
-    // Convert logical scroll offset to visual offset accounting for wrapped lines
-    let visual_offset = visual_scroll_offset(
+    // Only the lines on screen are built, so the cost of a frame doesn't grow
+    // with the size of the diff. Unwrapped, each line takes one row.
+    let wrap = state.wrap && state.x_offset == 0;
+    let width = if wrap { inner_area.width as usize } else { 0 };
+    let window = visible_window(
         &state.content,
         state.scroll_offset,
-        inner_area.width as usize,
+        width,
+        inner_area.height as usize,
     );
+    let lines: Vec<Line> = window
+        .clone()
+        .map(|i| display_line(state, i, colors))
+        .collect();
 
-    let paragraph = Paragraph::new(state.content.clone())
-        .block(block)
-        .wrap(Wrap { trim: false })
-        .scroll((visual_offset as u16, 0));
+    if let Some(gutter_area) = gutter_area {
+        // Under the sticky headers the numbers would belong to hidden lines
+        let sticky_rows = state.sticky_rows();
+        let style = Style::default().fg(colors.text_muted);
+        let numbers: Vec<Line> = window
+            .clone()
+            .zip(&lines)
+            .flat_map(|(i, line)| {
+                let (old, new) = state.line_numbers.get(i).copied().unwrap_or_default();
+                let number = |n: Option<usize>| n.map_or_else(String::new, |n| n.to_string());
+                let first = Line::styled(format!("{:>5} {:>5} ", number(old), number(new)), style);
+                let rows = visual_line_count(line, width);
+                std::iter::once(first).chain(std::iter::repeat_n(Line::default(), rows - 1))
+            })
+            .enumerate()
+            .map(|(row, line)| {
+                if row < sticky_rows {
+                    Line::default()
+                } else {
+                    line
+                }
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(numbers), gutter_area);
+    }
 
-    frame.render_widget(paragraph, area);
+    let paragraph = Paragraph::new(lines);
+    let paragraph = if wrap {
+        paragraph.wrap(Wrap { trim: false })
+    } else {
+        paragraph.scroll((0, state.x_offset.min(u16::MAX as usize) as u16))
+    };
+
+    frame.render_widget(paragraph, inner_area);
 
     // Render sticky file header if needed (file name + divider = 2 lines)
     if let Some(header_pos) = sticky_file_header {
         let line_indices = [header_pos, header_pos + 1];
-        render_sticky_header(frame, &state.content, &line_indices, inner_area, 0);
+        render_sticky_header(frame, state, &line_indices, inner_area, 0, colors);
     }
 
     // Render sticky hunk header if needed (box top + marker + box bottom = 3 lines)
@@ -66,7 +109,34 @@ pub fn render(
             0
         };
         let line_indices = [hunk_pos - 1, hunk_pos, hunk_pos + 1];
-        render_sticky_header(frame, &state.content, &line_indices, inner_area, y_offset);
+        render_sticky_header(frame, state, &line_indices, inner_area, y_offset, colors);
+    }
+
+    // Point at the current hunk, the one J/K move and +/-/e act on
+    if let Some(marker) = state.current_hunk_marker() {
+        let row = if sticky_hunk_header == Some(marker) {
+            let file_header_rows = if sticky_file_header.is_some() {
+                STICKY_FILE_HEADER_HEIGHT
+            } else {
+                0
+            };
+            Some(file_header_rows + 1)
+        } else {
+            window.contains(&marker).then(|| {
+                state.content.lines[window.start..marker]
+                    .iter()
+                    .map(|line| visual_line_count(line, width))
+                    .sum()
+            })
+        };
+        if let Some(row) = row.filter(|&row| row < inner_area.height as usize) {
+            frame.buffer_mut().set_string(
+                inner_area.x,
+                inner_area.y + row as u16,
+                "\u{25b6}",
+                Style::default().fg(colors.accent),
+            );
+        }
     }
 
     // Draw scrollbar indicator if content is longer than view
@@ -91,66 +161,137 @@ pub fn render(
     }
 }
 
-fn build_title(state: &DiffState, commit: Option<&CommitInfo>) -> String {
-    if let Some(c) = commit {
-        let msg = truncate_message(&c.message, 50);
-        return format!(" {}: {} ", c.oid, msg);
-    }
-
-    let staged_label = if state.showing_staged {
-        "staged"
-    } else {
-        "unstaged"
+/// Empty state shown in place of the diff once everything is committed.
+/// With `focus_file` (`--file`), only that file is known to be clean.
+pub fn render_clean(
+    frame: &mut Frame,
+    area: Rect,
+    focus_file: Option<&Path>,
+    colors: &ColorConfig,
+) {
+    let headline = match focus_file {
+        Some(path) => format!(
+            "\u{2714} {}",
+            tr_format("No changes to {}", &[&path.display().to_string()])
+        ),
+        None => format!("\u{2714} {}", tr("Working tree clean")),
     };
-    let toggle_hint = if state.has_both { " [s to toggle]" } else { "" };
+    let lines = vec![
+        Line::styled(
+            headline,
+            Style::default()
+                .fg(colors.success)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Line::from(""),
+        Line::styled(
+            tr("Nothing left to review. Press [ to browse history."),
+            Style::default().fg(colors.text_muted),
+        ),
+    ];
+    let [middle] = Layout::vertical([Constraint::Length(lines.len() as u16)])
+        .flex(Flex::Center)
+        .areas(area);
+    frame.render_widget(Paragraph::new(lines).alignment(Alignment::Center), middle);
+}
 
-    if state.showing_staged || state.has_both {
-        format!(" Diff ({staged_label}){toggle_hint} ")
-    } else {
-        " Diff ".to_string()
+/// Calculate how many visual rows a single logical line occupies when wrapped
+/// at `width` (0 for not wrapped), the way the paragraph wraps it
+fn visual_line_count(line: &Line, width: usize) -> usize {
+    if width == 0 {
+        return 1;
     }
+    Paragraph::new(line.clone())
+        .wrap(Wrap { trim: false })
+        .line_count(width.min(u16::MAX as usize) as u16)
+        .max(1)
 }
 
-fn truncate_message(msg: &str, max_len: usize) -> String {
-    if msg.len() > max_len {
-        format!("{}...", &msg[..max_len - 3])
-    } else {
-        msg.to_string()
+/// The lines from `start` that fill `height` rows of `width` columns
+fn visible_window(
+    content: &Text,
+    start: usize,
+    width: usize,
+    height: usize,
+) -> std::ops::Range<usize> {
+    let start = start.min(content.lines.len());
+    let mut end = start;
+    let mut rows = 0;
+    while end < content.lines.len() && rows < height {
+        rows += visual_line_count(&content.lines[end], width);
+        end += 1;
     }
+    start..end
 }
 
-/// Calculate how many visual rows a single logical line occupies when wrapped.
-fn visual_line_count(line: &Line, width: usize) -> usize {
-    if width == 0 {
-        return 1;
+/// Line `i` of the diff, with search hits on it reversed and the current one in
+/// the warning colour
+fn display_line(state: &DiffState, i: usize, colors: &ColorConfig) -> Line<'static> {
+    let mut line = state.content.lines[i].clone();
+    let Some(search) = &state.search else {
+        return line;
+    };
+    // Hits are in diff order
+    let first = search.matches.partition_point(|m| m.line < i);
+    for (n, m) in search.matches.iter().enumerate().skip(first) {
+        if m.line != i {
+            break;
+        }
+        let mut style = Style::default().add_modifier(Modifier::REVERSED);
+        if search.current == Some(n) {
+            style = style.fg(colors.warning);
+        }
+        restyle(&mut line, m.start..m.end, style);
     }
-    let len: usize = line.spans.iter().map(|s| s.content.chars().count()).sum();
-    if len == 0 { 1 } else { len.div_ceil(width) }
+    line
 }
 
-/// Calculate the visual scroll offset by summing up visual rows for all lines
-/// up to the logical scroll offset.
-fn visual_scroll_offset(content: &Text, logical_offset: usize, width: usize) -> usize {
-    content
-        .lines
-        .iter()
-        .take(logical_offset)
-        .map(|line| visual_line_count(line, width))
-        .sum()
+/// Patch `style` onto the chars of `line` in `range`, splitting spans as needed
+fn restyle(line: &mut Line<'static>, range: std::ops::Range<usize>, style: Style) {
+    let mut spans = Vec::with_capacity(line.spans.len() + 2);
+    let mut pos = 0;
+    for span in line.spans.drain(..) {
+        let len = span.content.chars().count();
+        let from = range.start.clamp(pos, pos + len) - pos;
+        let to = range.end.clamp(pos, pos + len) - pos;
+        pos += len;
+        if from == to {
+            spans.push(span);
+            continue;
+        }
+        let chars: Vec<char> = span.content.chars().collect();
+        for (part, patch) in [(0..from, false), (from..to, true), (to..len, false)] {
+            if part.is_empty() {
+                continue;
+            }
+            let part_style = if patch {
+                span.style.patch(style)
+            } else {
+                span.style
+            };
+            spans.push(Span::styled(
+                chars[part].iter().collect::<String>(),
+                part_style,
+            ));
+        }
+    }
+    line.spans = spans;
 }
 
 /// Render a sticky header by extracting lines at the given indices and displaying them
 /// at the specified y_offset within inner_area.
 fn render_sticky_header(
     frame: &mut Frame,
-    content: &Text<'static>,
+    state: &DiffState,
     line_indices: &[usize],
     inner_area: Rect,
     y_offset: u16,
+    colors: &ColorConfig,
 ) {
     let sticky_lines: Vec<_> = line_indices
         .iter()
-        .filter_map(|&idx| content.lines.get(idx).cloned())
+        .filter(|&&idx| idx < state.content.lines.len())
+        .map(|&idx| display_line(state, idx, colors))
         .collect();
 
     if sticky_lines.is_empty() {
@@ -1,5 +1,7 @@
 use crate::config::ColorConfig;
-use crate::model::{CommitInfo, FileStatus, FileTree, HorizontalItem};
+use crate::git::churn::Churn;
+use crate::i18n::tr;
+use crate::model::{FileStatus, FileTree, HorizontalItem, StatusSection};
 use ratatui::{
     Frame,
     layout::Rect,
@@ -21,16 +23,59 @@ fn status_icon_and_color(
         Some(FileStatus::Untracked) => ("? ", colors.text_muted),
         Some(FileStatus::Staged) => ("S ", colors.success),
         Some(FileStatus::StagedModified) => ("± ", colors.warning),
+        Some(FileStatus::Conflicted) => ("U ", colors.error),
+        Some(FileStatus::Submodule) => ("\u{25c6} ", colors.info),
         None => ("  ", ratatui::style::Color::Reset),
     }
 }
 
+/// Follows the names of files .gitattributes calls generated
+pub const GENERATED_TAG: &str = " (generated)";
+
+fn generated_tag(colors: &ColorConfig) -> Span<'static> {
+    Span::styled(GENERATED_TAG, Style::default().fg(colors.text_muted))
+}
+
+/// How often a file changed before (↻) and how many of those were fixes (✗),
+/// for files that changed more than once
+pub fn churn_tag(churn: Option<Churn>) -> Option<String> {
+    let churn = churn.filter(|c| c.commits > 1)?;
+    Some(match churn.fixes {
+        0 => format!(" \u{21bb}{}", churn.commits),
+        fixes => format!(" \u{21bb}{} \u{2717}{fixes}", churn.commits),
+    })
+}
+
+/// Fixes make a file's churn a warning
+fn churn_span(churn: Option<Churn>, colors: &ColorConfig) -> Option<Span<'static>> {
+    let color = match churn?.fixes {
+        0 => colors.text_muted,
+        _ => colors.warning,
+    };
+    Some(Span::styled(churn_tag(churn)?, Style::default().fg(color)))
+}
+
+/// Follows a file's name with its CODEOWNERS owners
+fn owners_tag(owners: &str, colors: &ColorConfig) -> Span<'static> {
+    Span::styled(format!(" {owners}"), Style::default().fg(colors.info))
+}
+
+/// Header color of a status section, matching its files' icons
+fn section_color(section: StatusSection, colors: &ColorConfig) -> ratatui::style::Color {
+    match section {
+        StatusSection::Staged => colors.success,
+        StatusSection::Unstaged => colors.warning,
+        StatusSection::Untracked => colors.text_muted,
+        StatusSection::Conflicted => colors.error,
+    }
+}
+
 pub fn render(
     frame: &mut Frame,
     area: Rect,
     tree: &FileTree,
     colors: &ColorConfig,
-    commit: Option<&CommitInfo>,
+    title: Option<&str>,
 ) {
     let visible = tree.visible_items();
     let items: Vec<ListItem> = visible
@@ -42,18 +87,26 @@ pub fn render(
             let mut spans = vec![Span::raw(indent)];
 
             if node.is_dir {
+                let color = node
+                    .section
+                    .map_or(colors.accent, |section| section_color(section, colors));
                 let icon = if node.expanded { "▼ " } else { "▶ " };
-                spans.push(Span::styled(icon, Style::default().fg(colors.accent)));
+                spans.push(Span::styled(icon, Style::default().fg(color)));
                 spans.push(Span::styled(
                     node.name.as_str(),
-                    Style::default()
-                        .fg(colors.accent)
-                        .add_modifier(Modifier::BOLD),
+                    Style::default().fg(color).add_modifier(Modifier::BOLD),
                 ));
             } else {
                 let (icon, icon_color) = status_icon_and_color(node.status, colors);
                 spans.push(Span::styled(icon, Style::default().fg(icon_color)));
                 spans.push(Span::raw(node.name.as_str()));
+                if node.generated {
+                    spans.push(generated_tag(colors));
+                }
+                spans.extend(churn_span(node.churn, colors));
+                if let Some(owners) = &node.owners {
+                    spans.push(owners_tag(owners, colors));
+                }
             }
 
             let mut item = ListItem::new(Line::from(spans));
@@ -71,9 +124,9 @@ pub fn render(
         })
         .collect();
 
-    let title = match commit {
-        Some(c) => format!(" {} ", c.oid),
-        None => " Changes ".to_string(),
+    let title = match title {
+        Some(t) => format!(" {t} "),
+        None => format!(" {} ", tr("Changes")),
     };
 
     let list = List::new(items).block(Block::default().borders(Borders::RIGHT).title(title));
@@ -86,7 +139,7 @@ pub fn render_horizontal(
     area: Rect,
     tree: &FileTree,
     colors: &ColorConfig,
-    commit: Option<&CommitInfo>,
+    title: Option<&str>,
 ) {
     let rows = tree.get_horizontal_rows();
 
@@ -109,9 +162,9 @@ pub fn render_horizontal(
         })
         .collect();
 
-    let title = match commit {
-        Some(c) => format!(" {} ", c.oid),
-        None => " Files ".to_string(),
+    let title = match title {
+        Some(t) => format!(" {t} "),
+        None => format!(" {} ", tr("Files")),
     };
 
     let paragraph =
@@ -150,6 +203,11 @@ fn render_horizontal_item(item: &HorizontalItem, colors: &ColorConfig) -> Vec<Sp
             .fg(colors.accent)
             .add_modifier(Modifier::BOLD)
             .add_modifier(Modifier::UNDERLINED)
+    } else if let Some(section) = item.section {
+        // Status section header: its own color, not dimmed
+        Style::default()
+            .fg(section_color(section, colors))
+            .add_modifier(Modifier::BOLD)
     } else if item.is_dir {
         // Directory not on path: accent color, dimmed
         Style::default()
@@ -161,6 +219,13 @@ fn render_horizontal_item(item: &HorizontalItem, colors: &ColorConfig) -> Vec<Sp
     };
 
     spans.push(Span::styled(name, style));
+    if item.generated {
+        spans.push(generated_tag(colors));
+    }
+    spans.extend(churn_span(item.churn, colors));
+    if let Some(owners) = &item.owners {
+        spans.push(owners_tag(owners, colors));
+    }
 
     spans
 }
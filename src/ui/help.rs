@@ -1,29 +1,96 @@
 use crate::config::ColorConfig;
-use crate::event::{KEYBINDINGS, KeyCategory};
+use crate::event::{KEYBINDINGS, ModKey};
+use crate::i18n::{tr, tr_format};
+use crate::ui::layout;
+use crate::view::{ModeKind, View};
 use ratatui::{
     Frame,
-    layout::{Constraint, Direction, Layout, Rect},
-    style::{Modifier, Style},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Clear, Paragraph},
 };
 
-pub fn render_hint_line(frame: &mut Frame, area: Rect, colors: &ColorConfig) {
-    let hint = Paragraph::new(" Press ? for help").style(Style::default().fg(colors.text));
-    frame.render_widget(hint, area);
+/// Render the hint line, or a notice in its place, after the mode badges. The
+/// macro being recorded and the sync state with upstream show too.
+pub fn render_hint_line(frame: &mut Frame, area: Rect, colors: &ColorConfig, view: &View) {
+    // Sync state with the upstream branch takes the right end
+    let mut area = area;
+    if let Some((ahead, behind)) = view.ahead_behind {
+        let color = if ahead + behind == 0 {
+            colors.text_muted
+        } else {
+            colors.info
+        };
+        let sync = Line::from(Span::styled(
+            format!(" \u{2191}{ahead} \u{2193}{behind} "),
+            Style::default().fg(color),
+        ));
+        let [rest, right] =
+            Layout::horizontal([Constraint::Min(0), Constraint::Length(sync.width() as u16)])
+                .areas(area);
+        frame.render_widget(Paragraph::new(sync), right);
+        area = rest;
+    }
+
+    let mut spans = Vec::new();
+    for mode in &view.modes {
+        spans.push(Span::styled(
+            format!(" {} ", mode.label),
+            Style::default()
+                .fg(Color::Black)
+                .bg(mode_color(mode.kind, colors))
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(register) = view.recording {
+        spans.push(Span::styled(
+            format!(
+                " \u{25cf} {}",
+                tr_format("recording @{} (Q to stop)", &[&register.to_string()])
+            ),
+            Style::default().fg(colors.error),
+        ));
+    }
+    spans.push(match &view.message {
+        Some(msg) => Span::styled(format!(" {msg}"), Style::default().fg(colors.warning)),
+        None => Span::styled(
+            format!(" {}", tr("Press ? for help")),
+            Style::default().fg(colors.text),
+        ),
+    });
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
+/// Each mode gets its own color so they're hard to mix up
+fn mode_color(kind: ModeKind, colors: &ColorConfig) -> Color {
+    match kind {
+        ModeKind::Worktree => colors.success,
+        ModeKind::Commit | ModeKind::FileHistory => colors.info,
+        ModeKind::Range | ModeKind::Pair => colors.accent,
+        ModeKind::Compare | ModeKind::NotSaving => colors.warning,
+        ModeKind::Stash => colors.annotation,
+        ModeKind::Blame => colors.error,
+    }
 }
 
-pub fn render_help_popup(frame: &mut Frame, colors: &ColorConfig) {
-    let area = centered_rect(60, 70, frame.area());
+/// Render the shortcuts popup. `plugin_keys` are the (keys, description) pairs
+/// bound by plugins, listed after the built-in keys.
+pub fn render_help_popup(
+    frame: &mut Frame,
+    colors: &ColorConfig,
+    plugin_keys: &[(String, String)],
+) {
+    let area = layout::centered_rect(60, 70, frame.area());
 
     // Clear the area behind the popup
     frame.render_widget(Clear, area);
 
-    let content = build_help_content(colors);
+    let content = build_help_content(colors, plugin_keys);
 
     let popup = Paragraph::new(content).block(
         Block::default()
-            .title(" Keyboard Shortcuts ")
+            .title(format!(" {} ", tr("Keyboard Shortcuts")))
             .borders(Borders::ALL)
             .border_style(Style::default().fg(colors.accent)),
     );
@@ -31,10 +98,29 @@ pub fn render_help_popup(frame: &mut Frame, colors: &ColorConfig) {
     frame.render_widget(popup, area);
 }
 
-fn build_help_content(colors: &ColorConfig) -> Vec<Line<'static>> {
-    let max_key_width = KEYBINDINGS
+fn build_help_content(
+    colors: &ColorConfig,
+    plugin_keys: &[(String, String)],
+) -> Vec<Line<'static>> {
+    // (category, keys, description) rows, plugins last
+    let rows: Vec<(&'static str, String, String)> = KEYBINDINGS
         .iter()
-        .map(|b| b.keys.chars().count())
+        .map(|b| {
+            (
+                tr(b.category.name()),
+                b.keys_display(),
+                tr(b.description).to_string(),
+            )
+        })
+        .chain(
+            plugin_keys
+                .iter()
+                .map(|(keys, description)| (tr("Plugins"), keys.clone(), description.clone())),
+        )
+        .collect();
+    let max_key_width = rows
+        .iter()
+        .map(|(_, k, _)| k.chars().count())
         .max()
         .unwrap_or(0);
     let key_col_width = max_key_width + 3;
@@ -46,55 +132,44 @@ fn build_help_content(colors: &ColorConfig) -> Vec<Line<'static>> {
     let key_style = Style::default().fg(colors.accent);
 
     let mut lines = vec![Line::from("")];
-    let mut current_category: Option<KeyCategory> = None;
+    let mut current_category: Option<&str> = None;
 
-    for binding in KEYBINDINGS {
-        if current_category != Some(binding.category) {
+    for (category, keys, description) in rows {
+        if current_category != Some(category) {
             if current_category.is_some() {
                 lines.push(Line::from(""));
             }
             lines.push(Line::from(vec![
                 Span::raw("  "),
-                Span::styled(binding.category.name(), category_style),
+                Span::styled(category, category_style),
             ]));
-            current_category = Some(binding.category);
+            current_category = Some(category);
         }
 
-        let padding = " ".repeat(key_col_width.saturating_sub(binding.keys.chars().count()));
+        let padding = " ".repeat(key_col_width.saturating_sub(keys.chars().count()));
         lines.push(Line::from(vec![
             Span::raw("    "),
-            Span::styled(binding.keys, key_style),
+            Span::styled(keys, key_style),
             Span::raw(padding),
-            Span::raw(binding.description),
+            Span::raw(description),
         ]));
     }
 
     lines.push(Line::from(""));
     lines.push(Line::from(Span::styled(
-        "  Press ?, q, or Esc to close",
+        format!(
+            "  {}",
+            tr_format(
+                "{} keys also work when the terminal sends them as an Esc prefix",
+                &[ModKey::Alt.label()]
+            )
+        ),
+        Style::default().fg(colors.text_muted),
+    )));
+    lines.push(Line::from(Span::styled(
+        format!("  {}", tr("Press ?, q, or Esc to close")),
         Style::default().fg(colors.text),
     )));
 
     lines
 }
-
-/// Create a centered rect of given percentage of parent
-fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
-    let popup_layout = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Percentage((100 - percent_y) / 2),
-            Constraint::Percentage(percent_y),
-            Constraint::Percentage((100 - percent_y) / 2),
-        ])
-        .split(area);
-
-    Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage((100 - percent_x) / 2),
-            Constraint::Percentage(percent_x),
-            Constraint::Percentage((100 - percent_x) / 2),
-        ])
-        .split(popup_layout[1])[1]
-}
@@ -0,0 +1,83 @@
+use crate::config::ColorConfig;
+use crate::i18n::{tr, tr_format};
+use crate::model::HistoryList;
+use crate::ui::layout;
+use ratatui::{
+    Frame,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState},
+};
+
+/// Author names are cut to this many chars to keep the subject column aligned
+const AUTHOR_WIDTH: usize = 16;
+
+/// Render the commit log pane. `viewing` is the full id of the commit being shown, if any.
+pub fn render(frame: &mut Frame, list: &HistoryList, viewing: Option<&str>, colors: &ColorConfig) {
+    let area = layout::centered_rect(80, 80, frame.area());
+    frame.render_widget(Clear, area);
+
+    let items: Vec<ListItem> = list
+        .entries
+        .iter()
+        .map(|entry| {
+            let marker = if viewing == Some(entry.commit.oid_full.as_str()) {
+                "\u{25b6} "
+            } else {
+                "  "
+            };
+            let author: String = entry.author.chars().take(AUTHOR_WIDTH).collect();
+            ListItem::new(Line::from(vec![
+                Span::styled(marker, Style::default().fg(colors.accent)),
+                Span::styled(
+                    format!("{} ", entry.graph),
+                    Style::default().fg(colors.accent),
+                ),
+                Span::styled(
+                    format!("{} ", entry.commit.oid),
+                    Style::default().fg(colors.warning),
+                ),
+                Span::styled(
+                    format!("{} ", entry.date),
+                    Style::default().fg(colors.text_muted),
+                ),
+                Span::styled(
+                    format!("{author:<AUTHOR_WIDTH$} "),
+                    Style::default().fg(colors.info),
+                ),
+                Span::styled(
+                    entry.commit.message.as_str(),
+                    Style::default().fg(colors.text),
+                ),
+            ]))
+        })
+        .collect();
+
+    let mut title = match (&list.file, &list.search) {
+        (Some(file), _) => tr_format("History of {}", &[&file.display().to_string()]),
+        (None, Some(query)) => tr_format("Commits changing {}", &[&format!("{query:?}")]),
+        (None, None) if list.filter.is_some() => tr("Commits").to_string(),
+        (None, None) => tr("History").to_string(),
+    };
+    if let Some(filter) = &list.filter {
+        title += " ";
+        title += &tr_format("matching {}", &[&format!("{filter:?}")]);
+    }
+    let more = if list.complete { "" } else { "+" };
+    let block = Block::default()
+        .title(format!(" {title} ({}{more}) ", list.entries.len()))
+        .title_bottom(Line::styled(
+            format!(" {} ", tr("Enter view \u{b7} / filter \u{b7} Esc close")),
+            Style::default().fg(colors.text_muted),
+        ))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(colors.accent));
+
+    let list_widget = List::new(items).block(block).highlight_style(
+        Style::default()
+            .add_modifier(Modifier::REVERSED)
+            .add_modifier(Modifier::BOLD),
+    );
+    let mut state = ListState::default().with_selected(Some(list.selected));
+    frame.render_stateful_widget(list_widget, area, &mut state);
+}
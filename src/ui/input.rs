@@ -0,0 +1,76 @@
+use crate::config::ColorConfig;
+use crate::i18n::tr;
+use crate::model::{CommitDraft, Prompt};
+use crate::ui::layout;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+/// Render an active prompt in place of the hint line, with the terminal cursor in the input
+pub fn render_prompt(frame: &mut Frame, area: Rect, prompt: &Prompt, colors: &ColorConfig) {
+    let label = format!(" {}: ", tr(prompt.kind.label()));
+    let cursor_x = area.x + (label.chars().count() + prompt.input.cursor()) as u16;
+
+    let line = Line::from(vec![
+        Span::styled(label, Style::default().fg(colors.accent)),
+        Span::styled(prompt.input.text(), Style::default().fg(colors.text)),
+    ]);
+    frame.render_widget(Paragraph::new(line), area);
+    frame.set_cursor_position((cursor_x.min(area.right().saturating_sub(1)), area.y));
+}
+
+/// Render the commit message modal, scrolled so the cursor stays visible
+pub fn render_commit_modal(
+    frame: &mut Frame,
+    draft: &CommitDraft,
+    committing: bool,
+    colors: &ColorConfig,
+) {
+    let area = layout::centered_rect(70, 50, frame.area());
+    frame.render_widget(Clear, area);
+
+    let title = if draft.amend {
+        tr("Amend commit")
+    } else {
+        tr("Commit")
+    };
+    let footer = if committing {
+        tr("Committing...")
+    } else {
+        tr("Ctrl-s commit \u{b7} Esc cancel")
+    };
+    let block = Block::default()
+        .title(format!(" {title} "))
+        .title_bottom(Line::styled(
+            format!(" {footer} "),
+            Style::default().fg(colors.text_muted),
+        ))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(colors.accent));
+    let inner = block.inner(area);
+
+    let (row, col) = draft.message.cursor();
+    let scroll = row.saturating_sub(inner.height.saturating_sub(1) as usize);
+    let lines: Vec<Line> = draft
+        .message
+        .lines()
+        .iter()
+        .map(|line| Line::styled(line.as_str(), Style::default().fg(colors.text)))
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(block)
+            .scroll((scroll as u16, 0)),
+        area,
+    );
+
+    if !committing && inner.width > 0 {
+        let x = inner.x + (col as u16).min(inner.width - 1);
+        let y = inner.y + (row - scroll) as u16;
+        frame.set_cursor_position((x, y));
+    }
+}
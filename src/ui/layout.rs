@@ -1,6 +1,8 @@
 use crate::config::LayoutMode;
 use crate::model::FileTree;
+use crate::ui::file_tree::{GENERATED_TAG, churn_tag};
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use unicode_width::UnicodeWidthStr;
 
 const MIN_TREE_WIDTH: u16 = 20;
 const MAX_TREE_WIDTH: u16 = 50;
@@ -70,7 +72,14 @@ fn calculate_tree_width(file_tree: &FileTree, max_available: u16) -> u16 {
         .iter()
         .map(|node| {
             // Calculate display width: indent (2 chars per depth) + icon (2) + name
-            (node.depth as u16 * 2) + 2 + node.name.len() as u16
+            let tag = if node.generated {
+                GENERATED_TAG.width()
+            } else {
+                0
+            };
+            let owners = node.owners.as_ref().map_or(0, |owners| owners.width() + 1);
+            let churn = churn_tag(node.churn).map_or(0, |churn| churn.width());
+            (node.depth as u16 * 2) + 2 + (node.name.width() + tag + owners + churn) as u16
         })
         .max()
         .unwrap_or(MIN_TREE_WIDTH);
@@ -111,3 +120,36 @@ fn create_horizontal_areas(
         }
     }
 }
+
+/// Create a centered rect of given percentage of parent
+pub fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(popup_layout[1])[1]
+}
+
+/// Create a centered rect of a fixed size, clamped to the parent
+pub fn centered_fixed(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
@@ -1,20 +1,73 @@
+mod activity;
+mod ansi;
+mod blame;
+mod comments;
+mod confirm;
 mod diff_view;
 mod file_tree;
 mod help;
+mod history;
+mod input;
 mod layout;
+mod output;
+pub mod patch_view;
+mod stash;
 
 use crate::app::App;
-use crate::config::LayoutMode;
+use crate::config::{ColorConfig, LayoutMode};
+use crate::i18n::tr;
+use crate::model::FileTree;
+use crate::view::View;
 use ratatui::Frame;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::Style;
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph};
 
-pub fn render(frame: &mut Frame, app: &App) {
-    let areas = layout::create_layout_for_mode(
-        frame.area(),
+pub use blame::BLAME_BAND_WIDTH;
+pub use diff_view::LINE_NUMBER_GUTTER_WIDTH;
+
+/// Width `render` gives the diff and output panes on a terminal of `size`, before
+/// the blame band takes its share
+pub fn diff_pane_width(app: &App, size: (u16, u16)) -> u16 {
+    areas(app, Rect::new(0, 0, size.0, size.1)).diff.width
+}
+
+/// Tree, diff and hint areas for the app's current layout settings
+fn areas(app: &App, area: Rect) -> layout::Areas {
+    layout::create_layout_for_mode(
+        area,
         app.show_tree,
         &app.file_tree,
         app.config.layout.mode,
         app.config.layout.max_rows,
+    )
+}
+
+/// The first frame, drawn before the repository is read: the panes, empty,
+/// and a note that the status is loading
+pub fn render_loading(frame: &mut Frame, show_tree: bool, mode: LayoutMode) {
+    let colors = ColorConfig::default();
+    let tree = FileTree::from_commit_files(Vec::new());
+    let areas = layout::create_layout_for_mode(frame.area(), show_tree, &tree, mode, 0);
+    if show_tree {
+        file_tree::render(frame, areas.tree, &tree, &colors, None);
+    }
+    frame.render_widget(
+        Block::default().title(format!(" {} ", tr("Diff"))),
+        areas.diff,
     );
+    let note = Line::styled(
+        tr("Loading status\u{2026}"),
+        Style::default().fg(colors.text_muted),
+    );
+    frame.render_widget(Paragraph::new(note), areas.hint);
+}
+
+pub fn render(frame: &mut Frame, app: &App) {
+    let areas = areas(app, frame.area());
+
+    let view = View::of(app);
 
     if app.show_tree {
         match app.config.layout.mode {
@@ -24,7 +77,7 @@ pub fn render(frame: &mut Frame, app: &App) {
                     areas.tree,
                     &app.file_tree,
                     &app.config.colors,
-                    app.current_commit.as_ref(),
+                    view.tree_title.as_deref(),
                 );
             }
             LayoutMode::Horizontal => {
@@ -33,25 +86,111 @@ pub fn render(frame: &mut Frame, app: &App) {
                     areas.tree,
                     &app.file_tree,
                     &app.config.colors,
-                    app.current_commit.as_ref(),
+                    view.tree_title.as_deref(),
                 );
             }
         }
     }
 
-    diff_view::render(
-        frame,
-        areas.diff,
-        &app.diff_state,
-        app.current_commit.as_ref(),
-        &app.config.colors,
-    );
+    let (main_area, activity_area) = if app.show_activity {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(1),
+                Constraint::Length(activity::ACTIVITY_PANEL_HEIGHT),
+            ])
+            .split(areas.diff);
+        (chunks[0], Some(chunks[1]))
+    } else {
+        (areas.diff, None)
+    };
+
+    let (main_area, output_area) = match &app.output {
+        Some(_) if app.show_output => {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Min(1),
+                    Constraint::Length(app.config.output.height),
+                ])
+                .split(main_area);
+            (chunks[0], Some(chunks[1]))
+        }
+        _ => (main_area, None),
+    };
+
+    let (diff_area, blame_area) = if app.show_blame {
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(1), Constraint::Length(BLAME_BAND_WIDTH)])
+            .split(main_area);
+        (chunks[0], Some(chunks[1]))
+    } else {
+        (main_area, None)
+    };
+
+    if app.is_clean() {
+        diff_view::render_clean(
+            frame,
+            diff_area,
+            app.focus_file.as_deref(),
+            &app.config.colors,
+        );
+    } else {
+        diff_view::render(frame, diff_area, &app.diff_state, &view, &app.config.colors);
+    }
+
+    if let (Some(band_area), Some(overview)) = (blame_area, &app.blame) {
+        blame::render(frame, band_area, diff_area, overview, &app.config.colors);
+    }
+
+    if let Some(area) = activity_area {
+        activity::render(frame, area, &app.activity, &app.config.colors);
+    }
+
+    if let (Some(area), Some(pane)) = (output_area, &app.output) {
+        output::render(frame, area, pane, &app.config.colors);
+    }
 
     // Render hint line at bottom
-    help::render_hint_line(frame, areas.hint, &app.config.colors);
+    match &app.prompt {
+        Some(prompt) => input::render_prompt(frame, areas.hint, prompt, &app.config.colors),
+        None => help::render_hint_line(frame, areas.hint, &app.config.colors, &view),
+    }
+
+    if let Some(draft) = &app.commit_draft {
+        input::render_commit_modal(frame, draft, app.is_committing(), &app.config.colors);
+    }
+
+    if let Some(list) = &app.history_list {
+        let viewing = app.current_commit.as_ref().map(|c| c.oid_full.as_str());
+        history::render(frame, list, viewing, &app.config.colors);
+    }
+
+    if let Some(picker) = &app.stash_picker {
+        stash::render_picker(frame, picker, &app.config.colors);
+    }
+
+    if let Some(confirmation) = &app.confirmation {
+        confirm::render(frame, confirmation, &app.config.colors);
+    }
+
+    if let Some(line) = app.ansi_line {
+        ansi::render(frame, &app.diff_state, line, &app.config.colors);
+    }
+
+    if let Some(thread) = &app.comment_thread {
+        comments::render(frame, thread, &app.config.colors);
+    }
 
     // Render help popup on top if active
     if app.show_help {
-        help::render_help_popup(frame, &app.config.colors);
+        let plugin_keys: Vec<(String, String)> = app
+            .plugins
+            .bindings()
+            .iter()
+            .map(|b| (b.keys.clone(), b.description.clone()))
+            .collect();
+        help::render_help_popup(frame, &app.config.colors, &plugin_keys);
     }
 }
@@ -0,0 +1,47 @@
+use crate::config::ColorConfig;
+use crate::model::OutputPane;
+use ansi_to_tui::IntoText;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+};
+
+/// Render the tail of the output command (or the part scrolled to with `{` / `}`)
+pub fn render(frame: &mut Frame, area: Rect, pane: &OutputPane, colors: &ColorConfig) {
+    let status = match &pane.status {
+        Some(status) => Span::styled(format!(" {status} "), Style::default().fg(colors.warning)),
+        None => Span::styled(" running ", Style::default().fg(colors.success)),
+    };
+    let mut block = Block::default()
+        .borders(Borders::TOP)
+        .title(Line::from(vec![
+            Span::raw(format!(" $ {} ", pane.command)),
+            status,
+        ]));
+    if pane.scroll > 0 {
+        block = block.title(
+            Line::styled(
+                format!(" \u{2191}{} \u{b7} }} to follow ", pane.scroll),
+                Style::default().fg(colors.text_muted),
+            )
+            .right_aligned(),
+        );
+    }
+    let visible = block.inner(area).height as usize;
+
+    let lines = pane.lines();
+    let end = lines.len().saturating_sub(pane.scroll);
+    let start = end.saturating_sub(visible);
+    let text: Vec<Line> = lines
+        .range(start..end)
+        .flat_map(|line| match line.into_text() {
+            Ok(text) => text.lines,
+            Err(_) => vec![Line::raw(line.clone())],
+        })
+        .collect();
+
+    frame.render_widget(Paragraph::new(text).block(block), area);
+}
@@ -0,0 +1,500 @@
+use crate::config::ColorConfig;
+use crate::git::patch::{self, FilePatch, Hunk};
+use crate::i18n::tr;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use unicode_width::UnicodeWidthChar;
+
+/// Columns between tab stops, the same as delta's default
+const TAB_WIDTH: usize = 4;
+
+/// How kibitz's own renderer lays out a patch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchLayout {
+    /// Removed and added lines one after the other, like delta's default
+    Unified,
+    /// Old lines on the left, new ones on the right
+    SideBySide,
+}
+
+/// Kibitz's own rendering of a unified patch, used when delta isn't installed or
+/// the side-by-side view is on. The changed part of each edited line is
+/// highlighted. File headers and hunk markers are laid out like delta's so hunk
+/// navigation and sticky headers work the same.
+/// With `words`, the words that changed between a removed line and the line
+/// replacing it are highlighted, rather than the span from the first change
+/// to the last.
+pub fn render(
+    patch: &str,
+    width: usize,
+    layout: PatchLayout,
+    colors: &ColorConfig,
+    words: bool,
+) -> Text<'static> {
+    let mut lines = Vec::new();
+    for file in patch::parse(patch) {
+        if file.old_path.is_none() && file.new_path.is_none() {
+            continue;
+        }
+        if !lines.is_empty() {
+            lines.push(Line::default());
+        }
+        render_file(&file, width, layout, colors, words, &mut lines);
+    }
+    Text::from(lines)
+}
+
+/// Replace the tabs in `line` with spaces up to the next tab stop, so they take
+/// the columns the rest of the diff's layout counts them as
+pub fn expand_tabs(line: &mut Line<'static>) {
+    if !line.spans.iter().any(|span| span.content.contains('\t')) {
+        return;
+    }
+    let mut column = 0;
+    for span in &mut line.spans {
+        let mut expanded = String::with_capacity(span.content.len());
+        for c in span.content.chars() {
+            if c == '\t' {
+                let spaces = TAB_WIDTH - column % TAB_WIDTH;
+                expanded.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            } else {
+                expanded.push(c);
+                column += c.width().unwrap_or(0);
+            }
+        }
+        span.content = expanded.into();
+    }
+}
+
+fn render_file(
+    file: &FilePatch,
+    width: usize,
+    layout: PatchLayout,
+    colors: &ColorConfig,
+    words: bool,
+    lines: &mut Vec<Line<'static>>,
+) {
+    let display = |path: &Option<std::path::PathBuf>| {
+        path.as_deref()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let title = match (&file.old_path, &file.new_path) {
+        (None, _) => format!("added: {}", display(&file.new_path)),
+        (_, None) => format!("removed: {}", display(&file.old_path)),
+        (old, new) if old != new => {
+            format!("renamed: {} \u{2192} {}", display(old), display(new))
+        }
+        _ => format!("\u{394} {}", display(&file.new_path)),
+    };
+    let accent = Style::default().fg(colors.accent);
+    lines.push(Line::styled(title, accent.add_modifier(Modifier::BOLD)));
+    lines.push(Line::styled("\u{2500}".repeat(width), accent));
+
+    if file.hunks.is_empty() {
+        let note = if file.header.iter().any(|l| l.starts_with("Binary files")) {
+            tr("Binary file differs")
+        } else {
+            tr("No content changes")
+        };
+        lines.push(Line::styled(note, Style::default().fg(colors.text_muted)));
+    }
+
+    // Wide enough for the largest line number in the file
+    let last_line = file
+        .hunks
+        .iter()
+        .map(|h| h.old_start.max(h.new_start) + h.lines.len())
+        .max()
+        .unwrap_or(0);
+    let painter = Painter::new(width, last_line.to_string().len(), layout, colors, words);
+    for hunk in &file.hunks {
+        render_hunk_marker(hunk, accent, lines);
+        render_hunk_body(hunk, &painter, lines);
+    }
+}
+
+/// Delta-style box around `• 12: fn section`
+fn render_hunk_marker(hunk: &Hunk, style: Style, lines: &mut Vec<Line<'static>>) {
+    // Whatever follows the closing `@@` is the enclosing section, if git found one
+    let section = hunk
+        .header
+        .splitn(3, "@@")
+        .nth(2)
+        .map(str::trim)
+        .unwrap_or_default();
+    let label = if section.is_empty() {
+        format!("\u{2022} {}:", hunk.new_start)
+    } else {
+        format!("\u{2022} {}: {section}", hunk.new_start)
+    };
+    let rule = "\u{2500}".repeat(label.chars().count() + 1);
+    lines.push(Line::styled(format!("{rule}\u{2510}"), style));
+    lines.push(Line::styled(format!("{label} \u{2502}"), style));
+    lines.push(Line::styled(format!("{rule}\u{2518}"), style));
+}
+
+fn render_hunk_body(hunk: &Hunk, painter: &Painter, lines: &mut Vec<Line<'static>>) {
+    let mut old_line = hunk.old_start;
+    let mut new_line = hunk.new_start;
+    // The run of changed lines being collected, paired up once it ends
+    let mut removed: Vec<(usize, &str)> = Vec::new();
+    let mut added: Vec<(usize, &str)> = Vec::new();
+
+    for line in &hunk.lines {
+        let (origin, text) = line.split_at(line.chars().next().map_or(0, char::len_utf8));
+        match origin {
+            "-" => {
+                // A removal after additions starts a new run
+                if !added.is_empty() {
+                    painter.changes(&removed, &added, lines);
+                    removed.clear();
+                    added.clear();
+                }
+                removed.push((old_line, text));
+                old_line += 1;
+            }
+            "+" => {
+                added.push((new_line, text));
+                new_line += 1;
+            }
+            " " => {
+                painter.changes(&removed, &added, lines);
+                removed.clear();
+                added.clear();
+                lines.push(painter.context(old_line, new_line, text));
+                old_line += 1;
+                new_line += 1;
+            }
+            // "\ No newline at end of file"
+            _ => {}
+        }
+    }
+    painter.changes(&removed, &added, lines);
+}
+
+/// The char ranges of `old` and `new` between their common prefix and suffix.
+/// None when the lines have nothing in common, so highlighting would add nothing.
+fn changed_ranges(old: &str, new: &str) -> Option<((usize, usize), (usize, usize))> {
+    let old: Vec<char> = old.chars().collect();
+    let new: Vec<char> = new.chars().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if prefix + suffix == 0 {
+        return None;
+    }
+    Some(((prefix, old.len() - suffix), (prefix, new.len() - suffix)))
+}
+
+/// Chars `start..end` of a line
+type CharRange = (usize, usize);
+
+/// Longest line, in words, diffed word by word; longer ones are compared by
+/// their common prefix and suffix
+const MAX_WORD_DIFF_TOKENS: usize = 500;
+
+/// The char ranges of the words that differ between `old` and `new`, from the
+/// longest run of words they share in order
+fn changed_words(old_text: &str, new_text: &str) -> (Vec<CharRange>, Vec<CharRange>) {
+    let (old, new) = (tokens(old_text), tokens(new_text));
+    if old.len().max(new.len()) > MAX_WORD_DIFF_TOKENS {
+        return changed_ranges(old_text, new_text)
+            .map_or_else(Default::default, |(old, new)| (vec![old], vec![new]));
+    }
+    // common[i][j]: words shared by old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i].1 == new[j].1 {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    // Nothing in common: highlighting would add nothing
+    if common[0][0] == 0 {
+        return Default::default();
+    }
+    let (mut old_changed, mut new_changed) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i].1 == new[j].1 {
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            old_changed.push(old[i].0);
+            i += 1;
+        } else {
+            new_changed.push(new[j].0);
+            j += 1;
+        }
+    }
+    (old_changed, new_changed)
+}
+
+/// `text` split into words, runs of spaces and single other chars, each with
+/// its char range
+fn tokens(text: &str) -> Vec<((usize, usize), &str)> {
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    };
+    let mut tokens: Vec<((usize, usize), &str)> = Vec::new();
+    let mut start: Option<(usize, usize, u8)> = None;
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    for (n, &(byte, c)) in chars.iter().enumerate() {
+        let kind = class(c);
+        match start {
+            Some((_, _, k)) if k == kind && kind != 2 => {}
+            _ => {
+                if let Some((from_n, from_byte, _)) = start {
+                    tokens.push(((from_n, n), &text[from_byte..byte]));
+                }
+                start = Some((n, byte, kind));
+            }
+        }
+    }
+    if let Some((from_n, from_byte, _)) = start {
+        tokens.push(((from_n, chars.len()), &text[from_byte..]));
+    }
+    tokens
+}
+
+/// Turns a hunk's lines into styled rows for one layout
+struct Painter<'a> {
+    layout: PatchLayout,
+    colors: &'a ColorConfig,
+    /// Highlight changed words rather than the span around them
+    words: bool,
+    number_width: usize,
+    /// Columns for each side's text in the side-by-side layout
+    text_width: usize,
+}
+
+impl<'a> Painter<'a> {
+    fn new(
+        width: usize,
+        number_width: usize,
+        layout: PatchLayout,
+        colors: &'a ColorConfig,
+        words: bool,
+    ) -> Self {
+        // Each half: number, space, text; one column between them for the `│`
+        let half = width.saturating_sub(1) / 2;
+        Self {
+            layout,
+            colors,
+            words,
+            number_width,
+            text_width: half.saturating_sub(number_width + 1),
+        }
+    }
+
+    fn context(&self, old: usize, new: usize, text: &str) -> Line<'static> {
+        let style = Style::default().fg(self.colors.text);
+        match self.layout {
+            PatchLayout::Unified => self.unified(Some(old), Some(new), text, style, &[]),
+            PatchLayout::SideBySide => self.row(
+                self.cell(old, text, style, &[]),
+                self.cell(new, text, style, &[]),
+            ),
+        }
+    }
+
+    /// A run of removed lines followed by the lines that replaced them, with what
+    /// changed highlighted between the lines at the same position in each
+    fn changes(
+        &self,
+        removed: &[(usize, &str)],
+        added: &[(usize, &str)],
+        lines: &mut Vec<Line<'static>>,
+    ) {
+        let removed_style = Style::default().fg(self.colors.error);
+        let added_style = Style::default().fg(self.colors.success);
+        let changed = |i: usize| match (removed.get(i), added.get(i)) {
+            (Some((_, old)), Some((_, new))) if self.words => changed_words(old, new),
+            (Some((_, old)), Some((_, new))) => changed_ranges(old, new)
+                .map_or_else(Default::default, |(old, new)| (vec![old], vec![new])),
+            _ => Default::default(),
+        };
+
+        match self.layout {
+            PatchLayout::Unified => {
+                for (i, &(number, text)) in removed.iter().enumerate() {
+                    let ranges = changed(i).0;
+                    lines.push(self.unified(Some(number), None, text, removed_style, &ranges));
+                }
+                for (i, &(number, text)) in added.iter().enumerate() {
+                    let ranges = changed(i).1;
+                    lines.push(self.unified(None, Some(number), text, added_style, &ranges));
+                }
+            }
+            PatchLayout::SideBySide => {
+                for i in 0..removed.len().max(added.len()) {
+                    let (old_changed, new_changed) = changed(i);
+                    let left = match removed.get(i) {
+                        Some(&(number, text)) => {
+                            self.cell(number, text, removed_style, &old_changed)
+                        }
+                        None => self.blank(),
+                    };
+                    let right = match added.get(i) {
+                        Some(&(number, text)) => self.cell(number, text, added_style, &new_changed),
+                        None => self.blank(),
+                    };
+                    lines.push(self.row(left, right));
+                }
+            }
+        }
+    }
+
+    /// Old and new line numbers, then the text, wrapping if it's long
+    fn unified(
+        &self,
+        old: Option<usize>,
+        new: Option<usize>,
+        text: &str,
+        style: Style,
+        changed: &[(usize, usize)],
+    ) -> Line<'static> {
+        let number = |n: Option<usize>| n.map(|n| n.to_string()).unwrap_or_default();
+        let mut spans = vec![Span::styled(
+            format!(
+                "{:>width$} {:>width$} \u{2502}",
+                number(old),
+                number(new),
+                width = self.number_width
+            ),
+            Style::default().fg(self.colors.text_muted),
+        )];
+        spans.extend(self.text(text, style, changed, None));
+        Line::from(spans)
+    }
+
+    fn row(&self, left: Vec<Span<'static>>, right: Vec<Span<'static>>) -> Line<'static> {
+        let mut spans = left;
+        spans.push(Span::styled(
+            "\u{2502}",
+            Style::default().fg(self.colors.text_muted),
+        ));
+        spans.extend(right);
+        Line::from(spans)
+    }
+
+    fn blank(&self) -> Vec<Span<'static>> {
+        vec![Span::raw(
+            " ".repeat(self.number_width + 1 + self.text_width),
+        )]
+    }
+
+    /// One half of a side-by-side row, cut or padded to its width
+    fn cell(
+        &self,
+        number: usize,
+        text: &str,
+        style: Style,
+        changed: &[(usize, usize)],
+    ) -> Vec<Span<'static>> {
+        let mut spans = vec![Span::styled(
+            format!("{number:>width$} ", width = self.number_width),
+            Style::default().fg(self.colors.text_muted),
+        )];
+        spans.extend(self.text(text, style, changed, Some(self.text_width)));
+        spans
+    }
+
+    /// `text` in `style` with `changed` (char ranges of it) emphasized. With a
+    /// `width`, cut to fit with a `…` or padded to fill it.
+    fn text(
+        &self,
+        text: &str,
+        style: Style,
+        changed: &[(usize, usize)],
+        width: Option<usize>,
+    ) -> Vec<Span<'static>> {
+        // Each char with where it came from and its columns. Tabs expand to the
+        // next stop; CRs would move the cursor.
+        let mut chars: Vec<(usize, char, usize)> = Vec::new();
+        let mut columns = 0;
+        for (i, c) in text.trim_end_matches(['\r', '\n']).chars().enumerate() {
+            if c == '\t' {
+                let spaces = TAB_WIDTH - columns % TAB_WIDTH;
+                chars.extend(std::iter::repeat_n((i, ' ', 1), spaces));
+                columns += spaces;
+            } else {
+                let c_width = c.width().unwrap_or(0);
+                chars.push((i, c, c_width));
+                columns += c_width;
+            }
+        }
+        let overflow = width.is_some_and(|w| columns > w);
+        let visible = match width {
+            Some(width) if overflow => {
+                let mut used = 0;
+                let fit = chars
+                    .iter()
+                    .take_while(|&&(_, _, c_width)| {
+                        used += c_width;
+                        used < width
+                    })
+                    .count();
+                &chars[..fit]
+            }
+            _ => &chars[..],
+        };
+        let shown: usize = visible.iter().map(|&(_, _, c_width)| c_width).sum();
+
+        let emphasized = style.fg(Color::Black).bg(style.fg.unwrap_or(Color::Reset));
+        let mut spans = Vec::new();
+        let mut current: Option<(Style, String)> = None;
+        for &(i, c, _) in visible {
+            let style = if changed
+                .iter()
+                .any(|&(start, end)| (start..end).contains(&i))
+            {
+                emphasized
+            } else {
+                style
+            };
+            match &mut current {
+                Some((s, text)) if *s == style => text.push(c),
+                _ => {
+                    if let Some((s, text)) = current.replace((style, c.to_string())) {
+                        spans.push(Span::styled(text, s));
+                    }
+                }
+            }
+        }
+        if let Some((s, text)) = current {
+            spans.push(Span::styled(text, s));
+        }
+
+        match width {
+            Some(width) if overflow && width > 0 => {
+                // A wide char that didn't fit leaves a column before the `…`
+                if shown + 1 < width {
+                    spans.push(Span::raw(" ".repeat(width - 1 - shown)));
+                }
+                spans.push(Span::styled(
+                    "\u{2026}",
+                    Style::default().fg(self.colors.text_muted),
+                ));
+            }
+            Some(width) if !overflow => spans.push(Span::raw(" ".repeat(width - shown))),
+            _ => {}
+        }
+        spans
+    }
+}
@@ -0,0 +1,50 @@
+use crate::config::ColorConfig;
+use crate::i18n::tr;
+use crate::model::StashPicker;
+use crate::ui::layout;
+use ratatui::{
+    Frame,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState},
+};
+
+/// Render the stash list popup
+pub fn render_picker(frame: &mut Frame, picker: &StashPicker, colors: &ColorConfig) {
+    let area = layout::centered_rect(70, 50, frame.area());
+    frame.render_widget(Clear, area);
+
+    let items: Vec<ListItem> = picker
+        .entries
+        .iter()
+        .map(|entry| {
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{:<11}", entry.name()),
+                    Style::default().fg(colors.accent),
+                ),
+                Span::styled(entry.message.as_str(), Style::default().fg(colors.text)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .highlight_style(
+            Style::default()
+                .add_modifier(Modifier::REVERSED)
+                .add_modifier(Modifier::BOLD),
+        )
+        .block(
+            Block::default()
+                .title(format!(" {} ", tr("Stashes")))
+                .title_bottom(Line::styled(
+                    " Enter view \u{b7} a apply \u{b7} p pop \u{b7} d drop \u{b7} Esc close ",
+                    Style::default().fg(colors.text_muted),
+                ))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(colors.accent)),
+        );
+
+    let mut state = ListState::default().with_selected(Some(picker.selected));
+    frame.render_stateful_widget(list, area, &mut state);
+}
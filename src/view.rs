@@ -0,0 +1,440 @@
+use crate::app::App;
+use crate::model::{CommitInfo, Comparison, DiffState, HistoryList, RevRange};
+use crate::pair::PairRole;
+use std::path::Path;
+
+/// What the main screen says, worked out from `App` without any ratatui types so
+/// another frontend can show the same thing, and tests can check it without a
+/// terminal or a repository. `ui` draws it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct View {
+    /// Title over the file tree: the commit, range or comparison being browsed
+    pub tree_title: Option<String>,
+    /// Left title over the diff
+    pub diff_title: String,
+    /// Right-aligned title over the diff: where the commit lives, or how to get
+    /// around stashes
+    pub diff_side_title: Option<String>,
+    /// Bottom title of the diff: hunk position and search results
+    pub diff_status: String,
+    /// What's being browsed, most important first
+    pub modes: Vec<Mode>,
+    pub message: Option<String>,
+    /// Register of the macro being recorded
+    pub recording: Option<char>,
+    /// Commits ahead of and behind the upstream branch
+    pub ahead_behind: Option<(usize, usize)>,
+}
+
+/// One badge of the mode indicator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mode {
+    pub kind: ModeKind,
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeKind {
+    Worktree,
+    Commit,
+    Range,
+    Compare,
+    Stash,
+    FileHistory,
+    Blame,
+    Pair,
+    NotSaving,
+}
+
+/// The parts of `App` the main screen is worked out from
+pub struct Browsing<'a> {
+    pub diff: &'a DiffState,
+    pub commit: Option<&'a CommitInfo>,
+    pub comparison: Option<&'a Comparison>,
+    pub range: Option<&'a RevRange>,
+    pub file_log: Option<&'a HistoryList>,
+    pub stash_index: Option<usize>,
+    /// Branches and tags containing the commit, None while they're looked up
+    pub commit_refs: Option<&'a [String]>,
+    pub ignoring_whitespace: bool,
+    pub blame: bool,
+    pub pair: Option<PairRole>,
+    pub not_saving: bool,
+    pub message: Option<&'a str>,
+    pub recording: Option<char>,
+    pub ahead_behind: Option<(usize, usize)>,
+}
+
+impl<'a> Browsing<'a> {
+    pub fn of(app: &'a App) -> Self {
+        Self {
+            diff: &app.diff_state,
+            commit: app.current_commit.as_ref(),
+            comparison: app.comparison.as_ref(),
+            range: app.range.as_ref(),
+            file_log: app.file_log.as_ref(),
+            stash_index: app.stash_index,
+            commit_refs: app.commit_refs.as_deref(),
+            ignoring_whitespace: app.ignores_whitespace(),
+            blame: app.show_blame,
+            pair: app.pair.as_ref().map(|pair| pair.role),
+            not_saving: app.not_saving(),
+            message: app.message.as_deref(),
+            recording: app.macros.recording(),
+            ahead_behind: app.ahead_behind(),
+        }
+    }
+}
+
+impl View {
+    pub fn of(app: &App) -> Self {
+        Self::new(&Browsing::of(app))
+    }
+
+    pub fn new(state: &Browsing) -> Self {
+        Self {
+            tree_title: tree_title(state),
+            diff_title: diff_title(state),
+            diff_side_title: diff_side_title(state),
+            diff_status: diff_status(state.diff),
+            modes: modes(state),
+            message: state.message.map(String::from),
+            recording: state.recording,
+            ahead_behind: state.ahead_behind,
+        }
+    }
+}
+
+fn tree_title(state: &Browsing) -> Option<String> {
+    match (state.comparison, state.commit) {
+        (Some(c), _) if c.dirs => Some(format!("{} \u{2192} {}", c.from, c.to)),
+        _ if let Some(range) = state.range => Some(range.spec.clone()),
+        (_, Some(commit)) => Some(commit.oid.clone()),
+        _ => None,
+    }
+}
+
+/// Comparisons, ranges and file histories replace the file / commit header
+fn diff_title(state: &Browsing) -> String {
+    let title = match (state.comparison, state.range) {
+        (Some(c), _) => format!(" Compare {} \u{2192} {} [Esc to leave] ", c.from, c.to),
+        (None, None) if let Some(log) = state.file_log => {
+            let file = log.file.as_deref().unwrap_or(Path::new(""));
+            let commit = log
+                .selected_entry()
+                .map(|e| format!("{} {}", e.commit.oid, e.commit.message))
+                .unwrap_or_default();
+            format!(
+                " History of {} ({} of {}): {commit} [Esc to leave] ",
+                file.display(),
+                log.selected + 1,
+                log.entries.len()
+            )
+        }
+        (None, Some(range)) => format!(
+            " {} {}: {} commit{} ({}..{}) [Esc to leave] ",
+            range.kind.label(),
+            range.spec,
+            range.commits,
+            if range.commits == 1 { "" } else { "s" },
+            &range.from[..7],
+            &range.to[..7]
+        ),
+        (None, None) => build_title(state.diff, state.commit),
+    };
+    if state.ignoring_whitespace {
+        format!("{title}[ignoring whitespace] ")
+    } else {
+        title
+    }
+}
+
+fn build_title(state: &DiffState, commit: Option<&CommitInfo>) -> String {
+    if let Some(c) = commit {
+        let msg = truncate_message(&c.message, 50);
+        return format!(" {}: {} ", c.oid, msg);
+    }
+
+    let staged_label = if state.showing_staged {
+        "staged"
+    } else {
+        "unstaged"
+    };
+    let toggle_hint = if state.has_both { " [s to toggle]" } else { "" };
+
+    if state.showing_staged || state.has_both {
+        format!(" Diff ({staged_label}){toggle_hint} ")
+    } else {
+        " Diff ".to_string()
+    }
+}
+
+fn truncate_message(msg: &str, max_len: usize) -> String {
+    if msg.len() > max_len {
+        format!("{}...", &msg[..max_len - 3])
+    } else {
+        msg.to_string()
+    }
+}
+
+fn diff_side_title(state: &Browsing) -> Option<String> {
+    match (state.commit, state.comparison) {
+        (Some(_), None) if state.stash_index.is_some() => {
+            Some(" [ ] older / newer stash \u{b7} Esc to leave ".to_string())
+        }
+        (Some(_), None) => Some(build_refs_title(state.commit_refs)),
+        _ => None,
+    }
+}
+
+/// Branches/tags shown before the rest are summarized as "+N"
+const MAX_REFS_SHOWN: usize = 3;
+
+/// Branches and tags containing the viewed commit
+fn build_refs_title(refs: Option<&[String]>) -> String {
+    match refs {
+        None => " finding branches... ".to_string(),
+        Some([]) => " not on any branch or tag ".to_string(),
+        Some(refs) => {
+            let shown = refs[..refs.len().min(MAX_REFS_SHOWN)].join(", ");
+            let more = refs.len().saturating_sub(MAX_REFS_SHOWN);
+            if more > 0 {
+                format!(" in {shown} +{more} ")
+            } else {
+                format!(" in {shown} ")
+            }
+        }
+    }
+}
+
+fn diff_status(state: &DiffState) -> String {
+    let hunk_info = if !state.hunk_positions.is_empty() {
+        format!(
+            " Hunk {}/{} ",
+            state.current_hunk + 1,
+            state.hunk_positions.len()
+        )
+    } else {
+        String::new()
+    };
+    let search_info = match &state.search {
+        Some(search) if search.matches.is_empty() => format!(" No match for {:?} ", search.query),
+        Some(search) => match search.current {
+            Some(i) => format!(" Match {}/{} ", i + 1, search.matches.len()),
+            None => format!(" {} matches ", search.matches.len()),
+        },
+        None => String::new(),
+    };
+    let column_info = if state.x_offset > 0 {
+        format!(" Column {} ", state.x_offset + 1)
+    } else {
+        String::new()
+    };
+    hunk_info + &search_info + &column_info
+}
+
+fn modes(state: &Browsing) -> Vec<Mode> {
+    let mode = |kind, label: String| Mode { kind, label };
+    let mut modes = vec![match (state.comparison, state.commit) {
+        (Some(c), _) => mode(
+            ModeKind::Compare,
+            format!("COMPARE {}\u{2026}{}", c.from, c.to),
+        ),
+        _ if let Some(range) = state.range => {
+            mode(ModeKind::Range, format!("RANGE {}", range.spec))
+        }
+        (_, Some(_)) if let Some(index) = state.stash_index => {
+            mode(ModeKind::Stash, format!("STASH@{{{index}}}"))
+        }
+        (_, Some(commit)) if let Some(log) = state.file_log => {
+            let file = log.file.as_deref().unwrap_or(Path::new(""));
+            mode(
+                ModeKind::FileHistory,
+                format!("HISTORY {} {}", file.display(), commit.oid),
+            )
+        }
+        (_, Some(commit)) => mode(ModeKind::Commit, format!("COMMIT {}", commit.oid)),
+        (None, None) => mode(ModeKind::Worktree, "WORKTREE".to_string()),
+    }];
+    if state.blame {
+        modes.push(mode(ModeKind::Blame, "BLAME".to_string()));
+    }
+    if let Some(role) = state.pair {
+        let label = match role {
+            PairRole::Lead => "LEADING",
+            PairRole::Follow => "FOLLOWING",
+        };
+        modes.push(mode(ModeKind::Pair, label.to_string()));
+    }
+    if state.not_saving {
+        modes.push(mode(ModeKind::NotSaving, "NOT SAVING".to_string()));
+    }
+    modes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::RangeKind;
+    use std::sync::Arc;
+
+    fn browsing(diff: &DiffState) -> Browsing<'_> {
+        Browsing {
+            diff,
+            commit: None,
+            comparison: None,
+            range: None,
+            file_log: None,
+            stash_index: None,
+            commit_refs: None,
+            ignoring_whitespace: false,
+            blame: false,
+            pair: None,
+            not_saving: false,
+            message: None,
+            recording: None,
+            ahead_behind: None,
+        }
+    }
+
+    fn commit() -> CommitInfo {
+        CommitInfo {
+            oid: "abc1234".to_string(),
+            oid_full: "abc1234".repeat(6),
+            message: "Fix the parser".to_string(),
+        }
+    }
+
+    fn labels(view: &View) -> Vec<&str> {
+        view.modes.iter().map(|mode| mode.label.as_str()).collect()
+    }
+
+    #[test]
+    fn the_working_tree() {
+        let diff = DiffState {
+            has_both: true,
+            hunk_positions: vec![0, 10, 20],
+            current_hunk: 1,
+            ..DiffState::new()
+        };
+        let view = View::new(&browsing(&diff));
+        assert_eq!(view.tree_title, None);
+        assert_eq!(view.diff_title, " Diff (unstaged) [s to toggle] ");
+        assert_eq!(view.diff_side_title, None);
+        assert_eq!(view.diff_status, " Hunk 2/3 ");
+        assert_eq!(labels(&view), ["WORKTREE"]);
+    }
+
+    #[test]
+    fn a_commit_and_where_it_lives() {
+        let diff = DiffState::new();
+        let commit = commit();
+        let refs = ["main".to_string(), "v1".into(), "v2".into(), "topic".into()];
+        let mut state = Browsing {
+            commit: Some(&commit),
+            ..browsing(&diff)
+        };
+        let view = View::new(&state);
+        assert_eq!(view.tree_title.as_deref(), Some("abc1234"));
+        assert_eq!(view.diff_title, " abc1234: Fix the parser ");
+        assert_eq!(
+            view.diff_side_title.as_deref(),
+            Some(" finding branches... ")
+        );
+        assert_eq!(labels(&view), ["COMMIT abc1234"]);
+
+        state.commit_refs = Some(&refs);
+        let view = View::new(&state);
+        assert_eq!(
+            view.diff_side_title.as_deref(),
+            Some(" in main, v1, v2 +1 ")
+        );
+
+        state.stash_index = Some(2);
+        assert_eq!(labels(&View::new(&state)), ["STASH@{2}"]);
+    }
+
+    #[test]
+    fn a_range_outranks_the_commit() {
+        let diff = DiffState::new();
+        let commit = commit();
+        let range = RevRange {
+            kind: RangeKind::Review,
+            spec: "main...HEAD".to_string(),
+            from: "1111111111".to_string(),
+            to: "2222222222".to_string(),
+            commits: 1,
+        };
+        let view = View::new(&Browsing {
+            commit: Some(&commit),
+            range: Some(&range),
+            ignoring_whitespace: true,
+            ..browsing(&diff)
+        });
+        assert_eq!(view.tree_title.as_deref(), Some("main...HEAD"));
+        assert!(
+            view.diff_title
+                .contains("main...HEAD: 1 commit (1111111..2222222)")
+        );
+        assert!(view.diff_title.ends_with("[ignoring whitespace] "));
+        assert_eq!(labels(&view), ["RANGE main...HEAD"]);
+    }
+
+    #[test]
+    fn a_directory_comparison() {
+        let diff = DiffState::new();
+        let mut comparison = Comparison::parse("src@v1 src", None).unwrap();
+        comparison.dirs = true;
+        let view = View::new(&Browsing {
+            comparison: Some(&comparison),
+            ..browsing(&diff)
+        });
+        assert_eq!(view.tree_title.as_deref(), Some("src@v1 \u{2192} src"));
+        assert_eq!(labels(&view), ["COMPARE src@v1\u{2026}src"]);
+    }
+
+    #[test]
+    fn badges_after_what_is_browsed() {
+        let diff = DiffState::new();
+        let view = View::new(&Browsing {
+            blame: true,
+            pair: Some(PairRole::Follow),
+            not_saving: true,
+            ..browsing(&diff)
+        });
+        assert_eq!(
+            labels(&view),
+            ["WORKTREE", "BLAME", "FOLLOWING", "NOT SAVING"]
+        );
+        let kinds: Vec<_> = view.modes.iter().map(|mode| mode.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                ModeKind::Worktree,
+                ModeKind::Blame,
+                ModeKind::Pair,
+                ModeKind::NotSaving
+            ]
+        );
+    }
+
+    #[test]
+    fn search_and_sideways_scroll_in_the_status() {
+        let mut diff = DiffState {
+            content: Arc::new("one\ntwo one".into()),
+            x_offset: 4,
+            ..DiffState::new()
+        };
+        diff.set_search("one".to_string());
+        assert_eq!(
+            View::new(&browsing(&diff)).diff_status,
+            " 2 matches  Column 5 "
+        );
+        diff.set_search("three".to_string());
+        assert!(
+            View::new(&browsing(&diff))
+                .diff_status
+                .starts_with(" No match for \"three\" ")
+        );
+    }
+}
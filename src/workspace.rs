@@ -0,0 +1,171 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A member of the repository's Cargo or npm workspace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    pub name: String,
+    /// Relative to the repository root
+    pub dir: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct CargoManifest {
+    package: Option<CargoPackage>,
+    workspace: Option<CargoWorkspace>,
+}
+
+#[derive(Deserialize)]
+struct CargoPackage {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct CargoWorkspace {
+    #[serde(default)]
+    members: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct PackageJson {
+    name: Option<String>,
+    workspaces: Option<Workspaces>,
+}
+
+/// `"workspaces"` is a list of globs, or (for Yarn) an object holding one
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Workspaces {
+    List(Vec<String>),
+    Object {
+        #[serde(default)]
+        packages: Vec<String>,
+    },
+}
+
+/// The workspace members declared by the root `Cargo.toml` and `package.json`,
+/// sorted by name. Anything unreadable is left out: grouping is only a view.
+pub fn packages(repo_path: &Path) -> Vec<Package> {
+    let mut packages = HashMap::new();
+    for package in cargo_packages(repo_path)
+        .into_iter()
+        .chain(npm_packages(repo_path))
+    {
+        packages.entry(package.dir.clone()).or_insert(package);
+    }
+    let mut packages: Vec<Package> = packages.into_values().collect();
+    packages.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.dir.cmp(&b.dir)));
+    packages
+}
+
+fn cargo_packages(repo_path: &Path) -> Vec<Package> {
+    let Some(workspace) = read_cargo(&repo_path.join("Cargo.toml")).and_then(|m| m.workspace)
+    else {
+        return Vec::new();
+    };
+    let excluded: Vec<PathBuf> = workspace
+        .exclude
+        .iter()
+        .flat_map(|pattern| expand(repo_path, pattern))
+        .collect();
+    workspace
+        .members
+        .iter()
+        .flat_map(|pattern| expand(repo_path, pattern))
+        .filter(|dir| !excluded.contains(dir))
+        .filter_map(|dir| {
+            let manifest = read_cargo(&repo_path.join(&dir).join("Cargo.toml"))?;
+            Some(Package {
+                name: manifest.package?.name,
+                dir,
+            })
+        })
+        .collect()
+}
+
+fn read_cargo(path: &Path) -> Option<CargoManifest> {
+    toml::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+fn npm_packages(repo_path: &Path) -> Vec<Package> {
+    let patterns =
+        match read_package_json(&repo_path.join("package.json")).and_then(|p| p.workspaces) {
+            Some(Workspaces::List(patterns)) => patterns,
+            Some(Workspaces::Object { packages }) => packages,
+            None => return Vec::new(),
+        };
+    // npm and Yarn accept `!pattern` to leave directories out
+    let (excluded, included): (Vec<_>, Vec<_>) = patterns
+        .iter()
+        .partition(|pattern| pattern.starts_with('!'));
+    let excluded: Vec<PathBuf> = excluded
+        .iter()
+        .flat_map(|pattern| expand(repo_path, &pattern[1..]))
+        .collect();
+    included
+        .iter()
+        .flat_map(|pattern| expand(repo_path, pattern))
+        .filter(|dir| !excluded.contains(dir))
+        .filter_map(|dir| {
+            let manifest = read_package_json(&repo_path.join(&dir).join("package.json"))?;
+            let name = manifest
+                .name
+                .unwrap_or_else(|| dir.to_string_lossy().into_owned());
+            Some(Package { name, dir })
+        })
+        .collect()
+}
+
+fn read_package_json(path: &Path) -> Option<PackageJson> {
+    serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+/// The directories under the repository matching a member pattern, where `*`
+/// matches within one path segment (`crates/*`, `packages/ui-*`)
+fn expand(repo_path: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::new()];
+    for segment in pattern.trim_start_matches("./").split('/') {
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+        if !segment.contains('*') {
+            dirs = dirs.into_iter().map(|dir| dir.join(segment)).collect();
+            continue;
+        }
+        dirs = dirs
+            .into_iter()
+            .flat_map(|dir| {
+                let entries = std::fs::read_dir(repo_path.join(&dir))
+                    .into_iter()
+                    .flatten();
+                entries
+                    .flatten()
+                    .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+                    .filter_map(|entry| entry.file_name().into_string().ok())
+                    .filter(|name| !name.starts_with('.') && matches(segment, name))
+                    .map(|name| dir.join(name))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+    }
+    dirs.retain(|dir| !dir.as_os_str().is_empty() && repo_path.join(dir).is_dir());
+    dirs
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of characters
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=name.len())
+                .filter(|&i| name.is_char_boundary(i))
+                .any(|i| matches(rest, &name[i..]))
+        }
+    }
+}